@@ -0,0 +1,29 @@
+//! Derives `PROTOCOL_VERSION` from the files that define what is sent over the network,
+//! so binaries built from commits with different replicated types refuse to talk to each other
+
+use std::{env, fs, path::PathBuf};
+
+/// `protocol.rs` holds `PROTOCOL_REVISION`, which is bumped by hand for changes made elsewhere
+const SOURCES: &[&str] = &["src/components.rs", "src/protocol.rs"];
+
+fn main() {
+    // FNV-1a, the std hasher is not stable between rust versions
+    let mut hash: u32 = 0x811c9dc5;
+
+    for source in SOURCES {
+        println!("cargo:rerun-if-changed={source}");
+
+        let contents = fs::read_to_string(source).expect("Read protocol source");
+        // Checkouts on windows may have CRLF line endings
+        for byte in contents.replace("\r\n", "\n").bytes() {
+            hash = (hash ^ byte as u32).wrapping_mul(0x01000193);
+        }
+    }
+
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    fs::write(
+        out_dir.join("protocol_version.rs"),
+        format!("{hash:#010x}_u32"),
+    )
+    .expect("Write protocol version");
+}
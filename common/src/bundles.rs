@@ -2,10 +2,11 @@ use bevy::{core::Name, ecs::bundle::Bundle, transform::components::Transform};
 
 use crate::components::{
     ActualForce, ActualMovement, Armed, Camera, Cores, CpuTotal, CurrentDraw, Depth, Disks,
-    Inertial, Leak, LoadAverage, Magnetic, MeasuredVoltage, Memory, MotorDefinition, Motors,
-    MovementAxisMaximums, MovementContribution, MovementCurrentCap, Networks, OperatingSystem,
-    Orientation, Processes, PwmChannel, PwmSignal, Robot, RobotId, RobotStatus, ServoDefinition,
-    ServoMode, ServoTargets, TargetForce, TargetMovement, Temperatures, Uptime,
+    FailsafeActive, Inertial, Leak, Leaks, LoadAverage, Magnetic, MeasuredVoltage, Memory,
+    MotorDefinition, Motors, MovementAxisBounds, MovementAxisMaximums, MovementContribution,
+    MovementCurrentCap, Networks, OperatingSystem, Orientation, Processes, PwmChannel, PwmSignal,
+    Robot, RobotId, RobotStatus, ServoDefinition, ServoMode, ServoTargets, TargetForce,
+    TargetMovement, Temperatures, Uptime,
 };
 
 #[derive(Bundle, PartialEq)]
@@ -34,6 +35,7 @@ pub struct RobotSensorBundle {
     pub mag: Magnetic,
     pub depth: Depth,
     pub leak: Leak,
+    pub leaks: Leaks,
 }
 
 #[derive(Bundle, PartialEq)]
@@ -57,9 +59,11 @@ pub struct RobotActuatorBundle {
 
     pub motor_config: Motors,
     pub axis_maximums: MovementAxisMaximums,
+    pub axis_bounds: MovementAxisBounds,
     pub current_cap: MovementCurrentCap,
 
     pub armed: Armed,
+    pub failsafe: FailsafeActive,
 }
 
 // TODO(mid): Sensor not implemented
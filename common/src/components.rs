@@ -13,36 +13,71 @@ use crate::{
     adapters::serde::ReflectSerdeAdapter,
     ecs_sync::{AppReplicateExt, NetId},
     types::{
+        error::{RobotError, Severity},
         hw::{DepthFrame, InertialFrame, MagneticFrame, PwmChannelId},
-        system::{ComponentTemperature, Cpu, Disk, Network, Process},
+        system::{ComponentTemperature, Cpu, Disk, GpuStats, Network, Process},
         units::{Amperes, Mbar, Meters, Newtons, Volts},
     },
 };
 
+/// Components marked `#[reliable]` are retransmitted until the peer acknowledges them,
+/// updates to components marked `#[priority]` are sent before the other updates from the same frame
 macro_rules! components {
-    ($($name:ident),*) => {
+    ($($(#[$delivery:ident])? $name:ident),*) => {
         pub fn register_components(app: &mut App) {
+            // Not components, but wrapped by the movement, camera, servo, and leak components
+            app.register_type::<Movement<f32>>();
+            app.register_type::<CameraId>();
+            app.register_type::<LeakProbe>();
+
             $(
-                app.replicate::<$name>();
+                components!(@replicate app, $name $(, $delivery)?);
             )*
         }
-    }
+    };
+    (@replicate $app:ident, $name:ident) => {
+        $app.replicate::<$name>();
+    };
+    (@replicate $app:ident, $name:ident, reliable) => {
+        $app.replicate_reliable::<$name>();
+    };
+    (@replicate $app:ident, $name:ident, priority) => {
+        $app.replicate_priority::<$name>();
+    };
 }
 
 components! {
     Singleton,
     Robot,
     Surface,
+    #[priority]
     Orientation,
     Inertial,
     Magnetic,
+    MagnetometerStatus,
+    #[reliable]
+    MagCalibrationCommand,
+    MagCalibrationResult,
+    #[priority]
     Depth,
+    #[priority]
     DepthTarget,
+    DepthRampLimit,
+    EffectiveDepthTarget,
     DepthSettings,
+    #[priority]
     OrientationTarget,
     Leak,
+    Leaks,
+    #[reliable]
+    ClearLeaks,
     RobotStatus,
+    #[reliable]
     Armed,
+    #[reliable]
+    FailsafeActive,
+    #[reliable]
+    EmergencyStop,
     Camera,
     RobotId,
     Processes,
@@ -51,6 +86,7 @@ components! {
     CpuTotal,
     Cores,
     Memory,
+    GpuInfo,
     Temperatures,
     Disks,
     Uptime,
@@ -63,13 +99,18 @@ components! {
     ServoMode,
     Motors,
     Servos,
+    #[priority]
     TargetMovement,
     ActualMovement,
     MeasuredVoltage,
+    #[priority]
     MovementContribution,
     ServoContribution,
     MotorContribution,
+    MotorSaturation,
+    MotorTrims,
     MovementAxisMaximums,
+    MovementAxisBounds,
     MovementCurrentCap,
     CurrentDraw,
     JerkLimit,
@@ -77,7 +118,12 @@ components! {
     PwmSignal,
     PwmManualControl,
     PidConfig,
-    PidResult
+    PidResult,
+    FeedForwardInput,
+    GainSchedule,
+    PeerLatency,
+    DroppedUpdates,
+    RobotErrors
 }
 
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
@@ -104,6 +150,37 @@ pub struct Inertial(pub InertialFrame);
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct Magnetic(pub MagneticFrame);
 
+/// Health of the magnetometer, a dead sensor shows up as a rate near zero and a growing age
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MagnetometerStatus {
+    /// New measurements per second, duplicates are not counted
+    pub rate_hz: f32,
+    pub last_reading_age: Duration,
+    /// Polls since startup that found no new measurement
+    pub skipped_polls: u64,
+}
+
+/// Asks the robot to collect magnetometer samples for `duration` while the vehicle is rotated
+/// through as many orientations as possible, then fit a new calibration from them
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MagCalibrationCommand {
+    pub duration: Duration,
+}
+
+/// The magnetometer calibration the robot is currently applying
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MagCalibrationResult {
+    /// Subtracted from each sample, in gauss
+    pub hard_iron: [f32; 3],
+    /// Row major matrix applied after the hard iron offset
+    pub soft_iron: [[f32; 3]; 3],
+    /// Samples the fit used, zero if the calibration was loaded from disk
+    pub samples: u32,
+}
+
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct Depth(pub DepthFrame);
@@ -112,6 +189,16 @@ pub struct Depth(pub DepthFrame);
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct DepthTarget(pub Meters);
 
+/// Fastest the depth hold's target may move, in meters per second
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DepthRampLimit(pub f32);
+
+/// The target the depth hold is currently holding, follows `DepthTarget` at up to `DepthRampLimit`
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct EffectiveDepthTarget(pub Meters);
+
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct DepthSettings {
@@ -124,9 +211,31 @@ pub struct DepthSettings {
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct OrientationTarget(pub Quat);
 
+/// Whether any leak probe has triggered
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
-pub struct Leak(pub bool);
+pub struct Leak {
+    pub detected: bool,
+    /// How far the wettest probe is through its debounce period, from 0.0 to 1.0
+    pub confidence: f32,
+}
+
+/// The state of each leak probe, a triggered probe stays triggered until `ClearLeaks`
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Leaks(pub Vec<LeakProbe>);
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct LeakProbe {
+    pub name: String,
+    pub triggered: bool,
+}
+
+/// Asks the robot to reset every triggered leak probe
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ClearLeaks;
 
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
@@ -138,6 +247,8 @@ pub enum RobotStatus {
     Disarmed,
     /// Peer is connected and robot is armed
     Armed,
+    /// A critical error was reported, the robot is disarmed until it is cleared from `RobotErrors`
+    Fault,
 }
 
 #[derive(
@@ -150,13 +261,100 @@ pub enum Armed {
     Disarmed,
 }
 
+/// Set by the robot when it stopped the motors because movement commands stopped arriving
+///
+/// Cleared when the robot is armed again
+#[derive(
+    Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Eq, Default,
+)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct FailsafeActive(pub bool);
+
+/// Latched by the surface, the robot stays disarmed while this is set
+#[derive(
+    Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Eq, Default,
+)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct EmergencyStop(pub bool);
+
+/// Identifies a camera across robot restarts, the name the robot's config uses for it
+#[derive(
+    Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default,
+)]
+#[serde(transparent)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq, Hash, Default)]
+pub struct CameraId(pub Cow<'static, str>);
+
+impl<T: Into<Cow<'static, str>>> From<T> for CameraId {
+    fn from(id: T) -> Self {
+        CameraId(id.into())
+    }
+}
+
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Eq)]
 #[reflect(from_reflect = false)]
 #[reflect(SerdeAdapter, /*Serialize, Deserialize,*/ Debug, PartialEq)]
 pub struct Camera {
+    // Robots from before these fields existed get rejected by the protocol version check,
+    // the defaults only help self describing formats such as config files
+    #[serde(default)]
+    pub id: CameraId,
+    /// Label shown with the feed, may be empty
+    #[serde(default)]
+    pub name: String,
     // TODO(low): This bad
     #[reflect(ignore)]
-    pub location: SocketAddr,
+    pub source: CameraSource,
+}
+
+impl Camera {
+    /// The name to label the feed with
+    pub fn label(&self) -> &str {
+        if !self.name.is_empty() {
+            &self.name
+        } else if !self.id.0.is_empty() {
+            &self.id.0
+        } else {
+            "Unnamed Camera"
+        }
+    }
+}
+
+/// Where the surface gets a camera's video from
+///
+/// Sent as a `kind:description` string so builds that do not know a kind can still decode it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(into = "String", from = "String")]
+pub enum CameraSource {
+    /// RTP H264 stream sent to this address
+    H264(SocketAddr),
+    /// Gstreamer launch description producing the video, the conversion and appsink are appended
+    Gstreamer(String),
+    /// A source this build does not understand, kept as received
+    Unsupported(String),
+}
+
+impl From<CameraSource> for String {
+    fn from(source: CameraSource) -> Self {
+        match source {
+            CameraSource::H264(location) => format!("h264:{location}"),
+            CameraSource::Gstreamer(pipeline) => format!("gstreamer:{pipeline}"),
+            CameraSource::Unsupported(source) => source,
+        }
+    }
+}
+
+impl From<String> for CameraSource {
+    fn from(source: String) -> Self {
+        match source.split_once(':') {
+            Some(("h264", location)) => match location.parse() {
+                Ok(location) => CameraSource::H264(location),
+                Err(_) => CameraSource::Unsupported(source),
+            },
+            Some(("gstreamer", pipeline)) => CameraSource::Gstreamer(pipeline.to_owned()),
+            _ => CameraSource::Unsupported(source),
+        }
+    }
 }
 
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Eq)]
@@ -200,6 +398,11 @@ pub struct Memory {
     pub free_swap: u64,
 }
 
+/// Not inserted on platforms without a supported gpu
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct GpuInfo(pub GpuStats);
+
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
 pub struct Temperatures(pub Vec<ComponentTemperature>);
@@ -237,9 +440,7 @@ pub struct MotorDefinition(pub ErasedMotorId, pub Motor<f32>);
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct ServoDefinition {
-    // TODO: Make CameraId type
-    // TODO: Reevaluate if using Cow makes sense
-    pub cameras: Vec<Cow<'static, str>>,
+    pub cameras: Vec<CameraId>,
 }
 
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
@@ -297,6 +498,18 @@ pub struct MotorContribution(
     #[reflect(ignore)] pub BTreeMap<ErasedMotorId, Newtons>,
 );
 
+/// Fraction of each motor's maximum force currently requested, from 0.0 to 1.0
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, /*Serialize, Deserialize,*/ Debug, PartialEq, Default)]
+#[reflect(from_reflect = false)]
+pub struct MotorSaturation(#[reflect(ignore)] pub BTreeMap<ErasedMotorId, f32>);
+
+/// Per motor force offsets in newtons, added to the solved motor forces to compensate for weak thrusters
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, /*Serialize, Deserialize,*/ Debug, PartialEq, Default)]
+#[reflect(from_reflect = false)]
+pub struct MotorTrims(#[reflect(ignore)] pub BTreeMap<ErasedMotorId, f32>);
+
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
 #[reflect(SerdeAdapter, /*Serialize, Deserialize,*/ Debug, PartialEq, Default)]
 #[reflect(from_reflect = false)]
@@ -312,6 +525,11 @@ pub struct MovementAxisMaximums(
     #[reflect(ignore)] pub BTreeMap<Axis, Newtons>,
 );
 
+/// Most negative and most positive force achievable along each axis
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MovementAxisBounds(#[reflect(ignore)] pub BTreeMap<Axis, (Newtons, Newtons)>);
+
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct MovementCurrentCap(pub Amperes);
@@ -320,9 +538,23 @@ pub struct MovementCurrentCap(pub Amperes);
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct CurrentDraw(pub Amperes);
 
+/// Fastest the thrusters' movement may change along each axis, indexed in `Axis::ALL` order
+///
+/// In newtons per second for translation and newton meters per second for rotation
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
-pub struct JerkLimit(pub f32);
+pub struct JerkLimit(pub [f32; 6]);
+
+impl JerkLimit {
+    /// The same limit on every axis
+    pub fn uniform(limit: f32) -> Self {
+        Self([limit; 6])
+    }
+
+    pub fn axis(&self, axis: Axis) -> f32 {
+        self.0[axis as usize]
+    }
+}
 
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, Copy, Eq, Hash, PartialEq)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
@@ -338,16 +570,58 @@ pub struct PwmSignal(pub Duration);
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct PwmManualControl;
 
-#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct PidConfig {
     pub kp: f32,
     pub ki: f32,
     pub kd: f32,
     // Target change
     pub kt: f32,
+    /// Gain applied to the entity's `FeedForwardInput`
+    #[serde(default)]
+    pub kf: f32,
 
     pub max_integral: f32,
+
+    /// Time constant of the low pass filter on the derivative term in seconds, 0 disables filtering
+    ///
+    /// The cutoff frequency is `1 / (2π * derivative_tau)`
+    #[serde(default)]
+    pub derivative_tau: f32,
+
+    /// Limits of the correction, the integral is unwound while the output is saturated
+    #[serde(default = "PidConfig::default_output_min")]
+    pub output_min: f32,
+    #[serde(default = "PidConfig::default_output_max")]
+    pub output_max: f32,
+}
+
+impl PidConfig {
+    fn default_output_min() -> f32 {
+        f32::NEG_INFINITY
+    }
+
+    fn default_output_max() -> f32 {
+        f32::INFINITY
+    }
+}
+
+impl Default for PidConfig {
+    fn default() -> Self {
+        Self {
+            kp: 0.0,
+            ki: 0.0,
+            kd: 0.0,
+            kt: 0.0,
+            kf: 0.0,
+            max_integral: 0.0,
+            derivative_tau: 0.0,
+            output_min: Self::default_output_min(),
+            output_max: Self::default_output_max(),
+        }
+    }
 }
 
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
@@ -358,6 +632,200 @@ pub struct PidResult {
     pub d: f32,
     // Target change
     pub td: f32,
+    pub ff: f32,
 
     pub correction: f32,
+    /// The correction was clamped to the configured output range
+    pub saturated: bool,
+}
+
+/// Known disturbance compensated by a PID controller, scaled by `PidConfig::kf`
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct FeedForwardInput(pub f32);
+
+/// Depth dependent multipliers for the depth hold `PidConfig`'s `kp` and `ki`
+///
+/// `breakpoints` are depths in meters sorted in increasing order, with one scale of each kind per breakpoint.
+/// Depths between breakpoints are linearly interpolated and depths outside of them use the nearest breakpoint
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct GainSchedule {
+    pub breakpoints: Vec<f32>,
+    pub kp_scale: Vec<f32>,
+    pub ki_scale: Vec<f32>,
+}
+
+impl GainSchedule {
+    /// The `(kp, ki)` multipliers at `depth`, an empty schedule leaves the gains unchanged
+    pub fn evaluate(&self, depth: f32) -> (f32, f32) {
+        let len = self
+            .breakpoints
+            .len()
+            .min(self.kp_scale.len())
+            .min(self.ki_scale.len());
+        if len == 0 {
+            return (1.0, 1.0);
+        }
+
+        let breakpoints = &self.breakpoints[..len];
+        let partition_point = breakpoints.partition_point(|&x| x < depth);
+
+        if partition_point == 0 {
+            return (self.kp_scale[0], self.ki_scale[0]);
+        }
+        if partition_point == len {
+            return (self.kp_scale[len - 1], self.ki_scale[len - 1]);
+        }
+
+        let a = partition_point - 1;
+        let b = partition_point;
+        let alpha = (depth - breakpoints[a]) / (breakpoints[b] - breakpoints[a]);
+
+        let lerp = |scales: &[f32]| scales[a] + (scales[b] - scales[a]) * alpha;
+
+        (lerp(&self.kp_scale), lerp(&self.ki_scale))
+    }
+}
+
+/// Link quality to a peer as measured by the other end of the connection
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct PeerLatency {
+    /// Mean round trip time over the recent pings
+    pub rtt: Duration,
+    /// Fastest and slowest round trip times over the recent pings
+    pub min_rtt: Duration,
+    pub max_rtt: Duration,
+    /// Mean difference between consecutive round trip times
+    pub jitter: Duration,
+    /// Fraction of the recent pings that never got a response, 0.0 to 1.0
+    pub packet_loss: f32,
+}
+
+/// Replication updates a peer has dropped to stay within its bandwidth budget, see `sync::BandwidthConfig`
+#[derive(
+    Component, Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq, Default,
+)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct DroppedUpdates(pub u64);
+
+/// The most recent errors reported by the robot, oldest first
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct RobotErrors(pub Vec<RobotError>);
+
+impl RobotErrors {
+    pub const MAX_LEN: usize = 64;
+
+    /// Adds an error, dropping the oldest errors once there are more than `MAX_LEN`
+    pub fn push(&mut self, error: RobotError) {
+        self.0.push(error);
+
+        if self.0.len() > Self::MAX_LEN {
+            let excess = self.0.len() - Self::MAX_LEN;
+            self.0.drain(..excess);
+        }
+    }
+
+    /// Fatal errors count as critical
+    pub fn has_critical(&self) -> bool {
+        self.0
+            .iter()
+            .any(|error| error.severity >= Severity::Critical)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(idx: usize, severity: Severity) -> RobotError {
+        RobotError {
+            message: format!("Error {idx}"),
+            severity,
+            timestamp: Duration::from_secs(idx as u64),
+            source: "test".to_owned(),
+        }
+    }
+
+    #[test]
+    fn robot_errors_drop_oldest() {
+        let mut errors = RobotErrors::default();
+
+        errors.push(error(0, Severity::Critical));
+        assert!(errors.has_critical());
+
+        for idx in 1..=RobotErrors::MAX_LEN {
+            errors.push(error(idx, Severity::Warning));
+        }
+
+        assert_eq!(errors.0.len(), RobotErrors::MAX_LEN);
+        assert_eq!(errors.0.first(), Some(&error(1, Severity::Warning)));
+        assert_eq!(
+            errors.0.last(),
+            Some(&error(RobotErrors::MAX_LEN, Severity::Warning))
+        );
+
+        // The critical error was the oldest
+        assert!(!errors.has_critical());
+    }
+
+    #[test]
+    fn camera_roundtrip() {
+        let cameras = [
+            CameraSource::H264("10.0.0.2:1024".parse().unwrap()),
+            CameraSource::H264("[::1]:5600".parse().unwrap()),
+            CameraSource::Gstreamer("v4l2src device=/dev/video2 ! jpegdec".to_owned()),
+        ];
+
+        for source in cameras {
+            let camera = Camera {
+                id: "front".into(),
+                name: "Front".to_owned(),
+                source,
+            };
+            let data = bincode::serialize(&camera).unwrap();
+
+            assert_eq!(bincode::deserialize::<Camera>(&data).unwrap(), camera);
+        }
+    }
+
+    #[test]
+    fn unknown_camera_source_is_unsupported() {
+        // What a newer build with another kind of source would send
+        let data = bincode::serialize(&("front", "", "mjpeg:http://10.0.0.2/stream")).unwrap();
+        let camera: Camera = bincode::deserialize(&data).unwrap();
+        assert_eq!(camera.label(), "front");
+
+        assert_eq!(
+            camera.source,
+            CameraSource::Unsupported("mjpeg:http://10.0.0.2/stream".to_owned())
+        );
+
+        let data = bincode::serialize(&("", "", "h264:not an address")).unwrap();
+        let camera: Camera = bincode::deserialize(&data).unwrap();
+
+        assert!(matches!(camera.source, CameraSource::Unsupported(_)));
+    }
+
+    #[test]
+    fn gain_schedule_interpolates() {
+        let schedule = GainSchedule {
+            breakpoints: vec![0.0, 2.0, 4.0],
+            kp_scale: vec![1.0, 2.0, 4.0],
+            ki_scale: vec![1.0, 0.5, 0.5],
+        };
+
+        assert_eq!(schedule.evaluate(0.0), (1.0, 1.0));
+        assert_eq!(schedule.evaluate(1.0), (1.5, 0.75));
+        assert_eq!(schedule.evaluate(2.0), (2.0, 0.5));
+        assert_eq!(schedule.evaluate(3.0), (3.0, 0.5));
+
+        // Clamped to the outermost breakpoints
+        assert_eq!(schedule.evaluate(-1.0), (1.0, 1.0));
+        assert_eq!(schedule.evaluate(10.0), (4.0, 0.5));
+
+        assert_eq!(GainSchedule::default().evaluate(3.0), (1.0, 1.0));
+    }
 }
@@ -1,5 +1,6 @@
 pub mod apply_changes;
 pub mod detect_changes;
+pub mod recorder;
 
 use std::any::Any;
 use std::sync::Arc;
@@ -48,6 +49,12 @@ impl NetId {
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct ForignOwned(pub(crate) usize);
 
+impl From<Token> for ForignOwned {
+    fn from(token: Token) -> Self {
+        Self(token.0)
+    }
+}
+
 pub type NetTypeId = Cow<'static, str>;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -94,6 +101,16 @@ pub struct ComponentInfo {
     type_adapter: ComponentTypeAdapter,
     ignore_component: ComponentId,
     remove_fn: RemoveFn,
+    delivery: Delivery,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Delivery {
+    Normal,
+    /// Updates are retransmitted until the peer acknowledges them, see `AppReplicateExt::replicate_reliable`
+    Reliable,
+    /// Updates are sent before the rest of the frame's updates, see `AppReplicateExt::replicate_priority`
+    Priority,
 }
 
 #[derive(Clone)]
@@ -126,11 +143,82 @@ impl FromWorld for SerializationSettings {
     }
 }
 
+impl SerializationSettings {
+    /// Sorted hashes of every replicated component and event token
+    ///
+    /// Peers built with different replicated types will have different hashes
+    pub fn token_hashes(&self) -> Vec<u64> {
+        let mut hashes: Vec<_> = self.tokens().map(|token| token_hash(token)).collect();
+        hashes.sort_unstable();
+
+        hashes
+    }
+
+    /// Single hash of `token_hashes`, see `recorder::RecordingHeader`
+    pub fn registry_hash(&self) -> u64 {
+        self.token_hashes()
+            .iter()
+            .flat_map(|hash| hash.to_le_bytes())
+            .fold(0xcbf29ce484222325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+
+    /// Whether a change should be retransmitted until the peer acknowledges it
+    pub fn is_reliable(&self, change: &SerializedChange) -> bool {
+        match change {
+            SerializedChange::ComponentUpdated(_, token, _) => self
+                .component_by_token
+                .get(token)
+                .is_some_and(|info| info.delivery == Delivery::Reliable),
+            _ => false,
+        }
+    }
+
+    /// Whether a change should be sent ahead of the other unreliable changes
+    pub fn is_priority(&self, change: &SerializedChange) -> bool {
+        match change {
+            SerializedChange::ComponentUpdated(_, token, _) => self
+                .component_by_token
+                .get(token)
+                .is_some_and(|info| info.delivery == Delivery::Priority),
+            _ => false,
+        }
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = &NetTypeId> {
+        self.component_by_token
+            .keys()
+            .chain(self.event_by_token.keys())
+    }
+}
+
+/// FNV-1a, unlike `DefaultHasher` this is stable across rust versions
+pub fn token_hash(token: &str) -> u64 {
+    token.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
 pub trait AppReplicateExt {
     fn replicate<C>(&mut self) -> &mut Self
     where
         C: Component + Typed + GetTypeRegistration + SerdeAdapter;
 
+    /// Like `replicate`, but updates are retransmitted until the peer acknowledges them
+    ///
+    /// For state that must not be lost, ie `Armed`
+    fn replicate_reliable<C>(&mut self) -> &mut Self
+    where
+        C: Component + Typed + GetTypeRegistration + SerdeAdapter;
+
+    /// Like `replicate`, but updates are sent before other updates from the same frame
+    ///
+    /// For time critical state that should not wait behind bulk telemetry, ie `TargetMovement`
+    fn replicate_priority<C>(&mut self) -> &mut Self
+    where
+        C: Component + Typed + GetTypeRegistration + SerdeAdapter;
+
     fn replicate_reflect<C>(&mut self) -> &mut Self
     where
         C: Component + Typed + GetTypeRegistration + FromReflect;
@@ -152,6 +240,33 @@ impl AppReplicateExt for App {
         replicate_inner::<C>(
             self,
             ComponentTypeAdapter::Serde(<ReflectSerdeAdapter as FromType<C>>::from_type()),
+            Delivery::Normal,
+        );
+
+        self
+    }
+
+    fn replicate_reliable<C>(&mut self) -> &mut Self
+    where
+        C: Component + Typed + GetTypeRegistration + SerdeAdapter,
+    {
+        replicate_inner::<C>(
+            self,
+            ComponentTypeAdapter::Serde(<ReflectSerdeAdapter as FromType<C>>::from_type()),
+            Delivery::Reliable,
+        );
+
+        self
+    }
+
+    fn replicate_priority<C>(&mut self) -> &mut Self
+    where
+        C: Component + Typed + GetTypeRegistration + SerdeAdapter,
+    {
+        replicate_inner::<C>(
+            self,
+            ComponentTypeAdapter::Serde(<ReflectSerdeAdapter as FromType<C>>::from_type()),
+            Delivery::Priority,
         );
 
         self
@@ -167,6 +282,7 @@ impl AppReplicateExt for App {
                 <ReflectFromPtr as FromType<C>>::from_type(),
                 <ReflectComponent as FromType<C>>::from_type(),
             ),
+            Delivery::Normal,
         );
 
         self
@@ -205,7 +321,7 @@ impl AppReplicateExt for App {
     }
 }
 
-fn replicate_inner<C>(app: &mut App, type_adapter: ComponentTypeAdapter)
+fn replicate_inner<C>(app: &mut App, type_adapter: ComponentTypeAdapter, delivery: Delivery)
 where
     C: Component + Typed + GetTypeRegistration,
 {
@@ -223,6 +339,7 @@ where
         remove_fn: |entity| {
             entity.remove::<C>();
         },
+        delivery,
     });
 
     let mut settings = app.world_mut().resource_mut::<SerializationSettings>();
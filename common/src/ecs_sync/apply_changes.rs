@@ -1,7 +1,8 @@
+use anyhow::anyhow;
 use bevy::{
     app::{App, Plugin, PreUpdate},
     ecs::{
-        event::EventReader,
+        event::{EventReader, EventWriter},
         reflect::AppTypeRegistry,
         schedule::{IntoSystemConfigs, SystemSet},
         system::{Commands, Res, ResMut, SystemChangeTick},
@@ -12,6 +13,7 @@ use tracing::error;
 
 use crate::{
     adapters::{dynamic::DynamicAdapter, ComponentTypeAdapter, EventTypeAdapter},
+    error::ErrorEvent,
     sync::Peers,
 };
 
@@ -39,6 +41,7 @@ fn apply_changes(
     mut entity_map: ResMut<EntityMap>,
     peers: Res<Peers>,
     mut reader: EventReader<SerializedChangeInEvent>,
+    mut errors: EventWriter<ErrorEvent>,
 ) {
     for SerializedChangeInEvent(change, token) in reader.read() {
         if !peers.valid_tokens.contains(token) {
@@ -48,6 +51,17 @@ fn apply_changes(
 
         match change {
             SerializedChange::EntitySpawned(forign) => {
+                // Replacing the mapping would orphan the existing entity and misroute its updates
+                if let Some(existing) = entity_map.forign_to_local.get(forign) {
+                    errors.send(
+                        anyhow!(
+                            "Peer {token:?} spawned {forign:?} which is already mapped to {existing:?}"
+                        )
+                        .into(),
+                    );
+                    continue;
+                }
+
                 let local = cmds.spawn((Replicate, *forign, ForignOwned(token.0))).id();
 
                 entity_map.local_to_forign.insert(local, *forign);
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use anyhow::Context;
 use bevy::app::{App, Plugin, PostUpdate};
 use bevy::ecs::event::{Event, EventReader};
 use bevy::ecs::reflect::AppTypeRegistry;
@@ -19,14 +20,16 @@ use bevy::ecs::{
     system::{Commands, Query, Res, ResMut, SystemChangeTick},
     world::{EntityRef, World},
 };
+use bevy::ptr::Ptr;
+use bevy::reflect::TypeRegistry;
 use bevy::utils::HashSet;
 
 use crate::adapters::dynamic::DynamicAdapter;
-use crate::adapters::{ComponentTypeAdapter, EventTypeAdapter};
+use crate::adapters::{AdapterError, BackingType, ComponentTypeAdapter, EventTypeAdapter};
 
 use super::{
-    EntityMap, ErasedManualEventReader, EventInfo, NetId, Replicate, SerializationSettings,
-    SerializedChange, SerializedChangeInEvent, SerializedChangeOutEvent,
+    ComponentInfo, EntityMap, ErasedManualEventReader, EventInfo, ForignOwned, NetId, Replicate,
+    SerializationSettings, SerializedChange, SerializedChangeInEvent, SerializedChangeOutEvent,
 };
 
 // TODO(mid): Events as RPC
@@ -170,16 +173,10 @@ fn detect_changes(
                 let changed = last_changed.is_newer_than(ticks.last_run(), ticks.this_run());
 
                 if changed || added {
-                    let serialized = match &sync_info.type_adapter {
-                        ComponentTypeAdapter::Serde(adapter) => unsafe { adapter.serialize(ptr) },
-                        ComponentTypeAdapter::Reflect(from_ptr, _) => {
-                            let reflect = unsafe { from_ptr.as_reflect(ptr) };
-                            let registry = registry.read();
-
-                            DynamicAdapter::serialize(reflect, &registry)
-                        }
-                    }
-                    .expect("serialize error");
+                    // SAFETY: The pointer came from `sync_info`'s component column
+                    let serialized =
+                        unsafe { serialize_component(sync_info, ptr, &registry.read()) }
+                            .expect("serialize error");
 
                     let remote_entity = entity_map
                         .local_to_forign
@@ -222,6 +219,80 @@ fn detect_changes(
     events.send_batch(changes);
 }
 
+/// Serializes the current state of every replicated entity this peer owns, used to bring new peers up to date
+///
+/// Entities that have not been through `ChangeDetectionSet` yet are skipped, their spawn will be sent as a normal change
+pub fn snapshot(world: &World) -> anyhow::Result<Vec<SerializedChange>> {
+    let settings = world.resource::<SerializationSettings>();
+    let entity_map = world.resource::<EntityMap>();
+    let registry = world.resource::<AppTypeRegistry>().read();
+
+    let mut spawns = Vec::new();
+    let mut components = Vec::new();
+
+    for archetype in world
+        .archetypes()
+        .iter()
+        .filter(|archetype| archetype.contains(settings.marker_id))
+    {
+        for entity in archetype.entities() {
+            let entity = world.entity(entity.id());
+
+            if entity.contains::<ForignOwned>() {
+                continue;
+            }
+            let Some(remote_entity) = entity_map.local_to_forign.get(&entity.id()) else {
+                continue;
+            };
+
+            spawns.push(SerializedChange::EntitySpawned(*remote_entity));
+
+            for (component_id, sync_info) in archetype
+                .components()
+                .filter_map(|it| Some(it).zip(settings.component_by_id.get(&it)))
+                .filter(|(_, sync_info)| !archetype.contains(sync_info.ignore_component))
+            {
+                let ptr = entity
+                    .get_by_id(component_id)
+                    .expect("Archetype has component");
+
+                // SAFETY: The pointer came from `sync_info`'s component id
+                let serialized = unsafe { serialize_component(sync_info, ptr, &registry) }
+                    .with_context(|| format!("Could not serialize {}", sync_info.type_name))?;
+
+                components.push(SerializedChange::ComponentUpdated(
+                    *remote_entity,
+                    sync_info.type_name.into(),
+                    Some(serialized),
+                ));
+            }
+        }
+    }
+
+    // Every entity must exist on the peer before its components can be applied
+    spawns.append(&mut components);
+
+    Ok(spawns)
+}
+
+/// # Safety
+///
+/// `ptr` must point to a value of the component described by `sync_info`
+unsafe fn serialize_component(
+    sync_info: &ComponentInfo,
+    ptr: Ptr<'_>,
+    registry: &TypeRegistry,
+) -> Result<BackingType, AdapterError> {
+    match &sync_info.type_adapter {
+        ComponentTypeAdapter::Serde(adapter) => unsafe { adapter.serialize(ptr) },
+        ComponentTypeAdapter::Reflect(from_ptr, _) => {
+            let reflect = unsafe { from_ptr.as_reflect(ptr) };
+
+            DynamicAdapter::serialize(reflect, registry)
+        }
+    }
+}
+
 // Detect when components are removed
 fn detect_removals(
     mut set: ParamSet<(
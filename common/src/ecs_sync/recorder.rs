@@ -0,0 +1,600 @@
+//! Records replicated changes to a file, and replays them into another app for debugging
+//!
+//! A recording is `MAGIC`, followed by a length prefixed `RecordingHeader`,
+//! followed by any number of length prefixed `(Duration, SerializedChange)` records.
+//! Lengths are little endian `u32`s and everything else is bincode.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use bevy::{
+    app::{App, Last, Plugin, PreUpdate, Startup},
+    ecs::{
+        event::{EventReader, EventWriter},
+        schedule::{common_conditions::resource_exists, IntoSystemConfigs},
+        system::{Commands, IntoSystem, Res, ResMut, Resource},
+        world::World,
+    },
+    time::{Real, Time},
+};
+use bincode::{DefaultOptions, Options};
+use networking::Token;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    error::{self, ErrorEvent},
+    protocol::PROTOCOL_VERSION,
+    sync::Peers,
+};
+
+use super::{
+    apply_changes::ChangeApplicationSet, EntityMap, SerializationSettings, SerializedChange,
+    SerializedChangeInEvent, SerializedChangeOutEvent,
+};
+
+/// Bump whenever the layout of a recording changes
+pub const RECORDING_VERSION: u32 = 1;
+
+const MAGIC: [u8; 8] = *b"ECSCHNGS";
+/// Refuse to read records claiming to be larger than this
+const MAX_RECORD_SIZE: u32 = 64 * 1024 * 1024;
+
+/// Replayed changes appear to come from this peer
+pub const PLAYBACK_TOKEN: Token = Token(usize::MAX);
+
+/// Identifies the builds a recording can be replayed into
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    pub version: u32,
+    pub protocol_version: u32,
+    /// See `SerializationSettings::registry_hash`
+    pub registry_hash: u64,
+}
+
+impl RecordingHeader {
+    pub fn new(settings: &SerializationSettings) -> Self {
+        Self {
+            version: RECORDING_VERSION,
+            protocol_version: PROTOCOL_VERSION,
+            registry_hash: settings.registry_hash(),
+        }
+    }
+
+    /// Fails if a recording with this header can not be replayed into the local build
+    pub fn check(&self, settings: &SerializationSettings) -> anyhow::Result<()> {
+        if self.version != RECORDING_VERSION {
+            bail!(
+                "Recording format mismatch, local: {RECORDING_VERSION}, recording: {}",
+                self.version
+            );
+        }
+
+        if self.protocol_version != PROTOCOL_VERSION {
+            bail!(
+                "Protocol version mismatch, local: {PROTOCOL_VERSION}, recording: {}",
+                self.protocol_version
+            );
+        }
+
+        if self.registry_hash != settings.registry_hash() {
+            bail!("Replicated types differ from the build that made the recording");
+        }
+
+        Ok(())
+    }
+}
+
+pub struct RecordingWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> RecordingWriter<W> {
+    pub fn new(mut writer: W, header: &RecordingHeader) -> anyhow::Result<Self> {
+        writer.write_all(&MAGIC).context("Write magic")?;
+        write_record(&mut writer, header).context("Write header")?;
+
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, timestamp: Duration, change: &SerializedChange) -> anyhow::Result<()> {
+        write_record(&mut self.writer, &(timestamp, change)).context("Write change")
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush().context("Flush recording")
+    }
+}
+
+pub struct RecordingReader<R: Read> {
+    reader: R,
+    header: RecordingHeader,
+}
+
+impl<R: Read> RecordingReader<R> {
+    /// Reads the header, failing if the recording is not compatible with `settings`
+    pub fn new(mut reader: R, settings: &SerializationSettings) -> anyhow::Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic).context("Read magic")?;
+        if magic != MAGIC {
+            bail!("Not a change recording");
+        }
+
+        let header: RecordingHeader = read_record(&mut reader)
+            .context("Read header")?
+            .ok_or_else(|| anyhow!("Recording has no header"))?;
+        header.check(settings).context("Incompatible recording")?;
+
+        Ok(Self { reader, header })
+    }
+
+    pub fn header(&self) -> &RecordingHeader {
+        &self.header
+    }
+
+    /// Returns `None` once every record has been read
+    pub fn read(&mut self) -> anyhow::Result<Option<(Duration, SerializedChange)>> {
+        read_record(&mut self.reader).context("Read change")
+    }
+}
+
+fn write_record(writer: &mut impl Write, record: &impl Serialize) -> anyhow::Result<()> {
+    let data = options().serialize(record).context("Serialize record")?;
+    let len = u32::try_from(data.len()).context("Record too large")?;
+
+    writer
+        .write_all(&len.to_le_bytes())
+        .and_then(|()| writer.write_all(&data))
+        .context("Write record")
+}
+
+fn read_record<T: for<'de> Deserialize<'de>>(reader: &mut impl Read) -> anyhow::Result<Option<T>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        // A clean end of the recording
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(anyhow!(err).context("Read record length")),
+    }
+
+    let len = u32::from_le_bytes(len);
+    if len > MAX_RECORD_SIZE {
+        bail!("Record claims to be {len} bytes");
+    }
+
+    let mut data = vec![0; len as usize];
+    reader.read_exact(&mut data).context("Truncated record")?;
+
+    options()
+        .deserialize(&data)
+        .map(Some)
+        .context("Deserialize record")
+}
+
+fn options() -> impl Options {
+    DefaultOptions::new()
+}
+
+/// Records every change sent or received while a recording is in progress, see `ChangeRecorder`
+pub struct ChangeRecorderPlugin;
+
+impl Plugin for ChangeRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChangeRecorder>()
+            .add_systems(Last, record_changes);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ChangeRecorder {
+    recording: Option<Recording>,
+}
+
+struct Recording {
+    path: PathBuf,
+    writer: RecordingWriter<BufWriter<File>>,
+    // Time the first changes were recorded, records are timestamped relative to this
+    started: Option<Duration>,
+}
+
+impl ChangeRecorder {
+    /// Starts recording to `path`, replacing any existing file and stopping any recording in progress
+    pub fn start(
+        &mut self,
+        path: impl AsRef<Path>,
+        settings: &SerializationSettings,
+    ) -> anyhow::Result<()> {
+        self.stop()?;
+
+        let path = path.as_ref().to_owned();
+        let file = File::create(&path).with_context(|| format!("Create {}", path.display()))?;
+        let writer = RecordingWriter::new(BufWriter::new(file), &RecordingHeader::new(settings))?;
+
+        info!("Recording changes to {}", path.display());
+
+        self.recording = Some(Recording {
+            path,
+            writer,
+            started: None,
+        });
+
+        Ok(())
+    }
+
+    /// Flushes and closes the recording in progress, if any
+    pub fn stop(&mut self) -> anyhow::Result<()> {
+        if let Some(mut recording) = self.recording.take() {
+            info!("Stopped recording changes to {}", recording.path.display());
+            recording.writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.recording.as_ref().map(|it| it.path.as_path())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+}
+
+fn record_changes(
+    mut recorder: ResMut<ChangeRecorder>,
+    time: Res<Time<Real>>,
+    mut changes_in: EventReader<SerializedChangeInEvent>,
+    mut changes_out: EventReader<SerializedChangeOutEvent>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let Some(recording) = &mut recorder.recording else {
+        changes_in.clear();
+        changes_out.clear();
+        return;
+    };
+
+    let now = time.elapsed();
+    let timestamp = now - *recording.started.get_or_insert(now);
+
+    // Received changes were applied in `PreUpdate`, before the changes detected this frame
+    let changes = changes_in
+        .read()
+        .map(|SerializedChangeInEvent(change, _)| change)
+        .chain(
+            changes_out
+                .read()
+                .map(|SerializedChangeOutEvent(change)| change),
+        );
+
+    let rst = changes.try_for_each(|change| recording.writer.write(timestamp, change));
+
+    if let Err(err) = rst {
+        errors.send(err.context("Record changes, stopping recording").into());
+        recorder.recording = None;
+    }
+}
+
+/// Replays a recording made by `ChangeRecorder` as `SerializedChangeInEvent`s, at the recorded pace
+///
+/// The app must also apply changes, ie with `ChangeApplicationPlugin`,
+/// and replicate the same types as the app that made the recording
+pub struct ChangePlayer {
+    pub path: PathBuf,
+    /// How many times faster than recorded to replay
+    pub speed: f32,
+}
+
+impl Plugin for ChangePlayer {
+    fn build(&self, app: &mut App) {
+        let path = self.path.clone();
+        let speed = self.speed;
+
+        app.add_event::<SerializedChangeInEvent>()
+            .add_event::<ErrorEvent>()
+            .init_resource::<SerializationSettings>()
+            .init_resource::<EntityMap>()
+            .init_resource::<Peers>()
+            .add_systems(
+                Startup,
+                (move |cmds: Commands, settings: Res<SerializationSettings>| {
+                    start_playback(cmds, settings, &path, speed)
+                })
+                .pipe(error::handle_errors),
+            )
+            .add_systems(
+                PreUpdate,
+                play_changes
+                    .before(ChangeApplicationSet)
+                    .run_if(resource_exists::<Playback>),
+            );
+    }
+}
+
+#[derive(Resource)]
+struct Playback {
+    reader: RecordingReader<BufReader<File>>,
+    speed: f32,
+    // Time the first changes were replayed
+    started: Option<Duration>,
+    // Read from the recording but not yet due
+    next: Option<(Duration, SerializedChange)>,
+}
+
+fn start_playback(
+    mut cmds: Commands,
+    settings: Res<SerializationSettings>,
+    path: &Path,
+    speed: f32,
+) -> anyhow::Result<()> {
+    if speed.is_nan() || speed <= 0.0 {
+        bail!("Playback speed must be positive, got: {speed}");
+    }
+
+    let file = File::open(path).with_context(|| format!("Open {}", path.display()))?;
+    let reader = RecordingReader::new(BufReader::new(file), &settings)
+        .with_context(|| format!("Read {}", path.display()))?;
+
+    info!("Replaying changes from {}", path.display());
+
+    // Replayed changes are dropped by `apply_changes` unless they come from a valid peer
+    cmds.add(|world: &mut World| {
+        world
+            .resource_mut::<Peers>()
+            .valid_tokens
+            .insert(PLAYBACK_TOKEN);
+    });
+
+    cmds.insert_resource(Playback {
+        reader,
+        speed,
+        started: None,
+        next: None,
+    });
+
+    Ok(())
+}
+
+fn play_changes(
+    mut cmds: Commands,
+    mut playback: ResMut<Playback>,
+    time: Res<Time<Real>>,
+    mut changes: EventWriter<SerializedChangeInEvent>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let now = time.elapsed();
+    let started = *playback.started.get_or_insert(now);
+    let position = (now - started).mul_f32(playback.speed);
+
+    loop {
+        let next = match playback.next.take() {
+            Some(next) => next,
+            None => match playback.reader.read() {
+                Ok(Some(next)) => next,
+                Ok(None) => {
+                    info!("Replay finished");
+                    cmds.remove_resource::<Playback>();
+                    return;
+                }
+                Err(err) => {
+                    errors.send(err.context("Replay changes, stopping replay").into());
+                    cmds.remove_resource::<Playback>();
+                    return;
+                }
+            },
+        };
+
+        let (timestamp, change) = next;
+        if timestamp > position {
+            playback.next = Some((timestamp, change));
+            return;
+        }
+
+        changes.send(SerializedChangeInEvent(change, PLAYBACK_TOKEN));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    use bevy::{
+        ecs::event::Events,
+        prelude::*,
+        time::{TimePlugin, TimeUpdateStrategy},
+    };
+
+    use crate::{
+        components::{Armed, Leak},
+        ecs_sync::{
+            apply_changes::ChangeApplicationPlugin, detect_changes::ChangeDetectionPlugin,
+            AppReplicateExt, EntityMap, NetId, Replicate, SerializationSettings,
+            SerializedChangeInEvent, SerializedChangeOutEvent,
+        },
+        error::ErrorEvent,
+    };
+
+    use super::{ChangePlayer, ChangeRecorder, ChangeRecorderPlugin};
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    fn leak(detected: bool) -> Leak {
+        Leak {
+            detected,
+            confidence: if detected { 1.0 } else { 0.0 },
+        }
+    }
+
+    fn recording_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("changes-{}-{name}.rec", std::process::id()))
+    }
+
+    /// Records an entity being spawned, then changed 200ms and 300ms later
+    fn record(path: &Path) -> NetId {
+        let mut app = App::new();
+        app.add_plugins(TimePlugin)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(FRAME))
+            .add_event::<ErrorEvent>()
+            .add_event::<SerializedChangeInEvent>()
+            .add_event::<SerializedChangeOutEvent>()
+            .init_resource::<SerializationSettings>()
+            .init_resource::<EntityMap>()
+            .add_plugins((ChangeDetectionPlugin, ChangeRecorderPlugin))
+            .replicate::<Leak>()
+            .replicate::<Armed>();
+
+        app.world_mut()
+            .resource_scope(|world, mut recorder: Mut<ChangeRecorder>| {
+                recorder.start(path, world.resource::<SerializationSettings>())
+            })
+            .expect("Start recording");
+
+        let entity = app
+            .world_mut()
+            .spawn((Replicate, leak(false), Armed::Disarmed))
+            .id();
+        app.update();
+        app.update();
+
+        app.world_mut().entity_mut(entity).insert(leak(true));
+        app.update();
+
+        app.world_mut().entity_mut(entity).insert(Armed::Armed);
+        app.update();
+
+        app.world_mut()
+            .resource_mut::<ChangeRecorder>()
+            .stop()
+            .expect("Stop recording");
+
+        *app.world().get::<NetId>(entity).unwrap()
+    }
+
+    fn replay_app(path: &Path, speed: f32) -> App {
+        let mut app = App::new();
+        app.add_plugins(TimePlugin)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(FRAME))
+            .add_plugins((
+                ChangePlayer {
+                    path: path.to_path_buf(),
+                    speed,
+                },
+                ChangeApplicationPlugin,
+            ))
+            .replicate::<Leak>()
+            .replicate::<Armed>();
+
+        app
+    }
+
+    fn replayed(app: &mut App, net_id: NetId) -> Option<(Option<Leak>, Option<Armed>)> {
+        app.world_mut()
+            .query::<(&NetId, Option<&Leak>, Option<&Armed>)>()
+            .iter(app.world())
+            .find(|(it, _, _)| **it == net_id)
+            .map(|(_, leak, armed)| (leak.copied(), armed.copied()))
+    }
+
+    fn error_messages(app: &App) -> Vec<String> {
+        let events = app.world().resource::<Events<ErrorEvent>>();
+
+        events
+            .get_reader()
+            .read(events)
+            .map(|ErrorEvent(err)| format!("{err:#}"))
+            .collect()
+    }
+
+    #[test]
+    fn replay_matches_recording() {
+        let path = recording_path("replay");
+        let net_id = record(&path);
+
+        let mut app = replay_app(&path, 1.0);
+
+        app.update();
+        assert_eq!(
+            replayed(&mut app, net_id),
+            Some((Some(leak(false)), Some(Armed::Disarmed)))
+        );
+
+        app.update();
+        app.update();
+        assert_eq!(
+            replayed(&mut app, net_id),
+            Some((Some(leak(true)), Some(Armed::Disarmed)))
+        );
+
+        app.update();
+        assert_eq!(
+            replayed(&mut app, net_id),
+            Some((Some(leak(true)), Some(Armed::Armed)))
+        );
+        assert!(error_messages(&app).is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replay_speed_multiplier() {
+        let path = recording_path("speed");
+        let net_id = record(&path);
+
+        let mut app = replay_app(&path, 2.0);
+
+        app.update();
+        app.update();
+        assert_eq!(
+            replayed(&mut app, net_id),
+            Some((Some(leak(true)), Some(Armed::Disarmed)))
+        );
+
+        app.update();
+        assert_eq!(
+            replayed(&mut app, net_id),
+            Some((Some(leak(true)), Some(Armed::Armed)))
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn incompatible_recording_is_rejected() {
+        let path = recording_path("incompatible");
+        let net_id = record(&path);
+
+        // Missing `Armed`, as if recorded by a different build
+        let mut app = App::new();
+        app.add_plugins(TimePlugin)
+            .add_plugins((
+                ChangePlayer {
+                    path: path.clone(),
+                    speed: 1.0,
+                },
+                ChangeApplicationPlugin,
+            ))
+            .replicate::<Leak>();
+
+        app.update();
+        app.update();
+
+        assert_eq!(replayed(&mut app, net_id), None);
+        let errors = error_messages(&app);
+        assert!(
+            errors
+                .iter()
+                .any(|it| it.contains("Replicated types differ")),
+            "{errors:?}"
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,16 +1,59 @@
 //! Repersents the protocol used for two way communication
 
-use anyhow::Context;
+use std::io::Write;
+
+use anyhow::{bail, Context};
 use bincode::{DefaultOptions, Options};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
 use crate::ecs_sync::SerializedChange;
 
+/// Bump whenever a replicated type outside of `components.rs` changes in an incompatible way,
+/// ie the types in `types` or `motor_math`
+///
+/// Changes to this file or `components.rs` already change `PROTOCOL_VERSION`
+pub const PROTOCOL_REVISION: u32 = 9;
+
+/// Hash of `components.rs` and this file, computed by the build script
+pub const PROTOCOL_VERSION: u32 = include!(concat!(env!("OUT_DIR"), "/protocol_version.rs"));
+
+/// Packets that serialize to more than this many bytes are compressed
+const COMPRESSION_THRESHOLD: u64 = 512;
+/// Refuse to decompress packets claiming to be larger than this
+const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+// Every packet starts with one of these to say how the rest is encoded.
+// Packets from before compression start with a small enum tag, so they are rejected instead of misread
+const ENCODING_RAW: u8 = 0xA0;
+const ENCODING_LZ4: u8 = 0xA1;
+
+/// Soft limit on the serialized size of an `EcsUpdateBatch`, see `batch_changes`
+pub const MAX_BATCH_SIZE: u64 = 16 * 1024;
+// Variant tag plus the largest possible varint length prefix
+const BATCH_OVERHEAD: u64 = 1 + 9;
+
 /// Representation of all messages that can be communicated between peers
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Protocol {
+    /// Sent by both peers on connect, `EcsUpdate`s are ignored until a matching `Hello` is received
+    Hello {
+        version: u32,
+        /// Hash of every replicated component and event token, see `SerializationSettings::registry_hash`
+        capabilities: u64,
+    },
     EcsUpdate(SerializedChange),
+    /// Several `EcsUpdate`s sent as one packet, applied in order
+    EcsUpdateBatch(Vec<SerializedChange>),
+    /// An `EcsUpdate` that is retransmitted until the peer responds with an `Ack` with the same `seq`
+    ReliableUpdate {
+        seq: u64,
+        change: SerializedChange,
+    },
+    /// Acknowledges a `ReliableUpdate`
+    Ack {
+        seq: u64,
+    },
     /// Asks the peer to reply with a Pong, used to measure communication latency
     Ping {
         payload: u32,
@@ -22,28 +65,300 @@ pub enum Protocol {
 }
 
 impl networking::Packet for Protocol {
+    /// Compression is only kept when it makes the packet smaller,
+    /// so the worst case is the uncompressed packet plus the encoding byte
     #[instrument(level = "trace", ret)]
     fn expected_size(&self) -> anyhow::Result<u64> {
-        options()
+        let size = options()
             .serialized_size(self)
-            .context("Could not compute expected size")
+            .context("Could not compute expected size")?;
+
+        Ok(1 + size)
     }
 
     #[instrument(level = "trace", skip(buffer))]
     fn write_buf(&self, buffer: &mut &mut [u8]) -> anyhow::Result<()> {
-        options()
-            .serialize_into(buffer, self)
-            .context("Could not serialize packet")
+        let size = options()
+            .serialized_size(self)
+            .context("Could not compute packet size")?;
+
+        if size <= COMPRESSION_THRESHOLD {
+            buffer
+                .write_all(&[ENCODING_RAW])
+                .context("Could not write packet encoding")?;
+
+            return options()
+                .serialize_into(buffer, self)
+                .context("Could not serialize packet");
+        }
+
+        let raw = options()
+            .serialize(self)
+            .context("Could not serialize packet")?;
+        let compressed = lz4_flex::compress_prepend_size(&raw);
+
+        // Incompressible data would only grow, send it as is
+        let (encoding, data) = if compressed.len() < raw.len() {
+            (ENCODING_LZ4, &compressed)
+        } else {
+            (ENCODING_RAW, &raw)
+        };
+
+        buffer
+            .write_all(&[encoding])
+            .and_then(|()| buffer.write_all(data))
+            .context("Could not write packet")
     }
 
     #[instrument(level = "trace", skip(buffer), ret)]
     fn read_buf(buffer: &mut &[u8]) -> anyhow::Result<Self> {
-        options()
-            .deserialize_from(buffer)
-            .context("Could not deserialize packet")
+        let Some((&encoding, data)) = buffer.split_first() else {
+            bail!("Empty packet");
+        };
+        *buffer = data;
+
+        match encoding {
+            ENCODING_RAW => options()
+                .deserialize_from(buffer)
+                .context("Could not deserialize packet"),
+            ENCODING_LZ4 => {
+                let Some(size) = buffer.first_chunk().copied().map(u32::from_le_bytes) else {
+                    bail!("Truncated compressed packet");
+                };
+                if size as usize > MAX_DECOMPRESSED_SIZE {
+                    bail!("Compressed packet claims to be {size} bytes");
+                }
+
+                let raw = lz4_flex::decompress_size_prepended(buffer)
+                    .context("Could not decompress packet")?;
+                *buffer = &[];
+
+                options()
+                    .deserialize(&raw)
+                    .context("Could not deserialize packet")
+            }
+            other => {
+                bail!("Unknown packet encoding {other:#x}, the peer may be running an incompatible version")
+            }
+        }
+    }
+}
+
+/// Groups `changes` into `EcsUpdateBatch` packets no larger than `max_size` bytes, preserving their order
+///
+/// A single change larger than `max_size` is sent in a batch of its own
+#[instrument(level = "trace", skip(changes))]
+pub fn batch_changes(
+    changes: impl IntoIterator<Item = SerializedChange>,
+    max_size: u64,
+) -> anyhow::Result<Vec<Protocol>> {
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_size = BATCH_OVERHEAD;
+
+    for change in changes {
+        let size = change_size(&change)?;
+
+        if !batch.is_empty() && batch_size + size > max_size {
+            batches.push(Protocol::EcsUpdateBatch(std::mem::take(&mut batch)));
+            batch_size = BATCH_OVERHEAD;
+        }
+
+        batch.push(change);
+        batch_size += size;
     }
+
+    if !batch.is_empty() {
+        batches.push(Protocol::EcsUpdateBatch(batch));
+    }
+
+    Ok(batches)
+}
+
+/// Serialized size of a single change within an `EcsUpdateBatch`
+pub fn change_size(change: &SerializedChange) -> anyhow::Result<u64> {
+    options()
+        .serialized_size(change)
+        .context("Could not compute change size")
 }
 
 fn options() -> impl Options {
     DefaultOptions::new()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use networking::Packet;
+
+    use crate::ecs_sync::{NetId, SerializedChange};
+
+    use super::*;
+
+    fn encode(packet: &Protocol) -> Vec<u8> {
+        let expected_size = packet.expected_size().expect("Expected size") as usize;
+
+        let mut data = vec![0; expected_size];
+        let mut buffer = &mut data[..];
+        packet.write_buf(&mut buffer).expect("Write packet");
+
+        let written = expected_size - buffer.len();
+        data.truncate(written);
+
+        data
+    }
+
+    fn decode(data: &[u8]) -> anyhow::Result<Protocol> {
+        let mut buffer = data;
+        let packet = Protocol::read_buf(&mut buffer)?;
+        assert!(buffer.is_empty());
+
+        Ok(packet)
+    }
+
+    fn component_update(payload: Vec<u8>) -> Protocol {
+        Protocol::EcsUpdate(SerializedChange::ComponentUpdated(
+            NetId::random(),
+            "common::components::Processes".into(),
+            Some(Arc::new(payload)),
+        ))
+    }
+
+    fn assert_roundtrip(packet: &Protocol, data: &[u8]) {
+        let decoded = decode(data).expect("Read packet");
+
+        match (packet, &decoded) {
+            (Protocol::EcsUpdate(a), Protocol::EcsUpdate(b)) => assert_eq!(a, b),
+            (Protocol::EcsUpdateBatch(a), Protocol::EcsUpdateBatch(b)) => assert_eq!(a, b),
+            (Protocol::Ping { payload: a }, Protocol::Ping { payload: b }) => assert_eq!(a, b),
+            _ => panic!("{packet:?} != {decoded:?}"),
+        }
+    }
+
+    #[test]
+    fn small_packets_are_not_compressed() {
+        let packet = Protocol::Ping { payload: 1234 };
+        let data = encode(&packet);
+
+        assert_eq!(data[0], ENCODING_RAW);
+        assert_roundtrip(&packet, &data);
+    }
+
+    #[test]
+    fn large_packets_are_compressed() {
+        let packet = component_update(b"cpu: 12.5%, ".repeat(200));
+        let data = encode(&packet);
+
+        assert_eq!(data[0], ENCODING_LZ4);
+        assert!((data.len() as u64) < packet.expected_size().unwrap() / 4);
+        assert_roundtrip(&packet, &data);
+    }
+
+    #[test]
+    fn compressed_snapshots_roundtrip() {
+        // Roughly what `sync_new_peers` sends for a robot's system information
+        let changes: Vec<_> = (0..32)
+            .flat_map(|idx| {
+                let entity = NetId::random();
+
+                [
+                    SerializedChange::EntitySpawned(entity),
+                    SerializedChange::ComponentUpdated(
+                        entity,
+                        "common::components::Processes".into(),
+                        Some(Arc::new(
+                            format!("process {idx}: sleeping, ").repeat(20).into(),
+                        )),
+                    ),
+                ]
+            })
+            .collect();
+
+        let batches = batch_changes(changes.clone(), MAX_BATCH_SIZE).expect("Batch changes");
+        assert_eq!(batches.len(), 1);
+
+        let data = encode(&batches[0]);
+        assert_eq!(data[0], ENCODING_LZ4);
+
+        let Protocol::EcsUpdateBatch(decoded) = decode(&data).expect("Read packet") else {
+            panic!("Expected a batch");
+        };
+        assert_eq!(decoded, changes);
+    }
+
+    #[test]
+    fn incompressible_packets_do_not_grow() {
+        let packet = component_update((0..4096).map(|_| rand::random()).collect());
+        let data = encode(&packet);
+
+        assert_eq!(data[0], ENCODING_RAW);
+        assert_eq!(data.len() as u64, packet.expected_size().unwrap());
+        assert_roundtrip(&packet, &data);
+    }
+
+    #[test]
+    fn unknown_encodings_are_rejected() {
+        // A ping from before packets carried an encoding byte
+        let old = options().serialize(&Protocol::Ping { payload: 1 }).unwrap();
+
+        let err = decode(&old).expect_err("Old packet should not parse");
+        assert!(format!("{err:#}").contains("Unknown packet encoding"));
+
+        assert!(decode(&[]).is_err());
+        assert!(decode(&[ENCODING_LZ4, 0xFF, 0xFF, 0xFF, 0xFF]).is_err());
+    }
+
+    #[test]
+    fn oversized_batches_are_split_in_order() {
+        let changes: Vec<_> = (0..200u8)
+            .map(|idx| {
+                SerializedChange::ComponentUpdated(
+                    NetId::random(),
+                    "common::components::Processes".into(),
+                    Some(Arc::new(vec![idx; 100])),
+                )
+            })
+            .collect();
+
+        let batches = batch_changes(changes.clone(), 1024).expect("Batch changes");
+        assert!(batches.len() > 1);
+
+        let mut received = Vec::new();
+        for batch in &batches {
+            let size = options().serialized_size(batch).unwrap();
+            assert!(size <= 1024, "Batch was {size} bytes");
+
+            let data = encode(batch);
+            assert_roundtrip(batch, &data);
+
+            let Protocol::EcsUpdateBatch(batch) = batch else {
+                panic!("Expected a batch, got {batch:?}");
+            };
+            received.extend(batch.iter().cloned());
+        }
+
+        assert_eq!(received, changes);
+    }
+
+    #[test]
+    fn large_changes_get_their_own_batch() {
+        let small = SerializedChange::EntitySpawned(NetId::random());
+        let large =
+            SerializedChange::EventEmitted("common::events::Log".into(), Arc::new(vec![0; 4096]));
+
+        let batches = batch_changes([small.clone(), large.clone(), small.clone()], 1024)
+            .expect("Batch changes");
+
+        let batches: Vec<_> = batches
+            .into_iter()
+            .map(|batch| match batch {
+                Protocol::EcsUpdateBatch(batch) => batch,
+                other => panic!("Expected a batch, got {other:?}"),
+            })
+            .collect();
+        assert_eq!(batches, vec![vec![small.clone()], vec![large], vec![small]]);
+
+        assert!(batch_changes([], 1024).unwrap().is_empty());
+    }
+}
@@ -1,21 +1,27 @@
 use std::{
+    collections::VecDeque,
     net::{Ipv4Addr, SocketAddr, ToSocketAddrs},
     thread,
+    time::Duration,
 };
 
 use crate::{
-    adapters,
-    components::Singleton,
+    components::{
+        Armed, DepthTarget, DroppedUpdates, EmergencyStop, Inertial, Magnetic, Motors,
+        MovementContribution, OrientationTarget, PeerLatency, RobotStatus, Singleton,
+    },
     ecs_sync::{
-        apply_changes::ChangeApplicationSet, detect_changes::ChangeDetectionSet, EntityMap,
-        ForignOwned, NetId, NetTypeId, SerializationSettings, SerializedChange,
+        apply_changes::ChangeApplicationSet,
+        detect_changes::{self, ChangeDetectionSet},
+        EntityMap, ForignOwned, NetId, NetTypeId, SerializationSettings, SerializedChange,
         SerializedChangeInEvent, SerializedChangeOutEvent,
     },
-    protocol::Protocol,
+    protocol::{self, Protocol, MAX_BATCH_SIZE, PROTOCOL_VERSION},
+    types::error::Severity,
     InstanceName,
 };
 use ahash::{HashMap, HashSet};
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
 use bevy::{app::AppExit, core::FrameCount, prelude::*};
 use crossbeam::channel::{self, Receiver};
 use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
@@ -39,8 +45,11 @@ impl Plugin for SyncPlugin {
             .add_event::<SerializedChangeOutEvent>()
             .init_resource::<SerializationSettings>()
             .init_resource::<EntityMap>()
-            .init_resource::<Deltas>()
             .init_resource::<Peers>()
+            .init_resource::<BandwidthConfig>()
+            .init_resource::<BandwidthStats>()
+            .init_resource::<ThrottleConfig>()
+            .init_resource::<InitialSyncOrder>()
             .insert_resource(self.0)
             .add_event::<ConnectToPeer>()
             .add_event::<DisconnectPeer>()
@@ -51,9 +60,11 @@ impl Plugin for SyncPlugin {
                 Update,
                 (
                     ping,
-                    flatten_deltas,
-                    sync_new_peers.after(flatten_deltas),
+                    retransmit_reliable,
+                    handshake_timeout,
+                    sync_new_peers,
                     spawn_peer_entities,
+                    publish_bandwidth_stats,
                     disconnect.pipe(error::handle_errors),
                 ),
             )
@@ -61,10 +72,11 @@ impl Plugin for SyncPlugin {
             .add_systems(Last, shutdown);
 
         if let SyncRole::Client = self.0 {
-            app.add_systems(
+            app.init_resource::<Reconnect>().add_systems(
                 Update,
                 (
                     connect.pipe(error::handle_errors),
+                    reconnect.pipe(error::handle_errors).after(connect),
                     discover_peers.run_if(resource_exists::<MdnsBrowse>),
                 ),
             );
@@ -80,13 +92,47 @@ pub struct Peers {
     by_token: HashMap<NetToken, Entity>,
     by_addrs: HashMap<SocketAddr, Entity>,
 
-    // In frames
-    pending: HashMap<NetToken, (SocketAddr, u32)>,
+    // In frames, since the peer completed the handshake
+    pending: HashMap<NetToken, (SocketAddr, Option<u32>)>,
+    // In frames, peers that have connected but not yet sent a valid handshake
+    awaiting_handshake: HashMap<NetToken, u32>,
+    // Peers we already cleaned up and asked the net thread to disconnect
+    dropped: HashSet<NetToken>,
+    // Reliable updates waiting on an ack, and the newest reliable updates received, per peer
+    reliable: HashMap<NetToken, ReliableChannel>,
 
     // TODO: This is kinda bad
+    /// Peers that have completed the handshake
     pub(crate) valid_tokens: HashSet<NetToken>,
 }
 
+impl Peers {
+    /// Indexes a peer's entity, refusing to replace a different entity already indexed under
+    /// the same token or address since that means two peers were mixed up
+    fn register(
+        &mut self,
+        token: NetToken,
+        addrs: SocketAddr,
+        entity: Entity,
+    ) -> anyhow::Result<()> {
+        if let Some(&existing) = self.by_token.get(&token) {
+            if existing != entity {
+                bail!("Peer token {token:?} is already used by {existing:?}, not replacing it with {entity:?}");
+            }
+        }
+        if let Some(&existing) = self.by_addrs.get(&addrs) {
+            if existing != entity {
+                bail!("Peer address {addrs} is already used by {existing:?}, not replacing it with {entity:?}");
+            }
+        }
+
+        self.by_token.insert(token, entity);
+        self.by_addrs.insert(addrs, entity);
+
+        Ok(())
+    }
+}
+
 #[derive(Component, Debug)]
 pub struct Peer {
     pub addrs: SocketAddr,
@@ -97,8 +143,474 @@ pub struct Peer {
 pub struct Latency {
     // In frames
     pub last_ping_sent: Option<u32>,
-    pub last_acknowledged: Option<u32>,
-    pub ping: Option<u32>,
+    #[reflect(ignore)]
+    pub window: PingWindow,
+}
+
+const PING_WINDOW: usize = 32;
+
+/// Rolling statistics over the last `PING_WINDOW` pings sent to a peer
+///
+/// Times are measured from an arbitrary fixed point, ie `Time<Real>::elapsed`
+#[derive(Debug, Clone, Default)]
+pub struct PingWindow {
+    // Payload, time sent, round trip time once a pong arrived
+    pings: VecDeque<(u32, Duration, Option<Duration>)>,
+    last_pong: Option<Duration>,
+}
+
+impl PingWindow {
+    pub fn ping_sent(&mut self, payload: u32, now: Duration) {
+        if self.pings.len() == PING_WINDOW {
+            self.pings.pop_front();
+        }
+
+        self.pings.push_back((payload, now, None));
+    }
+
+    /// Records the response to a ping, returns the round trip time
+    ///
+    /// Returns `None` if the ping is not in the window or was already answered
+    pub fn pong_received(&mut self, payload: u32, now: Duration) -> Option<Duration> {
+        let (_, sent, rtt) = self
+            .pings
+            .iter_mut()
+            .find(|(ping, _, rtt)| *ping == payload && rtt.is_none())?;
+
+        let sample = now.saturating_sub(*sent);
+        *rtt = Some(sample);
+        self.last_pong = Some(now);
+
+        Some(sample)
+    }
+
+    fn samples(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.pings.iter().filter_map(|(_, _, rtt)| *rtt)
+    }
+
+    /// Mean round trip time of the answered pings in the window
+    pub fn rtt(&self) -> Option<Duration> {
+        let count = self.samples().count();
+        if count == 0 {
+            return None;
+        }
+
+        Some(self.samples().sum::<Duration>() / count as u32)
+    }
+
+    /// Fastest and slowest round trip times of the answered pings in the window
+    pub fn rtt_range(&self) -> Option<(Duration, Duration)> {
+        let min = self.samples().min()?;
+        let max = self.samples().max()?;
+
+        Some((min, max))
+    }
+
+    /// Mean absolute difference between consecutive round trip times
+    pub fn jitter(&self) -> Duration {
+        let samples: Vec<_> = self.samples().collect();
+        if samples.len() < 2 {
+            return Duration::ZERO;
+        }
+
+        let total: Duration = samples
+            .windows(2)
+            .map(|pair| pair[0].abs_diff(pair[1]))
+            .sum();
+
+        total / (samples.len() - 1) as u32
+    }
+
+    /// Fraction of pings that went unanswered for longer than `timeout`
+    ///
+    /// Pings sent less than `timeout` ago are still in flight and are not counted
+    pub fn packet_loss(&self, now: Duration, timeout: Duration) -> f32 {
+        let settled = self
+            .pings
+            .iter()
+            .filter(|(_, sent, rtt)| rtt.is_some() || now.saturating_sub(*sent) > timeout);
+
+        let (total, lost) = settled.fold((0u32, 0u32), |(total, lost), (_, _, rtt)| {
+            (total + 1, lost + rtt.is_none() as u32)
+        });
+
+        if total == 0 {
+            return 0.0;
+        }
+
+        lost as f32 / total as f32
+    }
+
+    /// How long the peer has gone without answering a ping
+    pub fn unanswered_for(&self, now: Duration) -> Duration {
+        let since = self
+            .last_pong
+            .or_else(|| self.pings.front().map(|(_, sent, _)| *sent))
+            .unwrap_or(now);
+
+        now.saturating_sub(since)
+    }
+
+    pub fn stats(&self, now: Duration, timeout: Duration) -> PeerLatency {
+        let (min_rtt, max_rtt) = self.rtt_range().unwrap_or_default();
+
+        PeerLatency {
+            rtt: self.rtt().unwrap_or_default(),
+            min_rtt,
+            max_rtt,
+            jitter: self.jitter(),
+            packet_loss: self.packet_loss(now, timeout),
+        }
+    }
+}
+
+/// Limits the replication traffic sent to each peer
+///
+/// Once the budget is used up, updates to components not in `high_priority_tokens` are dropped.
+/// Spawns, despawns, removals, events and reliable updates are always sent
+#[derive(Resource, Debug, Clone)]
+pub struct BandwidthConfig {
+    pub max_bytes_per_sec: u32,
+    pub high_priority_tokens: Vec<NetTypeId>,
+}
+
+impl Default for BandwidthConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes_per_sec: u32::MAX,
+            high_priority_tokens: vec![
+                Armed::type_path().into(),
+                EmergencyStop::type_path().into(),
+                DepthTarget::type_path().into(),
+                OrientationTarget::type_path().into(),
+                MovementContribution::type_path().into(),
+            ],
+        }
+    }
+}
+
+/// Totals since startup, the dropped count is replicated as `DroppedUpdates`
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct BandwidthStats {
+    pub sent_bytes: u64,
+    pub dropped_updates: u64,
+}
+
+/// Token bucket holding up to one second of traffic
+#[derive(Debug, Default)]
+struct BandwidthBudget {
+    available: f64,
+    last_refill: Option<Duration>,
+}
+
+impl BandwidthBudget {
+    fn refill(&mut self, config: &BandwidthConfig, now: Duration) {
+        let rate = config.max_bytes_per_sec as f64;
+
+        self.available = match self.last_refill {
+            Some(last) => {
+                (self.available + now.saturating_sub(last).as_secs_f64() * rate).min(rate)
+            }
+            None => rate,
+        };
+        self.last_refill = Some(now);
+    }
+
+    /// Drops low priority component updates that do not fit in the budget, preserving order
+    fn apply(
+        &mut self,
+        changes: Vec<SerializedChange>,
+        config: &BandwidthConfig,
+        stats: &mut BandwidthStats,
+    ) -> anyhow::Result<Vec<SerializedChange>> {
+        let mut kept = Vec::with_capacity(changes.len());
+
+        for change in changes {
+            let size = protocol::change_size(&change)?;
+
+            let droppable = matches!(
+                &change,
+                SerializedChange::ComponentUpdated(_, token, Some(_))
+                    if !config.high_priority_tokens.contains(token)
+            );
+
+            if droppable && self.available < size as f64 {
+                stats.dropped_updates += 1;
+                continue;
+            }
+
+            // High priority changes may overdraw the budget, delaying the low priority ones after them
+            self.available -= size as f64;
+            stats.sent_bytes += size;
+            kept.push(change);
+        }
+
+        Ok(kept)
+    }
+}
+
+/// Coalesces updates to fast changing components so each is sent at most `max_rates` times per second
+///
+/// Only the newest update held back is sent once the component's interval has passed,
+/// removals and despawns are sent immediately and discard the held update
+#[derive(Resource, Debug, Clone)]
+pub struct ThrottleConfig {
+    pub max_rates: HashMap<NetTypeId, f32>,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self {
+            max_rates: [
+                (Inertial::type_path().into(), 20.0),
+                (Magnetic::type_path().into(), 20.0),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Throttle {
+    // Newest update held back for each throttled component
+    pending: HashMap<(NetId, NetTypeId), SerializedChange>,
+    last_sent: HashMap<(NetId, NetTypeId), Duration>,
+}
+
+impl Throttle {
+    fn interval(config: &ThrottleConfig, token: &NetTypeId) -> Option<Duration> {
+        config
+            .max_rates
+            .get(token)
+            .filter(|rate| **rate > 0.0)
+            .map(|rate| Duration::from_secs_f64(1.0 / *rate as f64))
+    }
+
+    /// Holds back throttled updates that arrive too soon and releases held updates that are due
+    fn apply(
+        &mut self,
+        changes: Vec<SerializedChange>,
+        config: &ThrottleConfig,
+        now: Duration,
+    ) -> Vec<SerializedChange> {
+        let mut out = Vec::with_capacity(changes.len());
+
+        for change in changes {
+            match &change {
+                SerializedChange::ComponentUpdated(net_id, token, Some(_)) => {
+                    if let Some(interval) = Self::interval(config, token) {
+                        let key = (*net_id, token.clone());
+                        let too_soon = self
+                            .last_sent
+                            .get(&key)
+                            .is_some_and(|last| now.saturating_sub(*last) < interval);
+
+                        if too_soon {
+                            self.pending.insert(key, change);
+                            continue;
+                        }
+
+                        self.pending.remove(&key);
+                        self.last_sent.insert(key, now);
+                    }
+                }
+                SerializedChange::ComponentUpdated(net_id, token, None) => {
+                    let key = (*net_id, token.clone());
+
+                    self.pending.remove(&key);
+                    self.last_sent.remove(&key);
+                }
+                SerializedChange::EntityDespawned(net_id) => {
+                    self.pending.retain(|(it, _), _| it != net_id);
+                    self.last_sent.retain(|(it, _), _| it != net_id);
+                }
+                _ => {}
+            }
+
+            out.push(change);
+        }
+
+        let last_sent = &mut self.last_sent;
+        self.pending.retain(|key, change| {
+            let due = match (Self::interval(config, &key.1), last_sent.get(key)) {
+                (Some(interval), Some(last)) => now.saturating_sub(*last) >= interval,
+                // No longer throttled
+                _ => true,
+            };
+
+            if due {
+                last_sent.insert(key.clone(), now);
+                out.push(change.clone());
+            }
+
+            !due
+        });
+
+        out
+    }
+}
+
+/// Components sent first, in this order, when a new peer connects
+///
+/// Everything else follows in no particular order, so the peer never shows stale critical state
+/// while the bulk of the snapshot is still arriving
+#[derive(Resource, Debug, Clone)]
+pub struct InitialSyncOrder {
+    pub first: Vec<NetTypeId>,
+}
+
+impl Default for InitialSyncOrder {
+    fn default() -> Self {
+        Self {
+            first: vec![
+                Armed::type_path().into(),
+                EmergencyStop::type_path().into(),
+                RobotStatus::type_path().into(),
+                Motors::type_path().into(),
+            ],
+        }
+    }
+}
+
+impl InitialSyncOrder {
+    /// Reorders a snapshot, spawns stay ahead of every component update
+    fn apply(&self, changes: &mut [SerializedChange]) {
+        changes.sort_by_key(|change| match change {
+            SerializedChange::EntitySpawned(_) => 0,
+            SerializedChange::ComponentUpdated(_, token, _) => {
+                let position = self.first.iter().position(|it| it == token);
+
+                1 + position.unwrap_or(self.first.len())
+            }
+            _ => 1 + self.first.len(),
+        });
+    }
+}
+
+// Reliable updates without an ack are resent this often
+const RETRANSMIT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// State for `ReliableUpdate`s exchanged with a single peer
+///
+/// Only the newest update for each component is kept, an older retransmission
+/// arriving after a newer update is ignored by the receiver
+#[derive(Debug, Default)]
+pub struct ReliableChannel {
+    next_seq: u64,
+    // Sequence number, change, time last sent
+    ack_pending: VecDeque<(u64, SerializedChange, Duration)>,
+
+    // Newest sequence number received for each component
+    received: HashMap<(NetId, NetTypeId), u64>,
+}
+
+impl ReliableChannel {
+    /// Queues a change until it is acknowledged, returns the sequence number to send it with
+    ///
+    /// Pending updates to the same component are superseded
+    pub fn push(&mut self, change: SerializedChange, now: Duration) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        if let Some(key) = change_key(&change) {
+            self.ack_pending
+                .retain(|(_, pending, _)| change_key(pending) != Some(key));
+        }
+
+        self.ack_pending.push_back((seq, change, now));
+
+        seq
+    }
+
+    pub fn ack(&mut self, seq: u64) {
+        self.ack_pending.retain(|(pending, _, _)| *pending != seq);
+    }
+
+    /// Drops pending updates to an entity that no longer exists
+    pub fn forget_entity(&mut self, net_id: NetId) {
+        self.ack_pending.retain(|(_, pending, _)| {
+            change_key(pending).is_none_or(|(pending, _)| pending != net_id)
+        });
+    }
+
+    /// Updates that have gone `interval` without an ack, marked as sent at `now`
+    pub fn due(
+        &mut self,
+        now: Duration,
+        interval: Duration,
+    ) -> impl Iterator<Item = (u64, &SerializedChange)> {
+        self.ack_pending
+            .iter_mut()
+            .filter(move |(_, _, sent)| now.saturating_sub(*sent) >= interval)
+            .map(move |(seq, change, sent)| {
+                *sent = now;
+                (*seq, &*change)
+            })
+    }
+
+    /// Records a received update, returns false if a newer update to the same component was already received
+    pub fn receive(&mut self, seq: u64, change: &SerializedChange) -> bool {
+        let Some((net_id, token)) = change_key(change) else {
+            return true;
+        };
+
+        match self.received.get_mut(&(net_id, token.clone())) {
+            Some(newest) if *newest >= seq => false,
+            Some(newest) => {
+                *newest = seq;
+                true
+            }
+            None => {
+                self.received.insert((net_id, token.clone()), seq);
+                true
+            }
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.ack_pending.len()
+    }
+}
+
+fn change_key(change: &SerializedChange) -> Option<(NetId, &NetTypeId)> {
+    match change {
+        SerializedChange::ComponentUpdated(net_id, token, _) => Some((*net_id, token)),
+        _ => None,
+    }
+}
+
+/// Orders a frame's unreliable changes so bulk telemetry cannot delay time critical updates
+///
+/// Only the newest change to each component is kept. Priority changes are moved ahead of the rest,
+/// except for changes to entities spawned in the same frame, which must stay after the spawn
+fn schedule_changes(
+    changes: Vec<SerializedChange>,
+    is_priority: impl Fn(&SerializedChange) -> bool,
+) -> Vec<SerializedChange> {
+    let mut newest = HashMap::default();
+    let mut spawned = HashSet::default();
+    for (idx, change) in changes.iter().enumerate() {
+        if let Some(key) = change_key(change) {
+            newest.insert(key, idx);
+        }
+
+        if let SerializedChange::EntitySpawned(net_id) = change {
+            spawned.insert(*net_id);
+        }
+    }
+
+    let (priority, bulk): (Vec<_>, Vec<_>) = changes
+        .iter()
+        .enumerate()
+        .filter(|(idx, change)| change_key(change).is_none_or(|key| newest[&key] == *idx))
+        .map(|(_, change)| change.clone())
+        .partition(|change| {
+            is_priority(change)
+                && !change_key(change).is_some_and(|(net_id, _)| spawned.contains(&net_id))
+        });
+
+    priority.into_iter().chain(bulk).collect()
 }
 
 #[derive(Resource)]
@@ -124,6 +636,130 @@ pub struct DisconnectPeer(pub NetToken);
 #[derive(Event)]
 pub struct SyncPeer(pub NetToken);
 
+// Delay before the first retry, doubled after every failed attempt
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(4);
+// Connection attempts the net thread has not resolved by now are counted as failed
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The peer a client should stay connected to
+///
+/// Set by `ConnectToPeer` and cleared by `DisconnectPeer`, when the link to the target drops
+/// it is retried with exponential backoff until it comes back or is cancelled
+#[derive(Resource, Debug, Default)]
+pub struct Reconnect {
+    target: Option<SocketAddr>,
+    status: ConnectionStatus,
+
+    // Failed attempts since the target was last connected
+    attempts: u32,
+    next_attempt: Duration,
+    // When the attempt the net thread is working on was started
+    in_flight: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    #[default]
+    Disconnected,
+    Connecting,
+    Connected,
+    Reconnecting {
+        attempt: u32,
+    },
+}
+
+impl Reconnect {
+    pub fn target(&self) -> Option<SocketAddr> {
+        self.target
+    }
+
+    pub fn status(&self) -> ConnectionStatus {
+        self.status
+    }
+
+    /// Stops trying to reach the current target
+    pub fn cancel(&mut self) {
+        *self = Self::default();
+    }
+
+    fn start(&mut self, target: SocketAddr, now: Duration) {
+        *self = Self {
+            target: Some(target),
+            status: ConnectionStatus::Connecting,
+            in_flight: Some(now),
+            ..default()
+        };
+    }
+
+    fn attempt_failed(&mut self, now: Duration) {
+        if self.target.is_none() {
+            return;
+        }
+
+        self.in_flight = None;
+        self.attempts += 1;
+        self.next_attempt = now + reconnect_backoff(self.attempts);
+        self.status = ConnectionStatus::Reconnecting {
+            attempt: self.attempts,
+        };
+    }
+
+    /// Advances the state machine, returns true if a new connection attempt should be made now
+    ///
+    /// `handshaking` means the target is connected but has not finished the handshake yet
+    fn poll(&mut self, connected: bool, handshaking: bool, now: Duration) -> bool {
+        if self.target.is_none() {
+            return false;
+        }
+
+        if connected {
+            self.attempts = 0;
+            self.in_flight = None;
+            self.status = ConnectionStatus::Connected;
+
+            return false;
+        }
+
+        if handshaking {
+            return false;
+        }
+
+        if let Some(started) = self.in_flight {
+            if now.saturating_sub(started) < CONNECT_TIMEOUT {
+                return false;
+            }
+
+            self.attempt_failed(now);
+        }
+
+        if self.status == ConnectionStatus::Connected {
+            // Just lost the link, retry right away
+            self.next_attempt = now;
+            self.status = ConnectionStatus::Reconnecting { attempt: 1 };
+        }
+
+        if now < self.next_attempt {
+            return false;
+        }
+
+        self.in_flight = Some(now);
+        self.status = ConnectionStatus::Reconnecting {
+            attempt: self.attempts + 1,
+        };
+
+        true
+    }
+}
+
+fn reconnect_backoff(attempts: u32) -> Duration {
+    let factor = 1u32 << attempts.saturating_sub(1).min(16);
+
+    RECONNECT_BACKOFF
+        .saturating_mul(factor)
+        .min(MAX_RECONNECT_BACKOFF)
+}
+
 fn setup_networking(
     mut cmds: Commands,
 
@@ -203,19 +839,61 @@ fn setup_networking(
     Ok(())
 }
 
-fn connect(net: Res<Net>, mut events: EventReader<ConnectToPeer>) -> anyhow::Result<()> {
+fn connect(
+    net: Res<Net>,
+    time: Res<Time<Real>>,
+    mut reconnect: ResMut<Reconnect>,
+    mut events: EventReader<ConnectToPeer>,
+) -> anyhow::Result<()> {
     for event in events.read() {
         info!("Connecting to {}", event.0);
         net.0.connect_to(event.0).context("Contact net thread")?;
+
+        reconnect.start(event.0, time.elapsed());
     }
 
     Ok(())
 }
 
-fn disconnect(net: Res<Net>, mut events: EventReader<DisconnectPeer>) -> anyhow::Result<()> {
+fn disconnect(
+    net: Res<Net>,
+    mut reconnect: Option<ResMut<Reconnect>>,
+    mut events: EventReader<DisconnectPeer>,
+) -> anyhow::Result<()> {
     for event in events.read() {
         info!("Disconnecting from {:?}", event.0);
         net.0.disconnect(event.0).context("Contact net thread")?;
+
+        // The user asked to leave, dont bring the link back
+        if let Some(reconnect) = &mut reconnect {
+            reconnect.cancel();
+        }
+    }
+
+    Ok(())
+}
+
+/// Retries the connection to the client's target after the link drops
+fn reconnect(
+    net: Res<Net>,
+    time: Res<Time<Real>>,
+    peers: Res<Peers>,
+    peer_query: Query<&Peer>,
+    mut reconnect: ResMut<Reconnect>,
+) -> anyhow::Result<()> {
+    let Some(target) = reconnect.target() else {
+        return Ok(());
+    };
+
+    let connected = peer_query.iter().any(|peer| peer.addrs == target);
+    let handshaking = peers.pending.values().any(|(addrs, _)| *addrs == target);
+
+    if reconnect.poll(connected, handshaking, time.elapsed()) {
+        info!(
+            "Reconnecting to {target}, attempt {}",
+            reconnect.attempts + 1
+        );
+        net.0.connect_to(target).context("Contact net thread")?;
     }
 
     Ok(())
@@ -259,6 +937,8 @@ fn net_read(
 
     net: Res<Net>,
     frame: Res<FrameCount>,
+    time: Res<Time<Real>>,
+    settings: Res<SerializationSettings>,
 
     mut peers: ResMut<Peers>,
     mut entity_map: ResMut<EntityMap>,
@@ -266,6 +946,7 @@ fn net_read(
     mut new_peers: EventWriter<SyncPeer>,
 
     mut peer_query: Query<(&Peer, &mut Latency)>,
+    mut reconnect: Option<ResMut<Reconnect>>,
 
     mut errors: EventWriter<ErrorEvent>,
 ) {
@@ -274,15 +955,93 @@ fn net_read(
             NetEvent::Conected(token, addrs) | NetEvent::Accepted(token, addrs) => {
                 info!(?token, ?addrs, "Peer connected");
 
-                new_peers.send(SyncPeer(token));
-                peers.pending.insert(token, (addrs, frame.0));
+                peers.pending.insert(token, (addrs, None));
+                peers.awaiting_handshake.insert(token, frame.0);
+
+                let handshake = Protocol::Hello {
+                    version: PROTOCOL_VERSION,
+                    capabilities: settings.registry_hash(),
+                };
+
+                let rst = net.0.send_packet(token, handshake);
 
-                peers.valid_tokens.insert(token);
+                if rst.is_err() {
+                    errors.send(anyhow!("Could not send handshake").into());
+                }
             }
             NetEvent::Data(token, packet) => match packet {
+                Protocol::Hello {
+                    version,
+                    capabilities,
+                } => {
+                    peers.awaiting_handshake.remove(&token);
+
+                    match check_handshake(version, capabilities, &settings) {
+                        Ok(()) => {
+                            info!(?token, "Peer completed handshake");
+
+                            peers.valid_tokens.insert(token);
+                            new_peers.send(SyncPeer(token));
+
+                            // The peer only starts replicating its singleton once it has our handshake
+                            if let Some((_, time)) = peers.pending.get_mut(&token) {
+                                *time = Some(frame.0);
+                            }
+                        }
+                        Err(err) => {
+                            errors.send(
+                                err.context(Severity::Fatal)
+                                    .context(format!("Handshake with peer {token:?} failed"))
+                                    .into(),
+                            );
+
+                            drop_peer(&mut cmds, &net, &mut peers, &mut entity_map, token);
+                        }
+                    }
+                }
                 Protocol::EcsUpdate(update) => {
+                    if !peers.valid_tokens.contains(&token) {
+                        // Changes sent before the handshake may be for types we dont know about
+                        continue;
+                    }
+
                     changes.send(SerializedChangeInEvent(update, token));
                 }
+                Protocol::EcsUpdateBatch(updates) => {
+                    if !peers.valid_tokens.contains(&token) {
+                        // Changes sent before the handshake may be for types we dont know about
+                        continue;
+                    }
+
+                    changes.send_batch(
+                        updates
+                            .into_iter()
+                            .map(|update| SerializedChangeInEvent(update, token)),
+                    );
+                }
+                Protocol::ReliableUpdate { seq, change } => {
+                    if !peers.valid_tokens.contains(&token) {
+                        // Changes sent before the handshake may be for types we dont know about
+                        continue;
+                    }
+
+                    // Always ack, the peer keeps resending until it hears back
+                    let rst = net.0.send_packet(token, Protocol::Ack { seq });
+
+                    if rst.is_err() {
+                        errors.send(anyhow!("Could not ack reliable update").into());
+                    }
+
+                    let channel = peers.reliable.entry(token).or_default();
+                    if channel.receive(seq, &change) {
+                        changes.send(SerializedChangeInEvent(change, token));
+                    }
+                }
+                Protocol::Ack { seq } => {
+                    if let Some(channel) = peers.reliable.get_mut(&token) {
+                        channel.ack(seq);
+                    }
+                }
                 Protocol::Ping { payload } => {
                     let response = Protocol::Pong { payload };
 
@@ -303,11 +1062,11 @@ fn net_read(
                         continue;
                     };
 
-                    let sent = payload;
-                    let frame = frame.0;
+                    let rtt = latency.window.pong_received(payload, time.elapsed());
 
-                    latency.last_acknowledged = sent.into();
-                    latency.ping = Some(frame.wrapping_sub(sent));
+                    if rtt.is_none() {
+                        warn!(?token, payload, "Got pong for unknown ping");
+                    }
                 }
             },
             NetEvent::Error(token, error) => {
@@ -318,52 +1077,199 @@ fn net_read(
                 );
             }
             NetEvent::Disconnect(token) => {
-                peers.valid_tokens.remove(&token);
-
-                let Some(entity) = peers.by_token.remove(&token) else {
-                    errors.send(anyhow!("Unknown peer disconnected").into());
+                if peers.dropped.remove(&token) {
+                    // Already cleaned up when the peer was dropped
                     continue;
-                };
-                let Ok((peer, _)) = peer_query.get(entity) else {
-                    errors.send(anyhow!("Unknown peer disconnected").into());
-                    continue;
-                };
-
-                peers.by_addrs.remove(&peer.addrs);
-
-                cmds.entity(entity).despawn();
-                if let Some(owned_entities) = entity_map.forign_owned.remove(&token) {
-                    for entity in owned_entities {
-                        let forign = entity_map.local_to_forign.remove(&entity);
-                        if let Some(forign) = forign {
-                            entity_map.forign_to_local.remove(&forign);
-                        };
-
-                        entity_map.local_modified.remove(&entity);
-
-                        let Some(mut entity) = cmds.get_entity(entity) else {
-                            continue;
-                        };
+                }
 
-                        entity.despawn();
+                let Some(addrs) = remove_peer(&mut cmds, &mut peers, &mut entity_map, token) else {
+                    match &mut reconnect {
+                        // A connection attempt that never got through
+                        Some(reconnect) if reconnect.in_flight.is_some() => {
+                            warn!("Could not connect to {:?}", reconnect.target());
+                            reconnect.attempt_failed(time.elapsed());
+                        }
+                        _ => {
+                            errors.send(anyhow!("Unknown peer disconnected").into());
+                        }
                     }
-                }
+                    continue;
+                };
 
-                info!("Peer ({token:?}) at {} disconnected", peer.addrs);
+                info!("Peer ({token:?}) at {addrs} disconnected");
             }
         }
     }
 }
-fn net_write(
-    net: Res<Net>,
+
+fn check_handshake(
+    version: u32,
+    capabilities: u64,
+    settings: &SerializationSettings,
+) -> anyhow::Result<()> {
+    if version != PROTOCOL_VERSION {
+        bail!("Protocol version mismatch, local: {PROTOCOL_VERSION}, peer: {version}");
+    }
+
+    let local = settings.registry_hash();
+    if capabilities != local {
+        bail!(
+            "Replicated types differ from peer, local registry: {local:016x}, peer registry: {capabilities:016x}"
+        );
+    }
+
+    Ok(())
+}
+
+/// Cleans up after a peer and asks the net thread to disconnect it
+fn drop_peer(
+    cmds: &mut Commands,
+    net: &Net,
+    peers: &mut Peers,
+    entity_map: &mut EntityMap,
+    token: NetToken,
+) {
+    remove_peer(cmds, peers, entity_map, token);
+    peers.dropped.insert(token);
+
+    // The peer is already forgotten, if the net thread is gone there is nothing left to disconnect
+    let _ = net.0.disconnect(token);
+}
+
+/// Forgets everything about a peer and despawns the entities it owns
+///
+/// Returns the peer's address if it was known
+fn remove_peer(
+    cmds: &mut Commands,
+    peers: &mut Peers,
+    entity_map: &mut EntityMap,
+    token: NetToken,
+) -> Option<SocketAddr> {
+    peers.valid_tokens.remove(&token);
+    peers.awaiting_handshake.remove(&token);
+    peers.reliable.remove(&token);
+
+    let mut addrs = peers.pending.remove(&token).map(|(addrs, _)| addrs);
+
+    if let Some(entity) = peers.by_token.remove(&token) {
+        peers.by_addrs.retain(|peer_addrs, peer_entity| {
+            if *peer_entity == entity {
+                addrs = Some(*peer_addrs);
+                false
+            } else {
+                true
+            }
+        });
+
+        cmds.entity(entity).despawn();
+    }
+
+    if let Some(owned_entities) = entity_map.forign_owned.remove(&token) {
+        for entity in owned_entities {
+            let forign = entity_map.local_to_forign.remove(&entity);
+            if let Some(forign) = forign {
+                entity_map.forign_to_local.remove(&forign);
+            };
+
+            entity_map.local_modified.remove(&entity);
+
+            let Some(mut entity) = cmds.get_entity(entity) else {
+                continue;
+            };
+
+            entity.despawn();
+        }
+    }
+
+    addrs
+}
+
+fn net_write(
+    mut budget: Local<BandwidthBudget>,
+    mut throttle: Local<Throttle>,
+
+    net: Res<Net>,
+    time: Res<Time<Real>>,
+    settings: Res<SerializationSettings>,
+    bandwidth: Res<BandwidthConfig>,
+    throttle_config: Res<ThrottleConfig>,
+    mut stats: ResMut<BandwidthStats>,
+    mut peers: ResMut<Peers>,
     mut changes: EventReader<SerializedChangeOutEvent>,
     mut errors: EventWriter<ErrorEvent>,
 ) {
-    for change in changes.read() {
-        let rst = net.0.brodcast_packet(Protocol::EcsUpdate(change.0.clone()));
+    let peers = &mut *peers;
+    let now = time.elapsed();
+
+    let (reliable, unreliable): (Vec<_>, Vec<_>) = changes
+        .read()
+        .map(|change| change.0.clone())
+        .partition(|change| settings.is_reliable(change));
+
+    let unreliable = schedule_changes(unreliable, |change| settings.is_priority(change));
+    let unreliable = throttle.apply(unreliable, &throttle_config, now);
+
+    budget.refill(&bandwidth, now);
+    let unreliable = match budget.apply(unreliable, &bandwidth, &mut stats) {
+        Ok(unreliable) => unreliable,
+        Err(err) => {
+            errors.send(err.context("Could not apply bandwidth budget").into());
+            return;
+        }
+    };
 
-        if rst.is_err() {
-            errors.send(anyhow!("Could not brodcast ECS update").into());
+    let despawned: Vec<_> = unreliable
+        .iter()
+        .filter_map(|change| match change {
+            SerializedChange::EntityDespawned(net_id) => Some(*net_id),
+            _ => None,
+        })
+        .collect();
+
+    let batches = protocol::batch_changes(unreliable, MAX_BATCH_SIZE);
+
+    match batches {
+        Ok(batches) => {
+            // Peers that have not completed the handshake may not know about the types being sent
+            for token in &peers.valid_tokens {
+                for batch in &batches {
+                    let rst = net.0.send_packet(*token, batch.clone());
+
+                    if rst.is_err() {
+                        errors.send(anyhow!("Could not send ECS update").into());
+                    }
+                }
+            }
+        }
+        Err(err) => {
+            errors.send(err.context("Could not batch ECS updates").into());
+        }
+    }
+
+    // Sent after the batches so the entities they update have already been spawned
+    for token in &peers.valid_tokens {
+        let channel = peers.reliable.entry(*token).or_default();
+
+        for net_id in &despawned {
+            channel.forget_entity(*net_id);
+        }
+
+        for change in &reliable {
+            if change_key(change).is_some_and(|(net_id, _)| despawned.contains(&net_id)) {
+                continue;
+            }
+
+            let seq = channel.push(change.clone(), now);
+            let packet = Protocol::ReliableUpdate {
+                seq,
+                change: change.clone(),
+            };
+
+            let rst = net.0.send_packet(*token, packet);
+
+            if rst.is_err() {
+                errors.send(anyhow!("Could not send reliable ECS update").into());
+            }
         }
     }
 
@@ -380,6 +1286,7 @@ fn spawn_peer_entities(
     frame: Res<FrameCount>,
     mut peers: ResMut<Peers>,
     query: Query<(Entity, &ForignOwned), Added<Singleton>>,
+    mut errors: EventWriter<ErrorEvent>,
 ) {
     let peers = &mut *peers;
 
@@ -388,24 +1295,38 @@ fn spawn_peer_entities(
         let data = peers.pending.remove(&token);
 
         if let Some((addrs, _)) = data {
+            let rst = peers.register(token, addrs, entity);
+            if let Err(err) = rst {
+                errors.send(err.into());
+                continue;
+            }
+
             cmds.entity(entity)
                 .insert((Peer { addrs, token }, Latency::default()));
-
-            peers.by_token.insert(token, entity);
-            peers.by_addrs.insert(addrs, entity);
         }
     }
 
     let frame = frame.0;
-    peers
+    let expired = peers
         .pending
-        .extract_if(|_, (_, time)| frame.wrapping_sub(*time) > SINGLETON_DEADLINE)
-        .for_each(|(token, (addrs, _))| {
-            let entity = cmds.spawn((Peer { addrs, token }, Latency::default())).id();
+        .extract_if(|_, (_, time)| {
+            time.is_some_and(|time| frame.wrapping_sub(time) > SINGLETON_DEADLINE)
+        })
+        .collect::<Vec<_>>();
 
-            peers.by_token.insert(token, entity);
-            peers.by_addrs.insert(addrs, entity);
-        });
+    for (token, (addrs, _)) in expired {
+        let entity = cmds.spawn_empty().id();
+
+        let rst = peers.register(token, addrs, entity);
+        if let Err(err) = rst {
+            errors.send(err.into());
+            cmds.entity(entity).despawn();
+            continue;
+        }
+
+        cmds.entity(entity)
+            .insert((Peer { addrs, token }, Latency::default()));
+    }
 }
 
 fn shutdown(
@@ -434,41 +1355,105 @@ fn shutdown(
     }
 }
 
+// In frames
+const HANDSHAKE_DEADLINE: u32 = 100;
+
+fn handshake_timeout(
+    mut cmds: Commands,
+    net: Res<Net>,
+    frame: Res<FrameCount>,
+    mut peers: ResMut<Peers>,
+    mut entity_map: ResMut<EntityMap>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let frame = frame.0;
+
+    let timed_out: Vec<_> = peers
+        .awaiting_handshake
+        .iter()
+        .filter(|(_, connected)| frame.wrapping_sub(**connected) > HANDSHAKE_DEADLINE)
+        .map(|(token, _)| *token)
+        .collect();
+
+    for token in timed_out {
+        errors.send(anyhow!("Peer {token:?} did not send a handshake in time").into());
+
+        drop_peer(&mut cmds, &net, &mut peers, &mut entity_map, token);
+    }
+}
+
+/// Replicates the number of dropped updates on our singleton so peers can see it
+fn publish_bandwidth_stats(
+    mut cmds: Commands,
+    stats: Res<BandwidthStats>,
+    singleton: Query<(Entity, Option<&DroppedUpdates>), (With<Singleton>, Without<ForignOwned>)>,
+) {
+    for (entity, published) in &singleton {
+        // Only insert on change, `sent_bytes` changes every frame
+        if published.map(|it| it.0) != Some(stats.dropped_updates) {
+            cmds.entity(entity)
+                .insert(DroppedUpdates(stats.dropped_updates));
+        }
+    }
+}
+
+fn retransmit_reliable(
+    net: Res<Net>,
+    time: Res<Time<Real>>,
+    mut peers: ResMut<Peers>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let now = time.elapsed();
+
+    for (token, channel) in &mut peers.reliable {
+        for (seq, change) in channel.due(now, RETRANSMIT_INTERVAL) {
+            trace!(?token, seq, "Retransmitting reliable update");
+
+            let packet = Protocol::ReliableUpdate {
+                seq,
+                change: change.clone(),
+            };
+
+            let rst = net.0.send_packet(*token, packet);
+
+            if rst.is_err() {
+                errors.send(anyhow!("Could not resend reliable ECS update").into());
+            }
+        }
+    }
+}
+
+// In frames
 const PING_INTERVAL: u32 = 50;
-const MAX_LATENCY: u32 = 15;
+// Compared against the mean round trip time, a single slow pong should not drop the peer
+const MAX_LATENCY: Duration = Duration::from_millis(150);
+// Pings without a response after this long are counted as lost
+const PONG_TIMEOUT: Duration = Duration::from_secs(1);
+// Disconnect peers that have not answered any ping for this long
+const LINK_TIMEOUT: Duration = Duration::from_secs(3);
 
-// TODO(high): Auto Reconnect
 fn ping(
+    mut cmds: Commands,
     net: Res<Net>,
     frame: Res<FrameCount>,
-    mut query: Query<(&Peer, &mut Latency)>,
+    time: Res<Time<Real>>,
+    mut query: Query<(Entity, &Peer, &mut Latency, Option<&PeerLatency>)>,
     mut errors: EventWriter<ErrorEvent>,
 ) {
     let frame = frame.0;
+    let now = time.elapsed();
 
-    for (peer, mut latency) in &mut query {
-        let should_disconnect = match (
-            latency.last_ping_sent,
-            latency.last_acknowledged,
-            latency.ping,
-        ) {
-            (_, _, Some(ping)) if ping > MAX_LATENCY => true,
-            (Some(last_ping), last_ack, _)
-                if Some(last_ping) != last_ack && frame.wrapping_sub(last_ping) > MAX_LATENCY =>
-            {
-                true
-            }
-            _ => false,
-        };
+    for (entity, peer, mut latency, peer_latency) in &mut query {
+        let rtt = latency.window.rtt();
+        let unanswered_for = latency.window.unanswered_for(now);
+
+        let should_disconnect =
+            rtt.is_some_and(|rtt| rtt > MAX_LATENCY) || unanswered_for > LINK_TIMEOUT;
 
         if should_disconnect {
             error!(
-                "Peer at {:?} timed out, now: {:?} lp: {:?}, la: {:?}, elapsed_since: {:?}",
-                peer.token,
-                frame,
-                latency.last_ping_sent,
-                latency.last_acknowledged,
-                latency.last_ping_sent.map(|it| frame - it)
+                "Peer at {:?} timed out, now: {:?} lp: {:?}, rtt: {:?}, unanswered_for: {:?}",
+                peer.token, frame, latency.last_ping_sent, rtt, unanswered_for
             );
             let rst = net.0.disconnect(peer.token);
 
@@ -478,12 +1463,9 @@ fn ping(
             continue;
         }
 
-        let should_ping = match (latency.last_ping_sent, latency.last_acknowledged) {
-            (Some(last_ping), Some(last_ack)) => {
-                last_ping == last_ack && frame >= PING_INTERVAL + last_ping
-            }
-            (Some(_), None) => false,
-            _ => true,
+        let should_ping = match latency.last_ping_sent {
+            Some(last_ping) => frame.wrapping_sub(last_ping) >= PING_INTERVAL,
+            None => true,
         };
 
         if should_ping {
@@ -495,110 +1477,807 @@ fn ping(
             }
 
             latency.last_ping_sent = frame.into();
+            latency.window.ping_sent(frame, now);
+        }
+
+        let stats = latency.window.stats(now, PONG_TIMEOUT);
+        if peer_latency != Some(&stats) {
+            // The peer may have been dropped earlier this frame
+            cmds.entity(entity).try_insert(stats);
         }
     }
 }
 
-#[derive(Resource, Default, Debug)]
-struct Deltas {
-    entities: HashMap<NetId, HashMap<NetTypeId, adapters::BackingType>>,
-}
+/// Sends every new peer a snapshot of the current state of the replicated entities we own
+fn sync_new_peers(
+    mut set: ParamSet<(
+        &World,
+        (Res<Net>, EventReader<SyncPeer>, EventWriter<ErrorEvent>),
+    )>,
+) {
+    if set.p1().1.is_empty() {
+        return;
+    }
 
-fn flatten_deltas(
-    mut deltas: ResMut<Deltas>,
-    entity_map: Res<EntityMap>,
+    let world = set.p0();
+    let batches = detect_changes::snapshot(world).and_then(|mut changes| {
+        if let Some(order) = world.get_resource::<InitialSyncOrder>() {
+            order.apply(&mut changes);
+        }
 
-    mut inbound: EventReader<SerializedChangeInEvent>,
-    mut outbound: EventReader<SerializedChangeOutEvent>,
+        protocol::batch_changes(changes, MAX_BATCH_SIZE)
+    });
 
-    mut errors: EventWriter<ErrorEvent>,
-) {
-    let iter = Iterator::chain(
-        outbound.read().map(|it| &it.0),
-        inbound.read().map(|it| &it.0),
-    );
-
-    for change in iter {
-        match change {
-            SerializedChange::EntitySpawned(net_id) => {
-                let Some(entity) = entity_map.forign_to_local.get(net_id) else {
-                    continue;
-                };
-                let forign_owned = entity_map
-                    .forign_owned
-                    .values()
-                    .any(|forign_set| forign_set.contains(entity));
+    let (net, mut new_peers, mut errors) = set.p1();
+    let batches = match batches {
+        Ok(batches) => batches,
+        Err(err) => {
+            errors.send(
+                err.context("Could not snapshot entities for new peers")
+                    .into(),
+            );
+            new_peers.clear();
+            return;
+        }
+    };
 
-                if !forign_owned {
-                    deltas.entities.insert(*net_id, HashMap::default());
-                }
-            }
-            SerializedChange::EntityDespawned(net_id) => {
-                deltas.entities.remove(net_id);
-            }
-            SerializedChange::ComponentUpdated(net_id, token, raw) => {
-                let Some(entity) = entity_map.forign_to_local.get(net_id) else {
-                    continue;
-                };
-                let forign_owned = entity_map
-                    .forign_owned
-                    .values()
-                    .any(|forign_set| forign_set.contains(entity));
-
-                if !forign_owned {
-                    if let Some(components) = deltas.entities.get_mut(net_id) {
-                        if let Some(raw) = raw {
-                            components.insert(token.clone(), raw.clone());
-                        } else {
-                            components.remove(token);
-                        }
-                    } else {
-                        errors.send(anyhow!("Got bad change event during flattening").into());
-                    }
-                }
-            }
-            SerializedChange::EventEmitted(_, _) => {
-                // New clients should not recieve old events
+    'outer: for &SyncPeer(peer) in new_peers.read() {
+        for batch in &batches {
+            let rst = net.0.send_packet(peer, batch.clone());
+
+            if rst.is_err() {
+                errors.send(anyhow!("Could not send sync packet").into());
+                continue 'outer;
             }
         }
     }
 }
 
-fn sync_new_peers(
-    net: Res<Net>,
-    deltas: Res<Deltas>,
-    mut new_peers: EventReader<SyncPeer>,
-    mut errors: EventWriter<ErrorEvent>,
-) {
-    'outer: for &SyncPeer(peer) in new_peers.read() {
-        for entity in deltas.entities.keys() {
-            let rst = net.0.send_packet(
-                peer,
-                Protocol::EcsUpdate(SerializedChange::EntitySpawned(*entity)),
+#[cfg(test)]
+mod tests {
+    use bevy::{ecs::event::Events, reflect::TypePath};
+
+    use crate::{
+        components::{Armed, Depth, Leak, Motors},
+        ecs_sync::{
+            detect_changes::ChangeDetectionPlugin, AppReplicateExt, NetId, Replicate,
+            SerializedChange,
+        },
+    };
+
+    use super::*;
+
+    fn test_app() -> (
+        App,
+        Networking<Protocol>,
+        channel::Sender<NetEvent<Protocol>>,
+    ) {
+        let networking = Networking::new().expect("Start networking");
+        let (tx, rx) = channel::unbounded();
+
+        let mut app = App::new();
+        app.add_event::<ErrorEvent>()
+            .add_event::<SerializedChangeInEvent>()
+            .add_event::<SyncPeer>()
+            .init_resource::<SerializationSettings>()
+            .init_resource::<EntityMap>()
+            .init_resource::<Peers>()
+            .insert_resource(FrameCount(0))
+            .init_resource::<Time<Real>>()
+            .insert_resource(Net(networking.messenger(), rx))
+            .add_systems(Update, (net_read, handshake_timeout.after(net_read)));
+
+        (app, networking, tx)
+    }
+
+    /// Pretends the peer's singleton has already been replicated
+    fn spawn_peer(app: &mut App, token: NetToken, addrs: SocketAddr) -> Entity {
+        let entity = app
+            .world_mut()
+            .spawn((Peer { addrs, token }, Latency::default()))
+            .id();
+
+        let mut peers = app.world_mut().resource_mut::<Peers>();
+        peers.pending.remove(&token);
+        peers.by_token.insert(token, entity);
+        peers.by_addrs.insert(addrs, entity);
+
+        entity
+    }
+
+    fn error_messages(app: &App) -> Vec<String> {
+        let events = app.world().resource::<Events<ErrorEvent>>();
+
+        events
+            .get_reader()
+            .read(events)
+            .map(|ErrorEvent(err)| format!("{err:#}"))
+            .collect()
+    }
+
+    #[test]
+    fn mismatched_handshake() {
+        let (mut app, _networking, tx) = test_app();
+
+        let token = NetToken(1);
+        let addrs = "127.0.0.1:44445".parse().unwrap();
+
+        tx.send(NetEvent::Accepted(token, addrs)).unwrap();
+        app.update();
+
+        let entity = spawn_peer(&mut app, token, addrs);
+
+        tx.send(NetEvent::Data(
+            token,
+            Protocol::Hello {
+                version: PROTOCOL_VERSION.wrapping_add(1),
+                capabilities: 0,
+            },
+        ))
+        .unwrap();
+        app.update();
+
+        assert!(app.world().get_entity(entity).is_none());
+        assert!(!app
+            .world()
+            .resource::<Peers>()
+            .valid_tokens
+            .contains(&token));
+        assert!(error_messages(&app)
+            .iter()
+            .any(|it| it.contains("Protocol version mismatch")));
+
+        let events = app.world().resource::<Events<ErrorEvent>>();
+        assert!(events
+            .get_reader()
+            .read(events)
+            .any(|ErrorEvent(err)| err.downcast_ref::<Severity>() == Some(&Severity::Fatal)));
+
+        // The net thread's disconnect event should not be reported as an unknown peer
+        tx.send(NetEvent::Disconnect(token)).unwrap();
+        app.update();
+
+        assert!(!error_messages(&app)
+            .iter()
+            .any(|it| it.contains("Unknown peer")));
+    }
+
+    #[test]
+    fn matching_handshake() {
+        let (mut app, _networking, tx) = test_app();
+
+        let token = NetToken(1);
+        let addrs = "127.0.0.1:44445".parse().unwrap();
+
+        let capabilities = app
+            .world()
+            .resource::<SerializationSettings>()
+            .registry_hash();
+
+        tx.send(NetEvent::Accepted(token, addrs)).unwrap();
+        tx.send(NetEvent::Data(
+            token,
+            Protocol::Hello {
+                version: PROTOCOL_VERSION,
+                capabilities,
+            },
+        ))
+        .unwrap();
+        app.update();
+
+        assert!(app
+            .world()
+            .resource::<Peers>()
+            .valid_tokens
+            .contains(&token));
+        assert!(error_messages(&app).is_empty());
+    }
+
+    #[test]
+    fn handshake_deadline() {
+        let (mut app, _networking, tx) = test_app();
+
+        let token = NetToken(1);
+        let addrs = "127.0.0.1:44445".parse().unwrap();
+
+        tx.send(NetEvent::Accepted(token, addrs)).unwrap();
+        app.update();
+
+        let entity = spawn_peer(&mut app, token, addrs);
+
+        app.world_mut().resource_mut::<FrameCount>().0 = HANDSHAKE_DEADLINE + 1;
+        app.update();
+
+        assert!(app.world().get_entity(entity).is_none());
+        assert!(error_messages(&app)
+            .iter()
+            .any(|it| it.contains("did not send a handshake")));
+    }
+
+    #[test]
+    fn singleton_deadline_starts_at_handshake() {
+        let (mut app, _networking, tx) = test_app();
+        app.add_systems(Update, spawn_peer_entities.after(net_read));
+
+        let token = NetToken(1);
+        let addrs = "127.0.0.1:44445".parse().unwrap();
+
+        let capabilities = app
+            .world()
+            .resource::<SerializationSettings>()
+            .registry_hash();
+
+        tx.send(NetEvent::Accepted(token, addrs)).unwrap();
+        app.update();
+
+        // A slow handshake must still leave the peer time to replicate its singleton
+        app.world_mut().resource_mut::<FrameCount>().0 = SINGLETON_DEADLINE * 2;
+        tx.send(NetEvent::Data(
+            token,
+            Protocol::Hello {
+                version: PROTOCOL_VERSION,
+                capabilities,
+            },
+        ))
+        .unwrap();
+        app.update();
+
+        assert!(!app
+            .world()
+            .resource::<Peers>()
+            .by_token
+            .contains_key(&token));
+
+        app.world_mut().resource_mut::<FrameCount>().0 = SINGLETON_DEADLINE * 3 + 1;
+        app.update();
+
+        assert!(app
+            .world()
+            .resource::<Peers>()
+            .by_token
+            .contains_key(&token));
+        assert!(error_messages(&app).is_empty());
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn ping_window_steady() {
+        let mut window = PingWindow::default();
+
+        for idx in 0..10 {
+            let sent = ms(500 * idx);
+            window.ping_sent(idx as u32, sent);
+            assert_eq!(
+                window.pong_received(idx as u32, sent + ms(20)),
+                Some(ms(20))
             );
+        }
 
-            if rst.is_err() {
-                errors.send(anyhow!("Could not send sync packet").into());
-                continue 'outer;
+        let now = ms(5000);
+        assert_eq!(window.rtt(), Some(ms(20)));
+        assert_eq!(window.jitter(), Duration::ZERO);
+        assert_eq!(window.packet_loss(now, PONG_TIMEOUT), 0.0);
+        assert_eq!(window.unanswered_for(now), ms(480));
+    }
+
+    #[test]
+    fn ping_window_jitter() {
+        let mut window = PingWindow::default();
+
+        for idx in 0..8 {
+            let sent = ms(500 * idx);
+            let rtt = if idx % 2 == 0 { ms(10) } else { ms(30) };
+
+            window.ping_sent(idx as u32, sent);
+            window.pong_received(idx as u32, sent + rtt);
+        }
+
+        assert_eq!(window.rtt(), Some(ms(20)));
+        assert_eq!(window.rtt_range(), Some((ms(10), ms(30))));
+        assert_eq!(window.jitter(), ms(20));
+    }
+
+    #[test]
+    fn ping_window_packet_loss() {
+        let mut window = PingWindow::default();
+
+        // Every other pong is lost
+        for idx in 0..10 {
+            let sent = ms(500 * idx);
+            window.ping_sent(idx as u32, sent);
+
+            if idx % 2 == 0 {
+                window.pong_received(idx as u32, sent + ms(20));
             }
         }
 
-        for (entity, components) in &deltas.entities {
-            for (token, raw) in components {
-                let rst = net.0.send_packet(
-                    peer,
-                    Protocol::EcsUpdate(SerializedChange::ComponentUpdated(
-                        *entity,
-                        token.clone(),
-                        Some(raw.clone()),
-                    )),
-                );
+        // The last ping was sent at 4500ms and is still in flight
+        assert_eq!(window.packet_loss(ms(4600), PONG_TIMEOUT), 4.0 / 9.0);
+        assert_eq!(window.packet_loss(ms(6000), PONG_TIMEOUT), 0.5);
+        assert_eq!(window.rtt(), Some(ms(20)));
 
-                if rst.is_err() {
-                    errors.send(anyhow!("Could not send sync packet").into());
-                    continue 'outer;
-                }
+        // Late pongs still count
+        assert_eq!(window.pong_received(9, ms(4700)), Some(ms(200)));
+        assert_eq!(window.pong_received(9, ms(4800)), None);
+        assert_eq!(window.packet_loss(ms(6000), PONG_TIMEOUT), 0.4);
+    }
+
+    #[test]
+    fn ping_window_rolls_over() {
+        let mut window = PingWindow::default();
+
+        // A single slow pong should not push the mean over the limit
+        window.ping_sent(0, ms(0));
+        window.pong_received(0, ms(400));
+        assert!(window.rtt().unwrap() > MAX_LATENCY);
+
+        for idx in 1..PING_WINDOW as u64 {
+            let sent = ms(500 * idx);
+            window.ping_sent(idx as u32, sent);
+            window.pong_received(idx as u32, sent + ms(10));
+        }
+
+        assert!(window.rtt().unwrap() < MAX_LATENCY);
+
+        // Once the slow ping leaves the window it no longer counts
+        let sent = ms(500 * PING_WINDOW as u64);
+        window.ping_sent(PING_WINDOW as u32, sent);
+        window.pong_received(PING_WINDOW as u32, sent + ms(10));
+
+        assert_eq!(window.rtt(), Some(ms(10)));
+        assert_eq!(window.pong_received(0, sent), None);
+    }
+
+    #[test]
+    fn ping_window_silent_peer() {
+        let mut window = PingWindow::default();
+
+        assert_eq!(window.rtt(), None);
+        assert_eq!(window.rtt_range(), None);
+        assert_eq!(window.unanswered_for(ms(100)), Duration::ZERO);
+
+        for idx in 0..8 {
+            window.ping_sent(idx, ms(500 * idx as u64));
+        }
+
+        assert_eq!(window.rtt(), None);
+        assert_eq!(window.packet_loss(ms(5000), PONG_TIMEOUT), 1.0);
+        assert!(window.unanswered_for(ms(5000)) > LINK_TIMEOUT);
+    }
+
+    fn armed_update(net_id: NetId, armed: Armed) -> SerializedChange {
+        let serialized = bincode::serialize(&armed).unwrap().into();
+
+        SerializedChange::ComponentUpdated(net_id, Armed::type_path().into(), Some(serialized))
+    }
+
+    #[test]
+    fn bandwidth_budget_drops_low_priority_updates() {
+        let net_id = NetId::random();
+        let telemetry = || {
+            SerializedChange::ComponentUpdated(
+                net_id,
+                Leak::type_path().into(),
+                Some(vec![0; 60].into()),
+            )
+        };
+
+        let spawn = SerializedChange::EntitySpawned(net_id);
+        let spawn_size = protocol::change_size(&spawn).unwrap();
+        let telemetry_size = protocol::change_size(&telemetry()).unwrap();
+
+        // Room for the spawn and one and a half telemetry updates
+        let config = BandwidthConfig {
+            max_bytes_per_sec: (spawn_size + telemetry_size * 3 / 2) as u32,
+            high_priority_tokens: vec![Armed::type_path().into()],
+        };
+        let mut stats = BandwidthStats::default();
+        let mut budget = BandwidthBudget::default();
+
+        budget.refill(&config, ms(0));
+        let changes = vec![
+            spawn,
+            telemetry(),
+            telemetry(),
+            armed_update(net_id, Armed::Armed),
+            SerializedChange::ComponentUpdated(net_id, Leak::type_path().into(), None),
+        ];
+        let kept = budget
+            .apply(changes.clone(), &config, &mut stats)
+            .expect("Apply budget");
+
+        // The second telemetry update does not fit, everything else is always sent
+        assert_eq!(
+            kept,
+            vec![
+                changes[0].clone(),
+                changes[1].clone(),
+                changes[3].clone(),
+                changes[4].clone()
+            ]
+        );
+        assert_eq!(stats.dropped_updates, 1);
+        assert!(budget.available < 0.0);
+
+        // The budget never holds more than one second of traffic
+        budget.refill(&config, ms(60_000));
+        assert_eq!(budget.available, config.max_bytes_per_sec as f64);
+
+        let kept = budget
+            .apply(vec![telemetry(), telemetry()], &config, &mut stats)
+            .expect("Apply budget");
+        assert_eq!(kept.len(), 1);
+        assert_eq!(stats.dropped_updates, 2);
+
+        let available = budget.available;
+        budget.refill(&config, ms(60_500));
+        assert!(
+            (budget.available - available - config.max_bytes_per_sec as f64 / 2.0).abs() < 1e-6
+        );
+    }
+
+    #[test]
+    fn priority_updates_are_sent_first() {
+        let mut app = App::new();
+        app.init_resource::<SerializationSettings>()
+            .replicate_priority::<Depth>()
+            .replicate::<Leak>();
+        let settings = app.world().resource::<SerializationSettings>();
+
+        let (robot, camera) = (NetId::random(), NetId::random());
+        let update = |net_id, token: &'static str, value: u8| {
+            SerializedChange::ComponentUpdated(net_id, token.into(), Some(vec![value].into()))
+        };
+        let (depth, leak) = (Depth::type_path(), Leak::type_path());
+
+        let changes = vec![
+            update(robot, leak, 1),
+            update(robot, depth, 1),
+            update(robot, leak, 2),
+            SerializedChange::EntitySpawned(camera),
+            update(camera, depth, 1),
+            SerializedChange::EventEmitted("common::events::Log".into(), vec![0].into()),
+            update(robot, depth, 2),
+        ];
+        let scheduled = schedule_changes(changes.clone(), |change| settings.is_priority(change));
+
+        let batches = protocol::batch_changes(scheduled, MAX_BATCH_SIZE).expect("Batch changes");
+        let [Protocol::EcsUpdateBatch(sent)] = batches.as_slice() else {
+            panic!("Expected one batch, got {batches:?}");
+        };
+
+        // Stale updates are dropped, the newest depth jumps the queue,
+        // the new entity's depth has to wait for its spawn
+        assert_eq!(
+            *sent,
+            vec![
+                changes[6].clone(),
+                changes[2].clone(),
+                changes[3].clone(),
+                changes[4].clone(),
+                changes[5].clone(),
+            ]
+        );
+    }
+
+    #[test]
+    fn removals_replace_queued_updates() {
+        let net_id = NetId::random();
+        let token: NetTypeId = Depth::type_path().into();
+
+        let changes = vec![
+            SerializedChange::ComponentUpdated(net_id, token.clone(), Some(vec![1].into())),
+            SerializedChange::ComponentUpdated(net_id, token.clone(), None),
+        ];
+        let scheduled = schedule_changes(changes.clone(), |_| true);
+
+        assert_eq!(scheduled, vec![changes[1].clone()]);
+    }
+
+    #[test]
+    fn peer_collisions_are_rejected() {
+        let mut peers = Peers::default();
+        let (first, second) = (Entity::from_raw(1), Entity::from_raw(2));
+        let addrs: SocketAddr = "10.0.0.2:44444".parse().unwrap();
+
+        peers.register(NetToken(1), addrs, first).unwrap();
+        // Registering the same entity again is harmless
+        peers.register(NetToken(1), addrs, first).unwrap();
+
+        assert!(peers
+            .register(NetToken(1), "10.0.0.3:44444".parse().unwrap(), second)
+            .is_err());
+        assert!(peers.register(NetToken(2), addrs, second).is_err());
+
+        assert_eq!(peers.by_token.get(&NetToken(1)), Some(&first));
+        assert_eq!(peers.by_addrs.get(&addrs), Some(&first));
+        assert_eq!(peers.by_token.get(&NetToken(2)), None);
+    }
+
+    #[test]
+    fn initial_sync_sends_critical_components_first() {
+        let (robot, camera) = (NetId::random(), NetId::random());
+        let update = |net_id, token: &str| {
+            SerializedChange::ComponentUpdated(
+                net_id,
+                token.to_owned().into(),
+                Some(Vec::<u8>::new().into()),
+            )
+        };
+
+        let mut changes = vec![
+            SerializedChange::EntitySpawned(robot),
+            SerializedChange::EntitySpawned(camera),
+            update(camera, Leak::type_path()),
+            update(robot, Motors::type_path()),
+            update(robot, Leak::type_path()),
+            update(robot, Armed::type_path()),
+        ];
+        InitialSyncOrder::default().apply(&mut changes);
+
+        assert_eq!(
+            changes,
+            vec![
+                SerializedChange::EntitySpawned(robot),
+                SerializedChange::EntitySpawned(camera),
+                update(robot, Armed::type_path()),
+                update(robot, Motors::type_path()),
+                update(camera, Leak::type_path()),
+                update(robot, Leak::type_path()),
+            ]
+        );
+    }
+
+    #[test]
+    fn throttle_coalesces_fast_updates() {
+        let net_id = NetId::random();
+        let update = |value: u8| {
+            SerializedChange::ComponentUpdated(
+                net_id,
+                Leak::type_path().into(),
+                Some(vec![value].into()),
+            )
+        };
+
+        let config = ThrottleConfig {
+            max_rates: [(Leak::type_path().into(), 20.0)].into_iter().collect(),
+        };
+        let mut throttle = Throttle::default();
+
+        // 100Hz updates for one second
+        let mut sent = Vec::new();
+        for step in 0..100u8 {
+            let mut changes = vec![update(step)];
+            if step == 0 {
+                changes.insert(0, SerializedChange::EntitySpawned(net_id));
+            }
+
+            sent.extend(throttle.apply(changes, &config, ms(step as u64 * 10)));
+        }
+
+        assert_eq!(sent[0], SerializedChange::EntitySpawned(net_id));
+        assert_eq!(sent.len(), 1 + 20);
+
+        // The newest update is sent once its interval passes, even without further changes
+        assert!(throttle.apply(vec![], &config, ms(995)).is_empty());
+        assert_eq!(throttle.apply(vec![], &config, ms(1000)), vec![update(99)]);
+        assert!(throttle.apply(vec![], &config, ms(2000)).is_empty());
+
+        // Removals go out right away and cancel the held update
+        assert_eq!(
+            throttle.apply(vec![update(1)], &config, ms(2000)),
+            vec![update(1)]
+        );
+        assert!(throttle
+            .apply(vec![update(2)], &config, ms(2010))
+            .is_empty());
+
+        let removed = SerializedChange::ComponentUpdated(net_id, Leak::type_path().into(), None);
+        assert_eq!(
+            throttle.apply(vec![removed.clone()], &config, ms(2020)),
+            vec![removed]
+        );
+        assert!(throttle.apply(vec![], &config, ms(3000)).is_empty());
+
+        // Other components are not throttled
+        let armed = armed_update(net_id, Armed::Armed);
+        assert_eq!(
+            throttle.apply(vec![armed.clone(), armed.clone()], &config, ms(3000)),
+            vec![armed.clone(), armed]
+        );
+    }
+
+    #[test]
+    fn reliable_channel_retransmits_until_acked() {
+        let mut channel = ReliableChannel::default();
+        let net_id = NetId::random();
+
+        let first = channel.push(armed_update(net_id, Armed::Armed), ms(0));
+        let second = channel.push(armed_update(net_id, Armed::Disarmed), ms(10));
+
+        // Only the newest update to a component is worth resending
+        assert_eq!(channel.pending(), 1);
+        channel.ack(first);
+        assert_eq!(channel.pending(), 1);
+
+        assert_eq!(channel.due(ms(59), RETRANSMIT_INTERVAL).count(), 0);
+        assert_eq!(
+            channel
+                .due(ms(60), RETRANSMIT_INTERVAL)
+                .map(|(seq, _)| seq)
+                .collect::<Vec<_>>(),
+            vec![second]
+        );
+        assert_eq!(channel.due(ms(100), RETRANSMIT_INTERVAL).count(), 0);
+        assert_eq!(channel.due(ms(110), RETRANSMIT_INTERVAL).count(), 1);
+
+        channel.ack(second);
+        assert_eq!(channel.pending(), 0);
+
+        channel.push(armed_update(net_id, Armed::Armed), ms(200));
+        channel.forget_entity(net_id);
+        assert_eq!(channel.pending(), 0);
+    }
+
+    #[test]
+    fn reliable_updates_are_applied_once() {
+        let (mut app, _networking, tx) = test_app();
+        app.replicate::<Armed>();
+
+        let token = NetToken(1);
+        let addrs = "127.0.0.1:44445".parse().unwrap();
+
+        let capabilities = app
+            .world()
+            .resource::<SerializationSettings>()
+            .registry_hash();
+
+        tx.send(NetEvent::Accepted(token, addrs)).unwrap();
+        tx.send(NetEvent::Data(
+            token,
+            Protocol::Hello {
+                version: PROTOCOL_VERSION,
+                capabilities,
+            },
+        ))
+        .unwrap();
+        app.update();
+
+        let net_id = NetId::random();
+        let reliable = |seq, armed| {
+            NetEvent::Data(
+                token,
+                Protocol::ReliableUpdate {
+                    seq,
+                    change: armed_update(net_id, armed),
+                },
+            )
+        };
+
+        // A retransmission of an update that was already superseded arrives last
+        tx.send(reliable(1, Armed::Armed)).unwrap();
+        tx.send(reliable(3, Armed::Disarmed)).unwrap();
+        tx.send(reliable(3, Armed::Disarmed)).unwrap();
+        tx.send(reliable(1, Armed::Armed)).unwrap();
+        app.update();
+
+        let events = app.world().resource::<Events<SerializedChangeInEvent>>();
+        let applied: Vec<_> = events
+            .get_reader()
+            .read(events)
+            .map(|SerializedChangeInEvent(change, _)| change.clone())
+            .collect();
+
+        assert_eq!(
+            applied,
+            vec![
+                armed_update(net_id, Armed::Armed),
+                armed_update(net_id, Armed::Disarmed)
+            ]
+        );
+        assert!(error_messages(&app).is_empty());
+    }
+
+    #[test]
+    fn snapshot_has_no_stale_components() {
+        let mut app = App::new();
+        app.add_event::<SerializedChangeInEvent>()
+            .add_event::<SerializedChangeOutEvent>()
+            .init_resource::<SerializationSettings>()
+            .init_resource::<EntityMap>()
+            .add_plugins(ChangeDetectionPlugin)
+            .replicate::<Leak>()
+            .replicate::<Armed>();
+
+        let entity = app
+            .world_mut()
+            .spawn((Replicate, Leak::default(), Armed::Disarmed))
+            .id();
+        app.update();
+
+        // Updated then removed before the peer connects
+        app.world_mut().entity_mut(entity).insert(Leak {
+            detected: true,
+            confidence: 1.0,
+        });
+        app.update();
+        app.world_mut().entity_mut(entity).remove::<Leak>();
+        app.update();
+
+        // Entities owned by other peers and entities that have not been detected yet are skipped
+        app.world_mut()
+            .spawn((Replicate, NetId::random(), ForignOwned(1), Leak::default()));
+        app.world_mut().spawn((Replicate, Leak::default()));
+
+        let net_id = *app.world().get::<NetId>(entity).unwrap();
+        let snapshot = detect_changes::snapshot(app.world()).expect("Snapshot");
+
+        match &snapshot[..] {
+            [SerializedChange::EntitySpawned(spawned), SerializedChange::ComponentUpdated(updated, token, Some(_))] =>
+            {
+                assert_eq!(*spawned, net_id);
+                assert_eq!(*updated, net_id);
+                assert_eq!(token, Armed::type_path());
             }
+            _ => panic!("Unexpected snapshot {snapshot:?}"),
         }
     }
+
+    #[test]
+    fn reconnect_backs_off_until_the_link_returns() {
+        let target: SocketAddr = ([192, 168, 1, 2], 44445).into();
+        let ms = Duration::from_millis;
+
+        let mut reconnect = Reconnect::default();
+        assert!(!reconnect.poll(false, false, ms(0)));
+
+        reconnect.start(target, ms(0));
+        assert_eq!(reconnect.status(), ConnectionStatus::Connecting);
+        assert!(!reconnect.poll(false, true, ms(10)));
+        assert!(!reconnect.poll(true, false, ms(20)));
+        assert_eq!(reconnect.status(), ConnectionStatus::Connected);
+
+        // Link drops, the first retry is immediate
+        assert!(reconnect.poll(false, false, ms(1000)));
+        assert_eq!(
+            reconnect.status(),
+            ConnectionStatus::Reconnecting { attempt: 1 }
+        );
+        assert!(!reconnect.poll(false, false, ms(1100)));
+
+        // Failures double the delay
+        reconnect.attempt_failed(ms(1100));
+        assert!(!reconnect.poll(false, false, ms(1300)));
+        assert!(reconnect.poll(false, false, ms(1350)));
+        assert_eq!(
+            reconnect.status(),
+            ConnectionStatus::Reconnecting { attempt: 2 }
+        );
+
+        reconnect.attempt_failed(ms(1350));
+        assert!(!reconnect.poll(false, false, ms(1800)));
+        assert!(reconnect.poll(false, false, ms(1850)));
+
+        // Attempts the net thread never answers time out
+        assert!(!reconnect.poll(false, false, ms(1850) + CONNECT_TIMEOUT - ms(1)));
+        assert!(!reconnect.poll(false, false, ms(1850) + CONNECT_TIMEOUT));
+        assert_eq!(
+            reconnect.status(),
+            ConnectionStatus::Reconnecting { attempt: 3 }
+        );
+
+        assert_eq!(reconnect_backoff(1), RECONNECT_BACKOFF);
+        assert_eq!(reconnect_backoff(20), MAX_RECONNECT_BACKOFF);
+
+        reconnect.poll(true, false, ms(20_000));
+        assert_eq!(reconnect.status(), ConnectionStatus::Connected);
+        assert_eq!(reconnect.attempts, 0);
+
+        reconnect.cancel();
+        assert!(!reconnect.poll(false, false, ms(30_000)));
+        assert_eq!(reconnect.status(), ConnectionStatus::Disconnected);
+    }
 }
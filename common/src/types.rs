@@ -1,11 +1,13 @@
 use bevy::app::App;
 
+pub mod error;
 pub mod hw;
 pub mod system;
 pub mod units;
 pub mod utils;
 
 pub fn register_types(app: &mut App) {
+    error::register_types(app);
     hw::register_types(app);
     system::register_types(app);
     units::register_types(app);
@@ -0,0 +1,56 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+use bevy::{
+    app::App,
+    reflect::{std_traits::ReflectDefault, Reflect, ReflectDeserialize, ReflectSerialize},
+};
+use serde::{Deserialize, Serialize};
+
+/// How serious a reported error is
+///
+/// Errors are `Warning`s unless a severity is attached with `anyhow::Context`,
+/// attach it before the context describing the failed operation so that stays the outermost message
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, Reflect, PartialEq, Eq, PartialOrd, Ord, Default,
+)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    /// The robot can not safely continue, it will disarm until the error is cleared
+    Critical,
+    /// The connection to a peer can not continue, ie a protocol mismatch, the peer is disconnected immediately
+    Fatal,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "Info"),
+            Severity::Warning => write!(f, "Warning"),
+            Severity::Critical => write!(f, "Critical error"),
+            Severity::Fatal => write!(f, "Fatal error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect, PartialEq)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq)]
+pub struct RobotError {
+    /// The full error chain
+    pub message: String,
+    pub severity: Severity,
+    /// Time since the robot started
+    pub timestamp: Duration,
+    /// The outermost context of the error, usually the operation that failed
+    pub source: String,
+}
+
+pub fn register_types(app: &mut App) {
+    app.register_type::<Severity>()
+        .register_type::<RobotError>();
+}
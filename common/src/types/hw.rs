@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use bevy::{
     app::App,
     reflect::{std_traits::ReflectDefault, Reflect, ReflectDeserialize, ReflectSerialize},
@@ -28,6 +30,10 @@ pub struct InertialFrame {
     pub accel_z: GForce,
 
     pub tempature: Celsius,
+
+    /// Monotonic time the frame was read from the sensor
+    #[serde(default)]
+    pub timestamp: Duration,
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, Reflect, PartialEq, Default)]
@@ -36,6 +42,10 @@ pub struct MagneticFrame {
     pub mag_x: Gauss,
     pub mag_y: Gauss,
     pub mag_z: Gauss,
+
+    /// Monotonic time the frame was read from the sensor
+    #[serde(default)]
+    pub timestamp: Duration,
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, Reflect, PartialEq, Default)]
@@ -55,10 +55,20 @@ pub struct Network {
     pub tx_errors: u64,
 }
 
+/// Only available on platforms where the robot knows where to find it, see `GpuInfo`
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect, PartialEq)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq)]
+pub struct GpuStats {
+    pub gpu_usage_pct: f32,
+    pub gpu_mem_used_mb: u64,
+    pub gpu_mem_total_mb: u64,
+}
+
 pub fn register_types(app: &mut App) {
     app.register_type::<Process>()
         .register_type::<Cpu>()
         .register_type::<ComponentTemperature>()
         .register_type::<Disk>()
-        .register_type::<Network>();
+        .register_type::<Network>()
+        .register_type::<GpuStats>();
 }
@@ -13,6 +13,7 @@ use crate::components::{PidConfig, PidResult};
 pub struct PidController {
     last_error: Option<f32>,
     integral: f32,
+    derivative: f32,
 
     last_deltas: [f32; 5],
     delta_idx: usize,
@@ -23,6 +24,7 @@ impl PidController {
         Self {
             last_error: None,
             integral: 0.0,
+            derivative: 0.0,
             last_deltas: [0.0; 5],
             delta_idx: 0,
         }
@@ -34,6 +36,19 @@ impl PidController {
         delta_target: f32,
         config: &PidConfig,
         interval: Duration,
+    ) -> PidResult {
+        self.update_with_feed_forward(error, delta_target, 0.0, config, interval)
+    }
+
+    /// Like `update` but adds `feed_forward * kf` to the correction, the integral then only
+    /// has to cover what the feed forward input does not predict
+    pub fn update_with_feed_forward(
+        &mut self,
+        error: f32,
+        delta_target: f32,
+        feed_forward: f32,
+        config: &PidConfig,
+        interval: Duration,
     ) -> PidResult {
         let cfg = config;
         let interval = interval.as_secs_f32();
@@ -42,8 +57,12 @@ impl PidController {
         self.integral = self.integral.clamp(-cfg.max_integral, cfg.max_integral);
 
         let proportional = error;
-        let integral = self.integral;
-        let derivative = (error - self.last_error.unwrap_or(error)) / interval;
+        let raw_derivative = (error - self.last_error.unwrap_or(error)) / interval;
+
+        // First order low pass, alpha is 1 when filtering is disabled
+        let alpha = interval / (cfg.derivative_tau.max(0.0) + interval);
+        self.derivative += alpha * (raw_derivative - self.derivative);
+        let derivative = self.derivative;
 
         self.last_deltas[self.delta_idx % self.last_deltas.len()] = delta_target;
         let avg_delta_target = self.last_deltas.iter().sum::<f32>() / self.last_deltas.len() as f32;
@@ -52,30 +71,254 @@ impl PidController {
         self.last_error = Some(error);
 
         let p = cfg.kp * proportional;
-        let i = cfg.ki * integral;
         let d = cfg.kd * derivative;
         let td = cfg.kt
             * avg_delta_target
                 .abs()
                 .max(delta_target.abs())
                 .copysign(delta_target);
+        let ff = cfg.kf * feed_forward;
+
+        let unsaturated = p + cfg.ki * self.integral + d + td + ff;
+        let correction = unsaturated.clamp(cfg.output_min, cfg.output_max);
+        let saturated = correction != unsaturated;
+
+        // Back calculation, drain the windup by however much the output was saturated
+        // but never push the integral past zero
+        if cfg.ki != 0.0 && saturated {
+            let adjustment = (correction - unsaturated) / cfg.ki;
+
+            if adjustment.signum() != self.integral.signum() {
+                let remaining = (self.integral.abs() - adjustment.abs()).max(0.0);
+                self.integral = remaining.copysign(self.integral);
+            }
+        }
 
-        let correction = p + i + d + td;
+        let i = cfg.ki * self.integral;
 
         PidResult {
             p,
             i,
             d,
             td,
+            ff,
             correction,
+            saturated,
         }
     }
 
     pub fn reset_i(&mut self) {
         self.integral = 0.0;
     }
+
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
 }
 
 pub fn register_types(app: &mut App) {
     app.register_type::<PidConfig>();
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::components::PidConfig;
+
+    use super::PidController;
+
+    /// Deterministic noise in [-1, 1]
+    fn noise(state: &mut u32) -> f32 {
+        *state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        (*state >> 8) as f32 / (1 << 23) as f32 - 1.0
+    }
+
+    fn variance(samples: &[f32]) -> f32 {
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        samples.iter().map(|it| (it - mean).powi(2)).sum::<f32>() / samples.len() as f32
+    }
+
+    fn run_noisy_depth(config: &PidConfig) -> Vec<f32> {
+        let interval = Duration::from_millis(10);
+        let mut controller = PidController::new();
+        let mut state = 1234;
+
+        (0..2000)
+            .map(|step| {
+                let depth = (step as f32 * 0.005).sin() * 0.5 + noise(&mut state) * 0.02;
+                let res = controller.update(1.0 - depth, 0.0, config, interval);
+
+                res.correction
+            })
+            .collect()
+    }
+
+    #[test]
+    fn derivative_filter_reduces_noise() {
+        let unfiltered = PidConfig {
+            kp: 100.0,
+            ki: 5.0,
+            kd: 1.5,
+            max_integral: 10.0,
+            ..Default::default()
+        };
+        let filtered = PidConfig {
+            derivative_tau: 0.1,
+            ..unfiltered.clone()
+        };
+
+        let unfiltered = variance(&run_noisy_depth(&unfiltered));
+        let filtered = variance(&run_noisy_depth(&filtered));
+
+        assert!(
+            filtered < unfiltered,
+            "filtered: {filtered}, unfiltered: {unfiltered}"
+        );
+    }
+
+    #[test]
+    fn derivative_filter_reduces_step_peak() {
+        let unfiltered = PidConfig {
+            kd: 1.0,
+            ..Default::default()
+        };
+        // 5 Hz cutoff
+        let filtered = PidConfig {
+            derivative_tau: 1.0 / (2.0 * std::f32::consts::PI * 5.0),
+            ..unfiltered.clone()
+        };
+
+        let step_response = |config: &PidConfig| {
+            let interval = Duration::from_millis(10);
+            let mut controller = PidController::new();
+
+            let mut peak = 0.0f32;
+            let mut last = 0.0;
+            for step in 0..200 {
+                let error = if step < 10 { 0.0 } else { 1.0 };
+                let res = controller.update(error, 0.0, config, interval);
+
+                peak = peak.max(res.d.abs());
+                last = res.d;
+            }
+
+            (peak, last)
+        };
+
+        let (unfiltered_peak, unfiltered_last) = step_response(&unfiltered);
+        let (filtered_peak, filtered_last) = step_response(&filtered);
+
+        assert!(
+            filtered_peak <= unfiltered_peak * 0.5,
+            "filtered: {filtered_peak}, unfiltered: {unfiltered_peak}"
+        );
+        // Both settle back to no derivative action once the error stops changing
+        assert!(unfiltered_last.abs() < 1e-6);
+        assert!(filtered_last.abs() < 1e-3);
+    }
+
+    #[test]
+    fn saturated_output_does_not_wind_up() {
+        let config = PidConfig {
+            kp: 1.0,
+            ki: 5.0,
+            kd: 0.0,
+            max_integral: 1000.0,
+            output_min: -2.0,
+            output_max: 2.0,
+            ..Default::default()
+        };
+
+        let interval = Duration::from_millis(10);
+        let mut controller = PidController::new();
+
+        for _ in 0..10_000 {
+            let res = controller.update(1.5, 0.0, &config, interval);
+
+            assert!(res.correction <= config.output_max);
+            assert!(controller.integral() * config.ki <= config.output_max);
+            assert!(controller.integral() >= 0.0);
+        }
+
+        // Once the error flips the output should leave saturation right away
+        let res = controller.update(-1.5, 0.0, &config, interval);
+        assert!(res.correction < config.output_max);
+    }
+
+    #[test]
+    fn integral_stops_growing_at_output_max() {
+        let config = PidConfig {
+            kp: 0.5,
+            ki: 1.0,
+            max_integral: 1000.0,
+            output_max: 1.0,
+            ..Default::default()
+        };
+
+        let interval = Duration::from_millis(10);
+        let mut controller = PidController::new();
+
+        let mut last_integral = 0.0;
+        let mut saturated_steps = 0;
+        for _ in 0..1000 {
+            let res = controller.update(1.0, 0.0, &config, interval);
+
+            if res.saturated {
+                assert_eq!(res.correction, config.output_max);
+                assert!(controller.integral() <= last_integral + 1e-6);
+                saturated_steps += 1;
+            }
+
+            last_integral = controller.integral();
+        }
+
+        assert!(saturated_steps > 900);
+        assert!((config.kp + config.ki * controller.integral() - config.output_max).abs() < 0.02);
+    }
+
+    #[test]
+    fn feed_forward_keeps_integral_small() {
+        // A constant disturbance the plant needs 3.0 of output to hold against
+        let disturbance = 3.0;
+        let config = PidConfig {
+            kp: 2.0,
+            ki: 1.0,
+            max_integral: 1000.0,
+            ..Default::default()
+        };
+        let with_ff = PidConfig {
+            kf: 1.0,
+            ..config.clone()
+        };
+
+        let interval = Duration::from_millis(10);
+        let settle = |config: &PidConfig| {
+            let mut controller = PidController::new();
+            let mut position = 0.0f32;
+
+            for _ in 0..5000 {
+                let res = controller.update_with_feed_forward(
+                    -position,
+                    0.0,
+                    disturbance,
+                    config,
+                    interval,
+                );
+                position += (res.correction - disturbance) * interval.as_secs_f32();
+            }
+
+            let res =
+                controller.update_with_feed_forward(-position, 0.0, disturbance, config, interval);
+            (res, controller.integral())
+        };
+
+        let (res, integral) = settle(&config);
+        assert_eq!(res.ff, 0.0);
+        assert!((integral - disturbance).abs() < 0.05, "{integral}");
+
+        let (res, integral) = settle(&with_ff);
+        assert_eq!(res.ff, disturbance);
+        assert!(integral.abs() < 0.05, "{integral}");
+    }
+}
@@ -0,0 +1,59 @@
+//! Finds the best seed motor orientation for an X3d config
+//!
+//! Usage: `cargo run --release --example optimize_x3d -- [motor data csv] [amperage cap]`
+
+use std::env;
+
+use anyhow::Context;
+use motor_math::{
+    motor_preformance,
+    optimize::{self, ObjectiveWeights},
+    solve::reverse::{axis_maximums, Axis},
+    utils::vec_from_angles,
+    x3d::X3dMotorId,
+    Direction, Motor, MotorConfig,
+};
+use nalgebra::{vector, Vector3};
+
+fn main() -> anyhow::Result<()> {
+    let mut args = env::args().skip(1);
+    let path = args
+        .next()
+        .unwrap_or_else(|| "../robot/motor_data.csv".to_owned());
+    let amperage_cap = args
+        .next()
+        .map(|it| it.parse::<f32>())
+        .transpose()
+        .context("Parse amperage cap")?
+        .unwrap_or(20.0);
+
+    let motor_data = motor_preformance::read_motor_data(&path)
+        .with_context(|| format!("Read motor data from {path}"))?;
+
+    let initial = Motor {
+        position: vector![0.19, 0.21, 0.09],
+        orientation: vec_from_angles(60f32.to_radians(), -40f32.to_radians()),
+        direction: Direction::CounterClockwise,
+    };
+    let objective = ObjectiveWeights {
+        z: 2.0,
+        z_rot: 2.0,
+        ..Default::default()
+    };
+
+    let optimized = optimize::optimize_x3d_seed(initial, &motor_data, amperage_cap, objective);
+
+    for (name, seed) in [("Initial", initial), ("Optimized", optimized)] {
+        let motor_config = MotorConfig::<X3dMotorId, f32>::new(seed, Vector3::zeros());
+        let maximums = axis_maximums(&motor_config, &motor_data, amperage_cap, 0.0001);
+
+        println!("{name}:");
+        println!("  orientation: {:?}", seed.orientation.data.0[0]);
+        for axis in Axis::ALL {
+            println!("  {axis:?}: {:.2}", maximums[&axis]);
+        }
+        println!("  score: {:.2}", objective.score(&maximums));
+    }
+
+    Ok(())
+}
@@ -69,7 +69,9 @@ impl<D: Number> MotorConfig<HeavyMotorId, D> {
                 Motor {
                     position,
                     orientation,
-                    direction: seed.direction.flip_n(transforms.len() as _),
+                    direction: seed
+                        .direction
+                        .flip_n(transforms.iter().filter(|it| it.is_reflection()).count() as _),
                 },
             )
         });
@@ -0,0 +1,192 @@
+//! Reachable force and torque envelopes of a motor config, used for visualization
+
+use std::{f32::consts::PI, fmt::Debug, hash::Hash};
+
+use nalgebra::{vector, Vector3};
+use tracing::instrument;
+
+use crate::{
+    motor_preformance::MotorDataSource,
+    solve::reverse::{binary_search_force_ratio, forces_to_cmds, reverse_solve},
+    MotorConfig, Movement,
+};
+
+const INITIAL_GUESS: f32 = 25.0;
+const EPSILON: f32 = 0.01;
+
+/// Samples the largest force achievable in `resolution` directions evenly spread over a sphere
+///
+/// Returns each unit direction along with the maximum force in newtons in that direction
+#[instrument(level = "trace", skip(motor_config, motor_data))]
+pub fn sample_envelope<MotorId: Hash + Ord + Clone + Debug>(
+    motor_config: &MotorConfig<MotorId, f32>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    amperage_cap: f32,
+    resolution: usize,
+) -> Vec<(Vector3<f32>, f32)> {
+    fibonacci_sphere(resolution)
+        .map(|direction| {
+            let maximum = max_force(direction, motor_config, motor_data, amperage_cap);
+            (direction, maximum)
+        })
+        .collect()
+}
+
+/// Same as `sample_envelope` but for torque, magnitudes are in newton meters
+#[instrument(level = "trace", skip(motor_config, motor_data))]
+pub fn sample_torque_envelope<MotorId: Hash + Ord + Clone + Debug>(
+    motor_config: &MotorConfig<MotorId, f32>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    amperage_cap: f32,
+    resolution: usize,
+) -> Vec<(Vector3<f32>, f32)> {
+    fibonacci_sphere(resolution)
+        .map(|direction| {
+            let maximum = max_torque(direction, motor_config, motor_data, amperage_cap);
+            (direction, maximum)
+        })
+        .collect()
+}
+
+/// The largest force achievable along `direction` without exceeding `amperage_cap`
+pub fn max_force<MotorId: Hash + Ord + Clone + Debug>(
+    direction: Vector3<f32>,
+    motor_config: &MotorConfig<MotorId, f32>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    amperage_cap: f32,
+) -> f32 {
+    let movement = Movement {
+        force: direction.normalize(),
+        torque: Vector3::zeros(),
+    };
+
+    max_movement(movement, motor_config, motor_data, amperage_cap)
+}
+
+/// The largest torque achievable around `direction` without exceeding `amperage_cap`
+pub fn max_torque<MotorId: Hash + Ord + Clone + Debug>(
+    direction: Vector3<f32>,
+    motor_config: &MotorConfig<MotorId, f32>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    amperage_cap: f32,
+) -> f32 {
+    let movement = Movement {
+        force: Vector3::zeros(),
+        torque: direction.normalize(),
+    };
+
+    max_movement(movement, motor_config, motor_data, amperage_cap)
+}
+
+/// The largest magnitude achievable along `direction` without exceeding `amperage_cap`
+///
+/// `direction` is normalized as a single 6 vector, so one newton counts the same as one newton meter.
+/// Generalizes `axis_maximums` to directions mixing several axes
+pub fn max_movement<MotorId: Hash + Ord + Clone + Debug>(
+    direction: Movement<f32>,
+    motor_config: &MotorConfig<MotorId, f32>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    amperage_cap: f32,
+) -> f32 {
+    let magnitude = (direction.force.norm_squared() + direction.torque.norm_squared()).sqrt();
+    if magnitude == 0.0 {
+        return 0.0;
+    }
+
+    let movement = direction / magnitude;
+    let forces = reverse_solve(movement * INITIAL_GUESS, motor_config);
+    let cmds = forces_to_cmds(forces, motor_config, motor_data);
+    let scale = binary_search_force_ratio(&cmds, motor_config, motor_data, amperage_cap, EPSILON);
+
+    scale * INITIAL_GUESS
+}
+
+/// Deterministically spreads `count` unit vectors evenly over a sphere
+fn fibonacci_sphere(count: usize) -> impl Iterator<Item = Vector3<f32>> {
+    let golden_angle = PI * (3.0 - 5.0f32.sqrt());
+
+    (0..count).map(move |idx| {
+        let z = 1.0 - 2.0 * (idx as f32 + 0.5) / count as f32;
+        let radius = (1.0 - z * z).sqrt();
+        let theta = golden_angle * idx as f32;
+
+        vector![radius * theta.cos(), radius * theta.sin(), z]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use nalgebra::vector;
+
+    use crate::{
+        fixtures,
+        solve::reverse::{axis_maximums, Axis},
+        utils::VectorTransform,
+    };
+
+    use super::*;
+
+    #[test]
+    fn envelope_x3d_symmetric() {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let envelope = sample_envelope(&motor_config, &motor_data, 20.0, 64);
+        assert_eq!(envelope.len(), 64);
+        assert_eq!(
+            envelope,
+            sample_envelope(&motor_config, &motor_data, 20.0, 64)
+        );
+
+        // The x3d config is symmetric across each axis
+        for (direction, maximum) in envelope {
+            assert!((direction.norm() - 1.0).abs() < 0.0001);
+
+            for transform in [
+                VectorTransform::ReflectXY,
+                VectorTransform::ReflectYZ,
+                VectorTransform::ReflectXZ,
+            ] {
+                let reflected = max_force(
+                    transform.transform(direction),
+                    &motor_config,
+                    &motor_data,
+                    20.0,
+                );
+
+                assert!((maximum - reflected).abs() < 0.01, "{direction:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn max_movement_generalizes_axis_maximums() {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let maximums = axis_maximums(&motor_config, &motor_data, 20.0, EPSILON);
+        for axis in Axis::ALL {
+            let maximum = max_movement(axis.movement() * 3.0, &motor_config, &motor_data, 20.0);
+
+            assert!((maximum - maximums[&axis]).abs() < 0.01, "{axis:?}");
+        }
+
+        let direction = Movement {
+            force: vector![1.0, 1.0, 0.0],
+            torque: vector![0.0, 0.0, 0.5],
+        };
+        let maximum = max_movement(direction, &motor_config, &motor_data, 20.0);
+
+        let unit = direction / 2.25f32.sqrt();
+        let forces = reverse_solve(unit * maximum, &motor_config);
+        let cmds = forces_to_cmds(forces, &motor_config, &motor_data);
+        let current = cmds.values().map(|it| it.current).sum::<f32>();
+        assert!((current - 20.0).abs() < 0.1, "{current}");
+
+        assert_eq!(
+            max_movement(Movement::default(), &motor_config, &motor_data, 20.0),
+            0.0
+        );
+    }
+}
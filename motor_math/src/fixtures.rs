@@ -0,0 +1,35 @@
+//! Setup shared by the unit tests
+
+use nalgebra::Vector3;
+
+use crate::{
+    motor_preformance::{self, MotorData, MotorRecord},
+    utils::vec_from_angles,
+    x3d::X3dMotorId,
+    Direction, Motor, MotorConfig,
+};
+
+const MOTOR_DATA_PATH: &str = "../robot/motor_data.csv";
+
+/// Roughly the size of a T200 at 12V, but linear so solver tests dont depend on the measured table
+pub fn motor_data() -> MotorData {
+    MotorData::synthetic_linear(36.4, 17.0, (1100.0, 1900.0))
+}
+
+/// The measured T200 table, only for tests about handling real, noisy data
+pub fn motor_records() -> Vec<MotorRecord<f32>> {
+    motor_preformance::read_motor_records(MOTOR_DATA_PATH).expect("Read motor data")
+}
+
+/// A clockwise thruster at `position` (normalized), pointed like the robot's front right top thruster
+pub fn seed_motor(position: Vector3<f32>) -> Motor<f32> {
+    Motor {
+        position: position.normalize(),
+        orientation: vec_from_angles(60.0, 40.0),
+        direction: Direction::Clockwise,
+    }
+}
+
+pub fn x3d_config(position: Vector3<f32>) -> MotorConfig<X3dMotorId, f32> {
+    MotorConfig::<X3dMotorId, f32>::new(seed_motor(position), Vector3::default())
+}
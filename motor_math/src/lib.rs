@@ -1,42 +1,180 @@
 #![feature(test)]
+#![cfg_attr(feature = "simd", feature(portable_simd))]
 
 // +X: Right, +Y: Forwards, +Z: Up
 // +XR: Pitch Up, +YR: Roll Clockwise, +ZR: Yaw Counter Clockwise (top view)
 
 pub mod blue_rov;
+pub mod envelope;
+#[cfg(test)]
+mod fixtures;
 pub mod motor_preformance;
+pub mod optimize;
+pub mod slew;
 pub mod solve;
 pub mod utils;
+pub mod vectored_six;
 pub mod x3d;
 
 use std::{
-    fmt::Debug,
+    fmt::{self, Debug, Display},
     ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign},
 };
 
 use bevy_reflect::{Reflect, ReflectDeserialize, ReflectSerialize};
-use nalgebra::{Matrix6xX, MatrixXx6, RealField, Vector3};
+use nalgebra::{Matrix6, Matrix6xX, MatrixXx6, RealField, Vector3, Vector6};
 use num_dual::DualNum;
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{DeserializeOwned, Error as _},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use solve::reverse::Axis;
 use tracing::instrument;
 
 // Should be implemented for f32 and f32 backed num-dual types
 pub trait Number: DualNum<f32> + RealField + Debug + Copy {}
 impl<T> Number for T where T: DualNum<f32> + RealField + Debug + Copy {}
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// Only the motors, center of mass, axis weights, and disabled motors are serialized. The matrices are
+/// derived from them and are rebuilt on load, so a peer can not send a matrix that does not match the motors
+#[derive(Debug, Clone, PartialEq)]
 pub struct MotorConfig<MotorId, D: Number> {
     motors: Vec<(MotorId, Motor<D>)>,
+    center_mass: Vector3<D>,
     matrix: Matrix6xX<D>,
     pseudo_inverse: MatrixXx6<D>,
+    axis_weights: Vector6<D>,
+}
+
+/// What a `MotorConfig` is serialized as
+#[derive(Serialize)]
+struct MotorConfigRef<'a, MotorId, D: Number> {
+    motors: &'a [(MotorId, Motor<D>)],
+    center_mass: &'a Vector3<D>,
+    axis_weights: &'a Vector6<D>,
+    disabled: Vec<&'a MotorId>,
+}
+
+/// What a `MotorConfig` is deserialized from
+#[derive(Deserialize)]
+struct MotorConfigDefinition<MotorId, D: Number> {
+    motors: Vec<(MotorId, Motor<D>)>,
+    center_mass: Vector3<D>,
+    axis_weights: Vector6<D>,
+    // A derived default would require `MotorId: Default`
+    #[serde(default = "Vec::new")]
+    disabled: Vec<MotorId>,
+}
+
+impl<MotorId: Serialize, D: Number + Serialize> Serialize for MotorConfig<MotorId, D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Disabling a motor zeros its column, disabling a motor that already has no effect is harmless
+        let disabled = self
+            .motors
+            .iter()
+            .zip(self.matrix.column_iter())
+            .filter(|(_, column)| column.iter().all(|it| it.is_zero()))
+            .map(|((motor_id, _), _)| motor_id)
+            .collect();
+
+        MotorConfigRef {
+            motors: &self.motors,
+            center_mass: &self.center_mass,
+            axis_weights: &self.axis_weights,
+            disabled,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, MotorId, D> Deserialize<'de> for MotorConfig<MotorId, D>
+where
+    MotorId: Deserialize<'de> + Ord + Debug + Clone,
+    D: Number + Deserialize<'de>,
+{
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        let MotorConfigDefinition {
+            motors,
+            center_mass,
+            axis_weights,
+            disabled,
+        } = MotorConfigDefinition::<MotorId, D>::deserialize(deserializer)?;
+
+        // The SVD does not converge on non finite values
+        let finite = motors
+            .iter()
+            .flat_map(|(_, motor)| motor.position.iter().chain(motor.orientation.iter()))
+            .chain(center_mass.iter())
+            .chain(axis_weights.iter())
+            .all(|it| it.re().is_finite());
+        if !finite {
+            return Err(De::Error::custom("Motor config has non finite values"));
+        }
+        if axis_weights.iter().any(|it| it.re() < 0.0) {
+            return Err(De::Error::custom("Axis weights must not be negative"));
+        }
+
+        let mut config =
+            Self::try_new_weighted(motors, center_mass, axis_weights).map_err(De::Error::custom)?;
+        if !disabled.is_empty() {
+            config
+                .disable_motors(&disabled)
+                .map_err(De::Error::custom)?;
+        }
+
+        Ok(config)
+    }
 }
 
 impl<MotorId: Ord + Debug, D: Number> MotorConfig<MotorId, D> {
+    /// Like `new_raw`, but fails if the motors can not produce movement along every axis
+    #[instrument(level = "trace", skip_all, ret)]
+    pub fn try_new_raw(
+        motors: impl IntoIterator<Item = (MotorId, Motor<D>)>,
+        center_mass: Vector3<D>,
+    ) -> Result<Self, MotorConfigError> {
+        let config = Self::new_raw(motors, center_mass);
+
+        let uncontrollable = config.uncontrollable_axes();
+        if !uncontrollable.is_empty() {
+            return Err(MotorConfigError::UncontrollableAxes {
+                rank: config.rank(),
+                axes: uncontrollable,
+            });
+        }
+
+        Ok(config)
+    }
+
     #[instrument(level = "trace", skip_all, ret)]
     pub fn new_raw(
         motors: impl IntoIterator<Item = (MotorId, Motor<D>)>,
         center_mass: Vector3<D>,
     ) -> Self {
+        Self::new_weighted(motors, center_mass, [1.0; 6])
+    }
+
+    /// Axis weights are in the order X, Y, Z, XRot, YRot, ZRot
+    ///
+    /// When the motors can not produce the requested movement exactly, error on axes with a
+    /// larger weight is penalized more heavily. If every movement is achievable the weights have no effect.
+    #[instrument(level = "trace", skip_all, ret)]
+    pub fn new_weighted(
+        motors: impl IntoIterator<Item = (MotorId, Motor<D>)>,
+        center_mass: Vector3<D>,
+        axis_weights: [f32; 6],
+    ) -> Self {
+        let axis_weights = Vector6::from_iterator(axis_weights.into_iter().map(D::from));
+
+        Self::try_new_weighted(motors, center_mass, axis_weights)
+            .expect("Pseudo inverse epsilon is positive")
+    }
+
+    fn try_new_weighted(
+        motors: impl IntoIterator<Item = (MotorId, Motor<D>)>,
+        center_mass: Vector3<D>,
+        axis_weights: Vector6<D>,
+    ) -> Result<Self, &'static str> {
         let mut motors: Vec<_> = motors.into_iter().collect();
         motors.sort_by(|a, b| MotorId::cmp(&a.0, &b.0));
         motors.dedup_by(|a, b| a.0 == b.0);
@@ -54,13 +192,15 @@ impl<MotorId: Ord + Debug, D: Number> MotorConfig<MotorId, D> {
             }),
         );
 
-        let pseudo_inverse = matrix.clone().pseudo_inverse(D::from(0.00001)).unwrap();
+        let pseudo_inverse = weighted_pseudo_inverse(&matrix, &axis_weights)?;
 
-        Self {
+        Ok(Self {
             motors,
+            center_mass,
             matrix,
             pseudo_inverse,
-        }
+            axis_weights,
+        })
     }
 
     pub fn motor(&self, motor: &MotorId) -> Option<&Motor<D>> {
@@ -71,6 +211,163 @@ impl<MotorId: Ord + Debug, D: Number> MotorConfig<MotorId, D> {
     pub fn motors(&self) -> impl Iterator<Item = (&MotorId, &Motor<D>)> {
         self.motors.iter().map(|it| (&it.0, &it.1))
     }
+
+    /// Ratio of the largest to the smallest non zero singular value of the motor matrix
+    ///
+    /// Large values mean small changes in the requested movement can cause large changes in motor forces
+    pub fn condition_number(&self) -> f32 {
+        let singular_values = self.singular_values();
+
+        let max = singular_values.iter().copied().fold(0.0, f32::max);
+        let min = singular_values
+            .iter()
+            .copied()
+            .filter(|it| *it > SINGULAR_VALUE_EPSILON)
+            .fold(f32::INFINITY, f32::min);
+
+        if min.is_finite() {
+            max / min
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    /// How a requested movement along each axis maps to the movement actually produced
+    ///
+    /// Column `i` is the movement produced when axis `i` is requested, axes are in the order
+    /// X, Y, Z, XRot, YRot, ZRot. This is the identity matrix for a fully actuated config
+    pub fn axis_coupling_matrix(&self) -> Matrix6<D> {
+        &self.matrix * &self.pseudo_inverse
+    }
+
+    /// The number of degrees of freedom the motors can independently control
+    pub fn rank(&self) -> usize {
+        self.singular_values()
+            .into_iter()
+            .filter(|it| *it > SINGULAR_VALUE_EPSILON)
+            .count()
+    }
+
+    /// Axes the motors can not produce movement along, in the order X, Y, Z, XRot, YRot, ZRot
+    ///
+    /// An axis is uncontrollable when it is not fully inside the span of the left singular vectors
+    /// with non zero singular values, ie some of it lies in a direction no combination of motors can push
+    pub fn uncontrollable_axes(&self) -> Vec<Axis> {
+        let svd = self.matrix.map(|it| it.re()).svd(true, false);
+        let Some(u) = svd.u else {
+            unreachable!("SVD was asked to compute U");
+        };
+
+        Axis::ALL
+            .into_iter()
+            .enumerate()
+            .filter(|&(row, _)| {
+                let reachable = u
+                    .row(row)
+                    .iter()
+                    .zip(svd.singular_values.iter())
+                    .filter(|(_, singular_value)| **singular_value > SINGULAR_VALUE_EPSILON)
+                    .map(|(it, _)| it * it)
+                    .sum::<f32>();
+
+                reachable < 1.0 - UNCONTROLLABLE_EPSILON
+            })
+            .map(|(_, axis)| axis)
+            .collect()
+    }
+
+    fn singular_values(&self) -> Vec<f32> {
+        self.matrix
+            .clone()
+            .singular_values()
+            .iter()
+            .map(|it| it.re())
+            .collect()
+    }
+}
+
+const SINGULAR_VALUE_EPSILON: f32 = 0.00001;
+const UNCONTROLLABLE_EPSILON: f32 = 0.001;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MotorConfigError {
+    /// The motors can only control `rank` degrees of freedom and can not move along `axes`
+    UncontrollableAxes { rank: usize, axes: Vec<Axis> },
+}
+
+impl Display for MotorConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MotorConfigError::UncontrollableAxes { rank, axes } => write!(
+                f,
+                "Motor config can not control {axes:?}, only {rank} of 6 degrees of freedom are controllable"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MotorConfigError {}
+
+impl<MotorId: Ord + Debug + Clone, D: Number> MotorConfig<MotorId, D> {
+    /// Returns a copy of this config where `motor` produces no force
+    ///
+    /// The motor keeps its entry so solvers still report a (zero) force for it
+    pub fn with_motor_disabled(&self, motor: &MotorId) -> Self {
+        self.with_disabled_motors(std::slice::from_ref(motor))
+    }
+
+    /// Returns a copy of this config where the `disabled` motors produce no force
+    ///
+    /// The pseudo-inverse is recomputed from only the remaining motors so `reverse_solve` redistributes
+    /// force across them. Disabled motors keep their entries so solvers still report a (zero) force for them
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn with_disabled_motors(&self, disabled: &[MotorId]) -> Self {
+        let mut config = self.clone();
+        config
+            .disable_motors(disabled)
+            .expect("Pseudo inverse epsilon is positive");
+
+        config
+    }
+
+    fn disable_motors(&mut self, disabled: &[MotorId]) -> Result<(), &'static str> {
+        let mut enabled = Vec::with_capacity(self.motors.len());
+        for (idx, (motor_id, _motor)) in self.motors.iter().enumerate() {
+            if disabled.contains(motor_id) {
+                self.matrix.column_mut(idx).fill(D::zero());
+            } else {
+                enabled.push(idx);
+            }
+        }
+
+        self.pseudo_inverse = MatrixXx6::zeros(self.motors.len());
+
+        if !enabled.is_empty() {
+            let reduced_matrix = self.matrix.select_columns(&enabled);
+            let reduced_inverse = weighted_pseudo_inverse(&reduced_matrix, &self.axis_weights)?;
+
+            for (row, &idx) in enabled.iter().enumerate() {
+                self.pseudo_inverse
+                    .row_mut(idx)
+                    .copy_from(&reduced_inverse.row(row));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Computes `(A^T W A)^+ A^T W` where `W` is the diagonal matrix of axis weights
+fn weighted_pseudo_inverse<D: Number>(
+    matrix: &Matrix6xX<D>,
+    axis_weights: &Vector6<D>,
+) -> Result<MatrixXx6<D>, &'static str> {
+    let weights = Matrix6::from_diagonal(axis_weights);
+    let weighted_transpose = matrix.transpose() * weights;
+
+    let inverse = (weighted_transpose.clone() * matrix).pseudo_inverse(D::from(0.00001))?;
+
+    Ok(inverse * weighted_transpose)
 }
 
 pub type ErasedMotorId = u8;
@@ -80,8 +377,10 @@ impl<MotorId: Ord + Into<ErasedMotorId> + Clone, D: Number> MotorConfig<MotorId,
     pub fn erase(self) -> MotorConfig<ErasedMotorId, D> {
         let MotorConfig {
             motors,
+            center_mass,
             matrix,
             pseudo_inverse,
+            axis_weights,
         } = self;
 
         let motors = motors
@@ -91,8 +390,10 @@ impl<MotorId: Ord + Into<ErasedMotorId> + Clone, D: Number> MotorConfig<MotorId,
 
         MotorConfig {
             motors,
+            center_mass,
             matrix,
             pseudo_inverse,
+            axis_weights,
         }
     }
 }
@@ -104,8 +405,10 @@ impl<D: Number> MotorConfig<ErasedMotorId, D> {
     ) -> Result<MotorConfig<MotorId, D>, <MotorId as TryFrom<ErasedMotorId>>::Error> {
         let MotorConfig {
             motors,
+            center_mass,
             matrix,
             pseudo_inverse,
+            axis_weights,
         } = self;
 
         let motors = motors
@@ -115,19 +418,27 @@ impl<D: Number> MotorConfig<ErasedMotorId, D> {
 
         Ok(MotorConfig {
             motors,
+            center_mass,
             matrix,
             pseudo_inverse,
+            axis_weights,
         })
     }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 // #[derive(Debug, Copy, Clone, Serialize, Deserialize, Reflect, PartialEq)]
 // #[reflect(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Motor<D: Number> {
     /// Offset from origin
+    ///
+    /// Any point on the line of thrust works, ie the center of a ducted thruster's nozzle.
+    /// Moving it along `orientation` does not change the torque since `orientation × orientation` is zero
+    #[cfg_attr(feature = "schemars", schemars(with = "[D; 3]"))]
     pub position: Vector3<D>,
     /// Unit vector
+    #[cfg_attr(feature = "schemars", schemars(with = "[D; 3]"))]
     pub orientation: Vector3<D>,
 
     pub direction: Direction,
@@ -135,6 +446,7 @@ pub struct Motor<D: Number> {
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, Reflect, PartialEq, Eq)]
 #[reflect(Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum Direction {
     Clockwise,
     CounterClockwise,
@@ -163,9 +475,10 @@ impl Direction {
     }
 }
 
-#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq)]
-// #[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, Reflect, PartialEq)]
-// #[reflect(Serialize, Deserialize, Debug, PartialEq)]
+/// Serde and reflection are only available when `D` is serializable, ie `f32`. num-dual is built without
+/// its serde feature so movements of dual numbers can not be serialized
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, Reflect, PartialEq)]
+#[reflect_value(Serialize, Deserialize, Debug, PartialEq, where D: Serialize + DeserializeOwned)]
 pub struct Movement<D: Number> {
     pub force: Vector3<D>,
     pub torque: Vector3<D>,
@@ -242,3 +555,205 @@ impl<D: Number> DivAssign<D> for Movement<D> {
         self.torque /= rhs;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy_reflect::{GetTypeRegistration, Reflect, ReflectDeserialize, ReflectSerialize};
+    use nalgebra::{vector, Vector3};
+
+    use crate::{
+        fixtures, solve, solve::reverse::Axis, x3d::X3dMotorId, Direction, Motor, MotorConfig,
+        MotorConfigError, Movement,
+    };
+
+    #[test]
+    fn movement_serde_roundtrip() {
+        let movement = Movement {
+            force: vector![-0.6, 0.5, 0.3],
+            torque: vector![0.2, 0.1, 0.4],
+        };
+
+        let bytes = bincode::serialize(&movement).expect("Serialize");
+        let decoded: Movement<f32> = bincode::deserialize(&bytes).expect("Deserialize");
+        assert_eq!(movement, decoded);
+
+        let json = serde_json::to_string(&movement).expect("Serialize");
+        let decoded: Movement<f32> = serde_json::from_str(&json).expect("Deserialize");
+        assert_eq!(movement, decoded);
+
+        let registration = <Movement<f32> as GetTypeRegistration>::get_type_registration();
+        assert!(registration.data::<ReflectSerialize>().is_some());
+        assert!(registration.data::<ReflectDeserialize>().is_some());
+        assert_eq!(movement.reflect_partial_eq(&decoded), Some(true));
+    }
+
+    #[test]
+    fn motor_config_conditioning() {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+        assert_eq!(motor_config.rank(), 6);
+        assert!(motor_config.condition_number().is_finite());
+        assert!(motor_config.condition_number() >= 1.0);
+
+        let motor_config = motor_config.with_motor_disabled(&X3dMotorId::FrontLeftTop);
+        assert_eq!(motor_config.rank(), 6);
+
+        let flat = Motor {
+            position: vector![0.0, 0.0, 0.0],
+            orientation: vector![0.0, 1.0, 0.0],
+            direction: Direction::Clockwise,
+        };
+        let motor_config =
+            MotorConfig::<u8, f32>::new_raw([(0, flat), (1, flat)], Vector3::default());
+        assert_eq!(motor_config.rank(), 1);
+    }
+
+    #[test]
+    fn thrust_point_along_orientation_does_not_change_torque() {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let shifted = motor_config.motors().map(|(motor_id, motor)| {
+            let motor = Motor {
+                position: motor.position + motor.orientation * 0.05,
+                ..*motor
+            };
+
+            (*motor_id, motor)
+        });
+        let shifted = MotorConfig::new_raw(shifted, Vector3::default());
+
+        let forces = motor_config
+            .motors()
+            .enumerate()
+            .map(|(idx, (motor_id, _))| (*motor_id, idx as f32 - 3.5))
+            .collect();
+
+        let expected = solve::forward::forward_solve(&motor_config, &forces);
+        let actual = solve::forward::forward_solve(&shifted, &forces);
+
+        assert!((expected.force - actual.force).norm() < 1e-5);
+        assert!((expected.torque - actual.torque).norm() < 1e-5);
+    }
+
+    #[test]
+    fn axis_coupling_x3d() {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+        let coupling = motor_config.axis_coupling_matrix();
+
+        for row in 0..6 {
+            for col in 0..6 {
+                let value = coupling[(row, col)];
+
+                if row == col {
+                    assert!(value <= 1.0 + 0.0001);
+                    assert!((value - 1.0).abs() < 0.001);
+                } else {
+                    assert!(value.abs() < 0.001);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn motor_config_serde_roundtrip() {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let bytes = bincode::serialize(&motor_config).expect("Serialize");
+        let decoded: MotorConfig<X3dMotorId, f32> =
+            bincode::deserialize(&bytes).expect("Deserialize");
+        assert_eq!(motor_config, decoded);
+
+        let json = serde_json::to_string(&motor_config).expect("Serialize");
+        let decoded: MotorConfig<X3dMotorId, f32> =
+            serde_json::from_str(&json).expect("Deserialize");
+        assert_eq!(motor_config, decoded);
+
+        let disabled = motor_config.with_motor_disabled(&X3dMotorId::FrontLeftTop);
+        let bytes = bincode::serialize(&disabled).expect("Serialize");
+        let decoded: MotorConfig<X3dMotorId, f32> =
+            bincode::deserialize(&bytes).expect("Deserialize");
+        assert_eq!(disabled, decoded);
+
+        let weighted = MotorConfig::new_weighted(
+            motor_config.motors().map(|(id, motor)| (*id, *motor)),
+            vector![0.01, -0.02, 0.03],
+            [1.0, 1.0, 4.0, 1.0, 1.0, 1.0],
+        );
+        let json = serde_json::to_string(&weighted).expect("Serialize");
+        let decoded: MotorConfig<X3dMotorId, f32> =
+            serde_json::from_str(&json).expect("Deserialize");
+        assert_eq!(weighted, decoded);
+    }
+
+    #[test]
+    fn motor_config_serialized_size() {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let full = bincode::serialize(&(
+            &motor_config.motors,
+            &motor_config.matrix,
+            &motor_config.pseudo_inverse,
+            &motor_config.axis_weights,
+        ))
+        .expect("Serialize");
+        let compact = bincode::serialize(&motor_config).expect("Serialize");
+
+        assert!(compact.len() < full.len());
+    }
+
+    #[test]
+    fn motor_config_rejects_invalid_definitions() {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+        let json = serde_json::to_value(&motor_config).expect("Serialize");
+
+        // Non finite values would otherwise reach the SVD
+        let mut nan = json.clone();
+        nan["center_mass"] = serde_json::json!([null, 0.0, 0.0]);
+        assert!(serde_json::from_value::<MotorConfig<X3dMotorId, f32>>(nan).is_err());
+
+        let mut nan = motor_config.clone();
+        nan.center_mass.x = f32::NAN;
+        let bytes = bincode::serialize(&nan).expect("Serialize");
+        assert!(bincode::deserialize::<MotorConfig<X3dMotorId, f32>>(&bytes).is_err());
+
+        let mut negative = json;
+        negative["axis_weights"] = serde_json::json!([1.0, 1.0, -4.0, 1.0, 1.0, 1.0]);
+        assert!(serde_json::from_value::<MotorConfig<X3dMotorId, f32>>(negative).is_err());
+    }
+
+    #[test]
+    fn try_new_raw_rejects_degenerate_configs() {
+        let seed = fixtures::seed_motor(vector![0.3, 0.5, 0.4]);
+        let motors = fixtures::x3d_config(vector![0.3, 0.5, 0.4])
+            .motors()
+            .map(|(id, motor)| (*id, *motor))
+            .collect::<Vec<_>>();
+
+        assert!(MotorConfig::try_new_raw(motors.clone(), Vector3::default()).is_ok());
+
+        // Every motor pushes horizontally from the same plane as the center of mass, nothing can heave, pitch or roll
+        let flat = motors.iter().map(|(id, motor)| {
+            let mut motor = *motor;
+            motor.position.z = 0.0;
+            motor.orientation.z = 0.0;
+            motor.orientation = motor.orientation.normalize();
+
+            (*id, motor)
+        });
+
+        let err = MotorConfig::try_new_raw(flat, Vector3::default()).expect_err("Flat config");
+        assert_eq!(
+            err,
+            MotorConfigError::UncontrollableAxes {
+                rank: 3,
+                axes: vec![Axis::Z, Axis::XRot, Axis::YRot],
+            }
+        );
+
+        // A single motor can only control the movement it directly produces
+        let single = [(X3dMotorId::FrontRightTop, seed)];
+        let err = MotorConfig::try_new_raw(single, Vector3::default()).expect_err("Single motor");
+        let MotorConfigError::UncontrollableAxes { rank, axes } = err;
+        assert_eq!(rank, 1);
+        assert_eq!(axes.len(), 6);
+    }
+}
@@ -1,36 +1,577 @@
-use std::path::Path;
+use std::{fmt::Debug, hash::Hash, path::Path};
 
-use anyhow::Context;
-use serde::Deserialize;
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use stable_hashmap::StableHashMap;
 use tracing::instrument;
 
 use crate::{Direction, Number};
 
+/// Motor performance data, optionally measured at several supply voltages
 pub struct MotorData {
+    /// Sorted by voltage
+    tables: Vec<MotorTable>,
+    /// The voltage used by lookups that dont specify one
+    default_voltage: f32,
+}
+
+/// Provides the performance data for each motor, allowing motors with different thruster models to be solved together
+pub trait MotorDataSource<MotorId> {
+    fn motor_data(&self, motor: &MotorId) -> &MotorData;
+}
+
+/// Every motor uses the same thruster model
+impl<MotorId> MotorDataSource<MotorId> for MotorData {
+    fn motor_data(&self, _motor: &MotorId) -> &MotorData {
+        self
+    }
+}
+
+impl<MotorId: Hash + Eq + Debug> MotorDataSource<MotorId> for StableHashMap<MotorId, MotorData> {
+    fn motor_data(&self, motor: &MotorId) -> &MotorData {
+        self.get(motor)
+            .unwrap_or_else(|| panic!("No motor data for motor {motor:?}"))
+    }
+}
+
+struct MotorTable {
+    voltage: f32,
+
     force_index: Vec<MotorRecord<f32>>,
     current_index: Vec<MotorRecord<f32>>,
+    rpm_index: Vec<MotorRecord<f32>>,
+    pwm_index: Vec<MotorRecord<f32>>,
+
+    // Derivatives of each record with respect to the index's key, used by cubic interpolation
+    force_tangents: Vec<MotorRecord<f32>>,
+    current_tangents: Vec<MotorRecord<f32>>,
+    rpm_tangents: Vec<MotorRecord<f32>>,
+    pwm_tangents: Vec<MotorRecord<f32>>,
 }
 
 impl MotorData {
+    /// Creates a `MotorData` from tables measured at different voltages
+    ///
+    /// The first table's voltage is used by lookups that dont specify a voltage
+    pub fn from_tables(tables: Vec<(f32, Vec<MotorRecord<f32>>)>) -> anyhow::Result<Self> {
+        let Some(&(default_voltage, _)) = tables.first() else {
+            bail!("No motor data tables provided");
+        };
+
+        let mut tables = tables
+            .into_iter()
+            .map(|(voltage, records)| MotorTable::new(voltage, records))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        tables.sort_by(|a, b| f32::total_cmp(&a.voltage, &b.voltage));
+
+        Ok(Self {
+            tables,
+            default_voltage,
+        })
+    }
+
+    /// Creates a `MotorData` from a single table, measured at the mean voltage of its records
+    pub fn from_records(records: Vec<MotorRecord<f32>>) -> anyhow::Result<Self> {
+        if records.is_empty() {
+            bail!("No motor records provided");
+        }
+
+        let voltage = records.iter().map(|it| it.voltage).sum::<f32>() / records.len() as f32;
+
+        Self::from_tables(vec![(voltage, records)])
+    }
+
+    /// Creates made up data where force, current and rpm all scale linearly with pwm,
+    /// for tests that should not depend on a measured table
+    ///
+    /// The motor produces no force at the center of `pwm_range`, and `max_force` and `max_current` at its ends.
+    /// Direction aware lookups mirror pwm around 1500, so ranges centered elsewhere only work with `Clockwise` motors
+    pub fn synthetic_linear(max_force: f32, max_current: f32, pwm_range: (f32, f32)) -> Self {
+        const STEPS_PER_SIDE: usize = 100;
+        const VOLTAGE: f32 = 12.0;
+        const MAX_RPM: f32 = 3000.0;
+
+        assert!(max_force > 0.0, "max_force must be positive");
+
+        let (min_pwm, max_pwm) = pwm_range;
+
+        let records = (0..=2 * STEPS_PER_SIDE)
+            .map(|step| {
+                // -1 at `min_pwm`, 1 at `max_pwm`
+                let ratio = step as f32 / STEPS_PER_SIDE as f32 - 1.0;
+
+                let force = ratio * max_force;
+                let current = ratio.abs() * max_current;
+                let power = current * VOLTAGE;
+                // Grams per watt, like the measured tables
+                let efficiency = if power > 0.0 {
+                    force.abs() / 9.80665 * 1000.0 / power
+                } else {
+                    0.0
+                };
+
+                MotorRecord {
+                    pwm: min_pwm + (max_pwm - min_pwm) * (ratio + 1.0) / 2.0,
+                    rpm: ratio.abs() * MAX_RPM,
+                    current,
+                    voltage: VOLTAGE,
+                    power,
+                    force,
+                    efficiency,
+                }
+            })
+            .collect();
+
+        Self::from_records(records).expect("Synthetic motor data is valid")
+    }
+
+    /// Starts building a `MotorData` that can preprocess its tables, see `MotorDataBuilder`
+    pub fn builder() -> MotorDataBuilder {
+        MotorDataBuilder::default()
+    }
+
+    pub fn default_voltage(&self) -> f32 {
+        self.default_voltage
+    }
+
+    /// Changes the voltage used by lookups that dont specify one, usually to the measured battery voltage
+    ///
+    /// This lets code that only knows about `MotorDataSource` follow the battery as it sags.
+    /// Voltages that are not finite or not positive are ignored
+    pub fn set_default_voltage(&mut self, voltage: f32) {
+        if voltage.is_finite() && voltage > 0.0 {
+            self.default_voltage = voltage;
+        }
+    }
+
+    /// The lowest and highest voltage motor data was measured at
+    pub fn voltage_range(&self) -> (f32, f32) {
+        let min = self.tables[0].voltage;
+        let max = self.tables[self.tables.len() - 1].voltage;
+
+        (min, max)
+    }
+
     #[instrument(level = "trace", skip(self), ret)]
     pub fn lookup_by_force<D: Number>(
         &self,
         force: D,
         interpolation: Interpolation,
     ) -> MotorRecord<D> {
+        self.lookup_by_force_at_voltage(force, self.default_voltage, interpolation)
+    }
+
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn lookup_by_current<D: Number>(
+        &self,
+        signed_current: D,
+        interpolation: Interpolation,
+    ) -> MotorRecord<D> {
+        self.lookup_by_current_at_voltage(signed_current, self.default_voltage, interpolation)
+    }
+
+    /// Looks up `force` in the two tables closest to `voltage` and interpolates between them
+    ///
+    /// Voltages outside of the measured range are clamped
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn lookup_by_force_at_voltage<D: Number>(
+        &self,
+        force: D,
+        voltage: f32,
+        interpolation: Interpolation,
+    ) -> MotorRecord<D> {
+        self.lookup_at_voltage(voltage, |table| table.lookup_by_force(force, interpolation))
+    }
+
+    /// Looks up `signed_current` in the two tables closest to `voltage` and interpolates between them
+    ///
+    /// Voltages outside of the measured range are clamped
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn lookup_by_current_at_voltage<D: Number>(
+        &self,
+        signed_current: D,
+        voltage: f32,
+        interpolation: Interpolation,
+    ) -> MotorRecord<D> {
+        self.lookup_at_voltage(voltage, |table| {
+            table.lookup_by_current(signed_current, interpolation)
+        })
+    }
+
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn lookup_by_rpm<D: Number>(
+        &self,
+        signed_rpm: D,
+        interpolation: Interpolation,
+    ) -> MotorRecord<D> {
+        self.lookup_by_rpm_at_voltage(signed_rpm, self.default_voltage, interpolation)
+    }
+
+    /// Looks up `signed_rpm` in the two tables closest to `voltage` and interpolates between them
+    ///
+    /// Voltages outside of the measured range are clamped
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn lookup_by_rpm_at_voltage<D: Number>(
+        &self,
+        signed_rpm: D,
+        voltage: f32,
+        interpolation: Interpolation,
+    ) -> MotorRecord<D> {
+        self.lookup_at_voltage(voltage, |table| {
+            table.lookup_by_rpm(signed_rpm, interpolation)
+        })
+    }
+
+    /// Looks up the record a motor commanded with `pwm` runs at
+    ///
+    /// Direction aware interpolations expect `pwm` to already be adjusted for the propeller's direction,
+    /// like the pwm returned by the other lookups
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn lookup_by_pwm<D: Number>(&self, pwm: D, interpolation: Interpolation) -> MotorRecord<D> {
+        self.lookup_by_pwm_at_voltage(pwm, self.default_voltage, interpolation)
+    }
+
+    /// Looks up `pwm` in the two tables closest to `voltage` and interpolates between them
+    ///
+    /// Voltages outside of the measured range are clamped
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn lookup_by_pwm_at_voltage<D: Number>(
+        &self,
+        pwm: D,
+        voltage: f32,
+        interpolation: Interpolation,
+    ) -> MotorRecord<D> {
+        self.lookup_at_voltage(voltage, |table| table.lookup_by_pwm(pwm, interpolation))
+    }
+
+    /// The smallest and largest force present in the performance table
+    ///
+    /// Forces outside of this range can not be produced by the motor
+    pub fn force_range(&self) -> (f32, f32) {
+        self.force_range_at_voltage(self.default_voltage)
+    }
+
+    /// Like `force_range` but for the tables closest to `voltage`
+    pub fn force_range_at_voltage(&self, voltage: f32) -> (f32, f32) {
+        let (a, b, alpha) = self.voltage_bracket(voltage);
+        let (min_a, max_a) = a.force_range();
+        let (min_b, max_b) = b.force_range();
+
+        (
+            min_a + (min_b - min_a) * alpha,
+            max_a + (max_b - max_a) * alpha,
+        )
+    }
+
+    /// The pwm range covered by the tables used by lookups that dont specify a voltage
+    ///
+    /// Lookups outside of this range extrapolate. The range is for a clockwise propeller,
+    /// counter clockwise motors use the range mirrored around 1500
+    pub fn pwm_range(&self) -> (f32, f32) {
+        let (a, b, _) = self.voltage_bracket(self.default_voltage);
+        let (min_a, max_a) = a.pwm_range();
+        let (min_b, max_b) = b.pwm_range();
+
+        (min_a.max(min_b), max_a.min(max_b))
+    }
+
+    fn lookup_at_voltage<D: Number>(
+        &self,
+        voltage: f32,
+        lookup: impl Fn(&MotorTable) -> MotorRecord<D>,
+    ) -> MotorRecord<D> {
+        let (a, b, alpha) = self.voltage_bracket(voltage);
+
+        if alpha == 0.0 {
+            lookup(a)
+        } else {
+            lookup(a).lerp_voltage(&lookup(b), alpha)
+        }
+    }
+
+    /// Finds the two tables surrounding `voltage` and how far `voltage` is between them
+    fn voltage_bracket(&self, voltage: f32) -> (&MotorTable, &MotorTable, f32) {
+        let partition_point = self.tables.partition_point(|x| x.voltage < voltage);
+
+        if partition_point == 0 {
+            let table = &self.tables[0];
+            return (table, table, 0.0);
+        }
+        if partition_point == self.tables.len() {
+            let table = &self.tables[self.tables.len() - 1];
+            return (table, table, 0.0);
+        }
+
+        let a = &self.tables[partition_point - 1];
+        let b = &self.tables[partition_point];
+        let alpha = (voltage - a.voltage) / (b.voltage - a.voltage);
+
+        (a, b, alpha)
+    }
+}
+
+/// Builds a `MotorData`, optionally smoothing the measured tables first
+#[derive(Debug, Clone, Default)]
+pub struct MotorDataBuilder {
+    tables: Vec<(f32, Vec<MotorRecord<f32>>)>,
+    smooth_window: Option<usize>,
+}
+
+impl MotorDataBuilder {
+    /// Adds a table measured at `voltage`, the first table's voltage is used by lookups that dont specify one
+    pub fn table(mut self, voltage: f32, records: Vec<MotorRecord<f32>>) -> Self {
+        self.tables.push((voltage, records));
+        self
+    }
+
+    /// Smooths every table with a Savitzky-Golay filter of this many records, must be 5, 7 or 9
+    ///
+    /// Removes measurement noise that would otherwise make interpolated values non-monotone
+    pub fn smooth_window(mut self, smooth_window: Option<usize>) -> Self {
+        self.smooth_window = smooth_window;
+        self
+    }
+
+    pub fn build(self) -> anyhow::Result<MotorData> {
+        let tables = match self.smooth_window {
+            Some(window) => self
+                .tables
+                .into_iter()
+                .map(|(voltage, records)| {
+                    savitzky_golay(records, window)
+                        .with_context(|| format!("Smooth motor data for {voltage}V"))
+                        .map(|records| (voltage, records))
+                })
+                .collect::<anyhow::Result<_>>()?,
+            None => self.tables,
+        };
+
+        MotorData::from_tables(tables)
+    }
+}
+
+/// Quadratic Savitzky-Golay coefficients, scaled by `norm`
+///
+/// `rows[p]` evaluates the fit at the `p`th record of the window, with the last row being the center.
+/// The rows for the right half of the window are the left half's reversed
+struct SavitzkyGolay {
+    norm: f32,
+    rows: &'static [&'static [f32]],
+}
+
+const SAVITZKY_GOLAY_5: SavitzkyGolay = SavitzkyGolay {
+    norm: 35.0,
+    rows: &[
+        &[31.0, 9.0, -3.0, -5.0, 3.0],
+        &[9.0, 13.0, 12.0, 6.0, -5.0],
+        &[-3.0, 12.0, 17.0, 12.0, -3.0],
+    ],
+};
+
+const SAVITZKY_GOLAY_7: SavitzkyGolay = SavitzkyGolay {
+    norm: 42.0,
+    rows: &[
+        &[32.0, 15.0, 3.0, -4.0, -6.0, -3.0, 5.0],
+        &[15.0, 12.0, 9.0, 6.0, 3.0, 0.0, -3.0],
+        &[3.0, 9.0, 12.0, 12.0, 9.0, 3.0, -6.0],
+        &[-4.0, 6.0, 12.0, 14.0, 12.0, 6.0, -4.0],
+    ],
+};
+
+const SAVITZKY_GOLAY_9: SavitzkyGolay = SavitzkyGolay {
+    norm: 2310.0,
+    rows: &[
+        &[
+            1526.0, 882.0, 378.0, 14.0, -210.0, -294.0, -238.0, -42.0, 294.0,
+        ],
+        &[882.0, 644.0, 441.0, 273.0, 140.0, 42.0, -21.0, -49.0, -42.0],
+        &[
+            378.0, 441.0, 464.0, 447.0, 390.0, 293.0, 156.0, -21.0, -238.0,
+        ],
+        &[14.0, 273.0, 447.0, 536.0, 540.0, 459.0, 293.0, 42.0, -294.0],
+        &[
+            -210.0, 140.0, 390.0, 540.0, 590.0, 540.0, 390.0, 140.0, -210.0,
+        ],
+    ],
+};
+
+/// Smooths every measured field of `records` along pwm, the records near the ends use an off center fit
+#[instrument(level = "trace", skip(records))]
+fn savitzky_golay(
+    mut records: Vec<MotorRecord<f32>>,
+    window: usize,
+) -> anyhow::Result<Vec<MotorRecord<f32>>> {
+    let filter = match window {
+        5 => SAVITZKY_GOLAY_5,
+        7 => SAVITZKY_GOLAY_7,
+        9 => SAVITZKY_GOLAY_9,
+        _ => bail!("Unsupported smoothing window {window}, expected 5, 7 or 9"),
+    };
+
+    if records.len() < window {
+        bail!(
+            "Smoothing window {window} is larger than the table's {} records",
+            records.len()
+        );
+    }
+
+    records.sort_by(|a, b| f32::total_cmp(&a.pwm, &b.pwm));
+
+    let half = window / 2;
+    let len = records.len();
+
+    let smoothed = (0..len)
+        .map(|idx| {
+            // Which row of coefficients to use, and whether it is mirrored for the right end of the table
+            let (start, row, mirrored) = if idx < half {
+                (0, filter.rows[idx], false)
+            } else if idx >= len - half {
+                (len - window, filter.rows[len - 1 - idx], true)
+            } else {
+                (idx - half, filter.rows[half], false)
+            };
+
+            let smooth = |field: fn(&MotorRecord<f32>) -> f32| {
+                records[start..start + window]
+                    .iter()
+                    .enumerate()
+                    .map(|(offset, record)| {
+                        let coefficient = if mirrored {
+                            row[window - 1 - offset]
+                        } else {
+                            row[offset]
+                        };
+
+                        coefficient * field(record)
+                    })
+                    .sum::<f32>()
+                    / filter.norm
+            };
+
+            let record = &records[idx];
+
+            MotorRecord {
+                pwm: record.pwm,
+                // Fitting a curve through the deadband can undershoot zero
+                rpm: smooth(|it| it.rpm).max(0.0),
+                current: smooth(|it| it.current).max(0.0),
+                voltage: record.voltage,
+                power: smooth(|it| it.power).max(0.0),
+                force: smooth(|it| it.force),
+                efficiency: smooth(|it| it.efficiency).max(0.0),
+            }
+        })
+        .collect();
+
+    Ok(smoothed)
+}
+
+impl MotorTable {
+    fn new(voltage: f32, value: Vec<MotorRecord<f32>>) -> anyhow::Result<Self> {
+        if value.len() < 2 {
+            bail!("Motor data table at {voltage}V needs at least two records");
+        }
+
+        let mut force_index = value.clone();
+
+        force_index.sort_by(|a, b| f32::total_cmp(&a.force, &b.force));
+        force_index.dedup_by_key(|it| it.force);
+
+        let mut current_index = value.clone();
+
+        current_index.sort_by(|a, b| {
+            f32::total_cmp(&a.current.copysign(a.force), &b.current.copysign(b.force))
+        });
+        current_index.dedup_by_key(|it| it.current.copysign(it.force));
+
+        let mut rpm_index = value.clone();
+
+        rpm_index
+            .sort_by(|a, b| f32::total_cmp(&a.rpm.copysign(a.force), &b.rpm.copysign(b.force)));
+        rpm_index.dedup_by_key(|it| it.rpm.copysign(it.force));
+
+        let mut pwm_index = value.clone();
+
+        pwm_index.sort_by(|a, b| f32::total_cmp(&a.pwm, &b.pwm));
+        pwm_index.dedup_by_key(|it| it.pwm);
+
+        let force_tangents = Self::tangents(&force_index, |it| it.force);
+        let current_tangents = Self::tangents(&current_index, |it| it.current.copysign(it.force));
+        let rpm_tangents = Self::tangents(&rpm_index, |it| it.rpm.copysign(it.force));
+        let pwm_tangents = Self::tangents(&pwm_index, |it| it.pwm);
+
+        Ok(Self {
+            voltage,
+            force_index,
+            current_index,
+            rpm_index,
+            pwm_index,
+            force_tangents,
+            current_tangents,
+            rpm_tangents,
+            pwm_tangents,
+        })
+    }
+
+    /// Catmull-Rom tangents of every field with respect to `key`, one sided at the ends of the table
+    fn tangents(
+        index: &[MotorRecord<f32>],
+        key: impl Fn(&MotorRecord<f32>) -> f32,
+    ) -> Vec<MotorRecord<f32>> {
+        (0..index.len())
+            .map(|idx| {
+                let prev = &index[idx.saturating_sub(1)];
+                let next = &index[(idx + 1).min(index.len() - 1)];
+                let key_delta = key(next) - key(prev);
+
+                let slope =
+                    |field: fn(&MotorRecord<f32>) -> f32| (field(next) - field(prev)) / key_delta;
+
+                MotorRecord {
+                    pwm: slope(|it| it.pwm),
+                    rpm: slope(|it| it.rpm),
+                    current: slope(|it| it.current),
+                    voltage: slope(|it| it.voltage),
+                    power: slope(|it| it.power),
+                    force: slope(|it| it.force),
+                    efficiency: slope(|it| it.efficiency),
+                }
+            })
+            .collect()
+    }
+
+    fn force_range(&self) -> (f32, f32) {
+        let min = self.force_index.first().map(|it| it.force).unwrap_or(0.0);
+        let max = self.force_index.last().map(|it| it.force).unwrap_or(0.0);
+
+        (min, max)
+    }
+
+    fn pwm_range(&self) -> (f32, f32) {
+        let min = self.pwm_index.first().map(|it| it.pwm).unwrap_or(0.0);
+        let max = self.pwm_index.last().map(|it| it.pwm).unwrap_or(0.0);
+
+        (min, max)
+    }
+
+    fn lookup_by_force<D: Number>(&self, force: D, interpolation: Interpolation) -> MotorRecord<D> {
         let partition_point = self.force_index.partition_point(|x| x.force < force.re());
 
         let idx_b = partition_point.max(1).min(self.force_index.len() - 1);
         let idx_a = idx_b - 1;
 
-        let a = &self.force_index[idx_a];
-        let b = &self.force_index[idx_b];
-
-        Self::interpolate(a, b, force, a.force, b.force, interpolation)
+        Self::interpolate(
+            &self.force_index,
+            &self.force_tangents,
+            idx_a,
+            force,
+            |it| it.force,
+            interpolation,
+        )
     }
 
-    #[instrument(level = "trace", skip(self), ret)]
-    pub fn lookup_by_current<D: Number>(
+    fn lookup_by_current<D: Number>(
         &self,
         signed_current: D,
         interpolation: Interpolation,
@@ -42,32 +583,89 @@ impl MotorData {
         let idx_b = partition_point.max(1).min(self.current_index.len() - 1);
         let idx_a = idx_b - 1;
 
-        let a = &self.current_index[idx_a];
-        let b = &self.current_index[idx_b];
-
         Self::interpolate(
-            a,
-            b,
+            &self.current_index,
+            &self.current_tangents,
+            idx_a,
             signed_current,
-            a.current.copysign(a.force),
-            b.current.copysign(b.force),
+            |it| it.current.copysign(it.force),
+            interpolation,
+        )
+    }
+
+    fn lookup_by_rpm<D: Number>(
+        &self,
+        signed_rpm: D,
+        interpolation: Interpolation,
+    ) -> MotorRecord<D> {
+        let partition_point = self
+            .rpm_index
+            .partition_point(|x| x.rpm.copysign(x.force) < signed_rpm.re());
+
+        let idx_b = partition_point.max(1).min(self.rpm_index.len() - 1);
+        let idx_a = idx_b - 1;
+
+        Self::interpolate(
+            &self.rpm_index,
+            &self.rpm_tangents,
+            idx_a,
+            signed_rpm,
+            |it| it.rpm.copysign(it.force),
+            interpolation,
+        )
+    }
+
+    fn lookup_by_pwm<D: Number>(&self, pwm: D, interpolation: Interpolation) -> MotorRecord<D> {
+        // The table is measured with a clockwise propeller, `interpolate` flips the result's pwm back
+        let pwm = match interpolation.direction() {
+            Some(Direction::CounterClockwise) => D::from(3000.0) - pwm,
+            _ => pwm,
+        };
+
+        let partition_point = self.pwm_index.partition_point(|x| x.pwm < pwm.re());
+
+        let idx_b = partition_point.max(1).min(self.pwm_index.len() - 1);
+        let idx_a = idx_b - 1;
+
+        Self::interpolate(
+            &self.pwm_index,
+            &self.pwm_tangents,
+            idx_a,
+            pwm,
+            |it| it.pwm,
             interpolation,
         )
     }
 
+    /// Interpolates between `index[idx_a]` and `index[idx_a + 1]`, `key` extracts the value the index is sorted by
     fn interpolate<D: Number>(
-        a: &MotorRecord<f32>,
-        b: &MotorRecord<f32>,
+        index: &[MotorRecord<f32>],
+        tangents: &[MotorRecord<f32>],
+        idx_a: usize,
         value: D,
-        value_a: f32,
-        value_b: f32,
+        key: impl Fn(&MotorRecord<f32>) -> f32,
         interpolation: Interpolation,
     ) -> MotorRecord<D> {
+        let a = &index[idx_a];
+        let b = &index[idx_a + 1];
+        let value_a = key(a);
+        let value_b = key(b);
+
         let record = match interpolation {
             Interpolation::LerpDirection(_) | Interpolation::Lerp => {
                 let alpha = (value - value_a) / (value_b - value_a);
                 a.lerp(b, alpha)
             }
+            Interpolation::CatmullRomDirection(_) | Interpolation::CatmullRom => {
+                let alpha = (value - value_a) / (value_b - value_a);
+
+                MotorRecord::hermite(
+                    [a, b],
+                    [&tangents[idx_a], &tangents[idx_a + 1]],
+                    value_b - value_a,
+                    alpha,
+                )
+            }
             Interpolation::Direction(_) | Interpolation::OriginalData => {
                 let dist_a = (value_a - value.re()).abs();
                 let dist_b = (value_b - value.re()).abs();
@@ -86,39 +684,13 @@ impl MotorData {
             }
         };
 
-        match interpolation {
-            Interpolation::LerpDirection(direction) | Interpolation::Direction(direction) => {
-                if let Direction::CounterClockwise = direction {
-                    MotorRecord {
-                        pwm: D::from(3000.0) - record.pwm,
-                        ..record
-                    }
-                } else {
-                    record
-                }
+        if let Some(Direction::CounterClockwise) = interpolation.direction() {
+            MotorRecord {
+                pwm: D::from(3000.0) - record.pwm,
+                ..record
             }
-            Interpolation::Lerp | Interpolation::OriginalData => record,
-        }
-    }
-}
-
-impl From<Vec<MotorRecord<f32>>> for MotorData {
-    fn from(value: Vec<MotorRecord<f32>>) -> Self {
-        let mut force_index = value.clone();
-
-        force_index.sort_by(|a, b| f32::total_cmp(&a.force, &b.force));
-        force_index.dedup_by_key(|it| it.force);
-
-        let mut current_index = value.clone();
-
-        current_index.sort_by(|a, b| {
-            f32::total_cmp(&a.current.copysign(a.force), &b.current.copysign(b.force))
-        });
-        current_index.dedup_by_key(|it| it.current.copysign(it.force));
-
-        Self {
-            force_index,
-            current_index,
+        } else {
+            record
         }
     }
 }
@@ -131,15 +703,35 @@ pub enum Interpolation {
     /// Return the raw data entry closest to the the requested data point
     /// Only modifies the pwm field to match the direction of the propeller
     Direction(Direction),
+    /// Return the catmull-rom spline between the two data entries closest to the the requested data point
+    /// and modifies the pwm field to match the direction of the propeller
+    /// The first derivative is continuous over the whole table, the ends of the data use one sided tangents
+    CatmullRomDirection(Direction),
     /// Return the linear interpolation betwwn the two data entries closest to the the requested data point
     #[default]
     Lerp,
+    /// Return the catmull-rom spline between the two data entries closest to the the requested data point
+    /// The first derivative is continuous over the whole table, the ends of the data use one sided tangents
+    CatmullRom,
     /// Return the raw data entry closest to the the requested data point
     /// Make no modifications to the data
     OriginalData,
 }
 
-#[derive(Deserialize, Debug, Clone, Copy)]
+impl Interpolation {
+    /// The propeller direction the pwm field is adjusted for, if any
+    fn direction(&self) -> Option<Direction> {
+        match *self {
+            Interpolation::LerpDirection(direction)
+            | Interpolation::CatmullRomDirection(direction)
+            | Interpolation::Direction(direction) => Some(direction),
+            Interpolation::Lerp | Interpolation::CatmullRom | Interpolation::OriginalData => None,
+        }
+    }
+}
+
+/// Like `Movement`, only serializable when `D` is, ie `f32`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct MotorRecord<D> {
     pub pwm: D,
     pub rpm: D,
@@ -167,11 +759,180 @@ impl<D1: Number> MotorRecord<D1> {
     }
 }
 
+impl<D: Number> MotorRecord<D> {
+    /// Lerps between records looked up in tables of different voltages
+    fn lerp_voltage(&self, other: &Self, alpha: f32) -> Self {
+        let lerp = |a: D, b: D| a * (1.0 - alpha) + b * alpha;
+
+        MotorRecord {
+            pwm: lerp(self.pwm, other.pwm),
+            rpm: lerp(self.rpm, other.rpm),
+            current: lerp(self.current, other.current),
+            voltage: lerp(self.voltage, other.voltage),
+            power: lerp(self.power, other.power),
+            force: lerp(self.force, other.force),
+            efficiency: lerp(self.efficiency, other.efficiency),
+        }
+    }
+}
+
+impl MotorRecord<f32> {
+    /// Evaluates the cubic hermite spline between `records[0]` and `records[1]`
+    ///
+    /// `tangents` are the derivatives at each record with respect to the key, `key_span` is the distance between the records' keys
+    fn hermite<D: Number>(
+        records: [&Self; 2],
+        tangents: [&Self; 2],
+        key_span: f32,
+        alpha: D,
+    ) -> MotorRecord<D> {
+        let spline = |field: fn(&Self) -> f32| {
+            let [p1, p2] = records.map(field);
+            let [m1, m2] = tangents.map(|it| field(it) * key_span);
+
+            let t = alpha;
+            let t2 = t * t;
+            let t3 = t2 * t;
+
+            (t3 * 2.0 - t2 * 3.0 + D::one()) * p1
+                + (t3 - t2 * 2.0 + t) * m1
+                + (t2 * 3.0 - t3 * 2.0) * p2
+                + (t3 - t2) * m2
+        };
+
+        MotorRecord {
+            pwm: spline(|it| it.pwm),
+            rpm: spline(|it| it.rpm),
+            current: spline(|it| it.current),
+            voltage: spline(|it| it.voltage),
+            power: spline(|it| it.power),
+            force: spline(|it| it.force),
+            efficiency: spline(|it| it.efficiency),
+        }
+    }
+}
+
+/// How a motor's current limit should be derated as it heats up
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ThermalParameters {
+    /// Current limit of a cold motor, in amps
+    pub max_current: f32,
+    /// Current limit of a fully derated motor, in amps
+    pub min_current: f32,
+    /// Stored heat, in joules, where derating begins
+    pub derate_start: f32,
+    /// Stored heat, in joules, where the motor is fully derated
+    pub derate_end: f32,
+    /// How quickly stored heat is shed, in seconds
+    ///
+    /// A motor drawing a constant power `P` settles at `P * cooling_time_constant` joules
+    pub cooling_time_constant: f32,
+}
+
+/// Estimates the heat built up in each motor from the power it draws and derates its current limit to match
+///
+/// This is advisory and is not used by the solvers,
+/// the limits are meant to be fed into `clamp_amperage_per_motor` by whatever runs the control loop
+#[derive(Debug, Clone)]
+pub struct ThermalModel<MotorId> {
+    parameters: ThermalParameters,
+    /// In joules
+    heat: StableHashMap<MotorId, f32>,
+}
+
+impl<MotorId: Hash + Eq + Clone> ThermalModel<MotorId> {
+    pub fn new(parameters: ThermalParameters) -> Self {
+        Self {
+            parameters,
+            heat: StableHashMap::default(),
+        }
+    }
+
+    /// Integrates the power drawn by each motor over the last `dt` seconds
+    ///
+    /// Motors missing from `motor_cmds` are treated as idle and only cool down
+    pub fn update<D: Number>(
+        &mut self,
+        dt: f32,
+        motor_cmds: &StableHashMap<MotorId, MotorRecord<D>>,
+    ) {
+        // Exact solution of `heat' = power - heat / tau` over the step, so large steps can not overshoot
+        let decay = (-dt / self.parameters.cooling_time_constant).exp();
+        let gain = self.parameters.cooling_time_constant * (1.0 - decay);
+
+        for heat in self.heat.values_mut() {
+            *heat *= decay;
+        }
+
+        for (motor_id, record) in motor_cmds {
+            let power = record.power.re().abs();
+            *self.heat.entry(motor_id.clone()).or_default() += power * gain;
+        }
+    }
+
+    /// The current the motor can sustain given its estimated heat
+    ///
+    /// Motors that have not been seen are assumed to be cold
+    pub fn current_limit(&self, motor_id: &MotorId) -> f32 {
+        let ThermalParameters {
+            max_current,
+            min_current,
+            derate_start,
+            derate_end,
+            ..
+        } = self.parameters;
+
+        let heat = self.heat.get(motor_id).copied().unwrap_or(0.0);
+        let derate = ((heat - derate_start) / (derate_end - derate_start)).clamp(0.0, 1.0);
+
+        max_current + (min_current - max_current) * derate
+    }
+
+    /// The current limit of every motor seen so far, in the form `clamp_amperage_per_motor` expects
+    pub fn current_limits(&self) -> StableHashMap<MotorId, f32> {
+        self.heat
+            .keys()
+            .map(|motor_id| (motor_id.clone(), self.current_limit(motor_id)))
+            .collect()
+    }
+
+    /// Estimated heat stored in the motor, in joules
+    pub fn heat(&self, motor_id: &MotorId) -> f32 {
+        self.heat.get(motor_id).copied().unwrap_or(0.0)
+    }
+
+    pub fn parameters(&self) -> &ThermalParameters {
+        &self.parameters
+    }
+}
+
 fn lerp<D: Number>(a: f32, b: f32, alpha: D) -> D {
     (D::one() - alpha) * a + alpha * b
 }
 
 pub fn read_motor_data<P: AsRef<Path>>(path: P) -> anyhow::Result<MotorData> {
+    let records = read_motor_records(path)?;
+
+    MotorData::from_records(records)
+}
+
+/// Reads a table of motor data for each voltage
+///
+/// The first table's voltage is used by lookups that dont specify a voltage
+pub fn read_motor_data_multi<P: AsRef<Path>>(tables: &[(f32, P)]) -> anyhow::Result<MotorData> {
+    let tables = tables
+        .iter()
+        .map(|(voltage, path)| {
+            read_motor_records(path)
+                .with_context(|| format!("Read motor data for {voltage}V"))
+                .map(|records| (*voltage, records))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    MotorData::from_tables(tables)
+}
+
+pub fn read_motor_records<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<MotorRecord<f32>>> {
     let csv = csv::Reader::from_path(path).context("Read data")?;
 
     let mut data = Vec::default();
@@ -180,5 +941,324 @@ pub fn read_motor_data<P: AsRef<Path>>(path: P) -> anyhow::Result<MotorData> {
         data.push(record);
     }
 
-    Ok(data.into())
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use num_dual::Dual32;
+
+    use crate::fixtures;
+
+    use super::*;
+
+    #[test]
+    fn motor_data_voltage_interpolation() {
+        let records = fixtures::motor_records();
+        let boosted = records
+            .iter()
+            .map(|record| MotorRecord {
+                force: record.force * 1.5,
+                ..*record
+            })
+            .collect();
+
+        let motor_data = MotorData::from_tables(vec![(12.0, records), (16.0, boosted)])
+            .expect("Build motor data");
+        assert_eq!(motor_data.default_voltage(), 12.0);
+
+        let interpolation = Interpolation::Lerp;
+        let low = motor_data.lookup_by_force_at_voltage(2.0f32, 12.0, interpolation);
+        let mid = motor_data.lookup_by_force_at_voltage(2.0f32, 14.0, interpolation);
+        let high = motor_data.lookup_by_force_at_voltage(2.0f32, 16.0, interpolation);
+        let clamped = motor_data.lookup_by_force_at_voltage(2.0f32, 30.0, interpolation);
+
+        assert!(low.pwm > mid.pwm && mid.pwm > high.pwm);
+        assert_eq!(high.pwm, clamped.pwm);
+        assert_eq!(
+            motor_data.lookup_by_force(2.0f32, interpolation).pwm,
+            low.pwm
+        );
+    }
+
+    #[test]
+    fn read_motor_data_multi_clamps_voltage() {
+        let motor_data = read_motor_data_multi(&[
+            (16.0, "../robot/motor_data.csv"),
+            (12.0, "../robot/motor_data.csv"),
+        ])
+        .expect("Read motor data");
+
+        assert_eq!(motor_data.default_voltage(), 16.0);
+        assert_eq!(motor_data.voltage_range(), (12.0, 16.0));
+
+        let interpolation = Interpolation::Lerp;
+        for voltage in [0.0, 12.0, 14.0, 16.0, 30.0] {
+            let record = motor_data.lookup_by_force_at_voltage(1.5f32, voltage, interpolation);
+            assert!((record.force - 1.5).abs() < 0.0001);
+            assert!(record.pwm.is_finite());
+        }
+
+        assert!(read_motor_data_multi::<&str>(&[]).is_err());
+    }
+
+    #[test]
+    fn motor_data_default_voltage() {
+        let records = fixtures::motor_records();
+        let boosted = records
+            .iter()
+            .map(|record| MotorRecord {
+                force: record.force * 1.5,
+                ..*record
+            })
+            .collect();
+
+        let mut motor_data = MotorData::from_tables(vec![(12.0, records), (16.0, boosted)])
+            .expect("Build motor data");
+        let interpolation = Interpolation::Lerp;
+
+        motor_data.set_default_voltage(14.0);
+        assert_eq!(motor_data.default_voltage(), 14.0);
+
+        for current in [-5.0f32, 2.0, 10.0] {
+            let sagged = motor_data.lookup_by_current(current, interpolation);
+            let explicit = motor_data.lookup_by_current_at_voltage(current, 14.0, interpolation);
+
+            assert_eq!(sagged.force, explicit.force);
+        }
+
+        for voltage in [0.0, -12.0, f32::NAN, f32::INFINITY] {
+            motor_data.set_default_voltage(voltage);
+            assert_eq!(motor_data.default_voltage(), 14.0);
+        }
+    }
+
+    #[test]
+    fn synthetic_linear_motor_data() {
+        let motor_data = MotorData::synthetic_linear(30.0, 20.0, (1100.0, 1900.0));
+        let interpolation = Interpolation::Lerp;
+
+        assert_eq!(motor_data.force_range(), (-30.0, 30.0));
+
+        for (force, pwm, current) in [
+            (-30.0, 1100.0, 20.0),
+            (0.0, 1500.0, 0.0),
+            (15.0, 1700.0, 10.0),
+        ] {
+            let record = motor_data.lookup_by_force(force, interpolation);
+
+            assert!((record.pwm - pwm).abs() < 0.01, "{force}: {}", record.pwm);
+            assert!(
+                (record.current - current).abs() < 0.01,
+                "{force}: {}",
+                record.current
+            );
+        }
+    }
+
+    #[test]
+    fn smoothed_force_index_is_monotone() {
+        let records = fixtures::motor_records();
+
+        // The raw table's force dips at both ends
+        let raw = MotorData::from_records(records.clone()).expect("Build motor data");
+        let is_monotone = |table: &MotorTable| {
+            table
+                .force_index
+                .windows(2)
+                .all(|pair| pair[0].force < pair[1].force && pair[0].pwm < pair[1].pwm)
+        };
+        assert!(!is_monotone(&raw.tables[0]));
+
+        for window in [7, 9] {
+            let smoothed = MotorData::builder()
+                .table(12.0, records.clone())
+                .smooth_window(Some(window))
+                .build()
+                .expect("Build smoothed motor data");
+
+            let table = &smoothed.tables[0];
+            assert_eq!(table.force_index.len(), records.len());
+            assert!(is_monotone(table), "window {window}");
+
+            // Smoothing should not noticeably change the data
+            for (smoothed, raw) in table.pwm_index.iter().zip(&raw.tables[0].pwm_index) {
+                assert_eq!(smoothed.pwm, raw.pwm);
+                assert!(
+                    (smoothed.force - raw.force).abs() < 0.5,
+                    "{smoothed:?} {raw:?}"
+                );
+            }
+        }
+
+        let build = |window| {
+            MotorData::builder()
+                .table(12.0, records[..6].to_vec())
+                .smooth_window(Some(window))
+                .build()
+        };
+        assert!(build(5).is_ok());
+        assert!(build(6).is_err());
+        assert!(build(7).is_err());
+    }
+
+    #[test]
+    fn motor_data_rejects_short_tables() {
+        let records = fixtures::motor_records();
+
+        assert!(MotorData::from_records(vec![]).is_err());
+        assert!(MotorData::from_records(records[..1].to_vec()).is_err());
+        assert!(MotorData::from_records(records).is_ok());
+    }
+
+    #[test]
+    fn catmull_rom_interpolation() {
+        let records = fixtures::motor_records();
+        let motor_data = MotorData::from_records(records.clone()).expect("Build motor data");
+
+        let cubic = Interpolation::CatmullRom;
+        let linear = Interpolation::Lerp;
+
+        for record in &records {
+            let interpolated = motor_data.lookup_by_force(record.force, cubic);
+            assert!((interpolated.force - record.force).abs() < 0.001);
+        }
+
+        for force in [-2.5f32, -0.05, 0.05, 2.5] {
+            let interpolated = motor_data.lookup_by_force(force, cubic);
+            let lerped = motor_data.lookup_by_force(force, linear);
+
+            assert!((interpolated.force - force).abs() < 0.001);
+            assert!((interpolated.pwm - lerped.pwm).abs() < 10.0);
+        }
+    }
+
+    #[test]
+    fn catmull_rom_continuous_derivative() {
+        let records = fixtures::motor_records();
+        let motor_data = MotorData::from_records(records.clone()).expect("Build motor data");
+
+        let cubic = Interpolation::CatmullRom;
+
+        // Duplicate forces are dropped from the table, only the first record with each force is kept
+        let mut knots: Vec<MotorRecord<f32>> = Vec::new();
+        for record in &records {
+            if knots.iter().all(|it| it.force != record.force) {
+                knots.push(*record);
+            }
+        }
+        knots.sort_by(|a, b| a.force.total_cmp(&b.force));
+
+        // Exactly at a knot the segment to its left is used, the next float up is in the segment to its right
+        let slope = |force: f32| {
+            motor_data
+                .lookup_by_force(Dual32::new(force, 1.0), cubic)
+                .pwm
+                .eps
+        };
+
+        for window in knots.windows(3) {
+            let [prev, knot, next] = window else {
+                unreachable!()
+            };
+
+            let tangent = (next.pwm - prev.pwm) / (next.force - prev.force);
+            let left = slope(knot.force);
+            let right = slope(knot.force.next_up());
+
+            let tolerance = tangent.abs() * 1e-3 + 1e-3;
+            assert!(
+                (left - tangent).abs() < tolerance,
+                "{}: {left} vs {tangent}",
+                knot.force
+            );
+            assert!(
+                (right - tangent).abs() < tolerance,
+                "{}: {right} vs {tangent}",
+                knot.force
+            );
+        }
+    }
+
+    #[test]
+    fn motor_record_serde_roundtrip() {
+        let records = fixtures::motor_records();
+
+        for record in records {
+            let json = serde_json::to_string(&record).expect("Serialize");
+            let decoded: MotorRecord<f32> = serde_json::from_str(&json).expect("Deserialize");
+
+            assert_eq!(format!("{record:?}"), format!("{decoded:?}"));
+        }
+    }
+
+    #[test]
+    fn lookup_by_rpm_roundtrip() {
+        let motor_data = fixtures::motor_data();
+
+        for force in [-20.0f32, -5.0, 3.0, 25.0] {
+            let interpolation = Interpolation::Lerp;
+
+            let by_force = motor_data.lookup_by_force(force, interpolation);
+            let signed_rpm = by_force.rpm.copysign(by_force.force);
+            let by_rpm = motor_data.lookup_by_rpm(signed_rpm, interpolation);
+
+            assert!((by_rpm.pwm - by_force.pwm).abs() < 5.0);
+            assert!((by_rpm.force - by_force.force).abs() < 0.5);
+        }
+    }
+
+    #[test]
+    fn thermal_model_derates_sustained_load() {
+        let parameters = ThermalParameters {
+            max_current: 20.0,
+            min_current: 5.0,
+            derate_start: 1000.0,
+            derate_end: 2000.0,
+            cooling_time_constant: 10.0,
+        };
+        let mut model = ThermalModel::new(parameters);
+
+        let record = MotorRecord {
+            pwm: 1800.0f32,
+            rpm: 3000.0,
+            current: 15.0,
+            voltage: 12.0,
+            power: 180.0,
+            force: 30.0,
+            efficiency: 0.1,
+        };
+        let cmds: StableHashMap<_, _> = [(0u8, record)].into_iter().collect();
+
+        assert_eq!(model.current_limit(&0), 20.0);
+
+        // Settles at 1800 J, most of the way to fully derated
+        for _ in 0..1000 {
+            model.update(0.1, &cmds);
+        }
+
+        assert!((model.heat(&0) - 1800.0).abs() < 1.0, "{}", model.heat(&0));
+        assert!((model.current_limit(&0) - 8.0).abs() < 0.1);
+        assert_eq!(
+            model.current_limits().get(&0),
+            Some(&model.current_limit(&0))
+        );
+
+        // Unseen motors are cold
+        assert_eq!(model.current_limit(&1), 20.0);
+
+        // A single large step must not overshoot the steady state
+        let mut coarse = ThermalModel::new(parameters);
+        coarse.update(1000.0, &cmds);
+        assert!((coarse.heat(&0) - 1800.0).abs() < 1.0);
+
+        // Idle motors cool back down
+        for _ in 0..1000 {
+            model.update::<f32>(0.1, &StableHashMap::default());
+        }
+
+        assert!(model.heat(&0) < 1.0);
+        assert_eq!(model.current_limit(&0), 20.0);
+    }
 }
@@ -0,0 +1,254 @@
+//! Searches for motor configs that maximize the achievable movement
+
+use nalgebra::Vector3;
+use num_dual::Dual32;
+use stable_hashmap::StableHashMap;
+use tracing::instrument;
+
+use crate::{
+    motor_preformance::MotorData,
+    solve::reverse::{forces_to_cmds, newton_raphson_force_ratio, reverse_solve, Axis},
+    utils::vec_from_angles,
+    x3d::X3dMotorId,
+    Direction, Motor, MotorConfig, Number,
+};
+
+const INITIAL_GUESS: f32 = 25.0;
+const EPSILON: f32 = 0.0001;
+
+const MAX_ITERATIONS: usize = 200;
+/// Radians, the first step taken along the gradient
+const INITIAL_STEP: f32 = 0.2;
+/// Radians, the search stops once steps get this small
+const MIN_STEP: f32 = 0.0001;
+
+/// How much each axis' maximum counts towards the objective being maximized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObjectiveWeights {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub x_rot: f32,
+    pub y_rot: f32,
+    pub z_rot: f32,
+}
+
+impl Default for ObjectiveWeights {
+    fn default() -> Self {
+        Self {
+            x: 1.0,
+            y: 1.0,
+            z: 1.0,
+            x_rot: 1.0,
+            y_rot: 1.0,
+            z_rot: 1.0,
+        }
+    }
+}
+
+impl ObjectiveWeights {
+    pub fn weight(&self, axis: Axis) -> f32 {
+        match axis {
+            Axis::X => self.x,
+            Axis::Y => self.y,
+            Axis::Z => self.z,
+            Axis::XRot => self.x_rot,
+            Axis::YRot => self.y_rot,
+            Axis::ZRot => self.z_rot,
+        }
+    }
+
+    /// Weighted sum of the axis maximums, as returned by `axis_maximums`
+    pub fn score<D: Number>(&self, maximums: &StableHashMap<Axis, D>) -> D {
+        maximums
+            .iter()
+            .map(|(axis, maximum)| *maximum * D::from(self.weight(*axis)))
+            .sum()
+    }
+}
+
+/// Finds the orientation of the seed motor of an X3d config that maximizes the weighted sum of
+/// the axis maximums, the position of the seed motor is left as is
+///
+/// The seed motor's position is relative to the center of mass. Gradients with respect to the
+/// orientation angles are exact, found by seeding a `Dual32` on each angle in turn.
+/// This is a local search, a different `initial` orientation may find a better config.
+#[instrument(level = "trace", skip(motor_data), ret)]
+pub fn optimize_x3d_seed(
+    initial: Motor<f32>,
+    motor_data: &MotorData,
+    amperage_cap: f32,
+    objective: ObjectiveWeights,
+) -> Motor<f32> {
+    let seed = |angles: (f32, f32)| Motor {
+        orientation: vec_from_angles(angles.0, angles.1),
+        ..initial
+    };
+
+    let mut angles = angles_from_vec(initial.orientation.normalize());
+    let mut value = x3d_objective(
+        initial.position,
+        angles_dual(angles, None),
+        initial.direction,
+        motor_data,
+        amperage_cap,
+        &objective,
+    )
+    .re;
+    let mut step = INITIAL_STEP;
+
+    for _ in 0..MAX_ITERATIONS {
+        let gradient = [0, 1].map(|idx| {
+            x3d_objective(
+                initial.position,
+                angles_dual(angles, Some(idx)),
+                initial.direction,
+                motor_data,
+                amperage_cap,
+                &objective,
+            )
+            .eps
+        });
+
+        let norm = gradient[0].hypot(gradient[1]);
+        if norm == 0.0 || !norm.is_finite() {
+            break;
+        }
+
+        // Backtrack along the gradient until the objective improves
+        loop {
+            let candidate = (
+                angles.0 + gradient[0] / norm * step,
+                angles.1 + gradient[1] / norm * step,
+            );
+            let candidate_value = x3d_objective(
+                initial.position,
+                angles_dual(candidate, None),
+                initial.direction,
+                motor_data,
+                amperage_cap,
+                &objective,
+            )
+            .re;
+
+            if candidate_value > value {
+                angles = candidate;
+                value = candidate_value;
+                step *= 1.5;
+                break;
+            }
+
+            step /= 2.0;
+            if step < MIN_STEP {
+                break;
+            }
+        }
+
+        if step < MIN_STEP {
+            break;
+        }
+    }
+
+    seed(angles)
+}
+
+/// Inverse of `vec_from_angles` for unit vectors
+fn angles_from_vec(vec: Vector3<f32>) -> (f32, f32) {
+    (vec.y.atan2(vec.x), vec.z.clamp(-1.0, 1.0).asin())
+}
+
+/// Seeds the derivative on the angle at `idx`, if any
+fn angles_dual(angles: (f32, f32), idx: Option<usize>) -> (Dual32, Dual32) {
+    let eps = |it| if idx == Some(it) { 1.0 } else { 0.0 };
+
+    (Dual32::new(angles.0, eps(0)), Dual32::new(angles.1, eps(1)))
+}
+
+fn x3d_objective<D: Number>(
+    position: Vector3<f32>,
+    angles: (D, D),
+    direction: Direction,
+    motor_data: &MotorData,
+    amperage_cap: f32,
+    objective: &ObjectiveWeights,
+) -> D {
+    let seed = Motor {
+        position: position.map(D::from),
+        orientation: vec_from_angles(angles.0, angles.1),
+        direction,
+    };
+    let motor_config = MotorConfig::<X3dMotorId, D>::new(seed, Vector3::zeros());
+
+    let maximums = Axis::ALL
+        .into_iter()
+        .map(|axis| {
+            (
+                axis,
+                axis_maximum(axis, &motor_config, motor_data, amperage_cap),
+            )
+        })
+        .collect();
+
+    objective.score(&maximums)
+}
+
+/// Like the maximums `axis_maximums` finds, but uses Newton's method since it carries the
+/// derivatives of the motor commands through to the result
+fn axis_maximum<D: Number>(
+    axis: Axis,
+    motor_config: &MotorConfig<X3dMotorId, D>,
+    motor_data: &MotorData,
+    amperage_cap: f32,
+) -> D {
+    let initial = D::from(INITIAL_GUESS);
+
+    let forces = reverse_solve(axis.movement::<D>() * initial, motor_config);
+    let cmds = forces_to_cmds(forces, motor_config, motor_data);
+    let ratio = newton_raphson_force_ratio(&cmds, motor_config, motor_data, amperage_cap, EPSILON);
+
+    ratio * initial
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::vector;
+
+    use crate::{fixtures, solve::reverse::axis_maximums};
+
+    use super::*;
+
+    #[test]
+    fn angles_round_trip() {
+        let angles = (0.7, -0.3);
+        let (xy, yz) = angles_from_vec(vec_from_angles(angles.0, angles.1));
+
+        assert!((xy - angles.0).abs() < 1e-5);
+        assert!((yz - angles.1).abs() < 1e-5);
+    }
+
+    #[test]
+    fn optimized_x3d_beats_initial() {
+        let motor_data = fixtures::motor_data();
+        let initial = fixtures::seed_motor(vector![0.3, 0.5, 0.4]);
+        let objective = ObjectiveWeights {
+            z: 2.0,
+            z_rot: 2.0,
+            ..Default::default()
+        };
+
+        let optimized = optimize_x3d_seed(initial, &motor_data, 20.0, objective);
+        assert_eq!(optimized.position, initial.position);
+        assert!((optimized.orientation.norm() - 1.0).abs() < 1e-5);
+
+        let score = |seed| {
+            let motor_config = MotorConfig::<X3dMotorId, f32>::new(seed, Vector3::zeros());
+            objective.score(&axis_maximums(&motor_config, &motor_data, 20.0, EPSILON))
+        };
+
+        let (initial_score, optimized_score) = (score(initial), score(optimized));
+        assert!(
+            optimized_score > initial_score,
+            "{optimized_score} <= {initial_score}"
+        );
+    }
+}
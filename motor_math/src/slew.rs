@@ -0,0 +1,221 @@
+//! Limits how quickly motor forces can change
+
+use std::{fmt::Debug, hash::Hash};
+
+use stable_hashmap::StableHashMap;
+
+use crate::{solve::forward, MotorConfig};
+
+type HashMap<K, V> = StableHashMap<K, V>;
+
+/// Remembers the last force sent to each motor and limits how far the next force can move from it
+#[derive(Debug, Clone)]
+pub struct SlewLimiter<MotorId> {
+    last_forces: HashMap<MotorId, f32>,
+    /// Stop at zero for one step before a motor reverses direction
+    pause_at_zero: bool,
+}
+
+impl<MotorId: Hash + Eq + Clone> SlewLimiter<MotorId> {
+    pub fn new(pause_at_zero: bool) -> Self {
+        Self {
+            last_forces: HashMap::default(),
+            pause_at_zero,
+        }
+    }
+
+    /// Moves each motor's force towards `new_forces` by at most `max_delta_per_sec * dt`
+    ///
+    /// Motors not seen in the previous call start from zero, motors missing from `new_forces` are forgotten
+    pub fn limit(
+        &mut self,
+        new_forces: HashMap<MotorId, f32>,
+        max_delta_per_sec: f32,
+        dt: f32,
+    ) -> HashMap<MotorId, f32> {
+        let max_delta = max_delta_per_sec * dt;
+
+        let limited: HashMap<_, _> = new_forces
+            .into_iter()
+            .map(|(motor_id, target)| {
+                let last = self.last_forces.get(&motor_id).copied().unwrap_or(0.0);
+
+                let mut force = last + (target - last).clamp(-max_delta, max_delta);
+
+                let crossed_zero = last != 0.0 && force != 0.0 && last.signum() != force.signum();
+                if self.pause_at_zero && crossed_zero {
+                    force = 0.0;
+                }
+
+                (motor_id, force)
+            })
+            .collect();
+
+        self.last_forces = limited.clone();
+
+        limited
+    }
+
+    /// Like `limit`, but limits how fast the movement produced by the motors changes along each
+    /// axis rather than how fast each motor's force changes
+    ///
+    /// `max_delta_per_sec` is indexed in `Axis::ALL` order, in newtons per second for translation
+    /// and newton meters per second for rotation. Every motor's step is scaled by the same factor
+    /// so the direction of the change in movement is kept
+    pub fn limit_axes(
+        &mut self,
+        new_forces: HashMap<MotorId, f32>,
+        motor_config: &MotorConfig<MotorId, f32>,
+        max_delta_per_sec: [f32; 6],
+        dt: f32,
+    ) -> HashMap<MotorId, f32>
+    where
+        MotorId: Ord + Debug,
+    {
+        let deltas: HashMap<_, _> = new_forces
+            .iter()
+            .map(|(motor_id, target)| {
+                let last = self.last_forces.get(motor_id).copied().unwrap_or(0.0);
+
+                (motor_id.clone(), target - last)
+            })
+            .collect();
+
+        let movement_delta = forward::forward_solve(motor_config, &deltas);
+        let axis_deltas = movement_delta.force.iter().chain(&movement_delta.torque);
+
+        let scale = axis_deltas
+            .zip(max_delta_per_sec)
+            .map(|(delta, max_delta_per_sec)| {
+                let max_delta = max_delta_per_sec.abs() * dt;
+
+                if delta.abs() > max_delta {
+                    max_delta / delta.abs()
+                } else {
+                    1.0
+                }
+            })
+            .fold(1.0f32, f32::min);
+
+        let limited: HashMap<_, _> = deltas
+            .into_iter()
+            .map(|(motor_id, delta)| {
+                let last = self.last_forces.get(&motor_id).copied().unwrap_or(0.0);
+
+                let mut force = last + delta * scale;
+
+                let crossed_zero = last != 0.0 && force != 0.0 && last.signum() != force.signum();
+                if self.pause_at_zero && crossed_zero {
+                    force = 0.0;
+                }
+
+                (motor_id, force)
+            })
+            .collect();
+
+        self.last_forces = limited.clone();
+
+        limited
+    }
+
+    /// Forgets all previous forces, the next call will ramp every motor up from zero
+    pub fn reset(&mut self) {
+        self.last_forces.clear();
+    }
+
+    pub fn last_forces(&self) -> &HashMap<MotorId, f32> {
+        &self.last_forces
+    }
+}
+
+impl<MotorId: Hash + Eq + Clone> Default for SlewLimiter<MotorId> {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::vector;
+
+    use crate::{fixtures, solve::forward, solve::reverse, Movement};
+
+    use super::*;
+
+    #[test]
+    fn slew_limiter_step_input() {
+        let mut limiter = SlewLimiter::new(true);
+
+        let step: HashMap<u8, f32> = [(0, 1.0), (1, -0.5)].into_iter().collect();
+        let mut ramp = Vec::new();
+        for _ in 0..6 {
+            let forces = limiter.limit(step.clone(), 2.0, 0.1);
+            ramp.push((forces[&0], forces[&1]));
+        }
+
+        let expected = [
+            (0.2, -0.2),
+            (0.4, -0.4),
+            (0.6, -0.5),
+            (0.8, -0.5),
+            (1.0, -0.5),
+            (1.0, -0.5),
+        ];
+        for ((a0, a1), (e0, e1)) in ramp.into_iter().zip(expected) {
+            assert!((a0 - e0).abs() < 0.0001 && (a1 - e1).abs() < 0.0001);
+        }
+
+        // Reversing direction pauses at zero
+        let reversed: stable_hashmap::StableHashMap<u8, f32> = [(0, -1.0)].into_iter().collect();
+        let forces = limiter.limit(reversed.clone(), 100.0, 0.1);
+        assert_eq!(forces[&0], 0.0);
+        assert!(!forces.contains_key(&1));
+        assert!(!limiter.last_forces().contains_key(&1));
+
+        let forces = limiter.limit(reversed, 100.0, 0.1);
+        assert_eq!(forces[&0], -1.0);
+
+        // Motors that reappear start from zero
+        let forces = limiter.limit(step, 2.0, 0.1);
+        assert!((forces[&1] + 0.2).abs() < 0.0001);
+    }
+
+    #[test]
+    fn slew_limiter_per_axis() {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+        let mut limiter = SlewLimiter::default();
+
+        // Translation is allowed to change much faster than rotation
+        let limits = [100.0, 100.0, 100.0, 1.0, 1.0, 1.0];
+        let dt = 0.1;
+
+        let target = Movement {
+            force: vector![5.0, 0.0, 0.0],
+            torque: vector![0.0, 0.0, 2.0],
+        };
+        let target_forces = reverse::reverse_solve(target, &motor_config);
+
+        let mut last = Movement::default();
+        for step in 1..=30 {
+            let forces = limiter.limit_axes(target_forces.clone(), &motor_config, limits, dt);
+            let movement = forward::forward_solve(&motor_config, &forces);
+
+            let delta = movement - last;
+            for (axis, (delta, limit)) in delta
+                .force
+                .iter()
+                .chain(&delta.torque)
+                .zip(limits)
+                .enumerate()
+            {
+                assert!(delta.abs() <= limit * dt + 0.0001, "{step} {axis}: {delta}");
+            }
+
+            last = movement;
+        }
+
+        // The rotation limit holds back the whole movement, but it still arrives
+        assert!((last.force - target.force).norm() < 0.0001);
+        assert!((last.torque - target.torque).norm() < 0.0001);
+    }
+}
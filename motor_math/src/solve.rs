@@ -10,26 +10,17 @@ mod tests {
 
     use crate::{
         blue_rov::HeavyMotorId,
-        motor_preformance::{self},
-        solve::forward,
-        utils::vec_from_angles,
+        fixtures,
+        solve::{forward, reverse},
+        vectored_six::VectoredSixMotorId,
         x3d::X3dMotorId,
-        Direction, Motor, MotorConfig, Movement,
+        Direction, ErasedMotorId, Motor, MotorConfig, Movement,
     };
 
-    use super::reverse;
-
     #[test]
     fn solve_roundtrip_x3d() {
-        let seed_motor = Motor {
-            position: vector![1.0, 1.0, 1.0].normalize(),
-            orientation: vec_from_angles(60.0, 40.0),
-            direction: Direction::Clockwise,
-        };
-
-        let motor_data =
-            motor_preformance::read_motor_data("../robot/motor_data.csv").expect("Read motor data");
-        let motor_config = MotorConfig::<X3dMotorId, f32>::new(seed_motor, Vector3::default());
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![1.0, 1.0, 1.0]);
 
         let movement = Movement {
             force: vector![-0.6, 0.5, 0.3],
@@ -69,8 +60,7 @@ mod tests {
             direction: Direction::Clockwise,
         };
 
-        let motor_data =
-            motor_preformance::read_motor_data("../robot/motor_data.csv").expect("Read motor data");
+        let motor_data = fixtures::motor_data();
         let motor_config =
             MotorConfig::<HeavyMotorId, f32>::new(lateral, vertical, Vector3::default());
 
@@ -99,10 +89,59 @@ mod tests {
         assert!(movement_error.torque.norm_squared() < 0.0001);
     }
 
+    #[test]
+    fn solve_roundtrip_vectored_six() {
+        let lateral = Motor {
+            position: vector![1.0, 1.0, 0.0],
+            orientation: vector![-1.0, 1.0, 0.0].normalize(),
+            direction: Direction::Clockwise,
+        };
+        let vertical = Motor {
+            position: vector![1.0, 0.0, 0.0],
+            orientation: vector![0.0, 0.0, 1.0].normalize(),
+            direction: Direction::Clockwise,
+        };
+
+        let motor_data = fixtures::motor_data();
+        let motor_config =
+            MotorConfig::<VectoredSixMotorId, f32>::new(lateral, vertical, Vector3::default());
+
+        // Both vertical motors are on the x axis, so they can not pitch the vehicle
+        assert_eq!(
+            motor_config.uncontrollable_axes(),
+            vec![reverse::Axis::XRot]
+        );
+
+        let movement = Movement {
+            force: vector![0.5, 0.1, 0.4],
+            torque: vector![0.0, 0.2, -0.3],
+        };
+
+        let forces = reverse::reverse_solve(movement, &motor_config);
+        let motor_cmds = reverse::forces_to_cmds(forces, &motor_config, &motor_data);
+
+        let actual_movement = forward::forward_solve(
+            &motor_config,
+            &motor_cmds
+                .iter()
+                .map(|(id, data)| (*id, data.force))
+                .collect(),
+        );
+
+        let movement_error = movement - actual_movement;
+        assert!(movement_error.force.norm_squared() < 0.0001);
+        assert!(movement_error.torque.norm_squared() < 0.0001);
+
+        let erased: MotorConfig<ErasedMotorId, f32> = motor_config.clone().erase();
+        let unerased = erased
+            .unerase::<VectoredSixMotorId>()
+            .expect("Unerase motor config");
+        assert_eq!(unerased, motor_config);
+    }
+
     #[test]
     fn solve_roundtrip_arbitrary() {
-        let motor_data =
-            motor_preformance::read_motor_data("../robot/motor_data.csv").expect("Read motor data");
+        let motor_data = fixtures::motor_data();
 
         let mut motors = HashMap::new();
 
@@ -197,17 +236,126 @@ mod tests {
         assert!(movement_error.torque.norm_squared() < 0.0001);
     }
 
-    #[bench]
-    fn bench_reverse_solver_x3d(b: &mut Bencher) {
-        let seed_motor = Motor {
-            position: vector![0.3, 0.5, 0.4].normalize(),
-            orientation: vec_from_angles(60.0, 40.0),
-            direction: Direction::Clockwise,
+    #[test]
+    fn solve_roundtrip_motor_disabled() {
+        let motor_config = fixtures::x3d_config(vector![1.0, 1.0, 1.0])
+            .with_motor_disabled(&X3dMotorId::FrontLeftTop)
+            .erase()
+            .unerase::<X3dMotorId>()
+            .expect("Unerase");
+
+        let movement = Movement {
+            force: vector![-0.6, 0.5, 0.3],
+            torque: vector![0.2, 0.1, 0.4],
+        };
+
+        let forces = reverse::reverse_solve(movement, &motor_config);
+        let actual_movement = forward::forward_solve(&motor_config, &forces);
+
+        assert_eq!(forces[&X3dMotorId::FrontLeftTop], 0.0);
+        assert!((movement.force - actual_movement.force).norm_squared() < 0.0001);
+        assert!((movement.torque - actual_movement.torque).norm_squared() < 0.0001);
+    }
+
+    #[test]
+    fn solve_roundtrip_motors_disabled() {
+        let disabled = [X3dMotorId::FrontLeftTop, X3dMotorId::BackRightBottom];
+        let motor_config =
+            fixtures::x3d_config(vector![1.0, 1.0, 1.0]).with_disabled_motors(&disabled);
+
+        let movement = Movement {
+            force: vector![-0.6, 0.5, 0.3],
+            torque: vector![0.2, 0.1, 0.4],
+        };
+
+        let forces = reverse::reverse_solve(movement, &motor_config);
+        let actual_movement = forward::forward_solve(&motor_config, &forces);
+
+        assert_eq!(forces.len(), 8);
+        for motor_id in &disabled {
+            assert_eq!(forces[motor_id], 0.0);
+        }
+        assert!((movement.force - actual_movement.force).norm_squared() < 0.0001);
+        assert!((movement.torque - actual_movement.torque).norm_squared() < 0.0001);
+
+        let motor_config = motor_config.with_disabled_motors(&[
+            X3dMotorId::FrontLeftTop,
+            X3dMotorId::FrontLeftBottom,
+            X3dMotorId::FrontRightTop,
+            X3dMotorId::FrontRightBottom,
+            X3dMotorId::BackLeftTop,
+            X3dMotorId::BackLeftBottom,
+            X3dMotorId::BackRightTop,
+            X3dMotorId::BackRightBottom,
+        ]);
+        let forces = reverse::reverse_solve(movement, &motor_config);
+        assert!(forces.values().all(|it| *it == 0.0));
+    }
+
+    #[test]
+    fn weighted_axis_priority() {
+        let motor_data = fixtures::motor_data();
+
+        #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+        enum MotorIds {
+            Forward,
+            Lateral,
+        }
+
+        // The forward motor is offset, so it can not produce Y force without also producing yaw
+        let motors = [
+            (
+                MotorIds::Forward,
+                Motor {
+                    position: vector![1.0, 0.0, 0.0],
+                    orientation: vector![0.0, 1.0, 0.0],
+                    direction: Direction::Clockwise,
+                },
+            ),
+            (
+                MotorIds::Lateral,
+                Motor {
+                    position: vector![0.0, 0.0, 0.0],
+                    orientation: vector![1.0, 0.0, 0.0],
+                    direction: Direction::Clockwise,
+                },
+            ),
+        ];
+
+        let unweighted = MotorConfig::new_raw(motors, Vector3::default());
+        let weighted =
+            MotorConfig::new_weighted(motors, Vector3::default(), [1.0, 10.0, 1.0, 1.0, 1.0, 1.0]);
+
+        let movement = Movement {
+            force: vector![0.0, 2.0, 0.0],
+            torque: vector![0.0, 0.0, 0.0],
+        };
+
+        let achieved = |motor_config: &MotorConfig<MotorIds, f32>| {
+            let forces = reverse::reverse_solve(movement, motor_config);
+            let motor_cmds = reverse::forces_to_cmds(forces, motor_config, &motor_data);
+            let motor_cmds =
+                reverse::clamp_amperage(motor_cmds, motor_config, &motor_data, 20.0, 0.01);
+
+            forward::forward_solve(
+                motor_config,
+                &motor_cmds
+                    .iter()
+                    .map(|(id, data)| (*id, data.force))
+                    .collect(),
+            )
         };
 
-        let motor_data =
-            motor_preformance::read_motor_data("../robot/motor_data.csv").expect("Read motor data");
-        let motor_config = MotorConfig::<X3dMotorId, f32>::new(seed_motor, Vector3::default());
+        let unweighted_movement = achieved(&unweighted);
+        let weighted_movement = achieved(&weighted);
+
+        assert!(weighted_movement.force.y > unweighted_movement.force.y + 0.1);
+    }
+
+    #[bench]
+    fn bench_reverse_solver_x3d(b: &mut Bencher) {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
 
         let movement = Movement {
             force: vector![0.6, 0.0, 0.3],
@@ -233,8 +381,7 @@ mod tests {
             direction: Direction::Clockwise,
         };
 
-        let motor_data =
-            motor_preformance::read_motor_data("../robot/motor_data.csv").expect("Read motor data");
+        let motor_data = fixtures::motor_data();
         let motor_config =
             MotorConfig::<HeavyMotorId, f32>::new(lateral, vertical, Vector3::default());
 
@@ -2,11 +2,13 @@
 
 use std::{fmt::Debug, hash::Hash};
 
-use nalgebra::DVector;
+#[cfg(feature = "simd")]
+use nalgebra::vector;
+use nalgebra::Vector6;
 use stable_hashmap::StableHashMap;
 use tracing::instrument;
 
-use crate::{MotorConfig, Movement, Number};
+use crate::{motor_preformance::MotorData, MotorConfig, Movement, Number};
 
 type HashMap<K, V> = StableHashMap<K, V>;
 
@@ -15,14 +17,41 @@ pub fn forward_solve<D: Number, MotorId: Hash + Ord + Debug>(
     motor_config: &MotorConfig<MotorId, D>,
     motor_forces: &HashMap<MotorId, D>,
 ) -> Movement<D> {
-    let force_vec = DVector::from_iterator(
-        motor_config.motors.len(),
+    solve_ordered(
+        motor_config,
         motor_config
             .motors()
             .map(|(id, _motor)| motor_forces.get(id).cloned().unwrap_or(D::zero())),
+    )
+}
+
+/// Like `forward_solve`, but takes one force per motor in `MotorConfig::motors` order
+///
+/// Nothing is looked up or allocated, so this is what should be called every tick
+#[instrument(level = "trace", skip(motor_config), ret)]
+pub fn forward_solve_ordered<D: Number, MotorId: Debug>(
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_forces: &[D],
+) -> Movement<D> {
+    debug_assert_eq!(
+        motor_forces.len(),
+        motor_config.motors.len(),
+        "Expected one force per motor"
     );
 
-    let movement = motor_config.matrix.clone() * force_vec;
+    solve_ordered(motor_config, motor_forces.iter().copied())
+}
+
+/// Accumulates one column of the matrix per motor, on the stack
+fn solve_ordered<D: Number, MotorId>(
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_forces: impl Iterator<Item = D>,
+) -> Movement<D> {
+    let mut movement = Vector6::zeros();
+    for (column, force) in motor_config.matrix.column_iter().zip(motor_forces) {
+        movement += column * force;
+    }
+
     let force = movement.fixed_rows::<3>(0);
     let torque = movement.fixed_rows::<3>(3);
 
@@ -31,3 +60,243 @@ pub fn forward_solve<D: Number, MotorId: Hash + Ord + Debug>(
         torque: torque.into(),
     }
 }
+
+/// Same as `forward_solve` specialized to `f32`, with the matrix multiply done in simd lanes
+///
+/// The matrix is column major, so each motor's six coefficients are contiguous and fill one vector.
+/// The movement is accumulated one motor at a time without allocating.
+#[cfg(feature = "simd")]
+#[instrument(level = "trace", skip(motor_config), ret)]
+pub fn forward_solve_simd<MotorId: Hash + Ord + Debug>(
+    motor_config: &MotorConfig<MotorId, f32>,
+    motor_forces: &HashMap<MotorId, f32>,
+) -> Movement<f32> {
+    use std::simd::f32x8;
+
+    let mut movement = f32x8::splat(0.0);
+    for ((id, _motor), column) in motor_config
+        .motors()
+        .zip(motor_config.matrix.as_slice().chunks_exact(6))
+    {
+        let force = motor_forces.get(id).cloned().unwrap_or(0.0);
+        movement += f32x8::load_or_default(column) * f32x8::splat(force);
+    }
+
+    let [x, y, z, x_rot, y_rot, z_rot, _, _] = movement.to_array();
+
+    Movement {
+        force: vector![x, y, z],
+        torque: vector![x_rot, y_rot, z_rot],
+    }
+}
+
+/// Like `forward_solve`, but first clamps each motor's force to what the motor can physically produce
+///
+/// Returns the resulting movement along with whether each motor's force was clamped
+#[instrument(level = "trace", skip(motor_config, motor_data), ret)]
+pub fn forward_solve_clamped<D: Number, MotorId: Hash + Ord + Clone + Debug>(
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_forces: &HashMap<MotorId, D>,
+    motor_data: &MotorData,
+) -> (Movement<D>, HashMap<MotorId, bool>) {
+    // The direction of a motor only mirrors the pwm, the achievable force range is the same
+    let (min_force, max_force) = motor_data.force_range();
+
+    let mut clamped_forces = HashMap::default();
+    let mut saturated = HashMap::default();
+
+    for (motor_id, force) in motor_forces {
+        let (force, clamped) = if force.re() > max_force {
+            (D::from(max_force), true)
+        } else if force.re() < min_force {
+            (D::from(min_force), true)
+        } else {
+            (*force, false)
+        };
+
+        clamped_forces.insert(motor_id.clone(), force);
+        saturated.insert(motor_id.clone(), clamped);
+    }
+
+    let movement = forward_solve(motor_config, &clamped_forces);
+
+    (movement, saturated)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
+    use nalgebra::vector;
+
+    use crate::{fixtures, solve::reverse, x3d::X3dMotorId, Movement};
+    use test::Bencher;
+
+    use super::*;
+
+    #[test]
+    fn forward_solve_clamped_saturation() {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![1.0, 1.0, 1.0]);
+
+        let (_, max_force) = motor_data.force_range();
+
+        let mut motor_forces = motor_config
+            .motors()
+            .map(|(id, _)| (*id, 1.0))
+            .collect::<stable_hashmap::StableHashMap<_, _>>();
+        motor_forces.insert(X3dMotorId::FrontRightTop, max_force * 2.0);
+
+        let naive_movement = forward_solve(&motor_config, &motor_forces);
+        let (clamped_movement, saturated) =
+            forward_solve_clamped(&motor_config, &motor_forces, &motor_data);
+
+        assert_eq!(saturated.get(&X3dMotorId::FrontRightTop), Some(&true));
+        assert!(saturated
+            .iter()
+            .filter(|(id, _)| **id != X3dMotorId::FrontRightTop)
+            .all(|(_, saturated)| !saturated));
+
+        let movement_error = naive_movement - clamped_movement;
+        assert!(movement_error.force.norm_squared() > 0.0001);
+
+        motor_forces.insert(X3dMotorId::FrontRightTop, max_force);
+        let expected_movement = forward_solve(&motor_config, &motor_forces);
+
+        let movement_error = expected_movement - clamped_movement;
+        assert!(movement_error.force.norm_squared() < 0.0001);
+        assert!(movement_error.torque.norm_squared() < 0.0001);
+    }
+
+    #[test]
+    fn forward_solve_ordered_matches_map() {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let ordered: Vec<f32> = (0..motor_config.motors().count())
+            .map(|idx| idx as f32 * 0.7 - 2.0)
+            .collect();
+        let forces = motor_config
+            .motors()
+            .zip(&ordered)
+            .map(|((id, _), force)| (*id, *force))
+            .collect::<stable_hashmap::StableHashMap<_, _>>();
+
+        let error =
+            forward_solve(&motor_config, &forces) - forward_solve_ordered(&motor_config, &ordered);
+        assert!(error.force.abs().max() < 1e-6);
+        assert!(error.torque.abs().max() < 1e-6);
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn forward_solve_simd_matches_generic() {
+        let motor_config = fixtures::x3d_config(vector![1.0, 1.0, 1.0]);
+
+        // Small xorshift so the test is deterministic without pulling in rand
+        let mut state = 0x2545_f491u32;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32 * 2.0 - 1.0
+        };
+
+        for _ in 0..100 {
+            let forces = motor_config
+                .motors()
+                .map(|(id, _)| (*id, next()))
+                .collect::<stable_hashmap::StableHashMap<_, _>>();
+
+            let generic = forward_solve(&motor_config, &forces);
+            let simd = forward_solve_simd(&motor_config, &forces);
+
+            let error = generic - simd;
+            assert!(error.force.abs().max() < 1e-6, "{generic:?} != {simd:?}");
+            assert!(error.torque.abs().max() < 1e-6, "{generic:?} != {simd:?}");
+        }
+    }
+
+    #[bench]
+    fn bench_forward_solver_x3d(b: &mut Bencher) {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let movement = Movement {
+            force: vector![0.6, 0.0, 0.3],
+            torque: vector![0.2, 0.1, 0.3],
+        };
+        let forces = reverse::reverse_solve(movement, &motor_config);
+
+        b.iter(|| forward_solve(&motor_config, &forces));
+    }
+
+    #[bench]
+    fn bench_forward_solver_x3d_ordered(b: &mut Bencher) {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let movement = Movement {
+            force: vector![0.6, 0.0, 0.3],
+            torque: vector![0.2, 0.1, 0.3],
+        };
+        let forces = reverse::reverse_solve_vec(movement, &motor_config);
+
+        b.iter(|| forward_solve_ordered(&motor_config, &forces));
+    }
+
+    /// The reverse, clamp, forward round trip the robot does every tick, through maps
+    #[bench]
+    fn bench_verify_movement_map(b: &mut Bencher) {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+        let (min_force, max_force) = motor_data.force_range();
+
+        let movement = Movement {
+            force: vector![6.0, 0.0, 3.0],
+            torque: vector![2.0, 1.0, 3.0],
+        };
+
+        b.iter(|| {
+            let mut forces = reverse::reverse_solve(movement, &motor_config);
+            for force in forces.values_mut() {
+                *force = force.clamp(min_force, max_force);
+            }
+
+            forward_solve(&motor_config, &forces)
+        });
+    }
+
+    /// Same as `bench_verify_movement_map`, in config order
+    #[bench]
+    fn bench_verify_movement_ordered(b: &mut Bencher) {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+        let (min_force, max_force) = motor_data.force_range();
+
+        let movement = Movement {
+            force: vector![6.0, 0.0, 3.0],
+            torque: vector![2.0, 1.0, 3.0],
+        };
+
+        b.iter(|| {
+            let mut forces = reverse::reverse_solve_vec(movement, &motor_config);
+            for force in &mut forces {
+                *force = force.clamp(min_force, max_force);
+            }
+
+            forward_solve_ordered(&motor_config, &forces)
+        });
+    }
+
+    #[cfg(feature = "simd")]
+    #[bench]
+    fn bench_forward_solver_x3d_simd(b: &mut Bencher) {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let movement = Movement {
+            force: vector![0.6, 0.0, 0.3],
+            torque: vector![0.2, 0.1, 0.3],
+        };
+        let forces = reverse::reverse_solve(movement, &motor_config);
+
+        b.iter(|| forward_solve_simd(&motor_config, &forces));
+    }
+}
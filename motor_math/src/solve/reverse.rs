@@ -3,14 +3,16 @@
 use std::fmt::Debug;
 use std::hash::Hash;
 
-use nalgebra::{vector, Vector6};
+use nalgebra::{vector, DVector, Matrix6, Vector6};
+use num_dual::Dual32;
 use serde::{Deserialize, Serialize};
 use stable_hashmap::StableHashMap;
 use tracing::instrument;
 
 use crate::{
-    motor_preformance::{Interpolation, MotorData, MotorRecord},
-    MotorConfig, Movement, Number,
+    motor_preformance::{Interpolation, MotorData, MotorDataSource, MotorRecord},
+    solve::forward::forward_solve,
+    Direction, MotorConfig, Movement, Number,
 };
 
 type HashMap<K, V> = StableHashMap<K, V>;
@@ -20,6 +22,24 @@ pub fn reverse_solve<D: Number, MotorId: Hash + Ord + Clone + Debug>(
     movement: Movement<D>,
     motor_config: &MotorConfig<MotorId, D>,
 ) -> HashMap<MotorId, D> {
+    let forces = reverse_solve_vec(movement, motor_config);
+
+    motor_config
+        .motors
+        .iter()
+        .zip(forces)
+        .map(|((motor_id, _motor), force)| (motor_id.clone(), force))
+        .collect()
+}
+
+/// Like `reverse_solve`, but returns one force per motor in `MotorConfig::motors` order
+///
+/// Pairs with `forward_solve_ordered` so checking a movement does not need any maps
+#[instrument(level = "trace", skip(motor_config), ret)]
+pub fn reverse_solve_vec<D: Number, MotorId: Debug>(
+    movement: Movement<D>,
+    motor_config: &MotorConfig<MotorId, D>,
+) -> Vec<D> {
     let movement_vec = Vector6::from_iterator(
         [movement.force, movement.torque]
             .iter()
@@ -27,32 +47,149 @@ pub fn reverse_solve<D: Number, MotorId: Hash + Ord + Clone + Debug>(
             .cloned(),
     );
 
-    let forces = motor_config.pseudo_inverse.clone() * movement_vec;
+    let forces = &motor_config.pseudo_inverse * movement_vec;
 
-    let mut motor_forces = HashMap::default();
-    for ((motor_id, _motor), force) in motor_config
-        .motors
-        .iter()
-        .zip(Vec::from(forces.data).into_iter())
-    {
-        motor_forces.insert(motor_id.clone(), force);
+    Vec::from(forces.data)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckedForces<MotorId: Hash + Eq, D> {
+    pub forces: HashMap<MotorId, D>,
+    /// True if the motor's requested force is outside of what the motor can produce
+    pub saturated: HashMap<MotorId, bool>,
+}
+
+impl<MotorId: Hash + Eq, D> CheckedForces<MotorId, D> {
+    /// True if the requested movement can not be achieved
+    pub fn is_saturated(&self) -> bool {
+        self.saturated.values().any(|it| *it)
     }
+}
 
-    motor_forces
+/// Like `reverse_solve`, but also reports which motors were asked for more force than they can produce
+#[instrument(level = "trace", skip(motor_config, motor_data), ret)]
+pub fn reverse_solve_checked<D: Number, MotorId: Hash + Ord + Clone + Debug>(
+    movement: Movement<D>,
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_data: &MotorData,
+) -> CheckedForces<MotorId, D> {
+    let forces = reverse_solve(movement, motor_config);
+    let (min_force, max_force) = motor_data.force_range();
+
+    let saturated = forces
+        .iter()
+        .map(|(motor_id, force)| {
+            let force = force.re();
+            (motor_id.clone(), force < min_force || force > max_force)
+        })
+        .collect();
+
+    CheckedForces { forces, saturated }
 }
 
 #[instrument(level = "trace", skip(motor_config, motor_data), ret)]
 pub fn forces_to_cmds<D: Number, MotorId: Hash + Ord + Clone + Debug>(
+    forces: HashMap<MotorId, D>,
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_data: &impl MotorDataSource<MotorId>,
+) -> HashMap<MotorId, MotorRecord<D>> {
+    lookup_cmds(forces, motor_config, |motor_id, force, direction| {
+        motor_data
+            .motor_data(motor_id)
+            .lookup_by_force(force, Interpolation::LerpDirection(direction))
+    })
+}
+
+/// Like `forces_to_cmds`, but for motors supplied with `voltage` instead of the motor data's default voltage
+///
+/// Voltages between the measured tables are interpolated. Outside of that range thrust at a given pwm
+/// is assumed to scale with the square of the voltage
+#[instrument(level = "trace", skip(motor_config, motor_data), ret)]
+pub fn forces_to_cmds_at_voltage<D: Number, MotorId: Hash + Ord + Clone + Debug>(
     forces: HashMap<MotorId, D>,
     motor_config: &MotorConfig<MotorId, D>,
     motor_data: &MotorData,
+    voltage: f32,
+) -> HashMap<MotorId, MotorRecord<D>> {
+    if !voltage.is_finite() || voltage <= 0.0 {
+        // No usable reading yet, ie before the first voltage measurement
+        return forces_to_cmds(forces, motor_config, motor_data);
+    }
+
+    let (min_voltage, max_voltage) = motor_data.voltage_range();
+    let table_voltage = voltage.clamp(min_voltage, max_voltage);
+    let force_scale = (table_voltage / voltage).powi(2);
+
+    lookup_cmds(forces, motor_config, |_, force, direction| {
+        let data = motor_data.lookup_by_force_at_voltage(
+            force * force_scale,
+            table_voltage,
+            Interpolation::LerpDirection(direction),
+        );
+
+        MotorRecord {
+            force: data.force / force_scale,
+            voltage: voltage.into(),
+            ..data
+        }
+    })
+}
+
+/// Looks up the command for each motor's force, `lookup` is given the motor's id, force and direction
+fn lookup_cmds<D: Number, MotorId: Hash + Ord + Clone + Debug>(
+    forces: HashMap<MotorId, D>,
+    motor_config: &MotorConfig<MotorId, D>,
+    mut lookup: impl FnMut(&MotorId, D, Direction) -> MotorRecord<D>,
 ) -> HashMap<MotorId, MotorRecord<D>> {
     let mut motor_cmds = HashMap::default();
     for (motor_id, force) in forces {
         let motor = motor_config.motor(&motor_id).expect("Bad motor id");
-        let data = motor_data.lookup_by_force(force, Interpolation::LerpDirection(motor.direction));
+        let data = lookup(&motor_id, force, motor.direction);
+
+        motor_cmds.insert(motor_id, data);
+    }
+
+    motor_cmds
+}
 
-        motor_cmds.insert(motor_id.clone(), data);
+/// Like `forces_to_cmds`, but any motor asked for a nonzero force is kept out of the deadband
+///
+/// `deadband` is the half width in microseconds of the pwm range around 1500 where the motor produces no thrust.
+/// Commands that land inside it are snapped to its edge so small forces still start the motor spinning,
+/// the rest of the snapped record is looked up again so it describes what the motor will actually do
+#[instrument(level = "trace", skip(motor_config, motor_data), ret)]
+pub fn forces_to_cmds_with_deadband<D: Number, MotorId: Hash + Ord + Clone + Debug>(
+    forces: HashMap<MotorId, D>,
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    deadband: f32,
+) -> HashMap<MotorId, MotorRecord<D>> {
+    let requested: HashMap<MotorId, D> = forces.clone();
+    let mut motor_cmds = forces_to_cmds(forces, motor_config, motor_data);
+
+    for (motor_id, record) in motor_cmds.iter_mut() {
+        let force = requested[motor_id];
+        if force == D::zero() {
+            continue;
+        }
+
+        let offset = record.pwm - D::from(1500.0);
+        if offset.abs() >= D::from(deadband) {
+            continue;
+        }
+
+        let sign = if offset != D::zero() {
+            offset.re().signum()
+        } else {
+            let motor = motor_config.motor(motor_id).expect("Bad motor id");
+            force.re().signum() * motor.direction.get_sign()
+        };
+
+        let motor = motor_config.motor(motor_id).expect("Bad motor id");
+        *record = motor_data.motor_data(motor_id).lookup_by_pwm(
+            D::from(1500.0 + sign * deadband),
+            Interpolation::LerpDirection(motor.direction),
+        );
     }
 
     motor_cmds
@@ -64,7 +201,7 @@ pub fn forces_to_cmds<D: Number, MotorId: Hash + Ord + Clone + Debug>(
 pub fn clamp_amperage_fast<D: Number, MotorId: Hash + Ord + Clone + Debug>(
     motor_cmds: HashMap<MotorId, MotorRecord<D>>,
     motor_config: &MotorConfig<MotorId, D>,
-    motor_data: &MotorData,
+    motor_data: &impl MotorDataSource<MotorId>,
     amperage_cap: f32,
 ) -> HashMap<MotorId, MotorRecord<D>> {
     let amperage_total = motor_cmds.values().map(|it| it.current).sum::<D>();
@@ -86,8 +223,9 @@ pub fn clamp_amperage_fast<D: Number, MotorId: Hash + Ord + Clone + Debug>(
             .unwrap_or(crate::Direction::Clockwise);
 
         let adjusted_current = data.current.copysign(data.force) * amperage_ratio;
-        let data_adjusted =
-            motor_data.lookup_by_current(adjusted_current, Interpolation::LerpDirection(direction));
+        let data_adjusted = motor_data
+            .motor_data(&motor_id)
+            .lookup_by_current(adjusted_current, Interpolation::LerpDirection(direction));
 
         adjusted_motor_cmds.insert(motor_id.clone(), data_adjusted);
     }
@@ -99,7 +237,7 @@ pub fn clamp_amperage_fast<D: Number, MotorId: Hash + Ord + Clone + Debug>(
 pub fn clamp_amperage<D: Number, MotorId: Hash + Ord + Clone + Debug>(
     motor_cmds: HashMap<MotorId, MotorRecord<D>>,
     motor_config: &MotorConfig<MotorId, D>,
-    motor_data: &MotorData,
+    motor_data: &impl MotorDataSource<MotorId>,
     amperage_cap: f32,
     epsilon: f32,
 ) -> HashMap<MotorId, MotorRecord<D>> {
@@ -123,8 +261,9 @@ pub fn clamp_amperage<D: Number, MotorId: Hash + Ord + Clone + Debug>(
             .unwrap_or(crate::Direction::Clockwise);
 
         let force_current = data.force * force_ratio;
-        let data_adjusted =
-            motor_data.lookup_by_force(force_current, Interpolation::LerpDirection(direction));
+        let data_adjusted = motor_data
+            .motor_data(&motor_id)
+            .lookup_by_force(force_current, Interpolation::LerpDirection(direction));
 
         adjusted_motor_cmds.insert(motor_id.clone(), data_adjusted);
     }
@@ -132,11 +271,248 @@ pub fn clamp_amperage<D: Number, MotorId: Hash + Ord + Clone + Debug>(
     adjusted_motor_cmds
 }
 
+/// Like `clamp_amperage`, but also keeps each motor under its entry in `per_motor_cap`
+///
+/// Motors without an entry are only limited by `amperage_cap`.
+/// Preserves force ratios, every motor is scaled by the tightest of the global and per motor constraints
+#[instrument(level = "trace", skip(motor_config, motor_data), ret)]
+pub fn clamp_amperage_per_motor<D: Number, MotorId: Hash + Ord + Clone + Debug>(
+    motor_cmds: HashMap<MotorId, MotorRecord<D>>,
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    amperage_cap: f32,
+    per_motor_cap: &HashMap<MotorId, f32>,
+    epsilon: f32,
+) -> HashMap<MotorId, MotorRecord<D>> {
+    let amperage_total = motor_cmds.values().map(|it| it.current).sum::<D>();
+
+    let mut force_ratio = D::one();
+    if amperage_total.re() > amperage_cap {
+        force_ratio =
+            binary_search_force_ratio(&motor_cmds, motor_config, motor_data, amperage_cap, epsilon);
+    }
+
+    for (motor_id, data) in &motor_cmds {
+        let Some(&motor_cap) = per_motor_cap.get(motor_id) else {
+            continue;
+        };
+        if data.current.re() <= motor_cap {
+            continue;
+        }
+
+        let motor_cmd = [(motor_id.clone(), *data)].into_iter().collect();
+        let motor_ratio =
+            binary_search_force_ratio(&motor_cmd, motor_config, motor_data, motor_cap, epsilon);
+
+        if motor_ratio.re() < force_ratio.re() {
+            force_ratio = motor_ratio;
+        }
+    }
+
+    if force_ratio == D::one() {
+        return motor_cmds;
+    }
+
+    let mut adjusted_motor_cmds = HashMap::default();
+    for (motor_id, data) in motor_cmds {
+        let direction = motor_config
+            .motor(&motor_id)
+            .map(|it| it.direction)
+            .unwrap_or(crate::Direction::Clockwise);
+
+        let force_current = data.force * force_ratio;
+        let data_adjusted = motor_data
+            .motor_data(&motor_id)
+            .lookup_by_force(force_current, Interpolation::LerpDirection(direction));
+
+        adjusted_motor_cmds.insert(motor_id.clone(), data_adjusted);
+    }
+
+    adjusted_motor_cmds
+}
+
+/// Which parts of a movement to give up first when the current budget is exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AxisPriority {
+    /// Scale force and torque equally, same as `clamp_amperage`
+    Uniform,
+    /// Keep translation exact and shed torque first
+    Translation,
+    /// Keep rotation exact and shed force first
+    Rotation,
+    /// The axis group with the lower weight is shed faster
+    Weighted { force: f32, torque: f32 },
+}
+
+impl AxisPriority {
+    /// How much of the force and torque are removed when fully shedding
+    fn shed_rates(&self) -> (f32, f32) {
+        let (force, torque) = match *self {
+            AxisPriority::Uniform => (1.0, 1.0),
+            AxisPriority::Translation => (1.0, 0.0),
+            AxisPriority::Rotation => (0.0, 1.0),
+            AxisPriority::Weighted { force, torque } => (force, torque),
+        };
+
+        let max = force.max(torque);
+        if max <= 0.0 {
+            return (0.0, 0.0);
+        }
+
+        (1.0 - force / max, 1.0 - torque / max)
+    }
+}
+
+/// Sheds the lower priority parts of the movement until the current budget is met
+///
+/// Falls back to uniformly scaling the remaining movement if shedding alone is not enough.
+/// The direction of the fully kept part of the movement is preserved
+#[instrument(level = "trace", skip(motor_config, motor_data), ret)]
+pub fn clamp_amperage_prioritized<D: Number, MotorId: Hash + Ord + Clone + Debug>(
+    motor_cmds: HashMap<MotorId, MotorRecord<D>>,
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    amperage_cap: f32,
+    epsilon: f32,
+    priority: AxisPriority,
+) -> HashMap<MotorId, MotorRecord<D>> {
+    let amperage_total = motor_cmds.values().map(|it| it.current).sum::<D>();
+
+    if amperage_total.re() <= amperage_cap {
+        return motor_cmds;
+    }
+
+    let forces = motor_cmds
+        .iter()
+        .map(|(motor_id, data)| (motor_id.clone(), data.force))
+        .collect();
+    let movement = forward_solve(motor_config, &forces);
+
+    let (force_shed, torque_shed) = priority.shed_rates();
+    let cmds_at = |shed: f32| {
+        let movement = Movement {
+            force: movement.force * D::from(1.0 - shed * force_shed),
+            torque: movement.torque * D::from(1.0 - shed * torque_shed),
+        };
+
+        let forces = reverse_solve(movement, motor_config);
+        forces_to_cmds(forces, motor_config, motor_data)
+    };
+    let amperage = |cmds: &HashMap<MotorId, MotorRecord<D>>| {
+        cmds.values().map(|it| it.current).sum::<D>().re()
+    };
+
+    let fully_shed = cmds_at(1.0);
+    if (force_shed == 0.0 && torque_shed == 0.0) || amperage(&fully_shed) > amperage_cap {
+        return clamp_amperage(fully_shed, motor_config, motor_data, amperage_cap, epsilon);
+    }
+
+    let (mut lower_bound, mut upper_bound) = (0.0f32, 1.0f32);
+    let mut best = fully_shed;
+
+    for _ in 0..PRIORITIZED_MAX_ITERATIONS {
+        let mid = (lower_bound + upper_bound) / 2.0;
+        let cmds = cmds_at(mid);
+        let mid_amperage = amperage(&cmds);
+
+        if mid_amperage > amperage_cap {
+            lower_bound = mid;
+        } else {
+            upper_bound = mid;
+            best = cmds;
+
+            if amperage_cap - mid_amperage < epsilon {
+                break;
+            }
+        }
+    }
+
+    best
+}
+
+const PRIORITIZED_MAX_ITERATIONS: usize = 32;
+
+/// Limits each motor's force magnitude to its entry in `per_motor_max`, motors without an entry are not limited
+///
+/// The current no longer used by the clamped motors is given to the remaining motors by scaling them up
+/// Does not preserve force ratios
+#[instrument(level = "trace", skip(motor_config, motor_data), ret)]
+pub fn clamp_forces<D: Number, MotorId: Hash + Ord + Clone + Debug>(
+    motor_cmds: HashMap<MotorId, MotorRecord<D>>,
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    per_motor_max: &HashMap<MotorId, f32>,
+    epsilon: f32,
+) -> HashMap<MotorId, MotorRecord<D>> {
+    let amperage_total = motor_cmds.values().map(|it| it.current).sum::<D>();
+
+    let mut clamped_motor_cmds = HashMap::default();
+    let mut remaining_motor_cmds = HashMap::default();
+    for (motor_id, data) in motor_cmds {
+        match per_motor_max.get(&motor_id) {
+            Some(&max) if data.force.re().abs() > max => {
+                let direction = motor_config
+                    .motor(&motor_id)
+                    .map(|it| it.direction)
+                    .unwrap_or(crate::Direction::Clockwise);
+
+                let force_clamped = D::from(max).copysign(data.force);
+                let data_adjusted = motor_data
+                    .motor_data(&motor_id)
+                    .lookup_by_force(force_clamped, Interpolation::LerpDirection(direction));
+
+                clamped_motor_cmds.insert(motor_id, data_adjusted);
+            }
+            _ => {
+                remaining_motor_cmds.insert(motor_id, data);
+            }
+        }
+    }
+
+    if clamped_motor_cmds.is_empty() || remaining_motor_cmds.is_empty() {
+        clamped_motor_cmds.extend(remaining_motor_cmds);
+        return clamped_motor_cmds;
+    }
+
+    let amperage_clamped = clamped_motor_cmds.values().map(|it| it.current).sum::<D>();
+    let amperage_remaining = (amperage_total - amperage_clamped).re();
+
+    let force_ratio = binary_search_force_ratio(
+        &remaining_motor_cmds,
+        motor_config,
+        motor_data,
+        amperage_remaining,
+        epsilon,
+    );
+
+    for (motor_id, data) in remaining_motor_cmds {
+        let direction = motor_config
+            .motor(&motor_id)
+            .map(|it| it.direction)
+            .unwrap_or(crate::Direction::Clockwise);
+
+        let mut force_adjusted = data.force * force_ratio;
+        if let Some(&max) = per_motor_max.get(&motor_id) {
+            if force_adjusted.re().abs() > max {
+                force_adjusted = D::from(max).copysign(force_adjusted);
+            }
+        }
+
+        let data_adjusted = motor_data
+            .motor_data(&motor_id)
+            .lookup_by_force(force_adjusted, Interpolation::LerpDirection(direction));
+
+        clamped_motor_cmds.insert(motor_id, data_adjusted);
+    }
+
+    clamped_motor_cmds
+}
+
 // TODO: Validate this is using dual numbers correctly
 pub fn binary_search_force_ratio<D: Number, MotorId: Hash + Ord + Clone + Debug>(
     motor_cmds: &HashMap<MotorId, MotorRecord<D>>,
     motor_config: &MotorConfig<MotorId, D>,
-    motor_data: &MotorData,
+    motor_data: &impl MotorDataSource<MotorId>,
     amperage_cap: f32,
     epsilon: f32,
 ) -> D {
@@ -157,6 +533,7 @@ pub fn binary_search_force_ratio<D: Number, MotorId: Hash + Ord + Clone + Debug>
                 // let adjusted_force = data.force.copysign(data.force) * mid;
                 let adjusted_force = data.force * mid;
                 let data = motor_data
+                    .motor_data(motor_id)
                     .lookup_by_force(adjusted_force, Interpolation::LerpDirection(direction));
 
                 data.current
@@ -189,6 +566,287 @@ pub fn binary_search_force_ratio<D: Number, MotorId: Hash + Ord + Clone + Debug>
     }
 }
 
+const NEWTON_RAPHSON_MAX_ITERATIONS: usize = 50;
+
+/// Same as `binary_search_force_ratio` but uses Newton's method, usually converging in a few steps
+///
+/// The derivative of the total current with respect to the ratio is taken by seeding a `Dual32` on the ratio.
+/// Steps that leave the bracket found so far fall back to bisection
+pub fn newton_raphson_force_ratio<D: Number, MotorId: Hash + Ord + Clone + Debug>(
+    motor_cmds: &HashMap<MotorId, MotorRecord<D>>,
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    amperage_cap: f32,
+    epsilon: f32,
+) -> D {
+    newton_raphson_force_ratio_counted(motor_cmds, motor_config, motor_data, amperage_cap, epsilon)
+        .0
+}
+
+/// `newton_raphson_force_ratio`, also returning the number of iterations taken
+pub(crate) fn newton_raphson_force_ratio_counted<D: Number, MotorId: Hash + Ord + Clone + Debug>(
+    motor_cmds: &HashMap<MotorId, MotorRecord<D>>,
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    amperage_cap: f32,
+    epsilon: f32,
+) -> (D, usize) {
+    let (mut lower_bound, mut upper_bound) = (0.0, f32::INFINITY);
+    let mut ratio = 1.0;
+
+    for iteration in 1..=NEWTON_RAPHSON_MAX_ITERATIONS {
+        let forces = motor_cmds.iter().map(|(motor_id, data)| {
+            (
+                motor_id,
+                Dual32::from(data.force.re()) * Dual32::new(ratio, 1.0),
+            )
+        });
+        let current = total_current(forces, motor_config, motor_data);
+
+        if current.re == 0.0 {
+            return (D::one(), iteration);
+        }
+
+        let error = current.re - amperage_cap;
+        let slope = current.eps;
+
+        if error.abs() < epsilon {
+            let ratio = propagate_ratio(
+                ratio,
+                slope,
+                motor_cmds,
+                motor_config,
+                motor_data,
+                amperage_cap,
+            );
+            return (ratio, iteration);
+        }
+
+        if error > 0.0 {
+            upper_bound = ratio;
+        } else {
+            lower_bound = ratio;
+        }
+
+        let next = ratio - error / slope;
+
+        ratio = if slope > 0.0 && next > lower_bound && next < upper_bound {
+            next
+        } else if upper_bound == f32::INFINITY {
+            ratio * 2.0
+        } else {
+            (lower_bound + upper_bound) / 2.0
+        };
+    }
+
+    let ratio = D::from(ratio);
+    (ratio, NEWTON_RAPHSON_MAX_ITERATIONS)
+}
+
+/// Takes one more Newton step from a converged `ratio` in `D`, so any derivatives carried by the
+/// motor commands flow through to the returned ratio
+fn propagate_ratio<D: Number, MotorId: Hash + Ord + Clone + Debug>(
+    ratio: f32,
+    slope: f32,
+    motor_cmds: &HashMap<MotorId, MotorRecord<D>>,
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    amperage_cap: f32,
+) -> D {
+    if slope == 0.0 {
+        return D::from(ratio);
+    }
+
+    let forces = motor_cmds
+        .iter()
+        .map(|(motor_id, data)| (motor_id, data.force * D::from(ratio)));
+    let current = total_current(forces, motor_config, motor_data);
+
+    D::from(ratio) - (current - D::from(amperage_cap)) / D::from(slope)
+}
+
+fn total_current<'a, D: Number, R: Number, MotorId: Hash + Ord + Clone + Debug + 'a>(
+    forces: impl Iterator<Item = (&'a MotorId, R)>,
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_data: &impl MotorDataSource<MotorId>,
+) -> R {
+    forces
+        .map(|(motor_id, force)| {
+            let direction = motor_config
+                .motor(motor_id)
+                .map(|it| it.direction)
+                .unwrap_or(crate::Direction::Clockwise);
+
+            motor_data
+                .motor_data(motor_id)
+                .lookup_by_force(force, Interpolation::LerpDirection(direction))
+                .current
+        })
+        .sum::<R>()
+}
+
+const NONLINEAR_MAX_ITERATIONS: usize = 50;
+const NONLINEAR_MAX_SCALE_ITERATIONS: usize = 30;
+// Keeps steps finite when a motor's force does not depend on its pwm, ie in the deadband or past the end of the table
+const NONLINEAR_DAMPING: f32 = 1e-6;
+
+/// Finds motor commands whose forward solved movement matches `movement` within `epsilon`, drawing at most `amperage_cap`
+///
+/// `reverse_solve` ignores the motors' performance curves, so motors asked for more than they can produce
+/// saturate and the achieved movement drifts from the request. This instead takes damped Gauss-Newton steps on
+/// each motor's pwm, with the derivative of force with respect to pwm found by seeding a `Dual32`, letting the
+/// other motors pick up the slack. Movements that need more than `amperage_cap` are scaled down until they fit.
+///
+/// Much slower than `reverse_solve`, meant for things like trim computation rather than every frame
+#[instrument(level = "trace", skip(motor_config, motor_data), ret)]
+pub fn reverse_solve_nonlinear<MotorId: Hash + Ord + Clone + Debug>(
+    movement: Movement<f32>,
+    motor_config: &MotorConfig<MotorId, f32>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    amperage_cap: f32,
+    epsilon: f32,
+) -> HashMap<MotorId, MotorRecord<f32>> {
+    let forces = reverse_solve(movement, motor_config);
+    let cmds = forces_to_cmds(forces, motor_config, motor_data);
+
+    let mut pwms: Vec<f32> = motor_config
+        .motors()
+        .map(|(motor_id, _)| cmds[motor_id].pwm)
+        .collect();
+
+    let solve = |scale: f32, pwms: &mut [f32]| {
+        nonlinear_solve_pwms(movement * scale, pwms, motor_config, motor_data, epsilon);
+        nonlinear_cmds(pwms, motor_config, motor_data)
+    };
+
+    let cmds = solve(1.0, &mut pwms);
+    if cmds.values().map(|it| it.current).sum::<f32>() <= amperage_cap + epsilon {
+        return cmds;
+    }
+
+    // Bisect the largest scale of the movement that fits under the cap, warm starting from the previous solution
+    let (mut lower, mut upper) = (0.0, 1.0);
+    let mut best = None;
+
+    for _ in 0..NONLINEAR_MAX_SCALE_ITERATIONS {
+        let scale = (lower + upper) / 2.0;
+        let cmds = solve(scale, &mut pwms);
+        let current = cmds.values().map(|it| it.current).sum::<f32>();
+
+        if current > amperage_cap {
+            upper = scale;
+        } else {
+            lower = scale;
+            best = Some(cmds);
+
+            if amperage_cap - current < epsilon {
+                break;
+            }
+        }
+    }
+
+    best.unwrap_or_else(|| solve(0.0, &mut pwms))
+}
+
+/// Takes Gauss-Newton steps on `pwms`, in the order of `motor_config.motors()`, until the movement is within `epsilon` of `target`
+fn nonlinear_solve_pwms<MotorId: Hash + Ord + Clone + Debug>(
+    target: Movement<f32>,
+    pwms: &mut [f32],
+    motor_config: &MotorConfig<MotorId, f32>,
+    motor_data: &impl MotorDataSource<MotorId>,
+    epsilon: f32,
+) {
+    let target = Vector6::from_iterator(
+        [target.force, target.torque]
+            .iter()
+            .flat_map(|it| it.as_slice())
+            .cloned(),
+    );
+
+    let ranges: Vec<(f32, f32)> = motor_config
+        .motors()
+        .map(|(motor_id, motor)| {
+            let (min, max) = motor_data.motor_data(motor_id).pwm_range();
+
+            match motor.direction {
+                Direction::Clockwise => (min, max),
+                Direction::CounterClockwise => (3000.0 - max, 3000.0 - min),
+            }
+        })
+        .collect();
+
+    for (pwm, &(min, max)) in pwms.iter_mut().zip(&ranges) {
+        *pwm = pwm.clamp(min, max);
+    }
+
+    for _ in 0..NONLINEAR_MAX_ITERATIONS {
+        let mut forces = DVector::zeros(pwms.len());
+        let mut jacobian = motor_config.matrix.clone();
+
+        for (idx, (motor_id, motor)) in motor_config.motors().enumerate() {
+            let record = motor_data.motor_data(motor_id).lookup_by_pwm(
+                Dual32::new(pwms[idx], 1.0),
+                Interpolation::LerpDirection(motor.direction),
+            );
+
+            forces[idx] = record.force.re;
+            jacobian.column_mut(idx).scale_mut(record.force.eps);
+        }
+
+        let residual = target - &motor_config.matrix * forces;
+        if residual.norm() < epsilon {
+            return;
+        }
+
+        // Motors pushed past the end of their range can not contribute to the step,
+        // solve again without them so the remaining motors make up the difference
+        let mut step;
+        loop {
+            let normal = &jacobian * jacobian.transpose() + Matrix6::identity() * NONLINEAR_DAMPING;
+            let Some(solution) = normal.cholesky().map(|it| it.solve(&residual)) else {
+                return;
+            };
+            step = jacobian.transpose() * solution;
+
+            let mut saturated = false;
+            for (idx, &(min, max)) in ranges.iter().enumerate() {
+                let next = pwms[idx] + step[idx];
+
+                if (next < min || next > max) && jacobian.column(idx).iter().any(|it| *it != 0.0) {
+                    jacobian.column_mut(idx).fill(0.0);
+                    saturated = true;
+                }
+            }
+
+            if !saturated {
+                break;
+            }
+        }
+
+        for (idx, &(min, max)) in ranges.iter().enumerate() {
+            pwms[idx] = (pwms[idx] + step[idx]).clamp(min, max);
+        }
+    }
+}
+
+fn nonlinear_cmds<MotorId: Hash + Ord + Clone + Debug>(
+    pwms: &[f32],
+    motor_config: &MotorConfig<MotorId, f32>,
+    motor_data: &impl MotorDataSource<MotorId>,
+) -> HashMap<MotorId, MotorRecord<f32>> {
+    motor_config
+        .motors()
+        .zip(pwms)
+        .map(|((motor_id, motor), &pwm)| {
+            let record = motor_data
+                .motor_data(motor_id)
+                .lookup_by_pwm(pwm, Interpolation::LerpDirection(motor.direction));
+
+            (motor_id.clone(), record)
+        })
+        .collect()
+}
+
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
 pub enum Axis {
     X,
@@ -200,6 +858,15 @@ pub enum Axis {
 }
 
 impl Axis {
+    pub const ALL: [Axis; 6] = [
+        Axis::X,
+        Axis::Y,
+        Axis::Z,
+        Axis::XRot,
+        Axis::YRot,
+        Axis::ZRot,
+    ];
+
     pub fn movement<D: Number>(&self) -> Movement<D> {
         match self {
             Axis::X => Movement {
@@ -236,26 +903,824 @@ pub fn axis_maximums<D: Number, MotorId: Hash + Ord + Clone + Debug>(
     amperage_cap: f32,
     epsilon: f32,
 ) -> HashMap<Axis, D> {
-    [
-        Axis::X,
-        Axis::Y,
-        Axis::Z,
-        Axis::XRot,
-        Axis::YRot,
-        Axis::ZRot,
-    ]
-    .into_iter()
-    .map(|it| (it, it.movement::<D>()))
-    .map(|(axis, movement)| {
-        let initial = 25.0;
-
-        let forces = reverse_solve(movement * initial.into(), motor_config);
-        let cmds = forces_to_cmds(forces, motor_config, motor_data);
-        let scale =
-            binary_search_force_ratio(&cmds, motor_config, motor_data, amperage_cap, epsilon);
-
-        let value = scale * initial;
-        (axis, value)
-    })
-    .collect()
+    Axis::ALL
+        .into_iter()
+        .map(|axis| {
+            let value = axis_maximum(
+                axis,
+                motor_config,
+                motor_data,
+                amperage_cap,
+                epsilon,
+                AXIS_MAXIMUM_GUESS.into(),
+            );
+
+            (axis, value)
+        })
+        .collect()
+}
+
+/// Same as `axis_maximums`, but each axis is solved on the rayon thread pool
+///
+/// Not every `DualNum` can be shared between threads, use `axis_maximums` for those
+#[cfg(feature = "rayon")]
+pub fn axis_maximums_par<D, MotorId>(
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_data: &MotorData,
+    amperage_cap: f32,
+    epsilon: f32,
+) -> HashMap<Axis, D>
+where
+    D: Number + Send + Sync,
+    MotorId: Hash + Ord + Clone + Debug + Send + Sync,
+{
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+    let maximums: Vec<_> = Axis::ALL
+        .into_par_iter()
+        .map(|axis| {
+            let value = axis_maximum(
+                axis,
+                motor_config,
+                motor_data,
+                amperage_cap,
+                epsilon,
+                AXIS_MAXIMUM_GUESS.into(),
+            );
+
+            (axis, value)
+        })
+        .collect();
+
+    maximums.into_iter().collect()
+}
+
+impl<MotorId: Hash + Ord + Clone + Debug, D: Number> MotorConfig<MotorId, D> {
+    /// The most negative and most positive force achievable along each axis without exceeding `amperage_cap`
+    ///
+    /// Unlike `axis_maximums` this captures asymmetric envelopes, motors typically produce less thrust in reverse
+    pub fn reachable_movement_bounds(
+        &self,
+        motor_data: &MotorData,
+        amperage_cap: f32,
+    ) -> HashMap<Axis, (D, D)> {
+        Axis::ALL
+            .into_iter()
+            .map(|axis| {
+                let min = axis_maximum(
+                    axis,
+                    self,
+                    motor_data,
+                    amperage_cap,
+                    AXIS_BOUNDS_EPSILON,
+                    (-AXIS_MAXIMUM_GUESS).into(),
+                );
+                let max = axis_maximum(
+                    axis,
+                    self,
+                    motor_data,
+                    amperage_cap,
+                    AXIS_BOUNDS_EPSILON,
+                    AXIS_MAXIMUM_GUESS.into(),
+                );
+
+                (axis, (min, max))
+            })
+            .collect()
+    }
+}
+
+const AXIS_BOUNDS_EPSILON: f32 = 0.01;
+
+const AXIS_MAXIMUM_GUESS: f32 = 25.0;
+
+fn axis_maximum<D: Number, MotorId: Hash + Ord + Clone + Debug>(
+    axis: Axis,
+    motor_config: &MotorConfig<MotorId, D>,
+    motor_data: &MotorData,
+    amperage_cap: f32,
+    epsilon: f32,
+    initial: D,
+) -> D {
+    let forces = reverse_solve(axis.movement::<D>() * initial, motor_config);
+    let cmds = forces_to_cmds(forces, motor_config, motor_data);
+    let scale = binary_search_force_ratio(&cmds, motor_config, motor_data, amperage_cap, epsilon);
+
+    scale * initial
+}
+
+/// Caches the results of `axis_maximums`
+///
+/// The previous maximums are used as the starting point for the next solve, so small changes to the
+/// amperage cap or motor config converge in a few iterations
+pub struct AxisMaximumsSolver<MotorId, D: Number> {
+    motor_config: MotorConfig<MotorId, D>,
+    motor_data: MotorData,
+    amperage_cap: f32,
+    epsilon: f32,
+
+    maximums: HashMap<Axis, D>,
+}
+
+impl<D: Number, MotorId: Hash + Ord + Clone + Debug> AxisMaximumsSolver<MotorId, D> {
+    pub fn new(
+        motor_config: MotorConfig<MotorId, D>,
+        motor_data: MotorData,
+        amperage_cap: f32,
+        epsilon: f32,
+    ) -> Self {
+        let maximums = axis_maximums(&motor_config, &motor_data, amperage_cap, epsilon);
+
+        Self {
+            motor_config,
+            motor_data,
+            amperage_cap,
+            epsilon,
+            maximums,
+        }
+    }
+
+    /// Resolves every axis for a new amperage cap
+    #[instrument(level = "trace", skip(self))]
+    pub fn update(&mut self, amperage_cap: f32) {
+        self.amperage_cap = amperage_cap;
+
+        for axis in Axis::ALL {
+            self.update_axis(axis);
+        }
+    }
+
+    /// Replaces the motor config and resolves every axis
+    #[instrument(level = "trace", skip_all)]
+    pub fn set_motor_config(&mut self, motor_config: MotorConfig<MotorId, D>) {
+        self.motor_config = motor_config;
+        self.update(self.amperage_cap);
+    }
+
+    /// Resolves a single axis, leaving the cached values for the other axes untouched
+    #[instrument(level = "trace", skip(self))]
+    pub fn update_axis(&mut self, axis: Axis) {
+        let initial = match self.maximums.get(&axis) {
+            Some(&previous) if previous.re() > 0.0 && previous.re().is_finite() => previous,
+            _ => AXIS_MAXIMUM_GUESS.into(),
+        };
+
+        let value = axis_maximum(
+            axis,
+            &self.motor_config,
+            &self.motor_data,
+            self.amperage_cap,
+            self.epsilon,
+            initial,
+        );
+
+        self.maximums.insert(axis, value);
+    }
+
+    pub fn maximum(&self, axis: Axis) -> D {
+        self.maximums[&axis]
+    }
+
+    pub fn maximums(&self) -> &HashMap<Axis, D> {
+        &self.maximums
+    }
+
+    pub fn amperage_cap(&self) -> f32 {
+        self.amperage_cap
+    }
+
+    pub fn motor_config(&self) -> &MotorConfig<MotorId, D> {
+        &self.motor_config
+    }
+
+    pub fn motor_data(&self) -> &MotorData {
+        &self.motor_data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
+    use nalgebra::{vector, Vector3};
+    use num_dual::Dual32;
+
+    use crate::{
+        fixtures, motor_preformance, solve::forward, x3d::X3dMotorId, Motor, MotorConfig, Movement,
+    };
+    use test::Bencher;
+
+    use super::*;
+
+    #[test]
+    fn reverse_solve_vec_is_in_config_order() {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let movement = Movement {
+            force: vector![0.6, 0.0, 0.3],
+            torque: vector![0.2, 0.1, 0.3],
+        };
+        let forces = reverse_solve(movement, &motor_config);
+        let ordered = reverse_solve_vec(movement, &motor_config);
+
+        assert_eq!(ordered.len(), forces.len());
+        for ((id, _), force) in motor_config.motors().zip(&ordered) {
+            assert_eq!(forces.get(id), Some(force));
+        }
+
+        let movement_error = movement - forward::forward_solve_ordered(&motor_config, &ordered);
+        assert!(movement_error.force.norm_squared() < 0.0001);
+        assert!(movement_error.torque.norm_squared() < 0.0001);
+    }
+
+    #[test]
+    fn reverse_solve_checked_saturation() {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![1.0, 1.0, 1.0]);
+
+        let movement = Movement {
+            force: vector![-0.6, 0.5, 0.3],
+            torque: vector![0.2, 0.1, 0.4],
+        };
+
+        let checked = reverse_solve_checked(movement, &motor_config, &motor_data);
+        assert!(!checked.is_saturated());
+
+        let checked = reverse_solve_checked(movement * 1000.0, &motor_config, &motor_data);
+        assert!(checked.is_saturated());
+        assert_eq!(checked.forces.len(), checked.saturated.len());
+    }
+
+    #[test]
+    fn forces_to_cmds_voltage_sag() {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![1.0, 1.0, 1.0]);
+
+        let movement = Movement {
+            force: vector![-0.6, 0.5, 0.3],
+            torque: vector![0.2, 0.1, 0.4],
+        };
+
+        let forces = reverse_solve(movement, &motor_config);
+        let nominal = forces_to_cmds_at_voltage(
+            forces.clone(),
+            &motor_config,
+            &motor_data,
+            motor_data.default_voltage(),
+        );
+        let sagged = forces_to_cmds_at_voltage(forces, &motor_config, &motor_data, 10.0);
+
+        for (motor_id, cmd) in &nominal {
+            let sagged_cmd = &sagged[motor_id];
+
+            // A drained battery needs more throttle for the same force
+            assert!((sagged_cmd.pwm - 1500.0).abs() >= (cmd.pwm - 1500.0).abs());
+            assert!((sagged_cmd.force - cmd.force).abs() < 0.001);
+        }
+
+        // Unusable voltage readings fall back to the default voltage
+        let forces = reverse_solve(movement, &motor_config);
+        let unscaled = forces_to_cmds(forces.clone(), &motor_config, &motor_data);
+        for voltage in [0.0, -12.0, f32::NAN, f32::INFINITY] {
+            let cmds =
+                forces_to_cmds_at_voltage(forces.clone(), &motor_config, &motor_data, voltage);
+
+            for (motor_id, cmd) in &unscaled {
+                assert_eq!(cmds[motor_id].pwm, cmd.pwm);
+                assert_eq!(cmds[motor_id].current, cmd.current);
+            }
+        }
+    }
+
+    #[test]
+    fn newton_raphson_matches_binary_search() {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        for (movement, amperage_cap) in [
+            (
+                Movement {
+                    force: vector![6.0, 0.0, 3.0],
+                    torque: vector![2.0, 1.0, 3.0],
+                },
+                20.0,
+            ),
+            (
+                Movement {
+                    force: vector![0.6, 0.5, 0.3],
+                    torque: vector![0.2, 0.1, 0.4],
+                },
+                10.0,
+            ),
+        ] {
+            let forces = reverse_solve(movement, &motor_config);
+            let cmds = forces_to_cmds(forces, &motor_config, &motor_data);
+
+            let binary =
+                binary_search_force_ratio(&cmds, &motor_config, &motor_data, amperage_cap, 0.0001);
+            let (newton, iterations) = newton_raphson_force_ratio_counted(
+                &cmds,
+                &motor_config,
+                &motor_data,
+                amperage_cap,
+                0.0001,
+            );
+
+            assert!(iterations <= 8, "took {iterations} iterations");
+            for cmd in cmds.values() {
+                assert!((cmd.force * binary - cmd.force * newton).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn newton_raphson_propagates_derivatives() {
+        let seed_motor = fixtures::seed_motor(vector![0.3, 0.5, 0.4]);
+
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+        let dual_config = MotorConfig::<X3dMotorId, Dual32>::new(
+            Motor {
+                position: seed_motor.position.map(Dual32::from),
+                orientation: seed_motor.orientation.map(Dual32::from),
+                direction: seed_motor.direction,
+            },
+            Vector3::zeros(),
+        );
+
+        let movement = Movement {
+            force: vector![6.0, 0.0, 3.0],
+            torque: vector![2.0, 1.0, 3.0],
+        };
+        let forces = reverse_solve(movement, &motor_config);
+        let cmds = forces_to_cmds(forces, &motor_config, &motor_data);
+
+        // Differentiate with respect to a uniform scale on every force, since the scaled forces
+        // must still land on the same current the ratio's derivative is minus the ratio
+        let dual_cmds = cmds
+            .iter()
+            .map(|(motor_id, cmd)| {
+                let cmd = motor_preformance::MotorRecord {
+                    force: Dual32::new(cmd.force, cmd.force),
+                    ..cmd.lerp(cmd, Dual32::from(0.0))
+                };
+
+                (*motor_id, cmd)
+            })
+            .collect();
+
+        let ratio = newton_raphson_force_ratio(&dual_cmds, &dual_config, &motor_data, 20.0, 0.0001);
+
+        assert!((ratio.eps + ratio.re).abs() < 1e-3, "{ratio:?}");
+    }
+
+    #[test]
+    fn clamp_amperage_per_motor() {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![1.0, 1.0, 1.0]);
+
+        let movement = Movement {
+            force: vector![-3.0, 2.5, 1.5],
+            torque: vector![0.2, 0.1, 0.4],
+        };
+
+        let forces = reverse_solve(movement, &motor_config);
+        let cmds = forces_to_cmds(forces, &motor_config, &motor_data);
+
+        let (&limited_id, limited) = cmds
+            .iter()
+            .max_by(|a, b| a.1.current.total_cmp(&b.1.current))
+            .unwrap();
+        let motor_cap = limited.current / 2.0;
+
+        let mut per_motor_cap = stable_hashmap::StableHashMap::default();
+        per_motor_cap.insert(limited_id, motor_cap);
+
+        // The global cap is not binding, only the per motor cap is
+        let clamped = super::clamp_amperage_per_motor(
+            cmds.clone(),
+            &motor_config,
+            &motor_data,
+            100.0,
+            &per_motor_cap,
+            0.01,
+        );
+
+        assert!(clamped[&limited_id].current <= motor_cap + 0.01);
+
+        // Every motor is scaled by the same ratio
+        let ratio = clamped[&limited_id].force / cmds[&limited_id].force;
+        for (motor_id, cmd) in &clamped {
+            assert!((cmd.force - cmds[motor_id].force * ratio).abs() < 0.001);
+        }
+
+        // A tighter global cap takes over
+        let amperage_cap = motor_cap;
+        let clamped = super::clamp_amperage_per_motor(
+            cmds.clone(),
+            &motor_config,
+            &motor_data,
+            amperage_cap,
+            &per_motor_cap,
+            0.01,
+        );
+
+        let amperage = clamped.values().map(|it| it.current).sum::<f32>();
+        assert!((amperage - amperage_cap).abs() < 0.01);
+    }
+
+    #[test]
+    fn clamp_forces_per_motor() {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![1.0, 1.0, 1.0]);
+
+        let movement = Movement {
+            force: vector![-3.0, 2.5, 1.5],
+            torque: vector![0.2, 0.1, 0.4],
+        };
+
+        let forces = reverse_solve(movement, &motor_config);
+        let cmds = forces_to_cmds(forces, &motor_config, &motor_data);
+
+        let (&limited_id, limited) = cmds
+            .iter()
+            .max_by(|a, b| a.1.force.abs().total_cmp(&b.1.force.abs()))
+            .unwrap();
+        let max = limited.force.abs() / 2.0;
+
+        let mut per_motor_max = stable_hashmap::StableHashMap::default();
+        per_motor_max.insert(limited_id, max);
+
+        let clamped = clamp_forces(
+            cmds.clone(),
+            &motor_config,
+            &motor_data,
+            &per_motor_max,
+            0.01,
+        );
+
+        assert!(clamped[&limited_id].force.abs() <= max + 0.001);
+
+        let amperage_before = cmds.values().map(|it| it.current).sum::<f32>();
+        let amperage_after = clamped.values().map(|it| it.current).sum::<f32>();
+        assert!((amperage_before - amperage_after).abs() < 0.05);
+
+        for (motor_id, cmd) in &cmds {
+            if *motor_id != limited_id {
+                assert!(clamped[motor_id].force.abs() >= cmd.force.abs());
+            }
+        }
+    }
+
+    #[test]
+    fn reverse_solve_nonlinear_redistributes_saturated_motors() {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        // One motor is much weaker than the rest, the pseudo inverse asks it for more than it can produce
+        let motor_data = motor_config
+            .motors()
+            .enumerate()
+            .map(|(idx, (id, _))| {
+                let max_force = if idx == 0 { 3.0 } else { 36.4 };
+                (
+                    *id,
+                    motor_preformance::MotorData::synthetic_linear(
+                        max_force,
+                        17.0,
+                        (1100.0, 1900.0),
+                    ),
+                )
+            })
+            .collect::<StableHashMap<_, _>>();
+
+        let movement = Movement {
+            force: vector![30.0, 10.0, 0.0],
+            torque: vector![0.0, 0.0, 0.0],
+        };
+        // What the motors actually produce once the esc clamps the pwm to the range it accepts
+        let achieved = |cmds: &HashMap<X3dMotorId, MotorRecord<f32>>| {
+            let forces = cmds
+                .iter()
+                .map(|(id, it)| {
+                    let direction = motor_config.motor(id).unwrap().direction;
+                    let record = motor_data[id].lookup_by_pwm(
+                        it.pwm.clamp(1100.0, 1900.0),
+                        Interpolation::LerpDirection(direction),
+                    );
+
+                    (*id, record.force)
+                })
+                .collect();
+
+            forward::forward_solve(&motor_config, &forces)
+        };
+        let error = |cmds: &HashMap<X3dMotorId, MotorRecord<f32>>| {
+            let error = achieved(cmds) - movement;
+            (error.force.norm_squared() + error.torque.norm_squared()).sqrt()
+        };
+
+        let linear = forces_to_cmds(
+            reverse_solve(movement, &motor_config),
+            &motor_config,
+            &motor_data,
+        );
+        let nonlinear = reverse_solve_nonlinear(movement, &motor_config, &motor_data, 1000.0, 0.01);
+
+        assert!(error(&linear) > 1.0, "{}", error(&linear));
+        assert!(error(&nonlinear) < 0.01, "{}", error(&nonlinear));
+
+        // Under a tight cap the movement is scaled down without changing direction
+        let nonlinear = reverse_solve_nonlinear(movement, &motor_config, &motor_data, 20.0, 0.01);
+        let current = nonlinear.values().map(|it| it.current).sum::<f32>();
+        assert!((current - 20.0).abs() < 0.05, "{current}");
+
+        let actual = achieved(&nonlinear);
+        let scale = actual.force.x / movement.force.x;
+        assert!(scale > 0.0 && scale < 1.0);
+        assert!((actual.force - movement.force * scale).norm() < 0.01);
+        assert!(actual.torque.norm() < 0.01);
+    }
+
+    #[test]
+    fn heterogeneous_motor_data() {
+        let motor_config = fixtures::x3d_config(vector![1.0, 1.0, 1.0]);
+
+        let records = fixtures::motor_records();
+        let stronger = records
+            .iter()
+            .map(|record| motor_preformance::MotorRecord {
+                force: record.force * 2.0,
+                ..*record
+            })
+            .collect::<Vec<_>>();
+
+        let small =
+            motor_preformance::MotorData::from_records(records.clone()).expect("Build motor data");
+        let large =
+            motor_preformance::MotorData::from_records(stronger.clone()).expect("Build motor data");
+
+        let motor_data = motor_config
+            .motors()
+            .enumerate()
+            .map(|(idx, (id, _))| {
+                let table = if idx % 2 == 0 {
+                    records.clone()
+                } else {
+                    stronger.clone()
+                };
+
+                (
+                    *id,
+                    motor_preformance::MotorData::from_records(table).expect("Build motor data"),
+                )
+            })
+            .collect::<stable_hashmap::StableHashMap<_, _>>();
+
+        let movement = Movement {
+            force: vector![-6.0, 5.0, 3.0],
+            torque: vector![0.2, 0.1, 0.4],
+        };
+
+        let forces = reverse_solve(movement, &motor_config);
+        let cmds = forces_to_cmds(forces.clone(), &motor_config, &motor_data);
+
+        for (idx, (id, motor)) in motor_config.motors().enumerate() {
+            let table = if idx % 2 == 0 { &small } else { &large };
+            let expected = table.lookup_by_force(
+                forces[id],
+                motor_preformance::Interpolation::LerpDirection(motor.direction),
+            );
+
+            assert_eq!(cmds[id].pwm, expected.pwm);
+            assert_eq!(cmds[id].current, expected.current);
+        }
+
+        let amperage = cmds.values().map(|it| it.current).sum::<f32>();
+        assert!(amperage > 2.0);
+
+        let clamped = clamp_amperage(cmds, &motor_config, &motor_data, 2.0, 0.01);
+        let amperage = clamped.values().map(|it| it.current).sum::<f32>();
+        assert!((amperage - 2.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn clamp_amperage_prioritized_translation() {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let movement = Movement {
+            force: vector![2.0, 3.0, -1.0],
+            torque: vector![1.0, -0.5, 2.0],
+        };
+
+        let forces = reverse_solve(movement, &motor_config);
+        let cmds = forces_to_cmds(forces, &motor_config, &motor_data);
+
+        let force_only = forces_to_cmds(
+            reverse_solve(
+                Movement {
+                    force: movement.force,
+                    torque: Vector3::zeros(),
+                },
+                &motor_config,
+            ),
+            &motor_config,
+            &motor_data,
+        );
+        let force_only_amperage = force_only.values().map(|it| it.current).sum::<f32>();
+        let total_amperage = cmds.values().map(|it| it.current).sum::<f32>();
+        assert!(force_only_amperage < total_amperage);
+
+        for (amperage_cap, exact_force) in [
+            ((force_only_amperage + total_amperage) / 2.0, true),
+            (force_only_amperage / 2.0, false),
+        ] {
+            let clamped = clamp_amperage_prioritized(
+                cmds.clone(),
+                &motor_config,
+                &motor_data,
+                amperage_cap,
+                0.01,
+                AxisPriority::Translation,
+            );
+
+            let amperage = clamped.values().map(|it| it.current).sum::<f32>();
+            assert!(amperage <= amperage_cap + 0.05);
+
+            let forces = clamped
+                .iter()
+                .map(|(motor, data)| (*motor, data.force))
+                .collect();
+            let actual = forward::forward_solve(&motor_config, &forces);
+
+            let direction_error = actual.force.normalize() - movement.force.normalize();
+            assert!(direction_error.norm() < 0.001);
+            assert!(actual.torque.norm() < movement.torque.norm());
+
+            if exact_force {
+                assert!((actual.force - movement.force).norm() < 0.001);
+            }
+        }
+    }
+
+    #[test]
+    fn forces_to_cmds_deadband() {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![1.0, 1.0, 1.0]);
+
+        let deadband = 25.0;
+        let forces: stable_hashmap::StableHashMap<X3dMotorId, f32> = [
+            (X3dMotorId::FrontLeftTop, 0.001),
+            (X3dMotorId::FrontRightTop, -0.001),
+            (X3dMotorId::BackLeftTop, 0.0),
+            (X3dMotorId::BackRightTop, 10.0),
+        ]
+        .into_iter()
+        .collect();
+
+        let plain = forces_to_cmds(forces.clone(), &motor_config, &motor_data);
+        let cmds =
+            forces_to_cmds_with_deadband(forces.clone(), &motor_config, &motor_data, deadband);
+
+        let mut snapped = 0;
+        for (motor_id, cmd) in &cmds {
+            let offset = (cmd.pwm - 1500.0).abs();
+
+            if *motor_id == X3dMotorId::BackLeftTop {
+                assert_eq!(cmd.pwm, plain[motor_id].pwm);
+            } else {
+                assert!(offset >= deadband - 0.0001, "{motor_id:?}: {}", cmd.pwm);
+            }
+
+            if *motor_id == X3dMotorId::BackRightTop {
+                assert_eq!(cmd.pwm, plain[motor_id].pwm);
+            }
+
+            // Snapping must not flip which way the motor spins
+            let plain_offset = plain[motor_id].pwm - 1500.0;
+            if plain_offset != 0.0 {
+                assert_eq!((cmd.pwm - 1500.0).signum(), plain_offset.signum());
+            }
+
+            // The rest of a snapped record must describe the snapped pwm
+            if offset < plain_offset.abs() + 0.0001 {
+                continue;
+            }
+
+            let requested = forces[motor_id];
+            assert!((cmd.pwm - (1500.0 + deadband * plain_offset.signum())).abs() < 0.001);
+            assert!(cmd.force.abs() > requested.abs());
+            assert_eq!(cmd.force.signum(), requested.signum());
+            assert!(cmd.current > plain[motor_id].current);
+            assert!(cmd.current > 0.0);
+            snapped += 1;
+        }
+
+        assert_eq!(snapped, 2);
+    }
+
+    #[test]
+    fn reachable_movement_bounds_x3d() {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let bounds = motor_config.reachable_movement_bounds(&motor_data, 15.0);
+        let maximums = axis_maximums(&motor_config, &motor_data, 15.0, 0.01);
+
+        for axis in Axis::ALL {
+            let (min, max) = bounds[&axis];
+
+            assert!(min < 0.0 && max > 0.0, "{axis:?}: {min} {max}");
+            assert!((max - maximums[&axis]).abs() < 0.01, "{axis:?}");
+
+            let forces = reverse_solve(axis.movement::<f32>() * min, &motor_config);
+            let cmds = forces_to_cmds(forces, &motor_config, &motor_data);
+            let current = cmds.values().map(|it| it.current).sum::<f32>();
+            assert!((current - 15.0).abs() < 0.1, "{axis:?}: {current}");
+        }
+    }
+
+    #[test]
+    fn axis_maximums_solver_matches_cold() {
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+        let motor_data = fixtures::motor_data();
+
+        let mut solver = AxisMaximumsSolver::new(motor_config.clone(), motor_data, 20.0, 0.01);
+        solver.update(15.0);
+
+        let cold = axis_maximums(&motor_config, solver.motor_data(), 15.0, 0.01);
+        for (axis, maximum) in cold {
+            assert!((solver.maximum(axis) - maximum).abs() < 0.05, "{axis:?}");
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn axis_maximums_par_matches_sequential() {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let sequential = axis_maximums(&motor_config, &motor_data, 15.0, 0.01);
+        let parallel = axis_maximums_par(&motor_config, &motor_data, 15.0, 0.01);
+
+        assert_eq!(sequential, parallel);
+    }
+
+    #[bench]
+    fn bench_axis_maximums_cold(b: &mut Bencher) {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let mut amperage_cap = 20.0;
+        b.iter(|| {
+            amperage_cap = 41.0 - amperage_cap;
+            axis_maximums(&motor_config, &motor_data, amperage_cap, 0.01)
+        });
+    }
+
+    #[bench]
+    fn bench_axis_maximums_warm(b: &mut Bencher) {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let mut solver = AxisMaximumsSolver::new(motor_config, motor_data, 20.0, 0.01);
+
+        let mut amperage_cap = 20.0;
+        b.iter(|| {
+            amperage_cap = 41.0 - amperage_cap;
+            solver.update(amperage_cap);
+        });
+    }
+
+    #[bench]
+    fn bench_force_ratio_binary_search(b: &mut Bencher) {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let movement = Movement {
+            force: vector![6.0, 0.0, 3.0],
+            torque: vector![2.0, 1.0, 3.0],
+        };
+
+        let forces = reverse_solve(movement, &motor_config);
+        let cmds = forces_to_cmds(forces, &motor_config, &motor_data);
+
+        b.iter(|| binary_search_force_ratio(&cmds, &motor_config, &motor_data, 20.0, 0.01));
+    }
+
+    #[bench]
+    fn bench_force_ratio_newton_raphson(b: &mut Bencher) {
+        let motor_data = fixtures::motor_data();
+        let motor_config = fixtures::x3d_config(vector![0.3, 0.5, 0.4]);
+
+        let movement = Movement {
+            force: vector![6.0, 0.0, 3.0],
+            torque: vector![2.0, 1.0, 3.0],
+        };
+
+        let forces = reverse_solve(movement, &motor_config);
+        let cmds = forces_to_cmds(forces, &motor_config, &motor_data);
+
+        b.iter(|| newton_raphson_force_ratio(&cmds, &motor_config, &motor_data, 20.0, 0.01));
+    }
 }
@@ -16,16 +16,109 @@ pub enum VectorTransform {
     ReflectXY,
     ReflectYZ,
     ReflectXZ,
+    /// Counter clockwise rotation about the axis by an angle in radians, viewed from the positive side
+    RotateX(f32),
+    RotateY(f32),
+    RotateZ(f32),
 }
 
 impl VectorTransform {
     pub fn transform<D: Number>(&self, vec: Vector3<D>) -> Vector3<D> {
         let [[x, y, z]] = vec.data.0;
 
-        match self {
+        match *self {
             VectorTransform::ReflectXY => vector![x, y, -z],
             VectorTransform::ReflectYZ => vector![-x, y, z],
             VectorTransform::ReflectXZ => vector![x, -y, z],
+            VectorTransform::RotateX(angle) => {
+                let (sin, cos) = (D::from(angle.sin()), D::from(angle.cos()));
+                vector![x, y * cos - z * sin, y * sin + z * cos]
+            }
+            VectorTransform::RotateY(angle) => {
+                let (sin, cos) = (D::from(angle.sin()), D::from(angle.cos()));
+                vector![x * cos + z * sin, y, -x * sin + z * cos]
+            }
+            VectorTransform::RotateZ(angle) => {
+                let (sin, cos) = (D::from(angle.sin()), D::from(angle.cos()));
+                vector![x * cos - y * sin, x * sin + y * cos, z]
+            }
         }
     }
+
+    /// Reflections mirror the motor, so its propeller must spin the other way to keep the same thrust
+    pub fn is_reflection(&self) -> bool {
+        matches!(
+            self,
+            VectorTransform::ReflectXY | VectorTransform::ReflectYZ | VectorTransform::ReflectXZ
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_3};
+
+    use nalgebra::{vector, Vector3};
+
+    use crate::{solve, Direction, Motor, MotorConfig};
+
+    use super::VectorTransform;
+
+    #[test]
+    fn rotations_follow_right_hand_rule() {
+        let x = vector![1.0f32, 0.0, 0.0];
+        let y = vector![0.0f32, 1.0, 0.0];
+        let z = vector![0.0f32, 0.0, 1.0];
+
+        let cases = [
+            (VectorTransform::RotateX(FRAC_PI_2), y, z),
+            (VectorTransform::RotateY(FRAC_PI_2), z, x),
+            (VectorTransform::RotateZ(FRAC_PI_2), x, y),
+        ];
+
+        for (transform, from, to) in cases {
+            let rotated = transform.transform(from);
+            assert!((rotated - to).norm() < 1e-6, "{transform:?}: {rotated}");
+            assert!(!transform.is_reflection());
+        }
+    }
+
+    #[test]
+    fn rotated_config_roundtrip() {
+        let seed = Motor {
+            position: vector![0.2, 0.0, 0.05],
+            orientation: vector![0.0, 1.0, 0.5].normalize(),
+            direction: Direction::Clockwise,
+        };
+
+        // Six motors at 60 degree spacing around Z, alternating spin direction
+        let motors = (0..6).map(|idx| {
+            let transform = VectorTransform::RotateZ(FRAC_PI_3 * idx as f32);
+            let direction = seed.direction.flip_n(idx);
+
+            (
+                idx as u8,
+                Motor {
+                    position: transform.transform(seed.position),
+                    orientation: transform.transform(seed.orientation),
+                    direction,
+                },
+            )
+        });
+        let config = MotorConfig::<u8, f32>::new_raw(motors, Vector3::zeros());
+
+        for (_, motor) in config.motors() {
+            assert!((motor.position.xy().norm() - 0.2).abs() < 1e-6);
+        }
+
+        // Rotationally symmetric configs are not always fully controllable, stay within the reachable movements
+        let forces = (0..6).map(|idx| (idx, idx as f32 * 0.3 - 0.7)).collect();
+        let movement = solve::forward::forward_solve(&config, &forces);
+
+        let forces = solve::reverse::reverse_solve(movement, &config);
+        let actual = solve::forward::forward_solve(&config, &forces);
+
+        assert!((movement.force - actual.force).norm() < 1e-3);
+        assert!((movement.torque - actual.torque).norm() < 1e-3);
+    }
 }
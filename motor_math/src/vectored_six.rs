@@ -0,0 +1,79 @@
+use bevy_reflect::{Reflect, ReflectDeserialize, ReflectSerialize};
+use nalgebra::Vector3;
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use serde::{Deserialize, Serialize};
+
+use crate::{utils::VectorTransform, Motor, MotorConfig, Number};
+
+/// Motor ids for four vectored lateral motors and two vertical motors, one on each side
+///
+/// The vertical motors are on the same line, so pitch can not be controlled
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialOrd,
+    Ord,
+    PartialEq,
+    Eq,
+    Hash,
+    IntoPrimitive,
+    TryFromPrimitive,
+    Serialize,
+    Deserialize,
+    Reflect,
+)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq, Hash)]
+#[repr(u8)]
+pub enum VectoredSixMotorId {
+    LateralFrontLeft,
+    LateralFrontRight,
+    LateralBackLeft,
+    LateralBackRight,
+    VerticalLeft,
+    VerticalRight,
+}
+
+impl<D: Number> MotorConfig<VectoredSixMotorId, D> {
+    pub fn new(
+        lateral_front_right: Motor<D>,
+        vertical_right: Motor<D>,
+        center_mass: Vector3<D>,
+    ) -> Self {
+        #[rustfmt::skip]
+        let motors = [
+            (VectoredSixMotorId::LateralFrontRight, lateral_front_right, &[].as_slice()),
+            (VectoredSixMotorId::LateralFrontLeft, lateral_front_right, &[VectorTransform::ReflectYZ].as_slice()),
+            (VectoredSixMotorId::LateralBackRight, lateral_front_right, &[VectorTransform::ReflectXZ].as_slice()),
+            (VectoredSixMotorId::LateralBackLeft, lateral_front_right, &[VectorTransform::ReflectYZ, VectorTransform::ReflectXZ].as_slice()),
+
+            (VectoredSixMotorId::VerticalRight, vertical_right, &[].as_slice()),
+            (VectoredSixMotorId::VerticalLeft, vertical_right, &[VectorTransform::ReflectYZ].as_slice()),
+        ];
+
+        let motors = motors.into_iter().map(|(motor_id, seed, transforms)| {
+            let (position, orientation) = transforms.iter().fold(
+                (seed.position, seed.orientation),
+                |(position, orientation), transform| {
+                    (
+                        transform.transform(position),
+                        transform.transform(orientation),
+                    )
+                },
+            );
+
+            (
+                motor_id,
+                Motor {
+                    position,
+                    orientation,
+                    direction: seed
+                        .direction
+                        .flip_n(transforms.iter().filter(|it| it.is_reflection()).count() as _),
+                },
+            )
+        });
+
+        Self::new_raw(motors, center_mass)
+    }
+}
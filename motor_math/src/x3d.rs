@@ -66,7 +66,9 @@ impl<D: Number> MotorConfig<X3dMotorId, D> {
                 Motor {
                     position,
                     orientation,
-                    direction: front_right_top.direction.flip_n(transforms.len() as _),
+                    direction: front_right_top
+                        .direction
+                        .flip_n(transforms.iter().filter(|it| it.is_reflection()).count() as _),
                 },
             )
         });
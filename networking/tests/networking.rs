@@ -2,7 +2,7 @@ use std::{
     net::ToSocketAddrs,
     sync::{
         atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
-        Barrier,
+        mpsc, Barrier,
     },
     thread,
     time::Duration,
@@ -209,6 +209,97 @@ fn test_real_server_client() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Waits for the first event matching `filter`, skipping any others
+fn wait_for<T>(
+    events: &mpsc::Receiver<Event<Protocol>>,
+    mut filter: impl FnMut(Event<Protocol>) -> Option<T>,
+) -> anyhow::Result<T> {
+    loop {
+        let event = events
+            .recv_timeout(Duration::from_secs(5))
+            .context("Timed out waiting for event")?;
+
+        if let Some(value) = filter(event) {
+            return Ok(value);
+        }
+    }
+}
+
+#[test]
+fn server_accepts_after_peer_disconnects() -> anyhow::Result<()> {
+    let addr = ("127.0.0.1", 6150)
+        .to_socket_addrs()?
+        .next()
+        .context("Find SocketAddr")?;
+
+    let server = Networking::<Protocol>::new()?;
+    let server_messenger = server.messenger();
+    let (server_tx, server_events) = mpsc::channel();
+
+    let client = Networking::<Protocol>::new()?;
+    let client_messenger = client.messenger();
+    let (client_tx, client_events) = mpsc::channel();
+
+    thread::scope(|scope| -> anyhow::Result<()> {
+        scope.spawn(|| server.start(|event| server_tx.send(event).unwrap()));
+        scope.spawn(|| client.start(|event| client_tx.send(event).unwrap()));
+
+        let result = (|| -> anyhow::Result<()> {
+            server_messenger.bind_at(addr)?;
+            server_messenger.wake()?;
+
+            let mut accepted_tokens = Vec::new();
+
+            for round in 0..3u64 {
+                client_messenger.connect_to(addr)?;
+                client_messenger.wake()?;
+
+                let accepted = wait_for(&server_events, |event| match event {
+                    Event::Accepted(token, _) => Some(token),
+                    _ => None,
+                })?;
+                let connected = wait_for(&client_events, |event| match event {
+                    Event::Conected(token, _) => Some(token),
+                    _ => None,
+                })?;
+
+                // The new connection is usable
+                client_messenger.send_packet(connected, Protocol::Ping(round))?;
+                client_messenger.wake()?;
+
+                let ping = wait_for(&server_events, |event| match event {
+                    Event::Data(token, Protocol::Ping(id)) if token == accepted => Some(id),
+                    _ => None,
+                })?;
+                assert_eq!(ping, round);
+
+                // Drop the connection from the client side, the server should notice and keep listening
+                client_messenger.disconnect(connected)?;
+                client_messenger.wake()?;
+
+                wait_for(&server_events, |event| match event {
+                    Event::Disconnect(token) if token == accepted => Some(()),
+                    _ => None,
+                })?;
+
+                accepted_tokens.push(accepted);
+            }
+
+            accepted_tokens.dedup();
+            assert_eq!(accepted_tokens.len(), 3);
+
+            Ok(())
+        })();
+
+        server_messenger.shutdown()?;
+        server_messenger.wake()?;
+        client_messenger.shutdown()?;
+        client_messenger.wake()?;
+
+        result
+    })
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 enum Protocol {
     Ping(u64),
@@ -0,0 +1,39 @@
+//! Writes JSON schemas for `robot.toml` to `schema/`, so editors can complete and check configs
+//!
+//! Run with `cargo run -p robot --bin generate_schema --features schema`
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use schemars::{schema::RootSchema, schema_for};
+
+// The config only depends on crates outside of robot, share it without making robot a library
+#[allow(dead_code)]
+#[path = "../config.rs"]
+mod config;
+
+fn main() -> anyhow::Result<()> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("../schema");
+    fs::create_dir_all(&directory).context("Create schema directory")?;
+
+    write_schema(
+        &directory.join("robot.schema.json"),
+        schema_for!(config::RobotConfig),
+    )?;
+    write_schema(
+        &directory.join("robot_new.schema.json"),
+        schema_for!(robot_new::config::Config),
+    )?;
+
+    Ok(())
+}
+
+fn write_schema(path: &Path, schema: RootSchema) -> anyhow::Result<()> {
+    let mut json = serde_json::to_string_pretty(&schema).context("Serialize schema")?;
+    json.push('\n');
+
+    fs::write(path, json).with_context(|| format!("Write {}", path.display()))?;
+    println!("Wrote {}", path.display());
+
+    Ok(())
+}
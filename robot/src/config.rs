@@ -1,11 +1,19 @@
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+};
+
 use ahash::{HashMap, HashSet};
+use anyhow::{bail, Context};
 use bevy::{ecs::system::Resource, transform::components::Transform};
-use common::types::hw::PwmChannelId;
+use common::{components::JerkLimit, types::hw::PwmChannelId};
 use glam::{vec3, EulerRot, Quat, Vec3A};
 use motor_math::{blue_rov::HeavyMotorId, x3d::X3dMotorId, ErasedMotorId, Motor, MotorConfig};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[derive(Resource, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct RobotConfig {
     pub name: String,
     pub port: u16,
@@ -14,86 +22,392 @@ pub struct RobotConfig {
     pub servo_config: ServoConfigDefinition,
 
     pub motor_amperage_budget: f32,
-    pub jerk_limit: f32,
+    /// Force limits for individual motors
+    /// Motors without an entry are only limited by `motor_amperage_budget`
+    pub per_motor_force_cap: Option<Vec<MotorForceCap>>,
+    /// Motor performance tables keyed by the voltage they were measured at, ie `"12v" = "motor_data_12v.csv"`
+    /// Defaults to `motor_data.csv` at the voltage recorded in the file
+    pub motor_data: Option<HashMap<String, String>>,
+    pub jerk_limit: JerkLimitDefinition,
+    /// Seconds without fresh movement commands before the motors are stopped, defaults to 0.25
+    #[serde(default = "default_failsafe_timeout")]
+    pub failsafe_timeout: f32,
+    #[schemars(with = "[f32; 3]")]
     pub center_of_mass: Vec3A,
 
     pub cameras: HashMap<String, CameraDefinition>,
+
+    /// Defaults to a single probe on gpio 27
+    #[serde(default = "default_leak_probes")]
+    pub leak_probes: Vec<LeakProbeDefinition>,
+
+    /// Where the magnetometer calibration is loaded from on startup and saved to after calibrating
+    #[serde(default = "default_mag_calibration_path")]
+    pub mag_calibration_path: PathBuf,
+
+    #[serde(default)]
+    pub hw_stat: HwStatDefinition,
+}
+
+/// How often each group of system statistics is collected, in seconds
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(default)]
+pub struct HwStatDefinition {
+    /// Cpu usage, memory, load average and uptime
+    pub cpu_interval: f32,
+    /// Network totals and temperatures
+    pub network_interval: f32,
+    /// Disks and processes
+    pub process_interval: f32,
+    /// Only the processes using the most cpu are replicated
+    pub max_processes: usize,
+}
+
+impl Default for HwStatDefinition {
+    fn default() -> Self {
+        Self {
+            cpu_interval: 1.0,
+            network_interval: 2.0,
+            process_interval: 10.0,
+            max_processes: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct LeakProbeDefinition {
+    pub name: String,
+    pub gpio: u8,
+    pub active_high: bool,
+    #[serde(default)]
+    pub debounce: LeakDebounce,
+}
+
+/// How long a probe must read wet before it reports a leak
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(default)]
+pub struct LeakDebounce {
+    /// Milliseconds the probe must stay wet without a single dry read
+    pub threshold_ms: u32,
+    /// Minimum number of wet reads within that time, so one read spanning a stall is not enough
+    pub consecutive_samples: u32,
+}
+
+impl Default for LeakDebounce {
+    fn default() -> Self {
+        Self {
+            threshold_ms: 500,
+            consecutive_samples: 3,
+        }
+    }
+}
+
+fn default_failsafe_timeout() -> f32 {
+    0.25
+}
+
+fn default_mag_calibration_path() -> PathBuf {
+    "mag_calibration.toml".into()
+}
+
+fn default_leak_probes() -> Vec<LeakProbeDefinition> {
+    vec![LeakProbeDefinition {
+        name: "Leak".to_owned(),
+        gpio: 27,
+        active_high: true,
+        debounce: LeakDebounce::default(),
+    }]
+}
+
+/// Either one limit for every axis or one per axis, in `Axis::ALL` order
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(untagged)]
+pub enum JerkLimitDefinition {
+    Uniform(f32),
+    PerAxis([f32; 6]),
+}
+
+impl From<JerkLimitDefinition> for JerkLimit {
+    fn from(definition: JerkLimitDefinition) -> Self {
+        match definition {
+            JerkLimitDefinition::Uniform(limit) => JerkLimit::uniform(limit),
+            JerkLimitDefinition::PerAxis(limits) => JerkLimit(limits),
+        }
+    }
+}
+
+/// Where the robot reads its config from, relative to the working directory
+pub const CONFIG_PATH: &str = "robot.toml";
+
+impl RobotConfig {
+    /// Reads and parses a config, rejecting it if `validate` finds any problems
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let config = fs::read_to_string(path).context("Read config")?;
+        let config: RobotConfig = toml::from_str(&config).context("Parse config")?;
+
+        let errors = config.validate();
+        if !errors.is_empty() {
+            let errors: Vec<_> = errors.iter().map(|it| format!("  {it}")).collect();
+            bail!("Invalid config:\n{}", errors.join("\n"));
+        }
+
+        Ok(config)
+    }
+
+    /// The entries of `motor_data` with their voltages parsed, sorted by voltage
+    pub fn motor_data_tables(&self) -> anyhow::Result<Vec<(f32, &str)>> {
+        let mut tables = self
+            .motor_data
+            .iter()
+            .flatten()
+            .map(|(voltage, path)| {
+                let volts = voltage
+                    .trim_end_matches(['v', 'V'])
+                    .trim()
+                    .parse::<f32>()
+                    .with_context(|| format!("Bad motor data voltage {voltage:?}"))?;
+
+                Ok((volts, path.as_str()))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        tables.sort_by(|a, b| f32::total_cmp(&a.0, &b.0));
+
+        Ok(tables)
+    }
+
+    /// Checks for mistakes that parse fine but would misbehave once the robot is running
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
+        if self.motor_amperage_budget.is_nan() || self.motor_amperage_budget <= 0.0 {
+            errors.push(ConfigError::new(
+                "motor_amperage_budget",
+                format!(
+                    "must be greater than zero, got {}",
+                    self.motor_amperage_budget
+                ),
+            ));
+        }
+
+        if self.failsafe_timeout.is_nan() || self.failsafe_timeout <= 0.0 {
+            errors.push(ConfigError::new(
+                "failsafe_timeout",
+                format!("must be greater than zero, got {}", self.failsafe_timeout),
+            ));
+        }
+
+        let intervals = [
+            ("hw_stat.cpu_interval", self.hw_stat.cpu_interval),
+            ("hw_stat.network_interval", self.hw_stat.network_interval),
+            ("hw_stat.process_interval", self.hw_stat.process_interval),
+        ];
+        for (field, interval) in intervals {
+            if interval.is_nan() || interval <= 0.0 {
+                errors.push(ConfigError::new(
+                    field,
+                    format!("must be greater than zero, got {interval}"),
+                ));
+            }
+        }
+
+        for (idx, probe) in self.leak_probes.iter().enumerate() {
+            if probe.debounce.consecutive_samples == 0 {
+                errors.push(ConfigError::new(
+                    format!("leak_probes[{idx}].debounce.consecutive_samples"),
+                    "must be at least one",
+                ));
+            }
+        }
+
+        // Motors and servos share the PCA9685
+        let mut assignments = self.motor_config.pwm_channels();
+        assignments.extend(self.servo_config.servos.iter().map(|(name, servo)| {
+            (
+                format!("servo_config.servos.{name}.pwm_channel"),
+                servo.pwm_channel,
+            )
+        }));
+        assignments.sort();
+
+        let mut used: HashMap<PwmChannelId, &str> = HashMap::default();
+        for (field, channel) in &assignments {
+            if *channel >= PWM_CHANNELS {
+                errors.push(out_of_range(field.clone(), *channel));
+            } else if let Some(other) = used.get(channel) {
+                errors.push(ConfigError::new(
+                    field.clone(),
+                    format!("pwm channel {channel} is already used by {other}"),
+                ));
+            } else {
+                used.insert(*channel, field);
+            }
+        }
+
+        for (idx, cap) in self.per_motor_force_cap.iter().flatten().enumerate() {
+            if cap.pwm_channel >= PWM_CHANNELS {
+                errors.push(out_of_range(
+                    format!("per_motor_force_cap[{idx}].pwm_channel"),
+                    cap.pwm_channel,
+                ));
+            }
+        }
+
+        let mut cameras: Vec<_> = self.cameras.iter().collect();
+        cameras.sort_by_key(|(id, _)| *id);
+
+        let mut names: HashMap<&str, &str> = HashMap::default();
+        for (id, camera) in cameras {
+            if let Some(other) = names.get(camera.name.as_str()) {
+                errors.push(ConfigError::new(
+                    format!("cameras.{id:?}.name"),
+                    format!("{:?} is already the name of {other:?}", camera.name),
+                ));
+            } else {
+                names.insert(&camera.name, id);
+            }
+        }
+
+        errors
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// The PCA9685 has 16 channels
+const PWM_CHANNELS: PwmChannelId = 16;
+
+/// A problem with a single field of `robot.toml`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// Path to the field, ie `servo_config.servos.Claw1.pwm_channel`
+    pub field: String,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+fn out_of_range(field: String, channel: PwmChannelId) -> ConfigError {
+    ConfigError::new(
+        field,
+        format!(
+            "pwm channel {channel} does not exist, expected 0-{}",
+            PWM_CHANNELS - 1
+        ),
+    )
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub enum MotorConfigDefinition {
     X3d(X3dDefinition),
     BlueRov(BlueRovDefinition),
     Custom(CustomDefinition),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct X3dDefinition {
-    pub seed_motor: Motor,
+    pub seed_motor: Motor<f32>,
 
     pub motors: HashMap<X3dMotorId, PwmChannelId>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct BlueRovDefinition {
-    pub vertical_seed_motor: Motor,
-    pub lateral_seed_motor: Motor,
+    pub vertical_seed_motor: Motor<f32>,
+    pub lateral_seed_motor: Motor<f32>,
 
     pub motors: HashMap<HeavyMotorId, PwmChannelId>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct CustomDefinition {
     pub motors: HashMap<String, CustomMotor>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct CustomMotor {
     pub pwm_channel: PwmChannelId,
-    pub motor: Motor,
+    pub motor: Motor<f32>,
 }
 
 impl X3dDefinition {
-    fn to_motor_config(&self, center_mass: Vec3A) -> MotorConfig<X3dMotorId> {
-        MotorConfig::<X3dMotorId>::new(self.seed_motor, center_mass)
+    fn to_motor_config(&self, center_mass: Vec3A) -> MotorConfig<X3dMotorId, f32> {
+        MotorConfig::<X3dMotorId, f32>::new(self.seed_motor, center_mass.to_array().into())
     }
 }
 
 impl BlueRovDefinition {
-    fn to_motor_config(&self, center_mass: Vec3A) -> MotorConfig<HeavyMotorId> {
-        MotorConfig::<HeavyMotorId>::new(
+    fn to_motor_config(&self, center_mass: Vec3A) -> MotorConfig<HeavyMotorId, f32> {
+        MotorConfig::<HeavyMotorId, f32>::new(
             self.lateral_seed_motor,
             self.vertical_seed_motor,
-            center_mass,
+            center_mass.to_array().into(),
         )
     }
 }
 
 impl CustomDefinition {
-    fn to_motor_config(&self, center_mass: Vec3A) -> MotorConfig<String> {
-        MotorConfig::<String>::new_raw(
+    fn to_motor_config(&self, center_mass: Vec3A) -> MotorConfig<String, f32> {
+        MotorConfig::<String, f32>::new_raw(
             self.motors
                 .iter()
                 .map(|(id, motor)| (id.to_owned(), motor.motor)),
-            center_mass,
+            center_mass.to_array().into(),
         )
     }
 }
 
 impl MotorConfigDefinition {
+    /// The pwm channel assigned to each motor, with the path to the field assigning it
+    fn pwm_channels(&self) -> Vec<(String, PwmChannelId)> {
+        match self {
+            MotorConfigDefinition::X3d(x3d) => x3d
+                .motors
+                .iter()
+                .map(|(id, channel)| (format!("motor_config.X3d.motors.{id:?}"), *channel))
+                .collect(),
+            MotorConfigDefinition::BlueRov(blue_rov) => blue_rov
+                .motors
+                .iter()
+                .map(|(id, channel)| (format!("motor_config.BlueRov.motors.{id:?}"), *channel))
+                .collect(),
+            MotorConfigDefinition::Custom(custom) => custom
+                .motors
+                .iter()
+                .map(|(name, motor)| {
+                    (
+                        format!("motor_config.Custom.motors.{name}.pwm_channel"),
+                        motor.pwm_channel,
+                    )
+                })
+                .collect(),
+        }
+    }
+
     // TODO(low): Rename and make less bad
     pub fn flatten(
         &self,
         center_mass: Vec3A,
     ) -> (
-        impl Iterator<Item = (ErasedMotorId, Motor, PwmChannelId)>,
-        MotorConfig<ErasedMotorId>,
+        impl Iterator<Item = (ErasedMotorId, Motor<f32>, PwmChannelId)>,
+        MotorConfig<ErasedMotorId, f32>,
     ) {
         let motors: Vec<_>;
 
         let config = match self {
             MotorConfigDefinition::X3d(x3d) => {
-                let config: MotorConfig<_> = x3d.to_motor_config(center_mass);
+                let config: MotorConfig<_, f32> = x3d.to_motor_config(center_mass);
 
                 motors = config
                     .motors()
@@ -112,7 +426,7 @@ impl MotorConfigDefinition {
                 config.erase()
             }
             MotorConfigDefinition::BlueRov(blue_rov) => {
-                let config: MotorConfig<_> = blue_rov.to_motor_config(center_mass);
+                let config: MotorConfig<_, f32> = blue_rov.to_motor_config(center_mass);
 
                 motors = config
                     .motors()
@@ -132,7 +446,7 @@ impl MotorConfigDefinition {
                 config.erase()
             }
             MotorConfigDefinition::Custom(custom) => {
-                let config: MotorConfig<_> = custom.to_motor_config(center_mass);
+                let config: MotorConfig<_, f32> = custom.to_motor_config(center_mass);
 
                 motors = config
                     .motors()
@@ -155,7 +469,7 @@ impl MotorConfigDefinition {
                         .motors()
                         .enumerate()
                         .map(|(idx, (_, motor))| (idx as _, *motor)),
-                    center_mass,
+                    center_mass.to_array().into(),
                 )
             }
         };
@@ -164,37 +478,44 @@ impl MotorConfigDefinition {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct MotorForceCap {
+    pub pwm_channel: PwmChannelId,
+    /// Newtons
+    pub max_force: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct ServoConfigDefinition {
     pub servos: HashMap<String, Servo>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct Servo {
     pub pwm_channel: PwmChannelId,
     pub cameras: HashSet<String>,
 }
 
-#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[derive(Resource, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct CameraDefinition {
     pub name: String,
     pub transform: ConfigTransform,
 }
 
-#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[derive(Resource, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct ConfigTransform {
     position: ConfigPosition,
     rotation: ConfigRotation,
 }
 
-#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[derive(Resource, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct ConfigPosition {
     x: f32,
     y: f32,
     z: f32,
 }
 
-#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[derive(Resource, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct ConfigRotation {
     yaw: f32,
     pitch: f32,
@@ -215,3 +536,160 @@ impl ConfigTransform {
             ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use motor_math::x3d::X3dMotorId;
+
+    use super::{
+        CameraDefinition, ConfigError, MotorConfigDefinition, MotorForceCap, RobotConfig, Servo,
+    };
+
+    fn fixture() -> RobotConfig {
+        toml::from_str(include_str!("../robot.toml")).expect("Parse robot.toml")
+    }
+
+    fn fields(errors: &[ConfigError]) -> Vec<&str> {
+        errors.iter().map(|it| it.field.as_str()).collect()
+    }
+
+    #[test]
+    fn shipped_config_is_valid() {
+        assert_eq!(fixture().validate(), vec![]);
+    }
+
+    #[test]
+    fn custom_config_is_valid() {
+        let mut config = fixture();
+        config.motor_config = toml::from_str(
+            r#"
+            [Custom.motors.Left]
+            pwm_channel = 0
+            motor = { position = [-0.2, 0.0, 0.0], orientation = [0.0, 1.0, 0.0], direction = "Clockwise" }
+
+            [Custom.motors.Right]
+            pwm_channel = 1
+            motor = { position = [0.2, 0.0, 0.0], orientation = [0.0, 1.0, 0.0], direction = "CounterClockwise" }
+            "#,
+        )
+        .expect("Parse custom motor config");
+
+        assert_eq!(config.validate(), vec![]);
+    }
+
+    #[test]
+    fn overlapping_channels() {
+        let mut config = fixture();
+        let MotorConfigDefinition::X3d(x3d) = &mut config.motor_config else {
+            unreachable!()
+        };
+        // FrontRightBottom is on 0 and Claw1 is on 14
+        x3d.motors.insert(X3dMotorId::FrontRightTop, 0);
+        x3d.motors.insert(X3dMotorId::BackLeftTop, 14);
+
+        let errors = config.validate();
+        assert_eq!(
+            fields(&errors),
+            [
+                "motor_config.X3d.motors.FrontRightTop",
+                "servo_config.servos.Claw1.pwm_channel",
+            ]
+        );
+        assert!(errors[0]
+            .message
+            .contains("motor_config.X3d.motors.FrontRightBottom"));
+        assert!(errors[1]
+            .message
+            .contains("motor_config.X3d.motors.BackLeftTop"));
+    }
+
+    #[test]
+    fn amperage_budget_must_be_positive() {
+        for budget in [0.0, -5.0, f32::NAN] {
+            let mut config = fixture();
+            config.motor_amperage_budget = budget;
+
+            assert_eq!(fields(&config.validate()), ["motor_amperage_budget"]);
+        }
+    }
+
+    #[test]
+    fn failsafe_timeout_must_be_positive() {
+        for timeout in [0.0, -0.25, f32::NAN] {
+            let mut config = fixture();
+            config.failsafe_timeout = timeout;
+
+            assert_eq!(fields(&config.validate()), ["failsafe_timeout"]);
+        }
+    }
+
+    #[test]
+    fn hw_stat_intervals_must_be_positive() {
+        let mut config = fixture();
+        config.hw_stat.network_interval = 0.0;
+        config.hw_stat.process_interval = f32::NAN;
+
+        assert_eq!(
+            fields(&config.validate()),
+            ["hw_stat.network_interval", "hw_stat.process_interval"]
+        );
+    }
+
+    #[test]
+    fn leak_debounce_needs_a_sample() {
+        let mut config = fixture();
+        config.leak_probes[0].debounce.consecutive_samples = 0;
+
+        assert_eq!(
+            fields(&config.validate()),
+            ["leak_probes[0].debounce.consecutive_samples"]
+        );
+    }
+
+    #[test]
+    fn camera_names_are_unique() {
+        let mut config = fixture();
+        let front = config.cameras["/dev/video2"].clone();
+        config.cameras.insert(
+            "/dev/video22".to_owned(),
+            CameraDefinition {
+                name: front.name,
+                transform: front.transform,
+            },
+        );
+
+        let errors = config.validate();
+        assert_eq!(fields(&errors), ["cameras.\"/dev/video22\".name"]);
+        assert!(errors[0].message.contains("/dev/video2"));
+    }
+
+    #[test]
+    fn pwm_channels_in_range() {
+        let mut config = fixture();
+        config.servo_config.servos.insert(
+            "Claw4".to_owned(),
+            Servo {
+                pwm_channel: 16,
+                cameras: Default::default(),
+            },
+        );
+        config.per_motor_force_cap = Some(vec![
+            MotorForceCap {
+                pwm_channel: 3,
+                max_force: 30.0,
+            },
+            MotorForceCap {
+                pwm_channel: 200,
+                max_force: 30.0,
+            },
+        ]);
+
+        assert_eq!(
+            fields(&config.validate()),
+            [
+                "servo_config.servos.Claw4.pwm_channel",
+                "per_motor_force_cap[1].pwm_channel",
+            ]
+        );
+    }
+}
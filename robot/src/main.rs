@@ -5,9 +5,8 @@ pub mod config;
 pub mod peripheral;
 pub mod plugins;
 
-use std::{fs, time::Duration};
+use std::time::Duration;
 
-use anyhow::Context;
 use bevy::{
     app::ScheduleRunnerPlugin,
     diagnostic::{DiagnosticsPlugin, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin},
@@ -26,8 +25,7 @@ fn main() -> anyhow::Result<()> {
     info!("---------- Starting Robot Code ----------");
 
     info!("Reading config");
-    let config = fs::read_to_string("robot.toml").context("Read config")?;
-    let config: RobotConfig = toml::from_str(&config).context("Parse config")?;
+    let config = RobotConfig::load(config::CONFIG_PATH)?;
 
     let name = config.name.clone();
     let port = config.port;
@@ -1,6 +1,18 @@
 pub mod ads1115;
 pub mod icm20602;
 pub mod mmc5983;
-pub mod ms5937;
+pub mod ms5837;
 pub mod neopixel;
 pub mod pca9685;
+
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+/// Monotonic time since the first call, for timestamping sensor frames as they are read
+pub fn timestamp() -> Duration {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+    EPOCH.get_or_init(Instant::now).elapsed()
+}
@@ -5,11 +5,15 @@ use common::types::{
 use std::{thread, time::Duration};
 use tracing::{debug, info, instrument};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
 
+use crate::peripheral;
+
 pub struct Icm20602 {
     spi: Spi,
+    // Time between frames written to the FIFO, `None` until it is enabled
+    fifo_period: Option<Duration>,
 }
 
 impl Icm20602 {
@@ -23,7 +27,10 @@ impl Icm20602 {
 
         let spi = Spi::new(bus, slave_select, clock_speed, Mode::Mode0).context("Open spi")?;
 
-        let mut this = Self { spi };
+        let mut this = Self {
+            spi,
+            fifo_period: None,
+        };
         this.initialize().context("Initialize")?;
 
         Ok(this)
@@ -32,10 +39,88 @@ impl Icm20602 {
     #[instrument(level = "trace", skip(self), ret)]
     pub fn read_frame(&mut self) -> anyhow::Result<InertialFrame> {
         let raw = self.read_raw_frame().context("Read raw frame")?;
+        let timestamp = peripheral::timestamp();
 
         // The first byte is junk
-        let raw = &raw[1..];
+        Ok(Self::parse_frame(&raw[1..], timestamp))
+    }
+
+    /// Has the sensor buffer frames sampled at `sample_rate_hz` for `read_fifo_burst`
+    ///
+    /// Rates are rounded to an integer divisor of 1kHz
+    #[instrument(level = "debug", skip(self))]
+    pub fn enable_fifo(&mut self, sample_rate_hz: u16) -> anyhow::Result<()> {
+        if !(4..=1000).contains(&sample_rate_hz) {
+            bail!("FIFO sample rate must be between 4Hz and 1000Hz, got: {sample_rate_hz}Hz");
+        }
+
+        let divider = (1000 / sample_rate_hz - 1) as u8;
+
+        self.spi
+            .write(&[Self::REG_SMPLRT_DIV, divider])
+            .context("Setup sample rate")?;
+
+        // Overwrite the oldest frames once full, keep the 1kHz lowpass filter
+        self.spi
+            .write(&[Self::REG_CONFIG, 0x1])
+            .context("Setup fifo mode")?;
+
+        self.spi
+            .write(&[Self::REG_FIFO_EN, Self::GYRO_FIFO_EN | Self::ACCEL_FIFO_EN])
+            .context("Select fifo data")?;
+
+        self.reset_fifo()?;
+
+        self.fifo_period = Some(Duration::from_millis(divider as u64 + 1));
+
+        Ok(())
+    }
+
+    /// Drains the FIFO, returning every frame sampled since the last burst, oldest first
+    #[instrument(level = "trace", skip(self))]
+    pub fn read_fifo_burst(&mut self) -> anyhow::Result<Vec<InertialFrame>> {
+        let period = self.fifo_period.context("FIFO not enabled")?;
+
+        let status = self.read_register(Self::REG_INT_STATUS)?;
+        if status & Self::FIFO_OFLOW_INT != 0 {
+            // The oldest frames were partially overwritten, so the rest are no longer aligned
+            self.reset_fifo()?;
+            bail!("IMU FIFO overflowed, dropped buffered frames");
+        }
+
+        let count_high = self.read_register(Self::REG_FIFO_COUNTH)?;
+        let count_low = self.read_register(Self::REG_FIFO_COUNTL)?;
+        let count = (count_high as usize) << 8 | count_low as usize;
 
+        // Partially written frames are left for the next burst
+        let frames = count / Self::FIFO_FRAME_LEN;
+        if frames == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut output = vec![0; frames * Self::FIFO_FRAME_LEN + 1];
+        let mut input = vec![0; frames * Self::FIFO_FRAME_LEN + 1];
+
+        output[0] = Self::REG_FIFO_R_W | Self::READ;
+
+        self.spi
+            .transfer(&mut input, &output)
+            .context("Read fifo")?;
+        let timestamp = peripheral::timestamp();
+
+        // The first byte is junk, the newest frame was sampled just before the read
+        let frames = input[1..]
+            .chunks_exact(Self::FIFO_FRAME_LEN)
+            .rev()
+            .enumerate()
+            .map(|(age, raw)| Self::parse_frame(raw, timestamp.saturating_sub(period * age as u32)))
+            .collect::<Vec<_>>();
+
+        Ok(frames.into_iter().rev().collect())
+    }
+
+    /// Decodes the accelerometer, temperature, and gyro registers, in the order they are read
+    fn parse_frame(raw: &[u8], timestamp: Duration) -> InertialFrame {
         let raw_accel_native_x = (raw[0] as u16) << 8 | raw[1] as u16;
         let raw_accel_native_y = (raw[2] as u16) << 8 | raw[3] as u16;
         let raw_accel_native_z = (raw[4] as u16) << 8 | raw[5] as u16;
@@ -64,7 +149,7 @@ impl Icm20602 {
         let gyro_y = -gyro_native_x;
         let gyro_z = -gyro_native_z;
 
-        Ok(InertialFrame {
+        InertialFrame {
             gyro_x: Dps(gyro_x),
             gyro_y: Dps(gyro_y),
             gyro_z: Dps(gyro_z),
@@ -72,7 +157,8 @@ impl Icm20602 {
             accel_y: GForce(accel_y),
             accel_z: GForce(accel_z),
             tempature: Celsius(tempature),
-        })
+            timestamp,
+        }
     }
 }
 
@@ -87,6 +173,22 @@ impl Icm20602 {
     const REG_PWR_MGMT_1: u8 = 0x6B;
     const REG_WHO_AM_I: u8 = 0x75;
     const REG_ACCEL_XOUT_H: u8 = 0x3B;
+    const REG_SMPLRT_DIV: u8 = 0x19;
+    const REG_FIFO_EN: u8 = 0x23;
+    const REG_INT_STATUS: u8 = 0x3A;
+    const REG_USER_CTRL: u8 = 0x6A;
+    const REG_FIFO_COUNTH: u8 = 0x72;
+    const REG_FIFO_COUNTL: u8 = 0x73;
+    const REG_FIFO_R_W: u8 = 0x74;
+
+    const GYRO_FIFO_EN: u8 = 1 << 4;
+    const ACCEL_FIFO_EN: u8 = 1 << 3;
+    const FIFO_OFLOW_INT: u8 = 1 << 4;
+    const USER_CTRL_FIFO_EN: u8 = 1 << 6;
+    const USER_CTRL_FIFO_RST: u8 = 1 << 2;
+
+    // Accelerometer, temperature, then gyro, like the data registers
+    const FIFO_FRAME_LEN: usize = 14;
 
     const READ: u8 = 0x80;
 
@@ -153,4 +255,26 @@ impl Icm20602 {
 
         Ok(input)
     }
+
+    fn read_register(&mut self, register: u8) -> anyhow::Result<u8> {
+        let mut input = [0, 0];
+
+        self.spi
+            .transfer(&mut input, &[register | Self::READ, 0])
+            .with_context(|| format!("Read register {register:#04x}"))?;
+
+        Ok(input[1])
+    }
+
+    fn reset_fifo(&mut self) -> anyhow::Result<()> {
+        self.spi
+            .write(&[Self::REG_USER_CTRL, Self::USER_CTRL_FIFO_RST])
+            .context("Reset fifo")?;
+
+        self.spi
+            .write(&[Self::REG_USER_CTRL, Self::USER_CTRL_FIFO_EN])
+            .context("Enable fifo")?;
+
+        Ok(())
+    }
 }
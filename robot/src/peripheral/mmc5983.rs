@@ -1,21 +1,33 @@
 use common::types::hw::MagneticFrame;
 use common::types::units::Gauss;
-use std::{thread, time::Duration};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
 use tracing::{debug, info, instrument, trace};
 
 use anyhow::Context;
+use nalgebra::{Matrix3, Matrix6, Vector3, Vector6};
 use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
 
+use crate::peripheral;
+use serde::{Deserialize, Serialize};
+
 pub struct Mcc5983 {
     spi: Spi,
-    // FIXME: Never read
+    /// Offset of the sensor itself, measured with the SET/RESET straps
     offset: [f32; 3],
+    calibration: MagCalibration,
+
+    degauss_interval: Option<Duration>,
+    last_degauss: Instant,
 }
 
 impl Mcc5983 {
     pub const SPI_BUS: Bus = Bus::Spi1;
     pub const SPI_SELECT: SlaveSelect = SlaveSelect::Ss1;
     pub const SPI_CLOCK: u32 = 10_000_000;
+    pub const DEGAUSS_INTERVAL: Duration = Duration::from_secs(10);
 
     #[instrument(level = "debug")]
     pub fn new(bus: Bus, slave_select: SlaveSelect, clock_speed: u32) -> anyhow::Result<Self> {
@@ -26,17 +38,102 @@ impl Mcc5983 {
         let mut this = Self {
             spi,
             offset: [0.0; 3],
+            calibration: MagCalibration::default(),
+            degauss_interval: None,
+            last_degauss: Instant::now(),
         };
         this.initialize().context("Initialize")?;
 
         Ok(this)
     }
 
-    // TODO(high): Hard and soft iron calibration?
+    /// Sets the hard and soft iron correction applied by `read_frame`
+    pub fn apply_calibration(&mut self, calibration: &MagCalibration) {
+        self.calibration = *calibration;
+    }
+
+    pub fn calibration(&self) -> MagCalibration {
+        self.calibration
+    }
+
+    /// How often `read_frame_if_ready` degausses the sensor, `None` to never degauss
+    pub fn set_degauss_interval(&mut self, interval: Option<Duration>) {
+        self.degauss_interval = interval;
+    }
+
+    /// Reads a frame only if the sensor has finished a measurement since the last read, so the
+    /// same measurement is never returned twice
+    ///
+    /// Degausses the sensor first if it is due
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn read_frame_if_ready(&mut self) -> anyhow::Result<Option<MagneticFrame>> {
+        let frame = self.read_uncalibrated_frame_if_ready()?;
+
+        Ok(frame.map(|frame| self.calibration.apply(frame)))
+    }
+
+    /// `read_frame_if_ready` without the hard and soft iron correction
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn read_uncalibrated_frame_if_ready(&mut self) -> anyhow::Result<Option<MagneticFrame>> {
+        let degauss_due = self
+            .degauss_interval
+            .is_some_and(|interval| self.last_degauss.elapsed() >= interval);
+        if degauss_due {
+            self.degauss().context("Degauss")?;
+        }
+
+        let status = self.read_reg(Self::REG_STATUS).context("Read status")?;
+        if status & Self::STATUS_MEAS_DONE == 0 {
+            return Ok(None);
+        }
+
+        let frame = self.read_uncalibrated_frame()?;
+
+        // Clear the measurement done flag
+        self.spi
+            .write(&[Self::REG_STATUS, Self::STATUS_MEAS_DONE])
+            .context("Clear status")?;
+
+        Ok(Some(frame))
+    }
+
+    /// Pulses the SET/RESET straps to remove any magnetization of the sensor, as the datasheet
+    /// recommends doing periodically, and remeasures the sensor's offset
+    pub fn degauss(&mut self) -> anyhow::Result<()> {
+        debug!("Degaussing MCC5982");
+
+        // Single measurements need continous mode to be off
+        self.spi
+            .write(&[Self::REG_CONTROL2, 0x00])
+            .context("Stop continous mode")?;
+
+        self.calibrate_offset().context("calibrate")?;
+
+        // The calibration measurements are not real samples
+        self.spi
+            .write(&[Self::REG_STATUS, Self::STATUS_MEAS_DONE])
+            .context("Clear status")?;
+        self.spi
+            .write(&[Self::REG_CONTROL2, 0x0D])
+            .context("Continous mode")?;
+
+        self.last_degauss = Instant::now();
+
+        Ok(())
+    }
 
     #[instrument(level = "trace", skip(self), ret)]
     pub fn read_frame(&mut self) -> anyhow::Result<MagneticFrame> {
+        let frame = self.read_uncalibrated_frame()?;
+
+        Ok(self.calibration.apply(frame))
+    }
+
+    /// Reads a frame without the hard and soft iron correction, for collecting calibration samples
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn read_uncalibrated_frame(&mut self) -> anyhow::Result<MagneticFrame> {
         let raw = self.read_raw_frame().context("Read raw frame")?;
+        let timestamp = peripheral::timestamp();
 
         // The first byte is junk
         let raw = &raw[1..];
@@ -52,18 +149,110 @@ impl Mcc5983 {
         let mag_native_y = (raw_mag_native_y as i32 - 131072) as f32 / 16384.0;
         let mag_native_z = (raw_mag_native_z as i32 - 131072) as f32 / 16384.0;
 
-        let mag_x = mag_native_y;
-        let mag_y = mag_native_x;
-        let mag_z = mag_native_z;
+        let mag_x = mag_native_y - self.offset[0];
+        let mag_y = mag_native_x - self.offset[1];
+        let mag_z = mag_native_z - self.offset[2];
 
         Ok(MagneticFrame {
             mag_x: Gauss(mag_x),
             mag_y: Gauss(mag_y),
             mag_z: Gauss(mag_z),
+            timestamp,
+        })
+    }
+}
+
+/// Hard and soft iron correction for magnetometer samples
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MagCalibration {
+    /// Hard iron offset, subtracted from each sample
+    pub offset: [f32; 3],
+    /// Soft iron correction, row major, applied after the offset
+    pub soft_iron: [[f32; 3]; 3],
+}
+
+impl MagCalibration {
+    /// Fewer samples than this cannot cover enough orientations for a meaningful fit
+    pub const MIN_SAMPLES: usize = 100;
+
+    pub fn apply(&self, frame: MagneticFrame) -> MagneticFrame {
+        let sample = Vector3::new(frame.mag_x.0, frame.mag_y.0, frame.mag_z.0);
+        let offset = Vector3::from(self.offset);
+        let soft_iron = Matrix3::from_fn(|row, col| self.soft_iron[row][col]);
+
+        let corrected = soft_iron * (sample - offset);
+
+        MagneticFrame {
+            mag_x: Gauss(corrected.x),
+            mag_y: Gauss(corrected.y),
+            mag_z: Gauss(corrected.z),
+            timestamp: frame.timestamp,
+        }
+    }
+
+    /// Fits an axis aligned ellipsoid to `samples` by least squares
+    ///
+    /// The offset is the ellipsoid's center and the soft iron matrix scales each axis to the
+    /// ellipsoid's mean radius, so the field strength is preserved. Returns `None` if the
+    /// samples do not describe an ellipsoid, usually because the vehicle was not rotated enough.
+    pub fn fit(samples: &[MagneticFrame]) -> Option<Self> {
+        if samples.len() < Self::MIN_SAMPLES {
+            return None;
+        }
+
+        // Solve `a x^2 + b y^2 + c z^2 + d x + e y + f z = 1` with the normal equations
+        let mut normal = Matrix6::<f64>::zeros();
+        let mut rhs = Vector6::<f64>::zeros();
+        for sample in samples {
+            let (x, y, z) = (
+                sample.mag_x.0 as f64,
+                sample.mag_y.0 as f64,
+                sample.mag_z.0 as f64,
+            );
+            let row = Vector6::new(x * x, y * y, z * z, x, y, z);
+
+            normal += row * row.transpose();
+            rhs += row;
+        }
+
+        let params = normal.lu().solve(&rhs)?;
+        let quadratic = params.fixed_rows::<3>(0);
+        let linear = params.fixed_rows::<3>(3);
+
+        if quadratic.iter().any(|&it| it <= 0.0 || !it.is_finite()) {
+            return None;
+        }
+
+        let center = -linear.component_div(&quadratic) / 2.0;
+        let scale = 1.0 + quadratic.dot(&center.component_mul(&center));
+        if scale <= 0.0 || !scale.is_finite() {
+            return None;
+        }
+
+        let radii = quadratic.map(|it| (scale / it).sqrt());
+        let mean_radius = radii.product().cbrt();
+
+        let mut soft_iron = [[0.0; 3]; 3];
+        for axis in 0..3 {
+            soft_iron[axis][axis] = (mean_radius / radii[axis]) as f32;
+        }
+
+        Some(Self {
+            offset: [center.x as f32, center.y as f32, center.z as f32],
+            soft_iron,
         })
     }
 }
 
+impl Default for MagCalibration {
+    fn default() -> Self {
+        Self {
+            offset: [0.0; 3],
+            soft_iron: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+}
+
 // Implementation based on https://github.com/bluerobotics/icm20602-python
 impl Mcc5983 {
     const REG_XOUT_L: u8 = 0x00;
@@ -73,6 +262,8 @@ impl Mcc5983 {
     const REG_CONTROL2: u8 = 0x0B;
     const REG_WHO_AM_I: u8 = 0x2F;
 
+    const STATUS_MEAS_DONE: u8 = 0x01;
+
     const READ: u8 = 0x80;
 
     fn initialize(&mut self) -> anyhow::Result<()> {
@@ -127,7 +318,7 @@ impl Mcc5983 {
             1
         );
 
-        let set = self.read_frame().context("Read Set")?;
+        let set = self.read_uncalibrated_frame().context("Read Set")?;
         trace!(?set, "Set calibration");
 
         // RESET
@@ -146,7 +337,7 @@ impl Mcc5983 {
             1
         );
 
-        let reset = self.read_frame().context("Read Reset")?;
+        let reset = self.read_uncalibrated_frame().context("Read Reset")?;
         trace!(?reset, "Reset calibration");
 
         let offset = [
@@ -188,3 +379,82 @@ impl Mcc5983 {
         Ok(input)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use common::types::{hw::MagneticFrame, units::Gauss};
+
+    use super::MagCalibration;
+
+    /// Points spread evenly over a sphere of `radius`
+    fn sphere(radius: f32, count: usize) -> Vec<[f32; 3]> {
+        let golden_angle = std::f32::consts::PI * (3.0 - 5f32.sqrt());
+
+        (0..count)
+            .map(|idx| {
+                let y = 1.0 - 2.0 * (idx as f32 + 0.5) / count as f32;
+                let ring = (1.0 - y * y).sqrt();
+                let theta = golden_angle * idx as f32;
+
+                [
+                    radius * ring * theta.cos(),
+                    radius * y,
+                    radius * ring * theta.sin(),
+                ]
+            })
+            .collect()
+    }
+
+    fn frame([x, y, z]: [f32; 3]) -> MagneticFrame {
+        MagneticFrame {
+            mag_x: Gauss(x),
+            mag_y: Gauss(y),
+            mag_z: Gauss(z),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn fit_recovers_distortion() {
+        let offset = [0.12, -0.2, 0.05];
+        let stretch = [1.3, 0.8, 1.0];
+
+        let samples = sphere(0.5, 500)
+            .into_iter()
+            .map(|point| {
+                frame([
+                    point[0] * stretch[0] + offset[0],
+                    point[1] * stretch[1] + offset[1],
+                    point[2] * stretch[2] + offset[2],
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        let calibration = MagCalibration::fit(&samples).expect("Fit");
+
+        for (fit, expected) in calibration.offset.iter().zip(offset) {
+            assert!((fit - expected).abs() < 1e-3, "{fit} != {expected}");
+        }
+
+        let magnitudes = samples.iter().map(|&sample| {
+            let corrected = calibration.apply(sample);
+
+            (corrected.mag_x.0.powi(2) + corrected.mag_y.0.powi(2) + corrected.mag_z.0.powi(2))
+                .sqrt()
+        });
+        let expected = 0.5 * (stretch[0] * stretch[1] * stretch[2]).cbrt();
+        for magnitude in magnitudes {
+            assert!(
+                (magnitude - expected).abs() < 1e-3,
+                "{magnitude} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn fit_rejects_too_few_samples() {
+        let samples = sphere(0.5, 10).into_iter().map(frame).collect::<Vec<_>>();
+
+        assert_eq!(MagCalibration::fit(&samples), None);
+    }
+}
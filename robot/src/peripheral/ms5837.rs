@@ -43,9 +43,9 @@ impl Ms5837 {
 
     #[instrument(level = "trace", skip(self), ret)]
     pub fn read_frame(&mut self) -> anyhow::Result<DepthFrame> {
-        let raw = self.read_raw().context("Read raw frame")?;
+        let (raw_d1, raw_d2) = self.read_raw().context("Read raw frame")?;
 
-        let (pressure, temperature) = calculate_pressure_and_temperature(raw, &self.calibration);
+        let (pressure, temperature) = compensated_pressure(raw_d1, raw_d2, self.calibration);
         let altitude = pressure_to_altitude(pressure, self.sea_level.0);
         let depth = pressure_to_depth(pressure, self.fluid_density, self.sea_level.0);
 
@@ -112,7 +112,7 @@ impl Ms5837 {
             .context("Begin d1 read")?;
         self.i2c.read(&mut buffer).context("D1 read")?;
 
-        let d1 = (buffer[0] as u32) << 16 | (buffer[1] as u32) << 8 | buffer[0] as u32;
+        let d1 = (buffer[0] as u32) << 16 | (buffer[1] as u32) << 8 | buffer[2] as u32;
 
         self.i2c
             .write(&[Self::CMD_CONVERT_D2_OSR1024])
@@ -124,55 +124,61 @@ impl Ms5837 {
             .context("Begin d2 read")?;
         self.i2c.read(&mut buffer).context("D2 read")?;
 
-        let d2 = (buffer[0] as u32) << 16 | (buffer[1] as u32) << 8 | buffer[0] as u32;
+        let d2 = (buffer[0] as u32) << 16 | (buffer[1] as u32) << 8 | buffer[2] as u32;
 
         Ok((d1, d2))
     }
 }
 
+/// Converts raw readings to pressure and temperature, with the datasheet's second order temperature compensation
+///
+/// `raw_d1` and `raw_d2` are the pressure and temperature conversions, `prom` is the factory calibration
 // Hippity hoppity the code in the data sheet is my property
-fn calculate_pressure_and_temperature(raw: (u32, u32), calibration: &[u16; 8]) -> (Mbar, Celsius) {
+pub fn compensated_pressure(raw_d1: u32, raw_d2: u32, prom: [u16; 8]) -> (Mbar, Celsius) {
+    let [_, c1, c2, c3, c4, c5, c6, _] = prom.map(|it| it as i64);
+    let (d1, d2) = (raw_d1 as i64, raw_d2 as i64);
+
     // Calculate temperature
-    let dt = raw.1 as i32 - calibration[5] as i32 * 256;
-    let temp = 2000 + dt * calibration[6] as i32 / 8388608;
+    let dt = d2 - c5 * (1 << 8);
+    let temp = 2000 + dt * c6 / (1 << 23);
 
     // Calculate actual offset and sensitivity
-    let off = calibration[2] as i64 * 65536 + (calibration[4] as i64 * dt as i64) / 128;
-    let sens = calibration[1] as i64 * 32768 + (calibration[3] as i64 * dt as i64) / 256;
-
-    let t_i;
-    let mut off_i;
-    let mut sens_i;
+    let off = c2 * (1 << 16) + c4 * dt / (1 << 7);
+    let sens = c1 * (1 << 15) + c3 * dt / (1 << 8);
 
     // Second order compensation
-    if temp / 100 < 20 {
+    let (t_i, off_i, sens_i) = if temp < 2000 {
         // Low temp
-        t_i = 3 * dt as i64 * dt as i64 / 8589934592;
-        off_i = 3 * (temp as i64 - 2000) * (temp as i64 - 2000) / 2;
-        sens_i = 5 * (temp as i64 - 2000) * (temp as i64 - 2000) / 8;
+        let mut off_i = 3 * (temp - 2000).pow(2) / 2;
+        let mut sens_i = 5 * (temp - 2000).pow(2) / (1 << 3);
 
-        if temp / 100 < -15 {
+        if temp < -1500 {
             // Very low temp
-            off_i += 7 * (temp as i64 + 1500) * (temp as i64 + 1500);
-            sens_i += 4 * (temp as i64 + 1500) * (temp as i64 + 1500);
+            off_i += 7 * (temp + 1500).pow(2);
+            sens_i += 4 * (temp + 1500).pow(2);
         }
+
+        (3 * dt.pow(2) / (1 << 33), off_i, sens_i)
     } else {
         // High temp
-        t_i = 2 * dt as i64 * dt as i64 / 137438953472;
-        off_i = (temp as i64 - 2000) * (temp as i64 - 2000) / 16;
-        sens_i = 0;
-    }
+        (
+            2 * dt.pow(2) / (1 << 37),
+            (temp - 2000).pow(2) / (1 << 4),
+            0,
+        )
+    };
 
     // Calculate corrected offset and sensitivity
     let off = off - off_i;
     let sens = sens - sens_i;
 
-    // Calculate pressure and temperature
-    let pressure_raw = ((raw.0 as i64 * sens / 2097152) - off) / 8192;
-    let temperature_raw = temp - t_i as i32;
+    // In tenths of a mbar, the datasheet truncates this to an integer but the extra resolution is free
+    let pressure_raw = (d1 * sens / (1 << 21) - off) as f64 / (1 << 13) as f64;
+    // In hundredths of a degree
+    let temperature_raw = temp - t_i;
 
     // Wrap in newtypes
-    let pressure = Mbar(pressure_raw as f32 / 10.0);
+    let pressure = Mbar((pressure_raw / 10.0) as f32);
     let temperature = Celsius(temperature_raw as f32 / 100.0);
 
     (pressure, temperature)
@@ -210,3 +216,85 @@ fn crc4(mut data: [u16; 8]) -> u8 {
 
     (n_rem >> 12) as u8
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::compensated_pressure;
+
+    /// The datasheet's compensation, without the integer arithmetic
+    fn reference(raw_d1: u32, raw_d2: u32, prom: [u16; 8]) -> (f64, f64) {
+        let [_, c1, c2, c3, c4, c5, c6, _] = prom.map(f64::from);
+        let (d1, d2) = (f64::from(raw_d1), f64::from(raw_d2));
+
+        let dt = d2 - c5 * 2f64.powi(8);
+        // TEMP and Ti are integers in the datasheet
+        let temp = 2000.0 + (dt * c6 / 2f64.powi(23)).trunc();
+
+        let off = c2 * 2f64.powi(16) + c4 * dt / 2f64.powi(7);
+        let sens = c1 * 2f64.powi(15) + c3 * dt / 2f64.powi(8);
+
+        let (t_i, mut off_i, mut sens_i) = if temp < 2000.0 {
+            (
+                3.0 * dt.powi(2) / 2f64.powi(33),
+                3.0 * (temp - 2000.0).powi(2) / 2.0,
+                5.0 * (temp - 2000.0).powi(2) / 2f64.powi(3),
+            )
+        } else {
+            (
+                2.0 * dt.powi(2) / 2f64.powi(37),
+                (temp - 2000.0).powi(2) / 2f64.powi(4),
+                0.0,
+            )
+        };
+        if temp < -1500.0 {
+            off_i += 7.0 * (temp + 1500.0).powi(2);
+            sens_i += 4.0 * (temp + 1500.0).powi(2);
+        }
+
+        let pressure = (d1 * (sens - sens_i) / 2f64.powi(21) - (off - off_i)) / 2f64.powi(13);
+
+        (pressure / 10.0, (temp - t_i.trunc()) / 100.0)
+    }
+
+    // A representative factory calibration
+    const TYPICAL_PROM: [u16; 8] = [0, 34982, 36352, 20328, 22354, 26646, 26146, 0];
+
+    /// Factory calibrations within 25% of `TYPICAL_PROM`
+    fn prom() -> impl Strategy<Value = [u16; 8]> {
+        proptest::array::uniform6(0.75..1.25f64).prop_map(|scale| {
+            let mut prom = TYPICAL_PROM;
+            for (value, scale) in prom[1..7].iter_mut().zip(scale) {
+                *value = (*value as f64 * scale) as u16;
+            }
+
+            prom
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn matches_reference(
+            prom in prom(),
+            // Roughly 0 to 30 bar with a typical calibration, the sensor's range
+            raw_d1 in 4_000_000u32..9_500_000,
+            // Roughly -20°C to 85°C, the sensor's operating range
+            dt in -1_300_000i64..2_100_000,
+        ) {
+            let raw_d2 = (prom[5] as i64 * 256 + dt) as u32;
+
+            let (pressure, temperature) = compensated_pressure(raw_d1, raw_d2, prom);
+            let (expected_pressure, expected_temperature) = reference(raw_d1, raw_d2, prom);
+
+            prop_assert!(
+                (pressure.0 as f64 - expected_pressure).abs() <= 0.1,
+                "{pressure:?} != {expected_pressure}"
+            );
+            prop_assert!(
+                (temperature.0 as f64 - expected_temperature).abs() <= 1e-3,
+                "{temperature:?} != {expected_temperature}"
+            );
+        }
+    }
+}
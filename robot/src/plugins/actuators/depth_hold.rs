@@ -2,10 +2,11 @@ use bevy::prelude::*;
 use common::{
     bundles::MovementContributionBundle,
     components::{
-        Armed, Depth, DepthTarget, MovementContribution, Orientation, PidConfig, PidResult, RobotId,
+        Armed, Depth, DepthRampLimit, DepthTarget, EffectiveDepthTarget, FeedForwardInput,
+        GainSchedule, MovementContribution, Orientation, PidConfig, PidResult, RobotId,
     },
     ecs_sync::Replicate,
-    types::{units::Meters, utils::PidController},
+    types::{hw::DepthFrame, units::Meters, utils::PidController},
 };
 use glam::Vec3A;
 use motor_math::Movement;
@@ -17,7 +18,7 @@ pub struct DepthHoldPlugin;
 impl Plugin for DepthHoldPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup_depth_hold)
-            .add_systems(Update, depth_hold_system);
+            .add_systems(Update, (ramp_depth_target, depth_hold_system).chain());
     }
 }
 
@@ -40,33 +41,113 @@ fn setup_depth_hold(mut cmds: Commands, robot: Res<LocalRobot>) {
                 kd: 1.5,
                 kt: 5000.0,
                 max_integral: 10.0,
+                ..Default::default()
             },
             Replicate,
         ))
         .id();
 
+    // Starts out flat, tuned from the surface
+    // TODO(high): Tune the ramp limit
+    cmds.entity(robot.entity)
+        .insert((GainSchedule::default(), DepthRampLimit(0.5)));
+
     cmds.insert_resource(DepthHoldState(entity, PidController::default()));
 }
 
+/// Moves the effective target towards the operator's target without exceeding the ramp limit
+fn ramp_depth_target(
+    mut cmds: Commands,
+    robot: Res<LocalRobot>,
+    robot_query: Query<(
+        &Armed,
+        &Depth,
+        Option<&DepthTarget>,
+        Option<&DepthRampLimit>,
+        Option<&EffectiveDepthTarget>,
+    )>,
+    time: Res<Time<Real>>,
+) {
+    let Ok((armed, depth, target, limit, effective)) = robot_query.get(robot.entity) else {
+        return;
+    };
+
+    match (armed, target) {
+        (Armed::Armed, Some(target)) => {
+            // Start from where the robot is, so engaging depth hold is ramped too
+            let effective = effective.map(|it| it.0).unwrap_or(depth.0.depth);
+            let effective = ramp(
+                effective,
+                target.0,
+                limit.map(|it| it.0),
+                time.delta_seconds(),
+            );
+
+            cmds.entity(robot.entity)
+                .insert(EffectiveDepthTarget(effective));
+        }
+        _ => {
+            if effective.is_some() {
+                cmds.entity(robot.entity).remove::<EffectiveDepthTarget>();
+            }
+        }
+    }
+}
+
+/// Steps `effective` towards `target` by at most `limit * delta_seconds`
+fn ramp(effective: Meters, target: Meters, limit: Option<f32>, delta_seconds: f32) -> Meters {
+    let Some(limit) = limit else {
+        return target;
+    };
+
+    let max_step = limit.abs() * delta_seconds;
+    let step = (target.0 - effective.0).clamp(-max_step, max_step);
+
+    Meters(effective.0 + step)
+}
+
 fn depth_hold_system(
     mut last_target: Local<Option<Meters>>,
     mut cmds: Commands,
     robot: Res<LocalRobot>,
     mut state: ResMut<DepthHoldState>,
-    robot_query: Query<(&Armed, &Depth, &DepthTarget, &Orientation)>,
+    robot_query: Query<(
+        &Armed,
+        &Depth,
+        &EffectiveDepthTarget,
+        &Orientation,
+        Option<&GainSchedule>,
+    )>,
     entity_query: Query<&PidConfig>,
     time: Res<Time<Real>>,
 ) {
     let robot = robot_query.get(robot.entity);
     let pid_config = entity_query.get(state.0).unwrap();
 
-    if let Ok((&Armed::Armed, depth, depth_target, orientation)) = robot {
+    if let Ok((&Armed::Armed, depth, depth_target, orientation, schedule)) = robot {
         let depth_error = depth_target.0 - depth.0.depth;
         let depth_td = depth_target.0 - last_target.unwrap_or(depth_target.0);
 
+        let feed_forward = buoyancy_estimate(&depth.0);
+
+        let (kp_scale, ki_scale) = schedule
+            .map(|it| it.evaluate(depth.0.depth.0))
+            .unwrap_or((1.0, 1.0));
+        let pid_config = &PidConfig {
+            kp: pid_config.kp * kp_scale,
+            ki: pid_config.ki * ki_scale,
+            ..pid_config.clone()
+        };
+
         let pid = &mut state.1;
         // Depth increases as Z decreases, flip the sign
-        let res = pid.update(-depth_error.0, -depth_td.0, pid_config, time.delta());
+        let res = pid.update_with_feed_forward(
+            -depth_error.0,
+            -depth_td.0,
+            feed_forward,
+            pid_config,
+            time.delta(),
+        );
 
         let correction = orientation.0.inverse() * Vec3A::Z * res.correction;
         let movement = Movement {
@@ -74,12 +155,57 @@ fn depth_hold_system(
             torque: Vec3A::ZERO,
         };
 
-        cmds.entity(state.0)
-            .insert((MovementContribution(movement), res));
+        cmds.entity(state.0).insert((
+            MovementContribution(movement),
+            res,
+            FeedForwardInput(feed_forward),
+        ));
         *last_target = Some(depth_target.0);
     } else {
         cmds.entity(state.0)
-            .remove::<(MovementContribution, PidResult)>();
+            .remove::<(MovementContribution, PidResult, FeedForwardInput)>();
         *last_target = None;
     }
 }
+
+/// Buoyancy relative to the buoyancy in 4°C fresh water, so `kf` is the force needed to
+/// cancel the robot's net buoyancy there
+///
+/// Only the water density's temperature dependence is modeled
+fn buoyancy_estimate(frame: &DepthFrame) -> f32 {
+    let t = frame.temperature.0;
+
+    // Thiesen's equation for the density of fresh water, relative to its maximum
+    1.0 - (t + 288.9414) / (508929.2 * (t + 68.12963)) * (t - 3.9863).powi(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use common::types::units::Meters;
+
+    use super::ramp;
+
+    #[test]
+    fn ramp_limits_target_rate() {
+        let delta_seconds = 1.0 / 60.0;
+        let target = Meters(10.0);
+
+        let mut effective = Meters(0.0);
+        for _ in 0..60 * 5 {
+            let next = ramp(effective, target, Some(1.0), delta_seconds);
+
+            assert!(next.0 >= effective.0);
+            assert!(next.0 - effective.0 <= 1.0 * delta_seconds + 1e-6);
+            effective = next;
+        }
+        assert!((effective.0 - 5.0).abs() < 1e-3, "{effective:?}");
+
+        // Settles on the target instead of overshooting
+        for _ in 0..60 * 6 {
+            effective = ramp(effective, target, Some(1.0), delta_seconds);
+        }
+        assert!((effective.0 - target.0).abs() < 1e-6, "{effective:?}");
+
+        assert_eq!(ramp(Meters(0.0), target, None, delta_seconds), target);
+    }
+}
@@ -243,6 +243,11 @@ fn update_leds(
             leds.2[0] = LedState::On;
             leds.2[1] = LedState::On;
         }
+        RobotStatus::Fault => {
+            if (now * TAU).sin() < 0.0 {
+                leds.2[2] = LedState::On;
+            }
+        }
     }
 
     if !errors.is_empty() {
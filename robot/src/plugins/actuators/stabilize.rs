@@ -51,6 +51,7 @@ fn setup_stabalize(mut cmds: Commands, robot: Res<LocalRobot>) {
                 kd: 0.15,
                 kt: 5.0,
                 max_integral: 60.0,
+                ..Default::default()
             },
             Replicate,
         ))
@@ -71,6 +72,7 @@ fn setup_stabalize(mut cmds: Commands, robot: Res<LocalRobot>) {
                 kd: 0.1,
                 kt: 3.5,
                 max_integral: 30.0,
+                ..Default::default()
             },
             Replicate,
         ))
@@ -91,6 +93,7 @@ fn setup_stabalize(mut cmds: Commands, robot: Res<LocalRobot>) {
                 kd: 0.12,
                 kt: 5.0,
                 max_integral: 20.0,
+                ..Default::default()
             },
             Replicate,
         ))
@@ -1,28 +1,33 @@
-use std::time::Duration;
+use std::{collections::BTreeMap, time::Duration};
 
 use ahash::HashMap;
 use bevy::prelude::*;
 use common::{
     bundles::{MotorBundle, PwmActuatorBundle, RobotActuatorBundle},
     components::{
-        ActualForce, ActualMovement, Armed, CurrentDraw, JerkLimit, MotorContribution,
-        MotorDefinition, Motors, MovementAxisMaximums, MovementContribution, MovementCurrentCap,
-        PwmChannel, PwmManualControl, PwmSignal, RobotId, TargetForce, TargetMovement,
+        ActualForce, ActualMovement, Armed, CurrentDraw, FailsafeActive, JerkLimit,
+        MeasuredVoltage, MotorContribution, MotorDefinition, MotorSaturation, MotorTrims, Motors,
+        MovementAxisBounds, MovementAxisMaximums, MovementContribution, MovementCurrentCap,
+        PwmChannel, PwmManualControl, PwmSignal, RobotId, RobotStatus, TargetForce, TargetMovement,
     },
-    ecs_sync::{NetId, Replicate},
+    ecs_sync::{ForignOwned, NetId, Replicate},
     types::units::Newtons,
 };
 use motor_math::{
     blue_rov::HeavyMotorId,
-    motor_preformance::{self, Interpolation, MotorData, MotorRecord},
-    solve::{self, reverse},
+    motor_preformance::{self, Interpolation, MotorData},
+    slew::SlewLimiter,
+    solve,
     x3d::X3dMotorId,
     Direction, ErasedMotorId, Movement,
 };
 
 use crate::{
     config::{MotorConfigDefinition, RobotConfig},
-    plugins::core::robot::{LocalRobot, LocalRobotMarker},
+    plugins::core::{
+        config_watcher::ConfigReloadEvent,
+        robot::{LocalRobot, LocalRobotMarker},
+    },
 };
 
 pub struct ThrusterPlugin;
@@ -30,17 +35,30 @@ pub struct ThrusterPlugin;
 impl Plugin for ThrusterPlugin {
     fn build(&self, app: &mut App) {
         // FIXME(low): This is kinda bad
-        let motor_data =
-            motor_preformance::read_motor_data("motor_data.csv").expect("Read motor data");
+        let config = app.world().resource::<RobotConfig>();
+        let motor_data = if config.motor_data.is_some() {
+            let tables = config
+                .motor_data_tables()
+                .expect("Parse motor data voltages");
+
+            motor_preformance::read_motor_data_multi(&tables)
+        } else {
+            motor_preformance::read_motor_data("motor_data.csv")
+        }
+        .expect("Read motor data");
 
-        // TODO(mid): Update motor config when motor definitions change
-        app.add_systems(Startup, (create_motors, setup_motor_math))
+        app.add_event::<ConfigReloadEvent>()
+            .add_systems(Startup, (create_motors, setup_motor_math))
             .add_systems(
                 Update,
                 (
+                    reload_motors.before(accumulate_movements),
+                    update_motor_voltage.before(accumulate_movements),
                     update_axis_maximums,
                     accumulate_movements,
                     accumulate_motor_forces.after(accumulate_movements),
+                    update_motor_saturation.after(accumulate_movements),
+                    failsafe.after(accumulate_motor_forces),
                 ),
             )
             .insert_resource(MotorDataRes(motor_data));
@@ -50,7 +68,46 @@ impl Plugin for ThrusterPlugin {
 #[derive(Resource)]
 pub struct MotorDataRes(pub MotorData);
 
+#[derive(Resource, Default)]
+pub struct MotorForceCaps(pub HashMap<ErasedMotorId, f32>);
+
 fn create_motors(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotConfig>) {
+    spawn_motors(&mut cmds, &robot, &config);
+}
+
+/// Respawns the motors when a reloaded config changes their layout, this also disarms the robot
+fn reload_motors(
+    mut cmds: Commands,
+    robot: Res<LocalRobot>,
+    config: Res<RobotConfig>,
+    mut reloads: EventReader<ConfigReloadEvent>,
+    motors: Query<(Entity, &RobotId), With<MotorDefinition>>,
+) {
+    // The motors were built from the config replaced by the first reload
+    let Some(previous) = reloads
+        .read()
+        .fold(None, |first, reload| first.or(Some(&reload.previous)))
+    else {
+        return;
+    };
+
+    let changed = previous.motor_config != config.motor_config
+        || previous.center_of_mass != config.center_of_mass
+        || previous.per_motor_force_cap != config.per_motor_force_cap;
+    if !changed {
+        return;
+    }
+
+    for (entity, &RobotId(robot_net_id)) in &motors {
+        if robot_net_id == robot.net_id {
+            cmds.entity(entity).despawn();
+        }
+    }
+
+    spawn_motors(&mut cmds, &robot, &config);
+}
+
+fn spawn_motors(cmds: &mut Commands, robot: &LocalRobot, config: &RobotConfig) {
     let (motors, motor_config) = config.motor_config.flatten(config.center_of_mass);
 
     info!("Generating motor config");
@@ -60,11 +117,25 @@ fn create_motors(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotCo
         movement_actual: ActualMovement(Default::default()),
         motor_config: Motors(motor_config),
         axis_maximums: MovementAxisMaximums(Default::default()),
+        axis_bounds: MovementAxisBounds(Default::default()),
         current_cap: MovementCurrentCap(config.motor_amperage_budget.into()),
         armed: Armed::Disarmed,
+        // Arming leaves the failsafe
+        failsafe: FailsafeActive(true),
     });
 
+    let mut force_caps = MotorForceCaps::default();
+
     for (motor_id, motor, pwm_channel) in motors {
+        if let Some(cap) = config
+            .per_motor_force_cap
+            .iter()
+            .flatten()
+            .find(|it| it.pwm_channel == pwm_channel)
+        {
+            force_caps.0.insert(motor_id, cap.max_force);
+        }
+
         let name = match config.motor_config {
             MotorConfigDefinition::X3d(_) => {
                 format!(
@@ -97,11 +168,23 @@ fn create_motors(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotCo
             Replicate,
         ));
     }
+
+    cmds.insert_resource(force_caps);
 }
 
 fn setup_motor_math(mut cmds: Commands, config: Res<RobotConfig>, robot: Res<LocalRobot>) {
     cmds.entity(robot.entity)
-        .insert(JerkLimit(config.jerk_limit));
+        .insert(JerkLimit::from(config.jerk_limit));
+}
+
+/// Looks up motor performance at the measured battery voltage instead of the voltage the data was recorded at
+fn update_motor_voltage(
+    robot: Query<&MeasuredVoltage, (With<LocalRobotMarker>, Changed<MeasuredVoltage>)>,
+    mut motor_data: ResMut<MotorDataRes>,
+) {
+    for MeasuredVoltage(voltage) in &robot {
+        motor_data.0.set_default_voltage(voltage.0);
+    }
 }
 
 fn update_axis_maximums(
@@ -117,25 +200,33 @@ fn update_axis_maximums(
         let motor_data = &motor_data.0;
         let current_cap = current_cap.0 .0;
 
-        let maximums = reverse::axis_maximums(motor_config, motor_data, current_cap, 0.01)
+        let bounds: BTreeMap<_, _> = motor_config
+            .reachable_movement_bounds(motor_data, current_cap)
             .into_iter()
-            .map(|(key, value)| (key, Newtons(value)))
+            .map(|(key, (min, max))| (key, (Newtons(min), Newtons(max))))
             .collect();
 
+        // The positive side of the bounds is exactly what `axis_maximums` would solve for
+        let maximums = bounds.iter().map(|(key, (_, max))| (*key, *max)).collect();
+
         info!("Updated motor axis maximums to {maximums:?} at {current_cap:.2}A");
 
-        cmds.entity(entity).insert(MovementAxisMaximums(maximums));
+        cmds.entity(entity)
+            .insert((MovementAxisMaximums(maximums), MovementAxisBounds(bounds)));
     }
 }
 
 fn accumulate_movements(
     mut cmds: Commands,
-    robot: Query<(Entity, &NetId, &Motors), (With<LocalRobotMarker>, Without<PwmManualControl>)>,
+    robot: Query<
+        (Entity, &NetId, &Motors, Option<Ref<MotorTrims>>),
+        (With<LocalRobotMarker>, Without<PwmManualControl>),
+    >,
     movements: Query<(&RobotId, &MovementContribution)>,
 
     motor_data: Res<MotorDataRes>,
 ) {
-    let Ok((entity, net_id, Motors(motor_config))) = robot.get_single() else {
+    let Ok((entity, net_id, Motors(motor_config), trims)) = robot.get_single() else {
         return;
     };
     let mut robot = cmds.entity(entity);
@@ -148,7 +239,24 @@ fn accumulate_movements(
         }
     }
 
-    let forces = solve::reverse::reverse_solve(total_movement, motor_config);
+    let mut forces = solve::reverse::reverse_solve(total_movement, motor_config);
+
+    if let Some(trims) = trims {
+        let (min_force, max_force) = motor_data.0.force_range();
+
+        for (motor, trim) in &trims.0 {
+            match forces.get_mut(motor) {
+                Some(force) => *force = (*force + trim).clamp(min_force, max_force),
+                None => {
+                    // Only warn when the trims are set, not every frame
+                    if trims.is_changed() {
+                        warn!("Ignoring trim for unknown motor {motor}");
+                    }
+                }
+            }
+        }
+    }
+
     let motor_cmds = solve::reverse::forces_to_cmds(forces, motor_config, &motor_data.0);
     let forces = motor_cmds
         .into_iter()
@@ -158,10 +266,48 @@ fn accumulate_movements(
     robot.insert(MotorContribution(forces));
 }
 
+fn update_motor_saturation(
+    mut cmds: Commands,
+    robot: Query<(Entity, &NetId), With<LocalRobotMarker>>,
+    changed: Query<(), Changed<MotorContribution>>,
+    motor_forces: Query<(&RobotId, &MotorContribution)>,
+
+    motor_data: Res<MotorDataRes>,
+) {
+    if changed.is_empty() {
+        return;
+    }
+
+    let Ok((entity, &net_id)) = robot.get_single() else {
+        return;
+    };
+
+    let mut all_forces = BTreeMap::<ErasedMotorId, f32>::new();
+
+    for (&RobotId(robot_net_id), motor_force_contributions) in &motor_forces {
+        if robot_net_id == net_id {
+            for (motor, force) in &motor_force_contributions.0 {
+                *all_forces.entry(*motor).or_default() += force.0;
+            }
+        }
+    }
+
+    let (min_force, max_force) = motor_data.0.force_range();
+    let saturation = all_forces
+        .into_iter()
+        .map(|(motor, force)| {
+            let max = if force >= 0.0 { max_force } else { -min_force };
+            (motor, (force / max).clamp(0.0, 1.0))
+        })
+        .collect();
+
+    cmds.entity(entity).insert(MotorSaturation(saturation));
+}
+
 // TODO(mid): Split into smaller systems
 fn accumulate_motor_forces(
     mut cmds: Commands,
-    mut last_movement: Local<HashMap<ErasedMotorId, MotorRecord>>,
+    mut slew_limiter: Local<SlewLimiter<ErasedMotorId>>,
 
     robot: Query<
         (Entity, &NetId, &Motors, &MovementCurrentCap, &JerkLimit),
@@ -172,13 +318,14 @@ fn accumulate_motor_forces(
 
     time: Res<Time<Real>>,
     motor_data: Res<MotorDataRes>,
+    force_caps: Res<MotorForceCaps>,
 ) {
     let Ok((
         entity,
         &net_id,
         Motors(motor_config),
         &MovementCurrentCap(current_cap),
-        &JerkLimit(jerk_limit),
+        JerkLimit(jerk_limit),
     )) = robot.get_single()
     else {
         return;
@@ -215,6 +362,18 @@ fn accumulate_motor_forces(
         })
         .collect();
 
+    let motor_cmds = if force_caps.0.is_empty() {
+        motor_cmds
+    } else {
+        solve::reverse::clamp_forces(
+            motor_cmds,
+            motor_config,
+            &motor_data.0,
+            &force_caps.0.iter().map(|(&id, &cap)| (id, cap)).collect(),
+            0.05,
+        )
+    };
+
     let motor_cmds = solve::reverse::clamp_amperage(
         motor_cmds,
         motor_config,
@@ -225,32 +384,14 @@ fn accumulate_motor_forces(
 
     // Implement slew rate limiting
     let motor_cmds = {
-        let slew_motor_cmds = motor_cmds
+        let forces = motor_cmds
             .iter()
-            .map(|(motor, record)| {
-                if let Some(last) = last_movement.get(motor) {
-                    let jerk_limit = jerk_limit * time.delta_seconds();
-                    let delta = record.force - last.force;
-
-                    if delta.abs() > jerk_limit {
-                        let direction = motor_config
-                            .motor(motor)
-                            .map(|it| it.direction)
-                            .unwrap_or(Direction::Clockwise);
-
-                        let clamped = delta.clamp(-jerk_limit, jerk_limit);
-                        let new_record = motor_data.0.lookup_by_force(
-                            clamped + last.force,
-                            Interpolation::LerpDirection(direction),
-                        );
-
-                        return (*motor, new_record);
-                    }
-                };
-
-                (*motor, *record)
-            })
+            .map(|(motor, record)| (*motor, record.force))
             .collect();
+        let slew_forces =
+            slew_limiter.limit_axes(forces, motor_config, *jerk_limit, time.delta_seconds());
+        let slew_motor_cmds =
+            solve::reverse::forces_to_cmds(slew_forces, motor_config, &motor_data.0);
 
         solve::reverse::clamp_amperage(
             slew_motor_cmds,
@@ -296,6 +437,319 @@ fn accumulate_motor_forces(
             }
         }
     }
+}
+
+/// Stops the motors when movement commands stop arriving or the surface disconnects
+///
+/// Servos are left alone so they hold their position. Only arming again leaves the failsafe
+fn failsafe(
+    mut cmds: Commands,
+    mut last_command: Local<Option<Duration>>,
+
+    robot: Query<
+        (Entity, &NetId, Ref<Armed>, &RobotStatus, &FailsafeActive),
+        (With<LocalRobotMarker>, Without<PwmManualControl>),
+    >,
+    // Only contributions from the surface, stabilize and depth hold update theirs every frame while armed
+    commands: Query<
+        &RobotId,
+        (
+            Or<(Changed<MovementContribution>, Changed<MotorContribution>)>,
+            With<ForignOwned>,
+            Without<LocalRobotMarker>,
+        ),
+    >,
+    motors: Query<(Entity, &RobotId), With<MotorDefinition>>,
+
+    time: Res<Time<Real>>,
+    config: Res<RobotConfig>,
+) {
+    let Ok((entity, &net_id, armed, status, &FailsafeActive(active))) = robot.get_single() else {
+        return;
+    };
+
+    let now = time.elapsed();
+
+    if commands.iter().any(|&RobotId(robot)| robot == net_id) || last_command.is_none() {
+        *last_command = Some(now);
+    }
+
+    let timeout = Duration::from_secs_f32(config.failsafe_timeout);
+    let stale = last_command.is_some_and(|last| now.saturating_sub(last) > timeout);
+    let no_peer = *status == RobotStatus::NoPeer;
+
+    let active = if active {
+        // The pilot acknowledges the failsafe by arming again
+        let rearmed = armed.is_changed() && *armed == Armed::Armed;
+        if rearmed && !no_peer {
+            info!("Failsafe cleared");
+
+            *last_command = Some(now);
+            cmds.entity(entity).insert(FailsafeActive(false));
+        }
+
+        !rearmed || no_peer
+    } else if stale || no_peer {
+        warn!("Failsafe triggered, stopping motors");
+
+        cmds.entity(entity).insert(FailsafeActive(true));
+        true
+    } else {
+        false
+    };
+
+    if active {
+        for (motor, &RobotId(robot)) in &motors {
+            if robot == net_id {
+                cmds.entity(motor)
+                    .insert(PwmSignal(Duration::from_micros(1500)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs, time::Duration};
+
+    use bevy::{
+        prelude::*,
+        time::{TimePlugin, TimeUpdateStrategy},
+    };
+    use common::{
+        components::{
+            Armed, FailsafeActive, MotorContribution, MotorDefinition, MotorTrims, Motors,
+            MovementContribution, PwmSignal, RobotId, RobotStatus,
+        },
+        ecs_sync::{ForignOwned, NetId},
+        types::units::Newtons,
+    };
+    use motor_math::{
+        motor_preformance::{self, Interpolation},
+        x3d::X3dMotorId,
+        ErasedMotorId, MotorConfig, Movement,
+    };
+    use networking::Token;
+
+    use crate::{config::RobotConfig, plugins::core::robot::LocalRobotMarker};
+
+    use super::{accumulate_movements, failsafe, MotorDataRes};
+
+    fn robot_config() -> RobotConfig {
+        let config = fs::read_to_string("robot.toml").expect("Read config");
+        toml::from_str(&config).expect("Parse config")
+    }
+
+    fn motor_config() -> MotorConfig<ErasedMotorId> {
+        let config = robot_config();
+
+        let (_, motor_config) = config.motor_config.flatten(config.center_of_mass);
+        motor_config
+    }
 
-    *last_movement = motor_cmds;
+    fn mix(trims: Option<MotorTrims>) -> BTreeMap<ErasedMotorId, Newtons> {
+        let motor_data =
+            motor_preformance::read_motor_data("motor_data.csv").expect("Read motor data");
+
+        let mut app = App::new();
+        app.insert_resource(MotorDataRes(motor_data))
+            .add_systems(Update, accumulate_movements);
+
+        let motor_config = motor_config();
+        let trimmed_motor: ErasedMotorId = X3dMotorId::FrontRightTop.into();
+        let movement = Movement {
+            force: motor_config.motor(&trimmed_motor).unwrap().orientation * 10.0,
+            ..Movement::default()
+        };
+
+        let net_id = NetId::random();
+        let mut robot = app
+            .world_mut()
+            .spawn((LocalRobotMarker, net_id, Motors(motor_config)));
+        if let Some(trims) = trims {
+            robot.insert(trims);
+        }
+        let robot = robot.id();
+
+        app.world_mut()
+            .spawn((RobotId(net_id), MovementContribution(movement)));
+
+        app.update();
+
+        app.world()
+            .get::<MotorContribution>(robot)
+            .expect("Motors mixed")
+            .0
+            .clone()
+    }
+
+    #[test]
+    fn trim_shifts_pwm() {
+        let motor: ErasedMotorId = X3dMotorId::FrontRightTop.into();
+        let direction = motor_config().motor(&motor).unwrap().direction;
+
+        let untrimmed = mix(None);
+        let trimmed = mix(Some(MotorTrims([(motor, 2.0)].into())));
+
+        assert!((trimmed[&motor].0 - untrimmed[&motor].0 - 2.0).abs() < 0.01);
+
+        // Motors without a trim are untouched
+        for (other, force) in &untrimmed {
+            if *other != motor {
+                assert!((trimmed[other].0 - force.0).abs() < 1e-4);
+            }
+        }
+
+        let motor_data =
+            motor_preformance::read_motor_data("motor_data.csv").expect("Read motor data");
+        let pwm = |force: f32| {
+            motor_data
+                .lookup_by_force(force, Interpolation::LerpDirection(direction))
+                .pwm
+        };
+
+        // Counter clockwise motors spin the other way for the same pwm offset
+        let shift = pwm(trimmed[&motor].0) - pwm(untrimmed[&motor].0);
+        assert!(shift * direction.get_sign() > 0.0, "pwm shift: {shift}");
+    }
+
+    #[test]
+    fn unknown_trims_are_ignored() {
+        let untrimmed = mix(None);
+        let trimmed = mix(Some(MotorTrims([(200, 5.0)].into())));
+
+        assert_eq!(untrimmed.len(), trimmed.len());
+        for (motor, force) in &untrimmed {
+            assert!((trimmed[motor].0 - force.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn failsafe_stops_motors_until_rearmed() {
+        let mut app = App::new();
+        app.add_plugins(TimePlugin)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                50,
+            )))
+            .insert_resource(robot_config())
+            .add_systems(Update, failsafe);
+
+        let net_id = NetId::random();
+        let robot = app
+            .world_mut()
+            .spawn((
+                LocalRobotMarker,
+                net_id,
+                Armed::Armed,
+                RobotStatus::Armed,
+                FailsafeActive(false),
+            ))
+            .id();
+
+        let motor_id: ErasedMotorId = X3dMotorId::FrontRightTop.into();
+        let motor = *motor_config().motor(&motor_id).unwrap();
+        let motor = app
+            .world_mut()
+            .spawn((
+                RobotId(net_id),
+                MotorDefinition(motor_id, motor),
+                PwmSignal(Duration::from_micros(1700)),
+            ))
+            .id();
+        let servo = app
+            .world_mut()
+            .spawn((RobotId(net_id), PwmSignal(Duration::from_micros(1200))))
+            .id();
+        let contribution = app
+            .world_mut()
+            .spawn((
+                RobotId(net_id),
+                MovementContribution(Movement::default()),
+                ForignOwned::from(Token(1)),
+            ))
+            .id();
+
+        let pwm = |app: &App, entity| app.world().get::<PwmSignal>(entity).unwrap().0;
+        let failsafe_active = |app: &App| app.world().get::<FailsafeActive>(robot).unwrap().0;
+        let command = |app: &mut App| {
+            app.world_mut()
+                .get_mut::<MovementContribution>(contribution)
+                .unwrap()
+                .set_changed();
+        };
+
+        // Fresh commands keep the motors running
+        for _ in 0..20 {
+            command(&mut app);
+            app.update();
+        }
+        assert!(!failsafe_active(&app));
+        assert_eq!(pwm(&app, motor), Duration::from_micros(1700));
+
+        // No commands for longer than the timeout
+        for _ in 0..10 {
+            app.update();
+        }
+        assert!(failsafe_active(&app));
+        assert_eq!(pwm(&app, motor), Duration::from_micros(1500));
+        assert_eq!(pwm(&app, servo), Duration::from_micros(1200));
+
+        // Commands coming back is not enough to leave the failsafe
+        app.world_mut()
+            .entity_mut(motor)
+            .insert(PwmSignal(Duration::from_micros(1700)));
+        command(&mut app);
+        app.update();
+        assert!(failsafe_active(&app));
+        assert_eq!(pwm(&app, motor), Duration::from_micros(1500));
+
+        // Arming again does
+        app.world_mut()
+            .entity_mut(motor)
+            .insert(PwmSignal(Duration::from_micros(1700)));
+        app.world_mut().entity_mut(robot).insert(Armed::Armed);
+        command(&mut app);
+        app.update();
+        assert!(!failsafe_active(&app));
+        assert_eq!(pwm(&app, motor), Duration::from_micros(1700));
+    }
+
+    #[test]
+    fn failsafe_ignores_robot_contributions() {
+        let mut app = App::new();
+        app.add_plugins(TimePlugin)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                50,
+            )))
+            .insert_resource(robot_config())
+            .add_systems(Update, failsafe);
+
+        let net_id = NetId::random();
+        let robot = app
+            .world_mut()
+            .spawn((
+                LocalRobotMarker,
+                net_id,
+                Armed::Armed,
+                RobotStatus::Armed,
+                FailsafeActive(false),
+            ))
+            .id();
+
+        // Like stabilize or depth hold, which update their contribution every frame while armed
+        let stabilize = app
+            .world_mut()
+            .spawn((RobotId(net_id), MovementContribution(Movement::default())))
+            .id();
+
+        for _ in 0..20 {
+            app.world_mut()
+                .get_mut::<MovementContribution>(stabilize)
+                .unwrap()
+                .set_changed();
+            app.update();
+        }
+
+        assert!(app.world().get::<FailsafeActive>(robot).unwrap().0);
+    }
 }
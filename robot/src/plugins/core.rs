@@ -1,5 +1,7 @@
 use bevy::{app::PluginGroupBuilder, prelude::PluginGroup};
 
+pub mod config_watcher;
+pub mod errors;
 pub mod robot;
 pub mod state;
 
@@ -10,5 +12,7 @@ impl PluginGroup for CorePlugins {
         PluginGroupBuilder::start::<Self>()
             .add(robot::RobotPlugin)
             .add(state::StatePlugin)
+            .add(errors::ErrorReportPlugin)
+            .add(config_watcher::ConfigWatcherPlugin)
     }
 }
@@ -0,0 +1,242 @@
+use std::{
+    mem,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use common::{
+    components::{JerkLimit, MovementCurrentCap},
+    error::{self, ErrorEvent},
+};
+use crossbeam::channel::{self, Receiver};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{
+    config::{RobotConfig, CONFIG_PATH},
+    plugins::core::robot::LocalRobot,
+};
+
+/// Reloads `robot.toml` when it is edited, so tuning does not need a restart
+pub struct ConfigWatcherPlugin;
+
+impl Plugin for ConfigWatcherPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ConfigReloadEvent>()
+            .add_systems(Startup, start_config_watcher.pipe(error::handle_errors))
+            .add_systems(
+                PreUpdate,
+                read_config_reloads.run_if(resource_exists::<ConfigWatcher>),
+            )
+            .add_systems(Update, apply_config_reload);
+    }
+}
+
+/// Sent after `RobotConfig` was replaced with an edited config
+#[derive(Event, Debug, Clone)]
+pub struct ConfigReloadEvent {
+    /// The config that was replaced
+    pub previous: RobotConfig,
+}
+
+#[derive(Resource)]
+struct ConfigWatcher {
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    configs: Receiver<anyhow::Result<RobotConfig>>,
+}
+
+// Editors often write a file in several steps, wait for them to finish before reading it
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+fn start_config_watcher(mut cmds: Commands) -> anyhow::Result<()> {
+    let (watcher, configs) = watch(CONFIG_PATH).context("Watch config")?;
+    cmds.insert_resource(ConfigWatcher {
+        _watcher: watcher,
+        configs,
+    });
+
+    Ok(())
+}
+
+/// Parses and validates the config at `path` on a background thread every time it changes
+fn watch(
+    path: impl AsRef<Path>,
+) -> anyhow::Result<(RecommendedWatcher, Receiver<anyhow::Result<RobotConfig>>)> {
+    let path = path.as_ref().to_owned();
+    let file_name = path
+        .file_name()
+        .context("Config path is not a file")?
+        .to_owned();
+
+    let (tx_changed, rx_changed) = channel::unbounded();
+    let (tx_config, rx_config) = channel::bounded(5);
+
+    // Watch the directory since many editors replace the file instead of modifying it
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let changed = match event {
+            Ok(event) => {
+                (event.kind.is_create() || event.kind.is_modify())
+                    && event
+                        .paths
+                        .iter()
+                        .any(|it| it.file_name() == Some(file_name.as_os_str()))
+            }
+            Err(err) => {
+                error!("Config watcher error: {err}");
+                false
+            }
+        };
+
+        if changed {
+            let _ = tx_changed.send(());
+        }
+    })
+    .context("Create file watcher")?;
+
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+        _ => PathBuf::from("."),
+    };
+    watcher
+        .watch(&directory, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Watch {}", directory.display()))?;
+
+    thread::Builder::new()
+        .name("Config Watcher Thread".to_owned())
+        .spawn(move || {
+            // Ends once the watcher, and its sender, are dropped
+            while let Ok(()) = rx_changed.recv() {
+                while rx_changed.recv_timeout(SETTLE_TIME).is_ok() {}
+
+                info!("Reloading {}", path.display());
+
+                let config = RobotConfig::load(&path);
+                if tx_config.send(config).is_err() {
+                    return;
+                }
+            }
+        })
+        .context("Spawn thread")?;
+
+    Ok((watcher, rx_config))
+}
+
+fn read_config_reloads(
+    mut config: ResMut<RobotConfig>,
+    watcher: Res<ConfigWatcher>,
+    mut reloads: EventWriter<ConfigReloadEvent>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    for new_config in watcher.configs.try_iter() {
+        match new_config {
+            Ok(new_config) => {
+                if new_config == *config {
+                    continue;
+                }
+
+                info!("Config reloaded");
+
+                let previous = mem::replace(&mut *config, new_config);
+                reloads.send(ConfigReloadEvent { previous });
+            }
+            Err(err) => {
+                errors.send(err.context("Reload config, keeping the old one").into());
+            }
+        }
+    }
+}
+
+/// Applies the settings that can change while the robot is running
+///
+/// Motors are rebuilt separately by the thruster plugin
+fn apply_config_reload(
+    mut cmds: Commands,
+    config: Res<RobotConfig>,
+    robot: Res<LocalRobot>,
+    mut reloads: EventReader<ConfigReloadEvent>,
+) {
+    for ConfigReloadEvent { previous } in reloads.read() {
+        if previous.motor_amperage_budget != config.motor_amperage_budget {
+            info!(
+                "Motor amperage budget changed to {}A",
+                config.motor_amperage_budget
+            );
+            cmds.entity(robot.entity)
+                .insert(MovementCurrentCap(config.motor_amperage_budget.into()));
+        }
+
+        if previous.jerk_limit != config.jerk_limit {
+            info!("Jerk limit changed to {:?}", config.jerk_limit);
+            cmds.entity(robot.entity)
+                .insert(JerkLimit::from(config.jerk_limit));
+        }
+
+        let needs_restart = [
+            ("name", previous.name != config.name),
+            ("port", previous.port != config.port),
+            ("motor_data", previous.motor_data != config.motor_data),
+            ("servo_config", previous.servo_config != config.servo_config),
+            ("cameras", previous.cameras != config.cameras),
+            ("leak_probes", previous.leak_probes != config.leak_probes),
+            (
+                "mag_calibration_path",
+                previous.mag_calibration_path != config.mag_calibration_path,
+            ),
+        ];
+        for (field, changed) in needs_restart {
+            if changed {
+                warn!("Changes to {field} take effect after a restart");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use super::watch;
+
+    #[test]
+    fn reloads_edited_config() {
+        let directory = std::env::temp_dir().join(format!("robot-config-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("robot.toml");
+
+        let original = include_str!("../../../robot.toml");
+        fs::write(&path, original).unwrap();
+
+        let (_watcher, configs) = watch(&path).expect("Watch config");
+        let timeout = Duration::from_secs(5);
+
+        let edited = original.replace(
+            "motor_amperage_budget = 25.0",
+            "motor_amperage_budget = 20.0",
+        );
+        fs::write(&path, edited).unwrap();
+
+        let config = configs
+            .recv_timeout(timeout)
+            .expect("Reload")
+            .expect("Valid config");
+        assert_eq!(config.motor_amperage_budget, 20.0);
+
+        // Invalid configs are reported instead of applied
+        let invalid = original.replace(
+            "motor_amperage_budget = 25.0",
+            "motor_amperage_budget = -1.0",
+        );
+        fs::write(&path, invalid).unwrap();
+
+        let err = configs
+            .recv_timeout(timeout)
+            .expect("Reload")
+            .expect_err("Invalid config");
+        assert!(format!("{err:#}").contains("motor_amperage_budget"));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,42 @@
+use bevy::prelude::*;
+use common::{
+    components::RobotErrors,
+    error::{self, ErrorEvent},
+    types::error::{RobotError, Severity},
+};
+
+use super::robot::LocalRobotMarker;
+
+/// Replicates the robot's errors to the surface through `RobotErrors`
+pub struct ErrorReportPlugin;
+
+impl Plugin for ErrorReportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Last, report_errors.after(error::error_channel));
+    }
+}
+
+fn report_errors(
+    mut robot: Query<&mut RobotErrors, With<LocalRobotMarker>>,
+    time: Res<Time<Real>>,
+    mut errors: EventReader<ErrorEvent>,
+) {
+    if errors.is_empty() {
+        return;
+    }
+
+    let mut robot_errors = robot.single_mut();
+    for ErrorEvent(error) in errors.read() {
+        let severity = error
+            .downcast_ref::<Severity>()
+            .copied()
+            .unwrap_or_default();
+
+        robot_errors.push(RobotError {
+            message: format!("{error:#}"),
+            severity,
+            timestamp: time.elapsed(),
+            source: error.to_string(),
+        });
+    }
+}
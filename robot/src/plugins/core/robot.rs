@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 use common::{
     bundles::RobotCoreBundle,
-    components::{Robot, RobotId, RobotStatus, Singleton},
+    components::{Robot, RobotErrors, RobotId, RobotStatus, Singleton},
     ecs_sync::{NetId, Replicate},
     InstanceName,
 };
@@ -34,6 +34,7 @@ fn setup_robot(mut cmds: Commands, name: Res<InstanceName>) {
                 robot_id: RobotId(net_id),
                 marker: Robot,
             },
+            RobotErrors::default(),
             LocalRobotMarker,
             Replicate,
             Singleton,
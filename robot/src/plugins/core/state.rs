@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 use common::{
-    components::{Armed, RobotStatus},
+    components::{Armed, EmergencyStop, RobotErrors, RobotStatus},
     sync::Peer,
 };
 
@@ -19,12 +19,39 @@ impl Plugin for StatePlugin {
 fn update_state(
     mut cmds: Commands,
     peers: Query<&Peer>,
-    robot: Query<(Entity, Option<&RobotStatus>, Option<&Armed>), With<LocalRobotMarker>>,
+    robot: Query<
+        (
+            Entity,
+            Option<&RobotStatus>,
+            Option<&Armed>,
+            Option<&RobotErrors>,
+            Option<&EmergencyStop>,
+        ),
+        With<LocalRobotMarker>,
+    >,
 ) {
-    let (robot, status, armed) = robot.single();
+    let (robot, status, armed, errors, emergency_stop) = robot.single();
     let mut robot = cmds.entity(robot);
 
-    if !peers.is_empty() {
+    // Arming is refused until the surface clears the emergency stop
+    let armed = match (armed, emergency_stop) {
+        (Some(Armed::Armed), Some(EmergencyStop(true))) => {
+            robot.insert(Armed::Disarmed);
+            Some(&Armed::Disarmed)
+        }
+        _ => armed,
+    };
+
+    if errors.is_some_and(RobotErrors::has_critical) {
+        if status != Some(&RobotStatus::Fault) {
+            robot.insert(RobotStatus::Fault);
+        }
+
+        // Critical errors are not safe to drive through
+        if let Some(Armed::Armed) = armed {
+            robot.insert(Armed::Disarmed);
+        }
+    } else if !peers.is_empty() {
         match armed {
             Some(Armed::Armed) => {
                 if status != Some(&RobotStatus::Armed) {
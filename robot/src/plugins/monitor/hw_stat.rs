@@ -1,16 +1,20 @@
-use std::{thread, time::Duration};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
 
-use anyhow::{anyhow, Context};
+use anyhow::Context;
 use bevy::{app::AppExit, prelude::*};
 use common::{
-    bundles::RobotSystemBundle,
     components::{
-        Cores, CpuTotal, Disks, LoadAverage, Memory, Networks, OperatingSystem, Processes,
+        Cores, CpuTotal, Disks, GpuInfo, LoadAverage, Memory, Networks, OperatingSystem, Processes,
         Temperatures, Uptime,
     },
-    error::{self, Errors},
+    error,
     types::{
-        system::{ComponentTemperature, Cpu, Disk, Network, Process},
+        system::{ComponentTemperature, Cpu, Disk, GpuStats, Network, Process},
         units::Celsius,
     },
 };
@@ -21,7 +25,10 @@ use sysinfo::{
 };
 use tracing::{span, Level};
 
-use crate::plugins::core::robot::LocalRobot;
+use crate::{
+    config::{HwStatDefinition, RobotConfig},
+    plugins::core::robot::LocalRobot,
+};
 
 pub struct HwStatPlugin;
 
@@ -34,15 +41,136 @@ impl Plugin for HwStatPlugin {
 }
 
 #[derive(Resource)]
-struct HwStatChannels(Receiver<RobotSystemBundle>, Sender<()>);
+struct HwStatChannels(Receiver<HwStatUpdate>, Sender<()>);
+
+/// One of the system statistics, each is collected on its own schedule
+#[derive(Debug, Clone, PartialEq)]
+enum HwStatUpdate {
+    Processes(Processes),
+    LoadAverage(LoadAverage),
+    Networks(Networks),
+    Cpu(CpuTotal),
+    Cores(Cores),
+    Memory(Memory),
+    Gpu(GpuInfo),
+    Temperatures(Temperatures),
+    Disks(Disks),
+    Uptime(Uptime),
+    OperatingSystem(OperatingSystem),
+}
+
+impl HwStatUpdate {
+    /// Inserts the statistic, unless it is the same as the current value
+    fn apply(self, entity: &mut EntityWorldMut) {
+        match self {
+            HwStatUpdate::Processes(it) => insert_if_changed(entity, it),
+            HwStatUpdate::LoadAverage(it) => insert_if_changed(entity, it),
+            HwStatUpdate::Networks(it) => insert_if_changed(entity, it),
+            HwStatUpdate::Cpu(it) => insert_if_changed(entity, it),
+            HwStatUpdate::Cores(it) => insert_if_changed(entity, it),
+            HwStatUpdate::Memory(it) => insert_if_changed(entity, it),
+            HwStatUpdate::Gpu(it) => insert_if_changed(entity, it),
+            HwStatUpdate::Temperatures(it) => insert_if_changed(entity, it),
+            HwStatUpdate::Disks(it) => insert_if_changed(entity, it),
+            HwStatUpdate::Uptime(it) => insert_if_changed(entity, it),
+            HwStatUpdate::OperatingSystem(it) => insert_if_changed(entity, it),
+        }
+    }
+}
+
+// Inserting an equal value would still trigger change detection and be replicated
+fn insert_if_changed<C: Component + PartialEq>(entity: &mut EntityWorldMut, value: C) {
+    if entity.get::<C>() != Some(&value) {
+        entity.insert(value);
+    }
+}
+
+/// Groups of statistics refreshed together
+#[derive(Debug, Clone, Copy)]
+enum Tier {
+    Cpu,
+    Network,
+    Process,
+}
+
+impl Tier {
+    const ALL: [Tier; 3] = [Tier::Cpu, Tier::Network, Tier::Process];
+
+    fn interval(&self, config: &HwStatDefinition) -> Duration {
+        let secs = match self {
+            Tier::Cpu => config.cpu_interval,
+            Tier::Network => config.network_interval,
+            Tier::Process => config.process_interval,
+        };
+
+        Duration::from_secs_f32(secs)
+    }
+
+    fn refresh(&self, system: &mut System) {
+        match self {
+            Tier::Cpu => {
+                system.refresh_cpu();
+                system.refresh_memory();
+            }
+            Tier::Network => {
+                system.refresh_networks_list();
+                system.refresh_networks();
+                system.refresh_components_list();
+                system.refresh_components();
+            }
+            Tier::Process => {
+                system.refresh_processes();
+                system.refresh_users_list();
+                system.refresh_disks_list();
+                system.refresh_disks();
+            }
+        }
+    }
+
+    fn collect(
+        &self,
+        system: &System,
+        gpu: Option<&GpuSource>,
+        config: &HwStatDefinition,
+    ) -> Vec<HwStatUpdate> {
+        match self {
+            Tier::Cpu => {
+                let mut updates = vec![
+                    HwStatUpdate::Cpu(collect_cpu(system)),
+                    HwStatUpdate::Cores(collect_cores(system)),
+                    HwStatUpdate::Memory(collect_memory(system)),
+                    HwStatUpdate::LoadAverage(collect_load_average(system)),
+                    HwStatUpdate::Uptime(Uptime(Duration::from_secs(system.uptime()))),
+                ];
+
+                if let Some(gpu) = gpu {
+                    match gpu.collect() {
+                        Ok(gpu) => updates.push(HwStatUpdate::Gpu(gpu)),
+                        Err(err) => warn!("Could not read gpu stats: {err:?}"),
+                    }
+                }
 
-fn start_hw_stat_thread(mut cmds: Commands, errors: Res<Errors>) -> anyhow::Result<()> {
-    let (tx_data, rx_data) = channel::bounded(10);
+                updates
+            }
+            Tier::Network => vec![
+                HwStatUpdate::Networks(collect_networks(system)),
+                HwStatUpdate::Temperatures(collect_temperatures(system)),
+            ],
+            Tier::Process => vec![
+                HwStatUpdate::Processes(collect_processes(system, config.max_processes)),
+                HwStatUpdate::Disks(collect_disks(system)),
+            ],
+        }
+    }
+}
+
+fn start_hw_stat_thread(mut cmds: Commands, config: Res<RobotConfig>) -> anyhow::Result<()> {
+    let (tx_data, rx_data) = channel::bounded(30);
     let (tx_exit, rx_exit) = channel::bounded(1);
 
     cmds.insert_resource(HwStatChannels(rx_data, tx_exit));
 
-    let errors = errors.0.clone();
+    let config = config.hw_stat.clone();
     thread::Builder::new()
         .name("Hardware monitor thread".to_owned())
         .spawn(move || {
@@ -50,29 +178,42 @@ fn start_hw_stat_thread(mut cmds: Commands, errors: Res<Errors>) -> anyhow::Resu
             let _enter = span.enter();
 
             let mut system = System::new();
+
+            // sysinfo does not know about embedded gpus
+            let gpu = GpuSource::detect();
+            match &gpu {
+                Some(gpu) => info!("Reading gpu stats from {gpu:?}"),
+                None => info!("No supported gpu found, not collecting gpu stats"),
+            }
+
+            // The operating system does not change while running
+            let os = HwStatUpdate::OperatingSystem(collect_os(&system));
+            if tx_data.send(os).is_err() {
+                return;
+            }
+
+            let start = Instant::now();
+            let mut next_refresh = Tier::ALL.map(|_| start);
+
             loop {
                 let span = span!(Level::INFO, "System Monitor Cycle").entered();
 
-                system.refresh_all();
-                system.refresh_disks_list();
-                system.refresh_disks();
-                system.refresh_components_list();
-                system.refresh_components();
-                system.refresh_networks_list();
-                system.refresh_networks();
-                system.refresh_users_list();
+                let now = Instant::now();
+                for (tier, next_refresh) in Tier::ALL.iter().zip(&mut next_refresh) {
+                    if now < *next_refresh {
+                        continue;
+                    }
 
-                match collect_system_state(&system) {
-                    Ok(hw_state) => {
-                        let res = tx_data.send(hw_state);
+                    tier.refresh(&mut system);
+                    for update in tier.collect(&system, gpu.as_ref(), &config) {
+                        let res = tx_data.send(update);
                         if res.is_err() {
                             // Peer disconnected
                             return;
                         }
                     }
-                    Err(err) => {
-                        let _ = errors.send(anyhow!(err).context("Could not collect system state"));
-                    }
+
+                    *next_refresh = now + tier.interval(&config);
                 }
 
                 if let Ok(()) = rx_exit.try_recv() {
@@ -81,7 +222,8 @@ fn start_hw_stat_thread(mut cmds: Commands, errors: Res<Errors>) -> anyhow::Resu
 
                 span.exit();
 
-                thread::sleep(Duration::from_secs(1));
+                let next = next_refresh.iter().min().copied().unwrap_or(now);
+                thread::sleep(next.saturating_duration_since(Instant::now()));
             }
         })
         .context("Spawn thread")?;
@@ -90,9 +232,9 @@ fn start_hw_stat_thread(mut cmds: Commands, errors: Res<Errors>) -> anyhow::Resu
 }
 
 fn read_new_data(mut cmds: Commands, channels: Res<HwStatChannels>, robot: Res<LocalRobot>) {
-    for info in channels.0.try_iter() {
-        // FIXME(mid): This will clobber change detection
-        cmds.entity(robot.entity).insert(info);
+    for update in channels.0.try_iter() {
+        cmds.entity(robot.entity)
+            .add(move |mut entity: EntityWorldMut| update.apply(&mut entity));
     }
 }
 
@@ -102,104 +244,340 @@ fn shutdown(channels: Res<HwStatChannels>, mut exit: EventReader<AppExit>) {
     }
 }
 
-fn collect_system_state(system: &System) -> anyhow::Result<RobotSystemBundle> {
-    // FIXME(mid): We dont use most of this data
-    // TODO(low): sorting?
-    let hw_state = RobotSystemBundle {
-        processes: Processes(
-            system
-                .processes()
-                .values()
-                .map(|process| Process {
-                    name: process.name().to_owned(),
-                    pid: process.pid().as_u32(),
-                    memory: process.memory(),
-                    cpu_usage: process.cpu_usage(),
-                    user: process
-                        .user_id()
-                        .and_then(|user| system.get_user_by_id(user))
-                        .map(|user| user.name().to_owned()),
-                })
-                .collect(),
-        ),
-        load_average: LoadAverage {
-            one_min: system.load_average().one,
-            five_min: system.load_average().five,
-            fifteen_min: system.load_average().fifteen,
-        },
-        networks: Networks(
-            system
-                .networks()
-                .iter()
-                .map(|(name, data)| Network {
-                    name: name.clone(),
-                    rx_bytes: data.total_received(),
-                    tx_bytes: data.total_transmitted(),
-                    rx_packets: data.total_packets_received(),
-                    tx_packets: data.total_packets_transmitted(),
-                    rx_errors: data.total_errors_on_received(),
-                    tx_errors: data.total_errors_on_transmitted(),
-                })
-                .collect(),
-        ),
-        cpu: CpuTotal(Cpu {
-            frequency: system.global_cpu_info().frequency(),
-            usage: system.global_cpu_info().cpu_usage(),
-            name: system.global_cpu_info().name().to_owned(),
-        }),
-        cores: Cores(
-            system
-                .cpus()
-                .iter()
-                .map(|cpu| Cpu {
-                    frequency: cpu.frequency(),
-                    usage: cpu.cpu_usage(),
-                    name: cpu.name().to_owned(),
-                })
-                .collect(),
-        ),
-        memory: Memory {
-            total_mem: system.total_memory(),
-            used_mem: system.used_memory(),
-            free_mem: system.free_memory(),
-            total_swap: system.total_swap(),
-            used_swap: system.used_swap(),
-            free_swap: system.free_swap(),
-        },
-        temps: Temperatures(
-            system
-                .components()
-                .iter()
-                .map(|component| ComponentTemperature {
-                    tempature: Celsius(component.temperature()),
-                    tempature_max: Celsius(component.max()),
-                    tempature_critical: component.critical().map(Celsius),
-                    name: component.label().to_owned(),
-                })
-                .collect(),
-        ),
-        disks: Disks(
-            system
-                .disks()
+fn collect_processes(system: &System, max_processes: usize) -> Processes {
+    let mut processes: Vec<_> = system
+        .processes()
+        .values()
+        .map(|process| Process {
+            name: process.name().to_owned(),
+            pid: process.pid().as_u32(),
+            memory: process.memory(),
+            cpu_usage: process.cpu_usage(),
+            user: process
+                .user_id()
+                .and_then(|user| system.get_user_by_id(user))
+                .map(|user| user.name().to_owned()),
+        })
+        .collect();
+
+    // Only the busiest processes are worth replicating
+    processes.sort_by(|a, b| b.cpu_usage.total_cmp(&a.cpu_usage));
+    processes.truncate(max_processes);
+
+    Processes(processes)
+}
+
+fn collect_load_average(system: &System) -> LoadAverage {
+    let load_average = system.load_average();
+
+    LoadAverage {
+        one_min: load_average.one,
+        five_min: load_average.five,
+        fifteen_min: load_average.fifteen,
+    }
+}
+
+fn collect_networks(system: &System) -> Networks {
+    Networks(
+        system
+            .networks()
+            .iter()
+            .map(|(name, data)| Network {
+                name: name.clone(),
+                rx_bytes: data.total_received(),
+                tx_bytes: data.total_transmitted(),
+                rx_packets: data.total_packets_received(),
+                tx_packets: data.total_packets_transmitted(),
+                rx_errors: data.total_errors_on_received(),
+                tx_errors: data.total_errors_on_transmitted(),
+            })
+            .collect(),
+    )
+}
+
+fn collect_cpu(system: &System) -> CpuTotal {
+    CpuTotal(Cpu {
+        frequency: system.global_cpu_info().frequency(),
+        usage: system.global_cpu_info().cpu_usage(),
+        name: system.global_cpu_info().name().to_owned(),
+    })
+}
+
+fn collect_cores(system: &System) -> Cores {
+    Cores(
+        system
+            .cpus()
+            .iter()
+            .map(|cpu| Cpu {
+                frequency: cpu.frequency(),
+                usage: cpu.cpu_usage(),
+                name: cpu.name().to_owned(),
+            })
+            .collect(),
+    )
+}
+
+fn collect_memory(system: &System) -> Memory {
+    Memory {
+        total_mem: system.total_memory(),
+        used_mem: system.used_memory(),
+        free_mem: system.free_memory(),
+        total_swap: system.total_swap(),
+        used_swap: system.used_swap(),
+        free_swap: system.free_swap(),
+    }
+}
+
+fn collect_temperatures(system: &System) -> Temperatures {
+    Temperatures(
+        system
+            .components()
+            .iter()
+            .map(|component| ComponentTemperature {
+                tempature: Celsius(component.temperature()),
+                tempature_max: Celsius(component.max()),
+                tempature_critical: component.critical().map(Celsius),
+                name: component.label().to_owned(),
+            })
+            .collect(),
+    )
+}
+
+fn collect_disks(system: &System) -> Disks {
+    Disks(
+        system
+            .disks()
+            .iter()
+            .map(|disk| Disk {
+                name: disk.name().to_string_lossy().to_string(),
+                mount_point: disk.mount_point().to_string_lossy().to_string(),
+                total_space: disk.total_space(),
+                available_space: disk.available_space(),
+                removable: disk.is_removable(),
+            })
+            .collect(),
+    )
+}
+
+fn collect_os(system: &System) -> OperatingSystem {
+    OperatingSystem {
+        name: system.name(),
+        kernel_version: system.kernel_version(),
+        os_version: system.long_os_version(),
+        distro: Some(system.distribution_id()),
+        host_name: system.host_name(),
+    }
+}
+
+/// Where gpu statistics are read from, both supported platforms share system memory with the gpu
+#[derive(Debug, Clone, PartialEq)]
+enum GpuSource {
+    /// Memory is what nvmap has mapped for the gpu
+    Jetson { load: PathBuf },
+    /// Memory is the contiguous memory allocator's pool, which the VideoCore allocates from
+    Devfreq { load: PathBuf },
+}
+
+const NVMAP_MAPS: &str = "/sys/kernel/debug/nvmap/iovmm/maps";
+const JETSON_GPU_LOADS: [&str; 2] = [
+    "/sys/devices/gpu.0/load",
+    "/sys/devices/platform/gpu.0/load",
+];
+const DEVFREQ: &str = "/sys/class/devfreq";
+const MEMINFO: &str = "/proc/meminfo";
+
+impl GpuSource {
+    /// Finds a supported gpu, `None` on anything else
+    fn detect() -> Option<Self> {
+        let source = if Path::new(NVMAP_MAPS).exists() {
+            let load = JETSON_GPU_LOADS
                 .iter()
-                .map(|disk| Disk {
-                    name: disk.name().to_string_lossy().to_string(),
-                    mount_point: disk.mount_point().to_string_lossy().to_string(),
-                    total_space: disk.total_space(),
-                    available_space: disk.available_space(),
-                    removable: disk.is_removable(),
-                })
-                .collect(),
-        ),
-        uptime: Uptime(Duration::from_secs(system.uptime())),
-        os: OperatingSystem {
-            name: system.name(),
-            kernel_version: system.kernel_version(),
-            os_version: system.long_os_version(),
-            distro: Some(system.distribution_id()),
-            host_name: system.host_name(),
-        },
+                .map(PathBuf::from)
+                .find(|it| it.exists())?;
+
+            GpuSource::Jetson { load }
+        } else {
+            let device = fs::read_dir(DEVFREQ)
+                .ok()?
+                .flatten()
+                .map(|it| it.path())
+                .find(|it| {
+                    let name = it.file_name().unwrap_or_default().to_string_lossy();
+                    (name.contains("gpu") || name.contains("v3d")) && it.join("load").exists()
+                })?;
+
+            GpuSource::Devfreq {
+                load: device.join("load"),
+            }
+        };
+
+        // Debugfs and devfreq may exist without being readable
+        match source.collect() {
+            Ok(_) => Some(source),
+            Err(err) => {
+                warn!("Found {source:?} but could not read it: {err:?}");
+                None
+            }
+        }
+    }
+
+    fn collect(&self) -> anyhow::Result<GpuInfo> {
+        let meminfo = fs::read_to_string(MEMINFO).context("Read meminfo")?;
+
+        let stats = match self {
+            GpuSource::Jetson { load } => {
+                let load = fs::read_to_string(load).context("Read gpu load")?;
+                let maps = fs::read_to_string(NVMAP_MAPS).context("Read nvmap maps")?;
+
+                GpuStats {
+                    gpu_usage_pct: parse_jetson_load(&load).context("Parse gpu load")?,
+                    gpu_mem_used_mb: parse_nvmap_total_kb(&maps).context("Parse nvmap maps")?
+                        / 1024,
+                    gpu_mem_total_mb: parse_meminfo_kb(&meminfo, "MemTotal")
+                        .context("Parse meminfo")?
+                        / 1024,
+                }
+            }
+            GpuSource::Devfreq { load } => {
+                let load = fs::read_to_string(load).context("Read gpu load")?;
+                let total = parse_meminfo_kb(&meminfo, "CmaTotal").context("Parse meminfo")?;
+                let free = parse_meminfo_kb(&meminfo, "CmaFree").context("Parse meminfo")?;
+
+                GpuStats {
+                    gpu_usage_pct: parse_devfreq_load(&load).context("Parse gpu load")?,
+                    gpu_mem_used_mb: total.saturating_sub(free) / 1024,
+                    gpu_mem_total_mb: total / 1024,
+                }
+            }
+        };
+
+        Ok(GpuInfo(stats))
+    }
+}
+
+/// Tenths of a percent, ie `523`
+fn parse_jetson_load(load: &str) -> Option<f32> {
+    let load: f32 = load.trim().parse().ok()?;
+
+    Some(load / 10.0)
+}
+
+/// Percent at the current frequency, ie `23@400000000Hz`
+fn parse_devfreq_load(load: &str) -> Option<f32> {
+    let (load, _frequency) = load.trim().split_once('@')?;
+
+    load.parse().ok()
+}
+
+/// The size on the `total` row, ie `total    123456K`
+fn parse_nvmap_total_kb(maps: &str) -> Option<u64> {
+    let total = maps
+        .lines()
+        .find(|it| it.trim_start().starts_with("total"))?;
+    let size = total.split_whitespace().last()?;
+
+    size.trim_end_matches('K').parse().ok()
+}
+
+/// ie `CmaTotal:  524288 kB`
+fn parse_meminfo_kb(meminfo: &str, key: &str) -> Option<u64> {
+    meminfo.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if name != key {
+            return None;
+        }
+
+        value.trim().trim_end_matches("kB").trim().parse().ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+    use common::components::{LoadAverage, Uptime};
+
+    use super::{
+        parse_devfreq_load, parse_jetson_load, parse_meminfo_kb, parse_nvmap_total_kb, HwStatUpdate,
     };
 
-    Ok(hw_state)
+    fn load_average(one_min: f64) -> LoadAverage {
+        LoadAverage {
+            one_min,
+            five_min: 0.0,
+            fifteen_min: 0.0,
+        }
+    }
+
+    #[test]
+    fn updates_only_insert_their_component() {
+        let mut world = World::new();
+        let entity = world.spawn_empty().id();
+
+        HwStatUpdate::LoadAverage(load_average(1.0)).apply(&mut world.entity_mut(entity));
+
+        let entity = world.entity(entity);
+        assert_eq!(entity.get::<LoadAverage>(), Some(&load_average(1.0)));
+        assert!(entity.get::<Uptime>().is_none());
+    }
+
+    #[test]
+    fn unchanged_updates_keep_change_ticks() {
+        let mut world = World::new();
+        let entity = world.spawn_empty().id();
+
+        HwStatUpdate::LoadAverage(load_average(1.0)).apply(&mut world.entity_mut(entity));
+        let inserted = world
+            .entity(entity)
+            .get_ref::<LoadAverage>()
+            .unwrap()
+            .last_changed();
+
+        world.increment_change_tick();
+        HwStatUpdate::LoadAverage(load_average(1.0)).apply(&mut world.entity_mut(entity));
+        let unchanged = world
+            .entity(entity)
+            .get_ref::<LoadAverage>()
+            .unwrap()
+            .last_changed();
+        assert_eq!(inserted, unchanged);
+
+        world.increment_change_tick();
+        HwStatUpdate::LoadAverage(load_average(2.0)).apply(&mut world.entity_mut(entity));
+        let changed = world
+            .entity(entity)
+            .get_ref::<LoadAverage>()
+            .unwrap()
+            .last_changed();
+        assert_ne!(inserted, changed);
+        assert_eq!(
+            world.entity(entity).get::<LoadAverage>(),
+            Some(&load_average(2.0))
+        );
+    }
+
+    #[test]
+    fn parses_gpu_stats() {
+        assert_eq!(parse_jetson_load("523\n"), Some(52.3));
+        assert_eq!(parse_devfreq_load("23@400000000Hz\n"), Some(23.0));
+        assert_eq!(parse_devfreq_load("garbage"), None);
+
+        let maps = "\
+CLIENT                        PROCESS      PID        SIZE
+user                   nvargus-daemon     1204      30212K
+user                          robot      1580       4096K
+total                                               34308K
+";
+        assert_eq!(parse_nvmap_total_kb(maps), Some(34308));
+        assert_eq!(parse_nvmap_total_kb("CLIENT PROCESS PID SIZE\n"), None);
+
+        let meminfo = "\
+MemTotal:        8039460 kB
+MemFree:         5133692 kB
+CmaTotal:         524288 kB
+CmaFree:          491520 kB
+";
+        assert_eq!(parse_meminfo_kb(meminfo, "MemTotal"), Some(8039460));
+        assert_eq!(parse_meminfo_kb(meminfo, "CmaFree"), Some(491520));
+        assert_eq!(parse_meminfo_kb(meminfo, "Cma"), None);
+    }
 }
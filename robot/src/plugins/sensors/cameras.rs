@@ -12,7 +12,7 @@ use anyhow::{anyhow, bail, Context};
 use bevy::{app::AppExit, prelude::*};
 use common::{
     bundles::CameraBundle,
-    components::{Camera, RobotId},
+    components::{Camera, CameraId, CameraSource, RobotId},
     ecs_sync::{NetId, Replicate},
     error::{self, Errors},
     events::ResyncCameras,
@@ -297,12 +297,11 @@ fn handle_peers(
     }
 }
 
-// TODO(low): Only update the cameras that changed
 fn read_new_data(
     mut cmds: Commands,
     channels: Res<CameraChannels>,
     robot: Query<(Entity, &NetId), With<LocalRobotMarker>>,
-    cameras: Query<(Entity, &RobotId), With<Camera>>,
+    cameras: Query<(Entity, &RobotId, &Camera)>,
 ) {
     let mut new_cameras = None;
     for camera_update in channels.1.try_iter() {
@@ -312,14 +311,24 @@ fn read_new_data(
     if let Some(new_cameras) = new_cameras {
         let (_robot, id) = robot.single();
 
-        for (entity, camera_robot) in &cameras {
-            if camera_robot.0 == *id {
-                cmds.entity(entity).despawn();
+        // Keep the entity of cameras that are still present so the surface sees the same
+        // camera, even if its stream moved to a different port
+        let mut old_cameras: HashMap<CameraId, Entity> = cameras
+            .iter()
+            .filter(|(_, camera_robot, _)| camera_robot.0 == *id)
+            .map(|(entity, _, camera)| (camera.id.clone(), entity))
+            .collect();
+
+        for camera in new_cameras {
+            if let Some(entity) = old_cameras.remove(&camera.camera.id) {
+                cmds.entity(entity).insert(camera);
+            } else {
+                cmds.spawn((camera, Replicate));
             }
         }
 
-        for camera in new_cameras {
-            cmds.spawn((camera, Replicate));
+        for entity in old_cameras.into_values() {
+            cmds.entity(entity).despawn();
         }
     }
 }
@@ -388,18 +397,23 @@ fn camera_list(
 ) -> Vec<CameraBundle> {
     let mut list = Vec::new();
 
-    for (name, &(_, location)) in cameras {
-        let (name, transform) = match config.cameras.get(name) {
+    for (id, &(_, location)) in cameras {
+        let (name, display_name, transform) = match config.cameras.get(id) {
             Some(definition) => (
-                format!("{} ({})", definition.name, name),
+                format!("{} ({})", definition.name, id),
+                definition.name.clone(),
                 definition.transform.flatten(),
             ),
-            None => (name.to_owned(), Transform::default()),
+            None => (id.to_owned(), id.to_owned(), Transform::default()),
         };
 
         list.push(CameraBundle {
             name: Name::new(name),
-            camera: Camera { location },
+            camera: Camera {
+                id: CameraId(id.clone().into()),
+                name: display_name,
+                source: CameraSource::H264(location),
+            },
             robot,
             transform,
         });
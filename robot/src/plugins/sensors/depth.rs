@@ -15,7 +15,7 @@ use crossbeam::channel::{self, Receiver, Sender};
 use tracing::{span, Level};
 
 use crate::{
-    peripheral::ms5937::Ms5837,
+    peripheral::ms5837::Ms5837,
     plugins::core::robot::{LocalRobot, LocalRobotMarker},
 };
 
@@ -1,73 +1,397 @@
+use std::time::Duration;
+
 use anyhow::Context;
-use bevy::{app::AppExit, prelude::*};
-use common::{components::Leak, error};
-use crossbeam::channel::Receiver;
-use rppal::gpio::{Gpio, InputPin, Level, Trigger};
+use bevy::prelude::*;
+use common::{
+    components::{ClearLeaks, Leak, LeakProbe, Leaks},
+    error,
+};
+use rppal::gpio::{Gpio, InputPin};
 
-use crate::plugins::core::robot::LocalRobot;
+use crate::{
+    config::{LeakDebounce, RobotConfig},
+    plugins::core::robot::{LocalRobot, LocalRobotMarker},
+};
 
 pub struct LeakPlugin;
 
 impl Plugin for LeakPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_leak_interupt.pipe(error::handle_errors));
+        app.add_systems(Startup, setup_leak_probes.pipe(error::handle_errors));
         app.add_systems(
             PreUpdate,
-            read_new_data.run_if(resource_exists::<LeakChannels>),
+            (clear_leaks, read_new_data)
+                .chain()
+                .run_if(resource_exists::<LeakProbes>),
         );
-        app.add_systems(Last, shutdown.run_if(resource_exists::<LeakChannels>));
     }
 }
 
 #[derive(Resource)]
-struct LeakChannels(Receiver<bool>, InputPin);
+struct LeakProbes(Vec<Probe>);
+
+struct Probe {
+    name: String,
+    pin: Box<dyn LeakPin>,
+    active_high: bool,
+    debounce: Debounce,
+}
+
+/// The level of a probe's gpio, split out so the debounce can be tested without hardware
+trait LeakPin: Send + Sync {
+    fn is_high(&self) -> bool;
+}
+
+impl LeakPin for InputPin {
+    fn is_high(&self) -> bool {
+        InputPin::is_high(self)
+    }
+}
+
+/// Filters out noisy reads, once a leak is seen it stays latched until cleared
+#[derive(Debug)]
+struct Debounce {
+    settings: LeakDebounce,
+    /// When the current run of wet reads started
+    wet_since: Option<Duration>,
+    consecutive: u32,
+    latched: bool,
+}
 
-const LEAK_PIN: u8 = 27;
+impl Debounce {
+    fn new(settings: LeakDebounce) -> Self {
+        Self {
+            settings,
+            wet_since: None,
+            consecutive: 0,
+            latched: false,
+        }
+    }
+
+    fn threshold(&self) -> Duration {
+        Duration::from_millis(self.settings.threshold_ms.into())
+    }
+
+    /// Records a read of the probe taken at `now`, returns whether a leak is latched
+    ///
+    /// A single dry read restarts the debounce period
+    fn update(&mut self, active: bool, now: Duration) -> bool {
+        if active {
+            self.wet_since.get_or_insert(now);
+            self.consecutive = self.consecutive.saturating_add(1);
+        } else {
+            self.wet_since = None;
+            self.consecutive = 0;
+        }
+
+        if let Some(wet_since) = self.wet_since {
+            if self.consecutive >= self.settings.consecutive_samples
+                && now.saturating_sub(wet_since) >= self.threshold()
+            {
+                self.latched = true;
+            }
+        }
 
-fn setup_leak_interupt(mut cmds: Commands, robot: Res<LocalRobot>) -> anyhow::Result<()> {
-    let (tx, rx) = crossbeam::channel::bounded(5);
+        self.latched
+    }
+
+    /// How far the probe is through its debounce period, from 0.0 to 1.0
+    fn confidence(&self, now: Duration) -> f32 {
+        if self.latched {
+            return 1.0;
+        }
+
+        let Some(wet_since) = self.wet_since else {
+            return 0.0;
+        };
 
+        let threshold = self.threshold();
+        if threshold.is_zero() {
+            return 1.0;
+        }
+
+        let wet_for = now.saturating_sub(wet_since);
+        (wet_for.as_secs_f32() / threshold.as_secs_f32()).clamp(0.0, 1.0)
+    }
+
+    /// Probes that are still wet latch again after a full debounce period
+    fn clear(&mut self) {
+        *self = Self::new(self.settings);
+    }
+}
+
+fn setup_leak_probes(
+    mut cmds: Commands,
+    robot: Res<LocalRobot>,
+    config: Res<RobotConfig>,
+) -> anyhow::Result<()> {
     let gpio = Gpio::new().context("Open gpio")?;
-    let mut leak_pin = gpio
-        .get(LEAK_PIN)
-        .context("Open leak pin")?
-        .into_input_pulldown();
 
-    let initial_leak = leak_pin.is_high();
-    cmds.entity(robot.entity).insert(Leak(initial_leak));
+    let mut probes = Vec::new();
+    for definition in &config.leak_probes {
+        let pin = gpio
+            .get(definition.gpio)
+            .with_context(|| format!("Open leak pin for {}", definition.name))?;
 
-    leak_pin
-        .set_async_interrupt(Trigger::Both, move |level| {
-            let level = match level {
-                Level::High => true,
-                Level::Low => false,
-            };
+        // Pull towards the dry level so a disconnected probe does not read as a leak
+        let pin = if definition.active_high {
+            pin.into_input_pulldown()
+        } else {
+            pin.into_input_pullup()
+        };
 
-            warn!(?level, "Leak interrupt triggered");
+        probes.push(Probe {
+            name: definition.name.clone(),
+            pin: Box::new(pin),
+            active_high: definition.active_high,
+            debounce: Debounce::new(definition.debounce),
+        });
+    }
 
-            tx.send(level).expect("Peer disconnected");
+    let leaks = probes
+        .iter()
+        .map(|probe| LeakProbe {
+            name: probe.name.clone(),
+            triggered: false,
         })
-        .context("Set async leak interrupt")?;
+        .collect();
+    cmds.entity(robot.entity)
+        .insert((Leak::default(), Leaks(leaks)));
 
-    cmds.insert_resource(LeakChannels(rx, leak_pin));
+    cmds.insert_resource(LeakProbes(probes));
 
     Ok(())
 }
 
-fn read_new_data(mut cmds: Commands, channels: Res<LeakChannels>, robot: Res<LocalRobot>) {
-    let mut leak = None;
+fn clear_leaks(
+    mut cmds: Commands,
+    mut probes: ResMut<LeakProbes>,
+    commands: Query<Entity, (With<ClearLeaks>, With<LocalRobotMarker>)>,
+) {
+    for entity in &commands {
+        info!("Clearing leaks");
 
-    for event in channels.0.try_iter() {
-        leak = Some(event);
+        for probe in &mut probes.0 {
+            probe.debounce.clear();
+        }
+
+        cmds.entity(entity).remove::<ClearLeaks>();
     }
+}
+
+fn read_new_data(
+    mut cmds: Commands,
+    mut probes: ResMut<LeakProbes>,
+    robot: Res<LocalRobot>,
+    time: Res<Time>,
+    published: Query<(&Leak, &Leaks), With<LocalRobotMarker>>,
+) {
+    let now = time.elapsed();
 
-    if let Some(leak) = leak {
-        cmds.entity(robot.entity).insert(Leak(leak));
+    let mut confidence: f32 = 0.0;
+    let leaks: Vec<_> = probes
+        .0
+        .iter_mut()
+        .map(|probe| {
+            let active = probe.pin.is_high() == probe.active_high;
+            let triggered = probe.debounce.update(active, now);
+            confidence = confidence.max(probe.debounce.confidence(now));
+
+            LeakProbe {
+                name: probe.name.clone(),
+                triggered,
+            }
+        })
+        .collect();
+
+    let leak = Leak {
+        detected: leaks.iter().any(|it| it.triggered),
+        confidence,
+    };
+
+    let Ok((published_leak, published_leaks)) = published.get(robot.entity) else {
+        cmds.entity(robot.entity).insert((leak, Leaks(leaks)));
+        return;
+    };
+
+    if *published_leak != leak {
+        cmds.entity(robot.entity).insert(leak);
+    }
+
+    if published_leaks.0 != leaks {
+        for leak in leaks.iter().filter(|it| it.triggered) {
+            warn!("Leak detected by {}", leak.name);
+        }
+
+        cmds.entity(robot.entity).insert(Leaks(leaks));
     }
 }
 
-fn shutdown(mut channels: ResMut<LeakChannels>, mut exit: EventReader<AppExit>) {
-    for _event in exit.read() {
-        let _ = channels.1.clear_async_interrupt();
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use bevy::{prelude::*, time::TimeUpdateStrategy};
+    use common::{components::Leak, ecs_sync::NetId};
+
+    use super::{read_new_data, Debounce, LeakPin, LeakProbes, Probe};
+    use crate::{
+        config::LeakDebounce,
+        plugins::core::robot::{LocalRobot, LocalRobotMarker},
+    };
+
+    const SETTINGS: LeakDebounce = LeakDebounce {
+        threshold_ms: 500,
+        consecutive_samples: 3,
+    };
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    /// Reads wet every 10ms from `start` until `end`
+    fn wet(debounce: &mut Debounce, start: u64, end: u64) -> bool {
+        let mut latched = false;
+        for now in (start..=end).step_by(10) {
+            latched = debounce.update(true, ms(now));
+        }
+
+        latched
+    }
+
+    #[test]
+    fn debounce_ignores_short_blips() {
+        let mut debounce = Debounce::new(SETTINGS);
+
+        for cycle in 0..10 {
+            let start = cycle * 1000;
+            assert!(!wet(&mut debounce, start, start + 490));
+            assert!(!debounce.update(false, ms(start + 500)));
+        }
+    }
+
+    #[test]
+    fn debounce_needs_enough_samples() {
+        let mut debounce = Debounce::new(SETTINGS);
+
+        // A stalled loop only sees the probe twice, even though it was wet long enough
+        assert!(!debounce.update(true, ms(0)));
+        assert!(!debounce.update(true, ms(2000)));
+        assert!(debounce.update(true, ms(2010)));
+    }
+
+    #[test]
+    fn leak_latches_until_cleared() {
+        let mut debounce = Debounce::new(SETTINGS);
+
+        assert!(!wet(&mut debounce, 0, 490));
+        assert!(debounce.update(true, ms(500)));
+
+        // The probe drying off does not clear the leak
+        for now in (510..1000).step_by(10) {
+            assert!(debounce.update(false, ms(now)));
+        }
+        assert_eq!(debounce.confidence(ms(1000)), 1.0);
+
+        debounce.clear();
+        assert!(!debounce.update(false, ms(1000)));
+        assert_eq!(debounce.confidence(ms(1000)), 0.0);
+    }
+
+    #[test]
+    fn wet_probe_relatches_after_clear() {
+        let mut debounce = Debounce::new(SETTINGS);
+
+        assert!(wet(&mut debounce, 0, 500));
+
+        debounce.clear();
+        assert!(!wet(&mut debounce, 510, 1000));
+        assert!(debounce.update(true, ms(1010)));
+    }
+
+    #[test]
+    fn confidence_tracks_wet_time() {
+        let mut debounce = Debounce::new(SETTINGS);
+
+        debounce.update(true, ms(0));
+        assert_eq!(debounce.confidence(ms(0)), 0.0);
+        assert!((debounce.confidence(ms(250)) - 0.5).abs() < 1e-6);
+
+        debounce.update(false, ms(250));
+        assert_eq!(debounce.confidence(ms(250)), 0.0);
+    }
+
+    struct MockPin(Arc<AtomicBool>);
+
+    impl LeakPin for MockPin {
+        fn is_high(&self) -> bool {
+            self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn leak_published_after_full_debounce() {
+        let pin = Arc::new(AtomicBool::new(false));
+
+        let mut app = App::new();
+        app.add_plugins(TimePlugin)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(ms(50)))
+            .insert_resource(LeakProbes(vec![Probe {
+                name: "Leak".to_owned(),
+                pin: Box::new(MockPin(pin.clone())),
+                active_high: true,
+                debounce: Debounce::new(SETTINGS),
+            }]))
+            .add_systems(Update, read_new_data);
+
+        let entity = app.world_mut().spawn(LocalRobotMarker).id();
+        app.insert_resource(LocalRobot {
+            entity,
+            net_id: NetId::random(),
+        });
+
+        let leak = |app: &App| *app.world().get::<Leak>(entity).unwrap();
+
+        app.update();
+        assert_eq!(leak(&app), Leak::default());
+
+        // Going dry part way through restarts the debounce period
+        pin.store(true, Ordering::Relaxed);
+        for _ in 0..5 {
+            app.update();
+        }
+        assert!(!leak(&app).detected);
+        assert!(leak(&app).confidence > 0.0);
+
+        pin.store(false, Ordering::Relaxed);
+        app.update();
+        assert_eq!(leak(&app), Leak::default());
+
+        // 500ms at 50ms per frame, the first wet frame starts the period
+        pin.store(true, Ordering::Relaxed);
+        let mut last_confidence = 0.0;
+        for _ in 0..10 {
+            app.update();
+
+            let leak = leak(&app);
+            assert!(!leak.detected);
+            assert!(leak.confidence >= last_confidence);
+            last_confidence = leak.confidence;
+        }
+
+        app.update();
+        assert_eq!(
+            leak(&app),
+            Leak {
+                detected: true,
+                confidence: 1.0
+            }
+        );
     }
 }
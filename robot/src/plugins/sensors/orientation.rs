@@ -1,5 +1,5 @@
 use std::{
-    thread,
+    fs, io, mem, thread,
     time::{Duration, Instant},
 };
 
@@ -7,17 +7,27 @@ use ahrs::{Ahrs, Madgwick};
 use anyhow::{anyhow, Context};
 use bevy::{app::AppExit, prelude::*};
 use common::{
-    components::{Inertial, Magnetic, Orientation},
+    components::{
+        Inertial, MagCalibrationCommand, MagCalibrationResult, Magnetic, MagnetometerStatus,
+        Orientation,
+    },
     error::{self, ErrorEvent, Errors},
     events::ResetYaw,
-    types::hw::{InertialFrame, MagneticFrame},
+    types::{
+        hw::{InertialFrame, MagneticFrame},
+        units::{Celsius, Dps, GForce},
+    },
 };
 use crossbeam::channel::{self, Receiver, Sender};
 use nalgebra::Vector3;
 use tracing::{span, Level};
 
 use crate::{
-    peripheral::{icm20602::Icm20602, mmc5983::Mcc5983},
+    config::RobotConfig,
+    peripheral::{
+        icm20602::Icm20602,
+        mmc5983::{MagCalibration, Mcc5983},
+    },
     plugins::core::robot::{LocalRobot, LocalRobotMarker},
 };
 
@@ -25,7 +35,7 @@ pub struct OrientationPlugin;
 
 impl Plugin for OrientationPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(MadgwickFilter(Madgwick::new(1.0 / 1000.0, 0.041)));
+        app.insert_resource(MadgwickFilter::new());
 
         app.add_systems(Startup, start_inertial_thread.pipe(error::handle_errors));
         app.add_systems(
@@ -33,6 +43,8 @@ impl Plugin for OrientationPlugin {
             (
                 reset_yaw_handler.before(read_new_data),
                 read_new_data.run_if(resource_exists::<InertialChannels>),
+                (start_mag_calibration, read_mag_calibration)
+                    .run_if(resource_exists::<InertialChannels>),
             ),
         );
         app.add_systems(Last, shutdown.run_if(resource_exists::<InertialChannels>));
@@ -41,23 +53,116 @@ impl Plugin for OrientationPlugin {
 
 #[derive(Resource)]
 struct InertialChannels(
-    Receiver<([InertialFrame; 10], [MagneticFrame; 1])>,
+    Receiver<(Option<InertialFrame>, [Option<MagneticFrame>; 2], MagPolls)>,
     Sender<()>,
+    // Magnetometer calibration requests and their results
+    Sender<Duration>,
+    Receiver<(MagCalibration, u32)>,
 );
 
+/// Magnetometer polling totals, sent with each batch of frames
+#[derive(Debug, Clone, Copy, Default)]
+struct MagPolls {
+    skipped: u64,
+    last_reading: Option<Instant>,
+}
+
+/// Fresh magnetometer readings counted over roughly a second
+#[derive(Default)]
+struct MagRate {
+    window_start: Option<Instant>,
+    readings: u32,
+}
+
+// The IMU samples into its FIFO at 1kHz
+const SAMPLE_RATE_HZ: u16 = 1000;
+const SAMPLE_PERIOD: Duration = Duration::from_millis(1);
+// Longer gaps, from a stalled sensor thread, are not integrated all at once
+const MAX_SAMPLE_PERIOD: Duration = Duration::from_millis(50);
+
 #[derive(Resource)]
-struct MadgwickFilter(Madgwick<f32>);
+struct MadgwickFilter {
+    filter: Madgwick<f32>,
+    // Timestamp of the newest frame integrated
+    last_timestamp: Option<Duration>,
+}
 
-fn start_inertial_thread(mut cmds: Commands, errors: Res<Errors>) -> anyhow::Result<()> {
+impl MadgwickFilter {
+    fn new() -> Self {
+        Self {
+            filter: Madgwick::new(SAMPLE_PERIOD.as_secs_f32(), 0.041),
+            last_timestamp: None,
+        }
+    }
+
+    /// Integrates a frame over the time since the previous frame was read
+    ///
+    /// Frames no newer than the last one integrated, left in the buffer by a failed read, are skipped
+    fn update(&mut self, frame: &InertialFrame) -> anyhow::Result<()> {
+        let dt = match self.last_timestamp {
+            Some(last) if frame.timestamp <= last => return Ok(()),
+            Some(last) => (frame.timestamp - last).min(MAX_SAMPLE_PERIOD),
+            None => SAMPLE_PERIOD,
+        };
+        self.last_timestamp = Some(frame.timestamp);
+
+        let gyro = Vector3::new(frame.gyro_x.0, frame.gyro_y.0, frame.gyro_z.0)
+            * (std::f32::consts::PI / 180.0);
+        let accel = Vector3::new(frame.accel_x.0, frame.accel_y.0, frame.accel_z.0);
+
+        *self.filter.sample_period_mut() = dt.as_secs_f32();
+        self.filter
+            .update_imu(&gyro, &accel)
+            .map_err(|msg| anyhow!("Process IMU frame: {msg}"))?;
+
+        Ok(())
+    }
+}
+
+fn start_inertial_thread(
+    mut cmds: Commands,
+    errors: Res<Errors>,
+    config: Res<RobotConfig>,
+) -> anyhow::Result<()> {
     let (tx_data, rx_data) = channel::bounded(5);
     let (tx_exit, rx_exit) = channel::bounded(1);
+    let (tx_calibrate, rx_calibrate) = channel::bounded(1);
+    let (tx_calibration, rx_calibration) = channel::bounded(5);
 
     let mut imu = Icm20602::new(Icm20602::SPI_BUS, Icm20602::SPI_SELECT, Icm20602::SPI_CLOCK)
         .context("Inerital Sensor (ICM20602)")?;
+    imu.enable_fifo(SAMPLE_RATE_HZ)
+        .context("Enable inertial FIFO")?;
     let mut mag = Mcc5983::new(Mcc5983::SPI_BUS, Mcc5983::SPI_SELECT, Mcc5983::SPI_CLOCK)
         .context("Magnmetic Sensor (MCC5983)")?;
+    mag.set_degauss_interval(Some(Mcc5983::DEGAUSS_INTERVAL));
+
+    let calibration_path = &config.mag_calibration_path;
+    match fs::read_to_string(calibration_path) {
+        Ok(calibration) => {
+            let calibration: MagCalibration = toml::from_str(&calibration)
+                .with_context(|| format!("Parse {}", calibration_path.display()))?;
+
+            mag.apply_calibration(&calibration);
+            let _ = tx_calibration.send((calibration, 0));
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            warn!(
+                "No magnetometer calibration at {}, using raw readings",
+                calibration_path.display()
+            );
+        }
+        Err(err) => {
+            return Err(anyhow!(err).context(format!("Read {}", calibration_path.display())));
+        }
+    }
 
-    cmds.insert_resource(InertialChannels(rx_data, tx_exit));
+    cmds.insert_resource(InertialChannels(
+        rx_data,
+        tx_exit,
+        tx_calibrate,
+        rx_calibration,
+    ));
 
     let errors = errors.0.clone();
     thread::Builder::new()
@@ -65,56 +170,105 @@ fn start_inertial_thread(mut cmds: Commands, errors: Res<Errors>) -> anyhow::Res
         .spawn(move || {
             let _span = span!(Level::INFO, "IMU sensor thread").entered();
 
-            let interval = Duration::from_secs_f32(1.0 / 1000.0);
-            let counts = 10;
+            let interval = Duration::from_secs_f32(1.0 / 200.0);
+            let counts = 2;
 
             let mut counter = 0;
 
-            let mut inertial_buffer = [InertialFrame::default(); 10];
-            let mut mag_buffer = [MagneticFrame::default(); 1];
+            // Drained from the IMU's FIFO each wake, averaged down to one frame per batch
+            let mut inertial_buffer = Vec::new();
+            // Polled twice as fast as the sensor measures so no measurement is missed,
+            // `None` where no new measurement was ready
+            let mut mag_buffer = [None; 2];
+            let mut mag_polls = MagPolls::default();
 
-            let inertial_divisor = counts / inertial_buffer.len();
             let mag_divisor = counts / mag_buffer.len();
 
             let mut deadline = Instant::now();
 
             let mut first_run = true;
 
+            // The deadline and samples collected for an in progress magnetometer calibration
+            let mut calibration: Option<(Instant, Vec<MagneticFrame>)> = None;
+
             loop {
                 let span = span!(Level::INFO, "IMU sensor cycle").entered();
 
                 if counter == 0 && !first_run {
-                    let res = tx_data.send((inertial_buffer, mag_buffer));
+                    let inertial = average_frames(&inertial_buffer);
+                    inertial_buffer.clear();
+
+                    let res = tx_data.send((inertial, mag_buffer, mag_polls));
                     if res.is_err() {
                         // Peer disconnected
                         return;
                     }
                 }
 
-                if counter % inertial_divisor == 0 {
-                    let rst = imu.read_frame().context("Read inertial frame");
+                let rst = imu.read_fifo_burst().context("Read inertial frames");
+                match rst {
+                    Ok(frames) => {
+                        inertial_buffer.extend(frames);
+                    }
+                    Err(err) => {
+                        let _ = errors.send(err);
+                    }
+                }
 
-                    match rst {
-                        Ok(frame) => {
-                            inertial_buffer[counter / inertial_divisor] = frame;
-                        }
-                        Err(err) => {
-                            let _ = errors.send(err);
+                if let Ok(duration) = rx_calibrate.try_recv() {
+                    info!(?duration, "Collecting magnetometer calibration samples");
+                    calibration = Some((Instant::now() + duration, Vec::new()));
+                }
+
+                if let Some((deadline, samples)) = &mut calibration {
+                    if Instant::now() >= *deadline {
+                        let samples = mem::take(samples);
+                        calibration = None;
+
+                        match MagCalibration::fit(&samples) {
+                            Some(fit) => {
+                                info!(?fit, samples = samples.len(), "Magnetometer calibrated");
+
+                                mag.apply_calibration(&fit);
+                                let _ = tx_calibration.send((fit, samples.len() as u32));
+                            }
+                            None => {
+                                let _ = errors.send(anyhow!(
+                                    "Could not fit magnetometer calibration to {} samples, rotate the robot through more orientations",
+                                    samples.len()
+                                ));
+                            }
                         }
                     }
                 }
 
                 if counter % mag_divisor == 0 {
-                    let rst = mag.read_frame().context("Read magnetic frame");
-
-                    match rst {
-                        Ok(frame) => {
-                            mag_buffer[counter / mag_divisor] = frame;
-                        }
+                    let rst = if let Some((_, samples)) = &mut calibration {
+                        mag.read_uncalibrated_frame_if_ready().map(|sample| {
+                            sample.map(|sample| {
+                                samples.push(sample);
+                                mag.calibration().apply(sample)
+                            })
+                        })
+                    } else {
+                        mag.read_frame_if_ready()
+                    };
+                    let rst = rst.context("Read magnetic frame");
+
+                    let frame = match rst {
+                        Ok(frame) => frame,
                         Err(err) => {
                             let _ = errors.send(err);
+                            None
                         }
+                    };
+
+                    if frame.is_some() {
+                        mag_polls.last_reading = Some(Instant::now());
+                    } else {
+                        mag_polls.skipped += 1;
                     }
+                    mag_buffer[counter / mag_divisor] = frame;
                 }
 
                 if let Ok(()) = rx_exit.try_recv() {
@@ -137,38 +291,134 @@ fn start_inertial_thread(mut cmds: Commands, errors: Res<Errors>) -> anyhow::Res
     Ok(())
 }
 
+/// Averages a batch of frames into one, timestamped with the newest frame
+///
+/// Averaging the gyro over the batch integrates the same rotation while rejecting high frequency noise
+fn average_frames(frames: &[InertialFrame]) -> Option<InertialFrame> {
+    let last = frames.last()?;
+    let count = frames.len() as f32;
+
+    let mut sum = InertialFrame::default();
+    for frame in frames {
+        sum.gyro_x += frame.gyro_x;
+        sum.gyro_y += frame.gyro_y;
+        sum.gyro_z += frame.gyro_z;
+        sum.accel_x += frame.accel_x;
+        sum.accel_y += frame.accel_y;
+        sum.accel_z += frame.accel_z;
+        sum.tempature += frame.tempature;
+    }
+
+    Some(InertialFrame {
+        gyro_x: sum.gyro_x / Dps(count),
+        gyro_y: sum.gyro_y / Dps(count),
+        gyro_z: sum.gyro_z / Dps(count),
+        accel_x: sum.accel_x / GForce(count),
+        accel_y: sum.accel_y / GForce(count),
+        accel_z: sum.accel_z / GForce(count),
+        tempature: sum.tempature / Celsius(count),
+        timestamp: last.timestamp,
+    })
+}
+
 fn read_new_data(
     mut cmds: Commands,
+    mut mag_rate: Local<MagRate>,
     channels: Res<InertialChannels>,
     mut madgwick_filter: ResMut<MadgwickFilter>,
     robot: Res<LocalRobot>,
     mut errors: EventWriter<ErrorEvent>,
 ) {
-    for (inertial, magnetic) in channels.0.try_iter() {
+    for (inertial, magnetic, mag_polls) in channels.0.try_iter() {
         // We currently ignore mag updates as the compass is not calibrated
         // TODO(high): Calibrate the compass
-        for inertial in inertial {
-            let gyro = Vector3::new(inertial.gyro_x.0, inertial.gyro_y.0, inertial.gyro_z.0)
-                * (std::f32::consts::PI / 180.0);
-            let accel = Vector3::new(inertial.accel_x.0, inertial.accel_y.0, inertial.accel_z.0);
-
-            let rst = madgwick_filter.0.update_imu(&gyro, &accel);
-            if let Err(msg) = rst {
-                errors.send(anyhow!("Process IMU frame: {msg}").into());
+        if let Some(inertial) = inertial {
+            let rst = madgwick_filter.update(&inertial);
+            if let Err(err) = rst {
+                errors.send(err.into());
             }
+
+            let quat: glam::Quat = madgwick_filter.filter.quat.into();
+            let orientation = Orientation(quat);
+
+            cmds.entity(robot.entity)
+                .insert((orientation, Inertial(inertial)));
+        }
+
+        // Stale magnetometer frames are dropped rather than repeated
+        if let Some(magnetic) = magnetic.iter().flatten().last() {
+            cmds.entity(robot.entity).insert(Magnetic(*magnetic));
+        }
+
+        let now = Instant::now();
+        let window_start = *mag_rate.window_start.get_or_insert(now);
+        mag_rate.readings += magnetic.iter().flatten().count() as u32;
+
+        let window = now - window_start;
+        if window >= Duration::from_secs(1) {
+            let last_reading_age = mag_polls
+                .last_reading
+                .map(|it| now - it)
+                .unwrap_or(Duration::MAX);
+
+            cmds.entity(robot.entity).insert(MagnetometerStatus {
+                rate_hz: mag_rate.readings as f32 / window.as_secs_f32(),
+                last_reading_age,
+                skipped_polls: mag_polls.skipped,
+            });
+
+            *mag_rate = MagRate {
+                window_start: Some(now),
+                readings: 0,
+            };
         }
+    }
+}
 
-        let quat: glam::Quat = madgwick_filter.0.quat.into();
-        let orientation = Orientation(quat);
+fn start_mag_calibration(
+    mut cmds: Commands,
+    channels: Res<InertialChannels>,
+    commands: Query<(Entity, &MagCalibrationCommand), With<LocalRobotMarker>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    for (entity, command) in &commands {
+        let rst = channels.2.try_send(command.duration);
+        if rst.is_err() {
+            errors.send(anyhow!("Magnetometer calibration already in progress").into());
+        }
 
-        let inertial = inertial.last().unwrap();
-        let inertial = Inertial(*inertial);
+        cmds.entity(entity).remove::<MagCalibrationCommand>();
+    }
+}
 
-        let magnetic = magnetic.last().unwrap();
-        let magnetic = Magnetic(*magnetic);
+fn read_mag_calibration(
+    mut cmds: Commands,
+    channels: Res<InertialChannels>,
+    robot: Res<LocalRobot>,
+    config: Res<RobotConfig>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let calibration_path = &config.mag_calibration_path;
+
+    for (calibration, samples) in channels.3.try_iter() {
+        // Calibrations loaded from disk do not need to be saved again
+        if samples > 0 {
+            let rst = toml::to_string(&calibration)
+                .context("Serialize magnetometer calibration")
+                .and_then(|it| {
+                    fs::write(calibration_path, it)
+                        .with_context(|| format!("Save {}", calibration_path.display()))
+                });
+            if let Err(err) = rst {
+                errors.send(err.into());
+            }
+        }
 
-        cmds.entity(robot.entity)
-            .insert((orientation, inertial, magnetic));
+        cmds.entity(robot.entity).insert(MagCalibrationResult {
+            hard_iron: calibration.offset,
+            soft_iron: calibration.soft_iron,
+            samples,
+        });
     }
 }
 
@@ -179,8 +429,8 @@ fn reset_yaw_handler(
     for _ in events.read() {
         info!("Resetting Yaw");
 
-        madgwick_filter.0.quat.as_mut_unchecked().vector_mut()[2] = 0.0;
-        madgwick_filter.0.quat.renormalize();
+        madgwick_filter.filter.quat.as_mut_unchecked().vector_mut()[2] = 0.0;
+        madgwick_filter.filter.quat.renormalize();
     }
 }
 
@@ -189,3 +439,115 @@ fn shutdown(channels: Res<InertialChannels>, mut exit: EventReader<AppExit>) {
         let _ = channels.1.send(());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{iter, time::Duration};
+
+    use common::types::{
+        hw::InertialFrame,
+        units::{Dps, GForce},
+    };
+
+    use super::{average_frames, MadgwickFilter};
+
+    /// Frames from spinning level at 90 dps around z, read at `timestamps`
+    fn spinning(timestamps: impl IntoIterator<Item = Duration>) -> Vec<InertialFrame> {
+        timestamps
+            .into_iter()
+            .map(|timestamp| InertialFrame {
+                gyro_z: Dps(90.0),
+                accel_z: GForce(1.0),
+                timestamp,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn yaw(filter: &MadgwickFilter) -> f32 {
+        filter.filter.quat.euler_angles().2
+    }
+
+    #[test]
+    fn integration_follows_timestamps() {
+        let regular = spinning((0..=1000).map(Duration::from_millis));
+
+        // The same second of rotation, read at uneven intervals
+        let steps = [500, 2000, 1000, 1500].into_iter().cycle().take(800);
+        let irregular = spinning(iter::once(Duration::ZERO).chain(steps.scan(
+            Duration::ZERO,
+            |timestamp, step| {
+                *timestamp += Duration::from_micros(step);
+                Some(*timestamp)
+            },
+        )));
+        assert_eq!(irregular.last().unwrap().timestamp, Duration::from_secs(1));
+
+        let mut expected = MadgwickFilter::new();
+        for frame in &regular {
+            expected.update(frame).unwrap();
+        }
+
+        // Delivered in uneven batches, each ending with a stale frame left by a failed read
+        let mut filter = MadgwickFilter::new();
+        let mut batch_sizes = [1, 7, 3, 10, 2].into_iter().cycle();
+        let mut frames = &irregular[..];
+        while !frames.is_empty() {
+            let size = batch_sizes.next().unwrap().min(frames.len());
+            let (batch, rest) = frames.split_at(size);
+
+            for frame in batch.iter().chain(batch.last()) {
+                filter.update(frame).unwrap();
+            }
+
+            frames = rest;
+        }
+
+        assert!((yaw(&filter) - yaw(&expected)).abs() < 1e-3);
+        assert!((yaw(&expected) - 90f32.to_radians()).abs() < 1e-2);
+    }
+
+    #[test]
+    fn averaged_batches_integrate_like_every_frame() {
+        let frames = spinning((0..=1000).map(Duration::from_millis));
+
+        let mut expected = MadgwickFilter::new();
+        for frame in &frames {
+            expected.update(frame).unwrap();
+        }
+
+        let mut filter = MadgwickFilter::new();
+        filter.update(&frames[0]).unwrap();
+        for batch in frames[1..].chunks(10) {
+            let average = average_frames(batch).unwrap();
+            assert_eq!(average.timestamp, batch.last().unwrap().timestamp);
+
+            filter.update(&average).unwrap();
+        }
+
+        assert!((yaw(&filter) - yaw(&expected)).abs() < 1e-2);
+    }
+
+    #[test]
+    fn average_frames_componentwise() {
+        assert!(average_frames(&[]).is_none());
+
+        let frames = [
+            InertialFrame {
+                gyro_x: Dps(1.0),
+                accel_z: GForce(0.5),
+                ..Default::default()
+            },
+            InertialFrame {
+                gyro_x: Dps(3.0),
+                accel_z: GForce(1.5),
+                timestamp: Duration::from_millis(1),
+                ..Default::default()
+            },
+        ];
+        let average = average_frames(&frames).unwrap();
+        assert_eq!(average.gyro_x, Dps(2.0));
+        assert_eq!(average.accel_z, GForce(1.0));
+        assert_eq!(average.timestamp, Duration::from_millis(1));
+    }
+}
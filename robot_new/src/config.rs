@@ -5,6 +5,7 @@ pub mod servo;
 pub mod thruster;
 
 use bevy::ecs::system::Resource;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::config::{
@@ -15,7 +16,7 @@ use crate::config::{
     thruster::{ThrusterConfigDefinition, ThrusterDefinition},
 };
 
-#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[derive(Resource, Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
     pub robot: RobotDefinition,
     pub interfaces: Vec<InterfaceDefinition>,
@@ -27,7 +28,7 @@ pub struct Config {
     pub control: ControlSystemDefinition,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RobotDefinition {
     pub name: String,
     pub port: u16,
@@ -1,8 +1,12 @@
 use bevy::transform::components::Transform;
-use glam::{vec3, EulerRot, Quat};
+use glam::{vec3, EulerRot, Mat3, Quat};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Yaw, pitch, roll
+const EULER_ROT: EulerRot = EulerRot::YXZ;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct CameraDefinition {
     pub name: String,
     #[serde(flatten)]
@@ -10,12 +14,15 @@ pub struct CameraDefinition {
     pub transform: Option<ConfigTransform>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type")]
 pub enum CameraTypeDefinition {
     H264,
     // MJPEG,
-    // GSTREAMER { tx: String, rx: String},
+    /// Opened by the surface from a gstreamer launch description, e.g. `v4l2src device=/dev/video2`
+    Gstreamer {
+        pipeline: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,20 +30,31 @@ pub enum CameraTypeDefinition {
 #[serde(from = "ConfigTransformImpl")]
 pub struct ConfigTransform(pub Transform);
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Described by the format it is written in
+impl JsonSchema for ConfigTransform {
+    fn schema_name() -> String {
+        "ConfigTransform".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        ConfigTransformImpl::json_schema(gen)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 struct ConfigTransformImpl {
     position: ConfigPosition,
     rotation: ConfigRotation,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 struct ConfigPosition {
     x: f32,
     y: f32,
     z: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 struct ConfigRotation {
     yaw: f32,
     pitch: f32,
@@ -52,7 +70,7 @@ impl From<ConfigTransformImpl> for ConfigTransform {
         ConfigTransform(
             Transform::from_translation(Quat::from_rotation_x(90f32.to_radians()) * vec3(x, -y, z))
                 .with_rotation(Quat::from_euler(
-                    EulerRot::default(),
+                    EULER_ROT,
                     yaw.to_radians(),
                     pitch.to_radians(),
                     roll.to_radians(),
@@ -63,7 +81,132 @@ impl From<ConfigTransformImpl> for ConfigTransform {
 
 impl From<ConfigTransform> for ConfigTransformImpl {
     fn from(value: ConfigTransform) -> Self {
-        // FIXME: Actually impl this when im not tired lol
-        todo!()
+        let Transform {
+            translation,
+            rotation,
+            ..
+        } = value.0;
+
+        let position = Quat::from_rotation_x(-90f32.to_radians()) * translation;
+        let (yaw, pitch, roll) = to_yaw_pitch_roll(rotation);
+
+        ConfigTransformImpl {
+            position: ConfigPosition {
+                x: position.x,
+                y: -position.y,
+                z: position.z,
+            },
+            rotation: ConfigRotation {
+                yaw: yaw.to_degrees(),
+                pitch: pitch.to_degrees(),
+                roll: roll.to_degrees(),
+            },
+        }
+    }
+}
+
+/// Inverse of `Quat::from_euler(EULER_ROT, yaw, pitch, roll)`
+///
+/// `Quat::to_euler` goes through `asin` and loses yaw and roll near pitch = ±90°,
+/// so this decomposes the rotation matrix directly and puts all of the rotation into yaw when gimbal locked
+fn to_yaw_pitch_roll(rotation: Quat) -> (f32, f32, f32) {
+    let matrix = Mat3::from_quat(rotation);
+
+    let cos_pitch = matrix.x_axis.y.hypot(matrix.y_axis.y);
+    let pitch = (-matrix.z_axis.y).atan2(cos_pitch);
+
+    if cos_pitch < 1e-4 {
+        let yaw = (-matrix.x_axis.z).atan2(matrix.x_axis.x);
+
+        (yaw, pitch, 0.0)
+    } else {
+        let yaw = matrix.z_axis.x.atan2(matrix.z_axis.z);
+        let roll = matrix.x_axis.y.atan2(matrix.y_axis.y);
+
+        (yaw, pitch, roll)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camera_type_serde() {
+        const PIPELINE: &str = "v4l2src device=/dev/video2 ! jpegdec";
+
+        let h264: CameraDefinition = toml::from_str(
+            r#"
+            name = "Front"
+            type = "H264"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(h264.camera_type, CameraTypeDefinition::H264));
+
+        let gstreamer: CameraDefinition = toml::from_str(&format!(
+            r#"
+            name = "Claw"
+            type = "Gstreamer"
+            pipeline = "{PIPELINE}"
+            "#
+        ))
+        .unwrap();
+        let roundtrip: CameraDefinition =
+            toml::from_str(&toml::to_string(&gstreamer).unwrap()).unwrap();
+
+        for definition in [gstreamer, roundtrip] {
+            let CameraTypeDefinition::Gstreamer { pipeline } = definition.camera_type else {
+                panic!("Expected a gstreamer camera, got {definition:?}");
+            };
+            assert_eq!(pipeline, PIPELINE);
+        }
+    }
+
+    #[test]
+    fn config_transform_roundtrip() {
+        let positions = [
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+            vec3(1.0, -2.0, 3.0),
+            vec3(-0.25, 0.5, -1.5),
+        ];
+        let pitches = [-90.0, -89.9, -45.0, 0.0, 30.0, 89.9, 90.0];
+
+        for position in positions {
+            for yaw in (-180..=180).step_by(15) {
+                for pitch in pitches {
+                    for roll in (-180..=180).step_by(30) {
+                        let original = ConfigTransform::from(ConfigTransformImpl {
+                            position: ConfigPosition {
+                                x: position.x,
+                                y: position.y,
+                                z: position.z,
+                            },
+                            rotation: ConfigRotation {
+                                yaw: yaw as f32,
+                                pitch,
+                                roll: roll as f32,
+                            },
+                        });
+
+                        let roundtrip =
+                            ConfigTransform::from(ConfigTransformImpl::from(original.clone()));
+
+                        let (expected, actual) = (original.0, roundtrip.0);
+                        assert!(
+                            expected.translation.abs_diff_eq(actual.translation, 1e-4),
+                            "{expected:?} != {actual:?}"
+                        );
+                        // q and -q are the same rotation
+                        assert!(
+                            expected.rotation.abs_diff_eq(actual.rotation, 1e-4)
+                                || expected.rotation.abs_diff_eq(-actual.rotation, 1e-4),
+                            "yaw: {yaw}, pitch: {pitch}, roll: {roll}, {expected:?} != {actual:?}"
+                        );
+                    }
+                }
+            }
+        }
     }
 }
@@ -1,13 +1,14 @@
 use common::components::PidConfig;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ControlSystemDefinition {
     pub depth_hold: PidConfig,
     pub stabilize: StabilizeDefinition,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct StabilizeDefinition {
     pub pitch: PidConfig,
     pub yaw: PidConfig,
@@ -1,13 +1,14 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct InterfaceDefinition {
     pub name: String,
     #[serde(flatten)]
     pub hardware: HardwareDefinition,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "hardware")]
 pub enum HardwareDefinition {
     #[serde(rename = "pca9685_i2c")]
@@ -28,20 +29,20 @@ pub enum HardwareDefinition {
     Leak(LeakDefinition),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct I2cDefinition {
     pub i2c_bus: u32,
     pub i2c_address: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SpiDefinition {
     pub spi_bus: u32,
     pub spi_cs: u32,
 }
 
 // TODO: Move to Pca9685 Module
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Pca9685Definition {
     #[serde(flatten)]
     pub i2c: I2cDefinition,
@@ -49,34 +50,37 @@ pub struct Pca9685Definition {
 }
 
 // TODO: Move to Ads1115 Module
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Ads1115Definition {
     #[serde(flatten)]
     pub i2c: I2cDefinition,
 }
 
 // TODO: Move to bluerov_powersense Module
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BlueRovPowerSenseDefinition {
     pub adc_name: String,
 }
 
 // TODO: Move to icm20602 Module
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Icm20602Definition {
     #[serde(flatten)]
     pub spi: SpiDefinition,
 }
 
 // TODO: Move to mmc5937 Module
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Mmc5983Definition {
     #[serde(flatten)]
     pub spi: SpiDefinition,
+    /// Seconds between SET/RESET degaussing pulses, never degausses if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub degauss_interval: Option<f32>,
 }
 
 // TODO: Move to ms5937 Module
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Ms5937Definition {
     #[serde(flatten)]
     pub i2c: I2cDefinition,
@@ -85,15 +89,40 @@ pub struct Ms5937Definition {
 }
 
 // TODO: Move to neopixel Module
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct NeopixelDefinition {
     #[serde(flatten)]
     pub spi: SpiDefinition,
 }
 
 // TODO: Move to leak Module
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct LeakDefinition {
+    pub probes: Vec<LeakProbeDefinition>,
+    #[serde(default)]
+    pub debounce: LeakDebounce,
+}
+
+/// How long a probe must read wet before it reports a leak
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct LeakDebounce {
+    pub threshold_ms: u32,
+    pub consecutive_samples: u32,
+}
+
+impl Default for LeakDebounce {
+    fn default() -> Self {
+        Self {
+            threshold_ms: 500,
+            consecutive_samples: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LeakProbeDefinition {
+    pub name: String,
     pub gpio: u32,
     pub active_high: bool,
 }
@@ -1,10 +1,25 @@
 use ahash::HashMap;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ServoDefinition {
     pub name: String,
 
     #[serde(flatten)]
+    #[schemars(with = "HashMap<String, AnyValue>")]
     pub interface: HashMap<String, toml::Value>,
 }
+
+/// Stands in for `toml::Value` in the schema, the fields depend on the servo's interface
+struct AnyValue;
+
+impl JsonSchema for AnyValue {
+    fn schema_name() -> String {
+        "AnyValue".to_owned()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        Schema::Bool(true)
+    }
+}
@@ -1,31 +1,281 @@
-use std::path::PathBuf;
+use std::{fmt::Debug, hash::Hash, path::PathBuf};
 
+use ahash::{HashMap, HashSet};
+use anyhow::{bail, Context};
+use common::types::hw::PwmChannelId;
 use glam::Vec3A;
-use motor_math::Motor;
-use serde::{Deserialize, Serialize};
+use motor_math::{
+    blue_rov::HeavyMotorId,
+    motor_preformance::{self, MotorData},
+    x3d::X3dMotorId,
+    ErasedMotorId, Motor, MotorConfig,
+};
+use nalgebra::vector;
+use schemars::JsonSchema;
+use serde::{
+    de::{value::StrDeserializer, DeserializeOwned, IntoDeserializer},
+    Deserialize, Serialize,
+};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ThrusterConfigDefinition {
     #[serde(flatten)]
     pub thruster_config_type: ThrusterConfigTypeDefinition,
 
+    #[schemars(with = "[f32; 3]")]
     pub center_of_mass: Vec3A,
     pub thruster_amperage_budget: f32,
     pub thruster_jerk_limit: f32,
+    /// Seconds without fresh movement commands before the thrusters are stopped, defaults to 0.25
+    #[serde(default = "default_failsafe_timeout")]
+    pub thruster_failsafe_timeout: f32,
     pub thruster_data_path: PathBuf,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type")]
 pub enum ThrusterConfigTypeDefinition {
     X3d {
-        seed_thruster: Motor,
+        seed_thruster: Motor<f32>,
     },
     BlueRov {
-        vertical_seed_thruster: Motor,
-        lateral_seed_thruster: Motor,
+        vertical_seed_thruster: Motor<f32>,
+        lateral_seed_thruster: Motor<f32>,
     },
     Custom,
 }
 
-pub type ThrusterDefinition = toml::Value;
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ThrusterDefinition {
+    /// For X3d and BlueRov configs this must be the name of the motor id, ie `FrontRightTop`
+    pub name: String,
+    pub interface: String,
+    pub pwm_channel: PwmChannelId,
+
+    /// Only used by custom configs, X3d and BlueRov thrusters are derived from the seed thrusters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub motor: Option<Motor<f32>>,
+}
+
+fn default_failsafe_timeout() -> f32 {
+    0.25
+}
+
+impl ThrusterConfigDefinition {
+    pub fn build(
+        &self,
+        thrusters: &[ThrusterDefinition],
+    ) -> anyhow::Result<(
+        MotorConfig<ErasedMotorId, f32>,
+        Vec<(ErasedMotorId, PwmChannelId)>,
+    )> {
+        let mut names = HashSet::default();
+        for thruster in thrusters {
+            if !names.insert(&thruster.name) {
+                bail!("Duplicate thruster name: {}", thruster.name);
+            }
+        }
+
+        // Fail early on a bad path instead of when the thrusters first move
+        self.motor_data().context("Load thruster data")?;
+
+        let center_mass = vector![
+            self.center_of_mass.x,
+            self.center_of_mass.y,
+            self.center_of_mass.z
+        ];
+
+        match &self.thruster_config_type {
+            ThrusterConfigTypeDefinition::X3d { seed_thruster } => {
+                let config = MotorConfig::<X3dMotorId, f32>::new(*seed_thruster, center_mass);
+                let channels = pwm_channels(&config, thrusters).context("X3d thrusters")?;
+
+                Ok((config.erase(), channels))
+            }
+            ThrusterConfigTypeDefinition::BlueRov {
+                vertical_seed_thruster,
+                lateral_seed_thruster,
+            } => {
+                let config = MotorConfig::<HeavyMotorId, f32>::new(
+                    *lateral_seed_thruster,
+                    *vertical_seed_thruster,
+                    center_mass,
+                );
+                let channels = pwm_channels(&config, thrusters).context("BlueRov thrusters")?;
+
+                Ok((config.erase(), channels))
+            }
+            ThrusterConfigTypeDefinition::Custom => {
+                let mut thrusters: Vec<_> = thrusters.iter().collect();
+                thrusters.sort_by(|a, b| a.name.cmp(&b.name));
+
+                if thrusters.len() > ErasedMotorId::MAX as usize + 1 {
+                    bail!("Too many thrusters: {}", thrusters.len());
+                }
+
+                let mut motors = Vec::new();
+                let mut channels = Vec::new();
+
+                for (idx, thruster) in thrusters.into_iter().enumerate() {
+                    let motor = thruster
+                        .motor
+                        .with_context(|| format!("Missing motor for thruster {}", thruster.name))?;
+
+                    motors.push((idx as ErasedMotorId, motor));
+                    channels.push((idx as ErasedMotorId, thruster.pwm_channel));
+                }
+
+                Ok((MotorConfig::new_raw(motors, center_mass), channels))
+            }
+        }
+    }
+
+    pub fn motor_data(&self) -> anyhow::Result<MotorData> {
+        motor_preformance::read_motor_data(&self.thruster_data_path)
+            .with_context(|| format!("Read {}", self.thruster_data_path.display()))
+    }
+}
+
+/// Matches each motor in `config` with the thruster of the same name
+fn pwm_channels<MotorId>(
+    config: &MotorConfig<MotorId, f32>,
+    thrusters: &[ThrusterDefinition],
+) -> anyhow::Result<Vec<(ErasedMotorId, PwmChannelId)>>
+where
+    MotorId: Ord + Debug + Copy + Into<ErasedMotorId> + DeserializeOwned + Hash,
+{
+    let mut channels = HashMap::default();
+    for thruster in thrusters {
+        let deserializer: StrDeserializer<serde::de::value::Error> =
+            thruster.name.as_str().into_deserializer();
+        let motor_id = MotorId::deserialize(deserializer)
+            .with_context(|| format!("Unknown thruster name: {}", thruster.name))?;
+
+        channels.insert(motor_id, thruster.pwm_channel);
+    }
+
+    config
+        .motors()
+        .map(|(motor_id, _)| {
+            let channel = channels
+                .get(motor_id)
+                .with_context(|| format!("Missing pwm channel for thruster {motor_id:?}"))?;
+
+            Ok(((*motor_id).into(), *channel))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::config::Config;
+
+    use super::*;
+
+    fn read_config() -> Config {
+        let config = fs::read_to_string("robot.toml").expect("Read config");
+        toml::from_str(&config).expect("Parse config")
+    }
+
+    #[test]
+    fn build_x3d() {
+        let config = read_config();
+        let (motor_config, channels) = config
+            .thruster_config
+            .build(&config.thrusters)
+            .expect("Build thruster config");
+
+        assert_eq!(motor_config.motors().count(), 8);
+        assert_eq!(channels.len(), 8);
+
+        let front_right_top: ErasedMotorId = X3dMotorId::FrontRightTop.into();
+        assert!(channels.contains(&(front_right_top, 7)));
+    }
+
+    #[test]
+    fn thruster_config_roundtrip() {
+        let config = read_config();
+
+        let serialized = toml::to_string(&config.thruster_config).expect("Serialize");
+        let thruster_config: ThrusterConfigDefinition =
+            toml::from_str(&serialized).expect("Deserialize");
+
+        #[derive(Serialize, Deserialize)]
+        struct Thrusters {
+            thrusters: Vec<ThrusterDefinition>,
+        }
+
+        let serialized = toml::to_string(&Thrusters {
+            thrusters: config.thrusters.clone(),
+        })
+        .expect("Serialize");
+        let Thrusters { thrusters } = toml::from_str(&serialized).expect("Deserialize");
+
+        let expected = config
+            .thruster_config
+            .build(&config.thrusters)
+            .expect("Build thruster config");
+        let actual = thruster_config
+            .build(&thrusters)
+            .expect("Build thruster config");
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn custom_roundtrip() {
+        let config = read_config();
+
+        let (x3d, x3d_channels) = config
+            .thruster_config
+            .build(&config.thrusters)
+            .expect("Build thruster config");
+
+        let thruster_config = ThrusterConfigDefinition {
+            thruster_config_type: ThrusterConfigTypeDefinition::Custom,
+            ..config.thruster_config.clone()
+        };
+        let thrusters: Vec<_> = x3d
+            .motors()
+            .zip(&x3d_channels)
+            .map(|((motor_id, motor), (_, pwm_channel))| ThrusterDefinition {
+                name: format!("Thruster{motor_id}"),
+                interface: "PWM".to_owned(),
+                pwm_channel: *pwm_channel,
+                motor: Some(*motor),
+            })
+            .collect();
+
+        let serialized = toml::to_string(&thruster_config).expect("Serialize");
+        let thruster_config: ThrusterConfigDefinition =
+            toml::from_str(&serialized).expect("Deserialize");
+
+        let (motor_config, channels) = thruster_config
+            .build(&thrusters)
+            .expect("Build thruster config");
+
+        assert_eq!(motor_config, x3d);
+        assert_eq!(channels, x3d_channels);
+    }
+
+    #[test]
+    fn duplicate_thruster() {
+        let config = read_config();
+
+        let mut thrusters = config.thrusters.clone();
+        thrusters.push(thrusters[0].clone());
+
+        assert!(config.thruster_config.build(&thrusters).is_err());
+    }
+
+    #[test]
+    fn missing_thruster() {
+        let config = read_config();
+
+        let thrusters = &config.thrusters[1..];
+
+        assert!(config.thruster_config.build(thrusters).is_err());
+    }
+}
@@ -1,10 +1,7 @@
 use std::fs;
 
 use anyhow::Context;
-
-use crate::config::Config;
-
-pub mod config;
+use robot_new::config::Config;
 
 fn main() -> anyhow::Result<()> {
     let config = fs::read_to_string("robot.toml").context("Read config")?;
@@ -12,5 +9,13 @@ fn main() -> anyhow::Result<()> {
 
     println!("Config: {config:#?}");
 
+    let (motor_config, pwm_channels) = config
+        .thruster_config
+        .build(&config.thrusters)
+        .context("Build thruster config")?;
+
+    println!("Motor Config: {motor_config:#?}");
+    println!("Thruster PWM Channels: {pwm_channels:?}");
+
     Ok(())
 }
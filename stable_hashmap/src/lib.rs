@@ -2,19 +2,124 @@ use std::{collections::HashMap as StdHashMap, hash::BuildHasher};
 
 pub type StableHashMap<K, V> = StdHashMap<K, V, StableState>;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct StableState;
+const DEFAULT_SEED: u64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StableState {
+    seed: u64,
+}
+
+impl StableState {
+    /// A fixed hasher state other than the default, `with_seed(1)` is the default
+    pub fn with_seed(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
 
 impl BuildHasher for StableState {
     type Hasher = ahash::AHasher;
 
     fn build_hasher(&self) -> Self::Hasher {
-        ahash::RandomState::with_seeds(1, 2, 3, 4).build_hasher()
+        ahash::RandomState::with_seeds(self.seed, 2, 3, 4).build_hasher()
     }
 }
 
 impl Default for StableState {
     fn default() -> StableState {
-        Self
+        Self::with_seed(DEFAULT_SEED)
+    }
+}
+
+pub trait StableHashMapExt {
+    fn with_seed(seed: u64) -> Self;
+}
+
+impl<K, V> StableHashMapExt for StableHashMap<K, V> {
+    fn with_seed(seed: u64) -> Self {
+        Self::with_hasher(StableState::with_seed(seed))
+    }
+}
+
+/// Key ordered iteration, for output that has to be diffable between runs
+pub trait SortedIterExt<K: Ord, V> {
+    fn sorted_iter(&self) -> std::vec::IntoIter<(&K, &V)>;
+
+    /// Like `sorted_iter` but reuses the allocation of `buffer`
+    fn sorted_iter_in<'a>(
+        &'a self,
+        buffer: &'a mut Vec<(&'a K, &'a V)>,
+    ) -> std::slice::Iter<'a, (&'a K, &'a V)>;
+}
+
+impl<K: Ord, V, S> SortedIterExt<K, V> for StdHashMap<K, V, S> {
+    fn sorted_iter(&self) -> std::vec::IntoIter<(&K, &V)> {
+        let mut entries = Vec::with_capacity(self.len());
+        entries.extend(self.iter());
+        entries.sort_unstable_by_key(|&(key, _)| key);
+
+        entries.into_iter()
+    }
+
+    fn sorted_iter_in<'a>(
+        &'a self,
+        buffer: &'a mut Vec<(&'a K, &'a V)>,
+    ) -> std::slice::Iter<'a, (&'a K, &'a V)> {
+        buffer.clear();
+        buffer.extend(self.iter());
+        buffer.sort_unstable_by_key(|&(key, _)| key);
+
+        buffer.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasher;
+
+    use super::{SortedIterExt, StableHashMap, StableHashMapExt, StableState};
+
+    #[test]
+    fn default_matches_original_seeds() {
+        let original = ahash::RandomState::with_seeds(1, 2, 3, 4);
+
+        assert_eq!(
+            StableState::default().hash_one("motor"),
+            original.hash_one("motor")
+        );
+    }
+
+    #[test]
+    fn seed_changes_hashes() {
+        let map: StableHashMap<u32, ()> = StableHashMap::with_seed(7);
+
+        assert_eq!(map.hasher(), &StableState::with_seed(7));
+        assert_ne!(
+            StableState::with_seed(7).hash_one(42u32),
+            StableState::default().hash_one(42u32)
+        );
+        assert_eq!(
+            StableState::with_seed(7).hash_one(42u32),
+            StableState::with_seed(7).hash_one(42u32)
+        );
+    }
+
+    #[test]
+    fn sorted_iter_is_key_ordered() {
+        let map: StableHashMap<u32, char> = (0..32).rev().zip('a'..).collect();
+
+        let keys = map.sorted_iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        assert_eq!(keys, (0..32).collect::<Vec<_>>());
+
+        let mut buffer = Vec::new();
+        let entries = map.sorted_iter_in(&mut buffer).collect::<Vec<_>>();
+        assert_eq!(entries.first(), Some(&&(&0, &'\u{80}')));
+        assert_eq!(
+            entries.into_iter().copied().collect::<Vec<_>>(),
+            map.sorted_iter().collect::<Vec<_>>()
+        );
     }
 }
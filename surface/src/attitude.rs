@@ -1,5 +1,5 @@
 use bevy::{
-    color::palettes::css,
+    color::{palettes::css, Mix},
     math::{vec3, Vec3A},
     prelude::*,
     render::{
@@ -11,20 +11,35 @@ use bevy::{
     },
 };
 use bevy_egui::EguiContexts;
-use common::components::{Motors, Orientation, OrientationTarget, Robot};
+use common::components::{
+    Armed, MotorContribution, MotorSaturation, Motors, Orientation, OrientationTarget, Robot,
+};
 use egui::TextureId;
 use motor_math::{x3d::X3dMotorId, Direction, ErasedMotorId, Motor, MotorConfig};
 
 use crate::DARK_MODE;
 
 const RENDER_LAYERS: RenderLayers = RenderLayers::layer(1);
+/// Length of a thrust line while disarmed
+const REST_THRUST_LENGTH: f32 = 1.0;
+/// Length of a thrust line at full load
+const MAX_THRUST_LENGTH: f32 = 2.0;
+/// Keeps idle thrusters visible while armed
+const MIN_THRUST_LENGTH: f32 = 0.05;
 
 pub struct AttitudePlugin;
 
 impl Plugin for AttitudePlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup)
-            .add_systems(Update, (update_motor_conf, rotator_system))
+            .add_systems(
+                Update,
+                (
+                    update_motor_conf,
+                    update_motor_thrust.after(update_motor_conf),
+                    rotator_system,
+                ),
+            )
             .insert_gizmo_config(
                 AttitudeGizmo,
                 GizmoConfig {
@@ -44,6 +59,12 @@ pub struct OrientationDisplay(pub Handle<Image>, pub TextureId);
 struct OrientationDisplayMarker;
 #[derive(Component)]
 struct MotorMarker(ErasedMotorId);
+/// The thrust line of a motor, scaled by the motor's load and colored by its direction
+#[derive(Component)]
+struct MotorThrustMarker {
+    origin: Vec3,
+    axis: Vec3,
+}
 
 fn setup(
     mut commands: Commands,
@@ -198,6 +219,10 @@ fn add_motor(
             ..default()
         },
         MotorMarker(motor_id),
+        MotorThrustMarker {
+            origin: Vec3::from(motor.position * 1.5),
+            axis: Vec3::from(motor.orientation),
+        },
         RENDER_LAYERS,
     ));
 
@@ -240,6 +265,96 @@ fn update_motor_conf(
     }
 }
 
+fn update_motor_thrust(
+    robot: Query<
+        (
+            Option<Ref<Armed>>,
+            Option<Ref<MotorContribution>>,
+            Option<Ref<MotorSaturation>>,
+        ),
+        With<Robot>,
+    >,
+    mut motors: Query<(
+        &MotorMarker,
+        &Handle<StandardMaterial>,
+        &mut Transform,
+        Ref<MotorThrustMarker>,
+    )>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let Ok((armed, contribution, saturation)) = robot.get_single() else {
+        return;
+    };
+
+    let robot_changed = armed.as_ref().is_some_and(|it| it.is_changed())
+        || contribution.as_ref().is_some_and(|it| it.is_changed())
+        || saturation.as_ref().is_some_and(|it| it.is_changed());
+    let armed = armed.is_some_and(|it| *it == Armed::Armed);
+
+    for (MotorMarker(motor_id), handle, mut transform, marker) in &mut motors {
+        // Markers respawned by `update_motor_conf` still need their initial state
+        if !robot_changed && !marker.is_added() {
+            continue;
+        }
+
+        // Fraction of the motor's maximum force, negative when reversed
+        let load = if armed {
+            let force = contribution
+                .as_ref()
+                .and_then(|it| it.0.get(motor_id))
+                .map(|it| it.0)
+                .unwrap_or(0.0);
+            let saturation = saturation
+                .as_ref()
+                .and_then(|it| it.0.get(motor_id))
+                .copied()
+                .unwrap_or(0.0);
+
+            saturation.copysign(force)
+        } else {
+            0.0
+        };
+
+        let (length, color) = if armed {
+            let length = (load * MAX_THRUST_LENGTH)
+                .abs()
+                .max(MIN_THRUST_LENGTH)
+                .copysign(load);
+
+            (length, thrust_color(load))
+        } else {
+            (REST_THRUST_LENGTH, thrust_color(1.0))
+        };
+
+        let translation = marker.origin + marker.axis * length / 2.0;
+        let scale = Vec3::new(1.0, length.abs(), 1.0);
+
+        // Avoid needlessly triggering change detection
+        if transform.translation != translation || transform.scale != scale {
+            transform.translation = translation;
+            transform.scale = scale;
+        }
+
+        // `get_mut` marks the material as modified and causes a re-upload, so only use it when needed
+        let outdated = materials
+            .get(handle)
+            .is_some_and(|material| material.base_color != color);
+
+        if outdated {
+            if let Some(material) = materials.get_mut(handle) {
+                material.base_color = color;
+            }
+        }
+    }
+}
+
+/// Green at full forward load, red at full reverse load
+fn thrust_color(load: f32) -> Color {
+    let load = load.clamp(-1.0, 1.0);
+
+    Color::from(css::GREEN.mix(&css::RED, (1.0 - load) / 2.0))
+}
+
 fn rotator_system(
     robot: Query<(&Orientation, Option<&OrientationTarget>), With<Robot>>,
     mut query: Query<&mut Transform, With<OrientationDisplayMarker>>,
@@ -0,0 +1,105 @@
+use bevy::{prelude::*, utils::HashMap};
+
+/// Index of the entities displaying each image, so image asset events can be
+/// routed to their displays without scanning every display
+#[derive(Default)]
+pub struct ImageDisplays {
+    displays: HashMap<AssetId<Image>, Vec<Entity>>,
+    images: HashMap<Entity, AssetId<Image>>,
+}
+
+impl ImageDisplays {
+    pub fn insert(&mut self, entity: Entity, image: AssetId<Image>) {
+        self.remove(entity);
+
+        self.displays.entry(image).or_default().push(entity);
+        self.images.insert(entity, image);
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        let Some(image) = self.images.remove(&entity) else {
+            return;
+        };
+
+        if let Some(displays) = self.displays.get_mut(&image) {
+            displays.retain(|&it| it != entity);
+
+            if displays.is_empty() {
+                self.displays.remove(&image);
+            }
+        }
+    }
+
+    pub fn displays(&self, image: AssetId<Image>) -> &[Entity] {
+        self.displays
+            .get(&image)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Entities displaying an image that was added or modified, along with that image
+    pub fn updated<'a>(
+        &'a self,
+        events: impl IntoIterator<Item = &'a AssetEvent<Image>> + 'a,
+    ) -> impl Iterator<Item = (Entity, AssetId<Image>)> + 'a {
+        events
+            .into_iter()
+            .filter_map(|event| match event {
+                AssetEvent::Added { id } | AssetEvent::Modified { id } => Some(*id),
+                _ => None,
+            })
+            .flat_map(|image| {
+                self.displays(image)
+                    .iter()
+                    .map(move |&entity| (entity, image))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use super::ImageDisplays;
+
+    fn image(idx: u128) -> AssetId<Image> {
+        Handle::weak_from_u128(idx).id()
+    }
+
+    #[test]
+    fn updated_only_yields_matching_displays() {
+        let mut index = ImageDisplays::default();
+
+        for idx in 0..1000 {
+            index.insert(Entity::from_raw(idx), image(idx as u128));
+        }
+        // Two displays showing the same feed
+        index.insert(Entity::from_raw(1000), image(7));
+
+        let events = [
+            AssetEvent::Modified { id: image(7) },
+            AssetEvent::Removed { id: image(8) },
+        ];
+        let updated = index
+            .updated(&events)
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        assert_eq!(updated, vec![Entity::from_raw(7), Entity::from_raw(1000)]);
+    }
+
+    #[test]
+    fn reinserting_moves_display() {
+        let mut index = ImageDisplays::default();
+        let entity = Entity::from_raw(1);
+
+        index.insert(entity, image(1));
+        index.insert(entity, image(2));
+
+        assert!(index.displays(image(1)).is_empty());
+        assert_eq!(index.displays(image(2)), &[entity]);
+
+        index.remove(entity);
+        assert!(index.displays(image(2)).is_empty());
+    }
+}
@@ -1,6 +1,7 @@
 #![feature(iter_intersperse, try_blocks)]
 
 pub mod attitude;
+pub mod image_displays;
 pub mod input;
 pub mod surface;
 pub mod ui;
@@ -8,6 +9,7 @@ pub mod video_display_2d_master;
 pub mod video_display_2d_tile;
 pub mod video_display_3d;
 pub mod video_pipelines;
+pub mod video_screenshot;
 pub mod video_stream;
 
 use std::time::Duration;
@@ -22,7 +24,10 @@ use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_mod_picking::{highlight::DefaultHighlightingPlugin, DefaultPickingPlugins};
 use bevy_panorbit_camera::PanOrbitCameraPlugin;
 use bevy_tokio_tasks::TokioTasksPlugin;
-use common::{over_run::OverRunSettings, sync::SyncRole, CommonPlugins};
+use common::{
+    ecs_sync::recorder::ChangeRecorderPlugin, over_run::OverRunSettings, sync::SyncRole,
+    CommonPlugins,
+};
 use crossbeam::channel::unbounded;
 use input::InputPlugin;
 use opencv::{highgui, imgcodecs};
@@ -31,6 +36,7 @@ use ui::{EguiUiPlugin, ShowInspector};
 // use video_display_2d_tile::{VideoDisplay2DPlugin, VideoDisplay2DSettings};
 use video_display_2d_master::{VideoDisplay2DPlugin, VideoDisplay2DSettings};
 // use video_display_3d::{VideoDisplay3DPlugin, VideoDisplay3DSettings};
+use video_screenshot::VideoScreenshotPlugin;
 use video_stream::VideoStreamPlugin;
 
 use crate::video_pipelines::{
@@ -86,6 +92,7 @@ fn main() -> anyhow::Result<()> {
                     name: "Control Station".to_owned(),
                     role: SyncRole::Client,
                 },
+                ChangeRecorderPlugin,
                 SurfacePlugin,
                 InputPlugin,
                 EguiUiPlugin,
@@ -94,6 +101,7 @@ fn main() -> anyhow::Result<()> {
                 VideoDisplay2DPlugin,
                 // VideoDisplay3DPlugin,
                 VideoPipelinePlugins,
+                VideoScreenshotPlugin,
             ),
             // 3rd Party
             (
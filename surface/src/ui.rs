@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use bevy::{app::AppExit, prelude::*};
 use bevy_egui::{EguiContexts, EguiPlugin};
@@ -6,13 +6,15 @@ use bevy_tokio_tasks::TokioTasksRuntime;
 use common::{
     bundles::MovementContributionBundle,
     components::{
-        Armed, Camera, CpuTotal, CurrentDraw, Depth, DepthTarget, Inertial, LoadAverage,
-        MeasuredVoltage, Memory, MovementAxisMaximums, MovementContribution, OrientationTarget,
-        PwmChannel, PwmManualControl, PwmSignal, Robot, RobotId, RobotStatus, Temperatures,
+        Armed, Camera, ClearLeaks, CpuTotal, CurrentDraw, Depth, DepthTarget, DroppedUpdates,
+        FailsafeActive, GpuInfo, Inertial, Leaks, LoadAverage, MeasuredVoltage, Memory, Motors,
+        MovementAxisBounds, MovementContribution, OrientationTarget, PeerLatency, PwmChannel,
+        PwmManualControl, PwmSignal, Robot, RobotId, RobotStatus, Temperatures,
     },
-    ecs_sync::{NetId, Replicate},
+    ecs_sync::{recorder::ChangeRecorder, NetId, Replicate, SerializationSettings},
+    error::ErrorEvent,
     events::{CalibrateSeaLevel, ResetServos, ResetYaw, ResyncCameras},
-    sync::{ConnectToPeer, DisconnectPeer, Latency, MdnsPeers, Peer},
+    sync::{ConnectToPeer, ConnectionStatus, DisconnectPeer, MdnsPeers, Peer, Reconnect},
 };
 use egui::{
     load::SizedTexture, text::LayoutJob, widgets, Align, Color32, Id, Label, Layout, RichText,
@@ -26,6 +28,7 @@ use crate::{
     attitude::OrientationDisplay,
     input::{Action, InputInterpolation, InputMarker, SelectedServo},
     video_pipelines::VideoPipelines,
+    video_screenshot::CaptureScreenshot,
     video_stream::{VideoProcessorFactory, VideoThread},
     DARK_MODE,
 };
@@ -34,7 +37,8 @@ pub struct EguiUiPlugin;
 
 impl Plugin for EguiUiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, set_style);
+        app.add_systems(Startup, set_style)
+            .init_resource::<MotorLayoutWarning>();
         app.add_plugins(EguiPlugin).add_systems(
             Update,
             (
@@ -48,6 +52,9 @@ impl Plugin for EguiUiPlugin {
                     .after(topbar)
                     .run_if(resource_removed::<PwmControl>()),
                 timer.after(topbar).run_if(resource_exists::<TimerUi>),
+                motor_config_ui
+                    .after(topbar)
+                    .run_if(resource_exists::<MotorConfigUi>),
             ),
         );
     }
@@ -56,12 +63,29 @@ impl Plugin for EguiUiPlugin {
 #[derive(Resource)]
 pub struct ShowInspector;
 
+/// Warn when the robot's motor layout amplifies input noise by more than this factor
+#[derive(Resource)]
+pub struct MotorLayoutWarning {
+    pub max_condition_number: f32,
+}
+
+impl Default for MotorLayoutWarning {
+    fn default() -> Self {
+        Self {
+            max_condition_number: 50.0,
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct PwmControl(bool);
 
 #[derive(Resource)]
 pub struct TimerUi(TimerState, TimerType);
 
+#[derive(Resource)]
+pub struct MotorConfigUi;
+
 pub enum TimerState {
     Running { start: Duration, offset: Duration },
     Paused { elapsed: Duration },
@@ -108,9 +132,13 @@ fn topbar(
     inspector: Option<Res<ShowInspector>>,
     pwm_control: Option<Res<PwmControl>>,
     timer_ui: Option<Res<TimerUi>>,
+    motor_config_ui: Option<Res<MotorConfigUi>>,
 
     peers: Query<(&Peer, Option<&Name>)>,
     mut disconnect: EventWriter<DisconnectPeer>,
+
+    recorder: Res<ChangeRecorder>,
+    mut screenshots: EventWriter<CaptureScreenshot>,
 ) {
     egui::TopBottomPanel::top("Top Bar").show(contexts.ctx_mut(), |ui| {
         egui::menu::bar(ui, |ui| {
@@ -133,6 +161,40 @@ fn topbar(
                     }
                 });
 
+                if let Some(path) = recorder.path() {
+                    if ui
+                        .button(format!("Stop Recording ({})", path.display()))
+                        .clicked()
+                    {
+                        cmds.add(|world: &mut World| {
+                            let rst = world.resource_mut::<ChangeRecorder>().stop();
+                            if let Err(err) = rst {
+                                world.send_event(ErrorEvent(err));
+                            }
+                        })
+                    }
+                } else if ui.button("Record Changes").clicked() {
+                    cmds.add(|world: &mut World| {
+                        let timestamp = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs();
+                        let path = format!("changes-{timestamp}.rec");
+
+                        let rst =
+                            world.resource_scope(|world, mut recorder: Mut<ChangeRecorder>| {
+                                recorder.start(path, world.resource::<SerializationSettings>())
+                            });
+                        if let Err(err) = rst {
+                            world.send_event(ErrorEvent(err));
+                        }
+                    })
+                }
+
+                if ui.button("Screenshot Video").clicked() {
+                    screenshots.send(CaptureScreenshot);
+                }
+
                 if ui.button("Exit").clicked() {
                     cmds.add(|world: &mut World| {
                         world.send_event(AppExit::Success);
@@ -227,6 +289,17 @@ fn topbar(
                     }
                 }
 
+                if ui
+                    .selectable_label(motor_config_ui.is_some(), "Motor Config")
+                    .clicked()
+                {
+                    if motor_config_ui.is_some() {
+                        cmds.remove_resource::<MotorConfigUi>()
+                    } else {
+                        cmds.insert_resource(MotorConfigUi);
+                    }
+                }
+
                 if ui.selectable_label(timer_ui.is_some(), "Timer").clicked() {
                     if timer_ui.is_some() {
                         cmds.remove_resource::<TimerUi>()
@@ -329,6 +402,16 @@ fn topbar(
                                     );
                                 }
                             }
+                            RobotStatus::Fault => {
+                                layout_job.append(
+                                    "Fault",
+                                    7.0,
+                                    TextFormat {
+                                        color: Color32::RED,
+                                        ..default()
+                                    },
+                                );
+                            }
                         };
                     }
 
@@ -368,7 +451,14 @@ fn hud(
             Option<&DepthTarget>,
             Option<&OrientationTarget>,
             Option<&Peer>,
-            Option<&Latency>,
+            (
+                Option<&PeerLatency>,
+                Option<&DroppedUpdates>,
+                Option<&Leaks>,
+                Option<&FailsafeActive>,
+                Option<&GpuInfo>,
+                Entity,
+            ),
             &RobotId,
         ),
         With<Robot>,
@@ -383,8 +473,11 @@ fn hud(
         ),
         With<InputMarker>,
     >,
+    motors: Query<(&Motors, &RobotId)>,
+    layout_warning: Res<MotorLayoutWarning>,
 
     peers: Option<Res<MdnsPeers>>,
+    reconnect: Option<ResMut<Reconnect>>,
 
     mut disconnect: EventWriter<DisconnectPeer>,
 ) {
@@ -405,7 +498,7 @@ fn hud(
         depth_target,
         orientation_target,
         peer,
-        latency,
+        (latency, dropped_updates, leaks, failsafe, gpu, robot),
         robot_id,
     )) = robots.get_single()
     {
@@ -454,6 +547,38 @@ fn hud(
                         });
                     }
 
+                    // The motors are held stopped until the robot is armed again
+                    if armed == Some(&Armed::Armed) && failsafe.is_some_and(|it| it.0) {
+                        ui.label(
+                            RichText::new("Failsafe: Re-arm to drive")
+                                .size(size)
+                                .color(Color32::YELLOW),
+                        );
+                    }
+
+                    if let Some(leaks) = leaks {
+                        let triggered: Vec<_> = leaks
+                            .0
+                            .iter()
+                            .filter(|it| it.triggered)
+                            .map(|it| it.name.as_str())
+                            .collect();
+
+                        if !triggered.is_empty() {
+                            ui.horizontal(|ui| {
+                                ui.label(
+                                    RichText::new(format!("Leak: {}", triggered.join(", ")))
+                                        .size(size)
+                                        .color(Color32::RED),
+                                );
+
+                                if ui.button("Clear").clicked() {
+                                    cmds.entity(robot).insert(ClearLeaks);
+                                }
+                            });
+                        }
+                    }
+
                     if let Some((selected_servo, input_interpolation, input_map, _)) =
                         inputs.iter().find(|(_, _, _, robot)| **robot == *robot_id)
                     {
@@ -546,6 +671,31 @@ fn hud(
                         ui.add_space(10.0);
                     }
 
+                    if let Some((Motors(motor_config), _)) =
+                        motors.iter().find(|(_, robot)| **robot == *robot_id)
+                    {
+                        let condition_number = motor_config.condition_number();
+                        let rank = motor_config.rank();
+
+                        if rank < 6 || condition_number > layout_warning.max_condition_number {
+                            let color = if rank < 6 {
+                                Color32::RED
+                            } else {
+                                Color32::YELLOW
+                            };
+
+                            ui.label(
+                                RichText::new(format!(
+                                    "Motor Layout: {rank} DOF, Cond {condition_number:.1}"
+                                ))
+                                .size(size)
+                                .color(color),
+                            );
+
+                            ui.add_space(10.0);
+                        }
+                    }
+
                     if let Some(cpu) = cpu {
                         ui.label(RichText::new(format!("CPU: {:.2}%", cpu.0.usage)).size(size));
                     }
@@ -564,7 +714,18 @@ fn hud(
                         ui.label(RichText::new(format!("RAM: {:.2}%", ram_usage)).size(size));
                     }
 
-                    if cpu.is_some() || load.is_some() || memory.is_some() {
+                    if let Some(GpuInfo(gpu)) = gpu {
+                        let gpu_mem_usage =
+                            gpu.gpu_mem_used_mb as f64 / gpu.gpu_mem_total_mb as f64 * 100.0;
+                        ui.label(
+                            RichText::new(format!("GPU: {:.2}%", gpu.gpu_usage_pct)).size(size),
+                        );
+                        ui.label(
+                            RichText::new(format!("GPU Mem: {:.2}%", gpu_mem_usage)).size(size),
+                        );
+                    }
+
+                    if cpu.is_some() || load.is_some() || memory.is_some() || gpu.is_some() {
                         ui.add_space(10.0);
                     }
                 });
@@ -578,9 +739,28 @@ fn hud(
                             ui.label(RichText::new(format!("{:?}", peer.addrs)).size(size * 0.75));
                         });
 
-                        if let Some(ping) = latency.ping {
+                        ui.label(RichText::new(format!("RTT: {:.2?}", latency.rtt)).size(size));
+                        ui.label(
+                            RichText::new(format!(
+                                "RTT Range: {:.2?} - {:.2?}",
+                                latency.min_rtt, latency.max_rtt
+                            ))
+                            .size(size),
+                        );
+                        ui.label(
+                            RichText::new(format!("Jitter: {:.2?}", latency.jitter)).size(size),
+                        );
+                        ui.label(
+                            RichText::new(format!(
+                                "Packet Loss: {:.0}%",
+                                latency.packet_loss * 100.0
+                            ))
+                            .size(size),
+                        );
+
+                        if let Some(DroppedUpdates(dropped)) = dropped_updates {
                             ui.label(
-                                RichText::new(format!("Ping: {:.2?} frames", ping)).size(size),
+                                RichText::new(format!("Dropped Updates: {dropped}")).size(size),
                             );
                         }
 
@@ -647,6 +827,27 @@ fn hud(
             .constrain_to(context.available_rect().shrink(20.0))
             // .movable(false)
             .show(contexts.ctx_mut(), |ui| {
+                if let Some(mut reconnect) = reconnect {
+                    if let Some(target) = reconnect.target() {
+                        let status = match reconnect.status() {
+                            ConnectionStatus::Reconnecting { attempt } => {
+                                format!("Reconnecting to {target} (attempt {attempt})")
+                            }
+                            _ => format!("Connecting to {target}"),
+                        };
+
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new(status).color(Color32::YELLOW));
+
+                            if ui.button("Cancel").clicked() {
+                                reconnect.cancel();
+                            }
+                        });
+
+                        ui.add_space(10.0);
+                    }
+                }
+
                 ui.horizontal(|ui| {
                     ui.label("Connect To:");
                     let line_response = ui.text_edit_singleline(&mut *host);
@@ -789,6 +990,70 @@ fn cleanup_pwm_control(mut cmds: Commands, robots: Query<Entity, With<Robot>>) {
     }
 }
 
+fn motor_config_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    robots: Query<&Motors, With<Robot>>,
+) {
+    let context = contexts.ctx_mut();
+    let mut open = true;
+
+    egui::Window::new("Motor Config")
+        .constrain_to(context.available_rect().shrink(20.0))
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            if let Ok(Motors(motor_config)) = robots.get_single() {
+                ui.label(format!(
+                    "Rank: {}, Condition Number: {:.1}",
+                    motor_config.rank(),
+                    motor_config.condition_number()
+                ));
+
+                ui.add_space(10.0);
+                ui.label("Axis Coupling (requested axis -> produced axis)");
+
+                let axes = ["X", "Y", "Z", "XRot", "YRot", "ZRot"];
+                let coupling = motor_config.axis_coupling_matrix();
+
+                egui::Grid::new("Axis Coupling").show(ui, |ui| {
+                    ui.label("");
+                    for axis in axes {
+                        ui.label(axis);
+                    }
+                    ui.end_row();
+
+                    for (row, axis) in axes.iter().enumerate() {
+                        ui.label(*axis);
+
+                        for col in 0..6 {
+                            let value = coupling[(row, col)];
+                            let expected = if row == col { 1.0 } else { 0.0 };
+
+                            ui.label(
+                                RichText::new(format!("{value:.2}"))
+                                    .color(coupling_color(value, expected)),
+                            );
+                        }
+                        ui.end_row();
+                    }
+                });
+            } else {
+                ui.label("No robot");
+            }
+        });
+
+    if !open {
+        cmds.remove_resource::<MotorConfigUi>()
+    }
+}
+
+/// Green when `value` matches `expected`, fading to red as the error approaches 1.0
+fn coupling_color(value: f32, expected: f32) -> Color32 {
+    let error = (value - expected).abs().clamp(0.0, 1.0);
+
+    Color32::from_rgb((255.0 * error) as u8, (255.0 * (1.0 - error)) as u8, 0)
+}
+
 fn movement_control(
     mut cmds: Commands,
     mut contexts: EguiContexts,
@@ -797,7 +1062,7 @@ fn movement_control(
         (Entity, &mut RobotId, &mut MovementContribution),
         (With<MovementController>, Without<Robot>),
     >,
-    robots: Query<(&Name, &RobotId, &MovementAxisMaximums), With<Robot>>,
+    robots: Query<(&Name, &RobotId, &MovementAxisBounds), With<Robot>>,
     // motors: Query<(Entity, Option<&PwmSignal>, &PwmChannel, &RobotId)>,
 ) {
     for (contoller, mut selected_robot, mut contribution) in &mut controllers {
@@ -810,21 +1075,21 @@ fn movement_control(
             .open(&mut open)
             .show(context, |ui| {
                 ui.label("Robot:");
-                let Some(maximums) = ui
+                let Some(bounds) = ui
                     .horizontal(|ui| {
-                        let mut maximums = None;
+                        let mut bounds = None;
 
-                        for (name, robot_id, this_maximums) in &robots {
+                        for (name, robot_id, this_bounds) in &robots {
                             ui.selectable_value(&mut selected_robot.0, robot_id.0, name.as_str());
 
                             if selected_robot.0 == robot_id.0 {
-                                maximums = Some(this_maximums.0.clone());
+                                bounds = Some(this_bounds.0.clone());
                             }
                         }
                         ui.selectable_value(&mut selected_robot.0, NetId::invalid(), "None");
 
                         if selected_robot.0 != NetId::invalid() {
-                            maximums
+                            bounds
                         } else {
                             None
                         }
@@ -838,38 +1103,38 @@ fn movement_control(
 
                 ui.horizontal(|ui| {
                     ui.add_sized([40.0, 0.0], Label::new("X:"));
-                    let max = maximums[&Axis::X].0;
-                    ui.add(widgets::Slider::new(&mut movement.force.x, -max..=max));
+                    let (min, max) = bounds[&Axis::X];
+                    ui.add(widgets::Slider::new(&mut movement.force.x, min.0..=max.0));
                 });
 
                 ui.horizontal(|ui| {
                     ui.add_sized([40.0, 0.0], Label::new("Y:"));
-                    let max = maximums[&Axis::Y].0;
-                    ui.add(widgets::Slider::new(&mut movement.force.y, -max..=max));
+                    let (min, max) = bounds[&Axis::Y];
+                    ui.add(widgets::Slider::new(&mut movement.force.y, min.0..=max.0));
                 });
 
                 ui.horizontal(|ui| {
                     ui.add_sized([40.0, 0.0], Label::new("Z:"));
-                    let max = maximums[&Axis::Z].0;
-                    ui.add(widgets::Slider::new(&mut movement.force.z, -max..=max));
+                    let (min, max) = bounds[&Axis::Z];
+                    ui.add(widgets::Slider::new(&mut movement.force.z, min.0..=max.0));
                 });
 
                 ui.horizontal(|ui| {
                     ui.add_sized([40.0, 0.0], Label::new("Pitch"));
-                    let max = maximums[&Axis::XRot].0;
-                    ui.add(widgets::Slider::new(&mut movement.torque.x, -max..=max));
+                    let (min, max) = bounds[&Axis::XRot];
+                    ui.add(widgets::Slider::new(&mut movement.torque.x, min.0..=max.0));
                 });
 
                 ui.horizontal(|ui| {
                     ui.add_sized([40.0, 0.0], Label::new("Roll:"));
-                    let max = maximums[&Axis::YRot].0;
-                    ui.add(widgets::Slider::new(&mut movement.torque.y, -max..=max));
+                    let (min, max) = bounds[&Axis::YRot];
+                    ui.add(widgets::Slider::new(&mut movement.torque.y, min.0..=max.0));
                 });
 
                 ui.horizontal(|ui| {
                     ui.add_sized([40.0, 0.0], Label::new("Yaw:"));
-                    let max = maximums[&Axis::ZRot].0;
-                    ui.add(widgets::Slider::new(&mut movement.torque.z, -max..=max));
+                    let (min, max) = bounds[&Axis::ZRot];
+                    ui.add(widgets::Slider::new(&mut movement.torque.z, min.0..=max.0));
                 });
 
                 ui.add_space(7.0);
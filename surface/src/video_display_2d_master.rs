@@ -7,6 +7,8 @@ use bevy::{
 use bevy_mod_picking::prelude::*;
 use common::components::Camera;
 
+use crate::video_screenshot::ScreenshotSource;
+
 const RENDER_LAYERS: RenderLayers = RenderLayers::layer(2);
 
 pub struct VideoDisplay2DPlugin;
@@ -70,6 +72,7 @@ fn setup(mut cmds: Commands, mut meshes: ResMut<Assets<Mesh>>) {
                 ..default()
             },
             DisplayCamera,
+            ScreenshotSource,
             RENDER_LAYERS,
         ))
         .id();
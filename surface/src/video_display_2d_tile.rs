@@ -1,4 +1,4 @@
-use std::mem;
+use std::{collections::BTreeMap, mem};
 
 use bevy::{
     color::palettes::css,
@@ -7,7 +7,11 @@ use bevy::{
 };
 use common::components::Camera;
 
+use crate::{image_displays::ImageDisplays, video_screenshot::ScreenshotSource};
+
 const RENDER_LAYERS: RenderLayers = RenderLayers::layer(2);
+/// Percent of the display width taken by the primary feed
+const PRIMARY_WIDTH: f32 = 75.0;
 
 pub struct VideoDisplay2DPlugin;
 
@@ -33,11 +37,13 @@ enum VideoNode {
     Branch(Vec<VideoNode>),
     Leaf(Entity),
 }
-#[derive(Default, Clone, Copy)]
-enum VideoLayout {
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoLayout {
     #[default]
     Horizontal,
     Vertical,
+    /// Equally sized tiles in a roughly square grid, filled row by row
+    Grid,
 }
 
 impl VideoNode {
@@ -90,6 +96,61 @@ impl VideoNode {
         }
     }
 
+    fn contains(&self, entity: Entity) -> bool {
+        match self {
+            VideoNode::Branch(children) => children.iter().any(|it| it.contains(entity)),
+            VideoNode::Leaf(this) => *this == entity,
+        }
+    }
+
+    fn leaves(&self, out: &mut Vec<Entity>) {
+        match self {
+            VideoNode::Branch(children) => {
+                for child in children {
+                    child.leaves(out);
+                }
+            }
+            VideoNode::Leaf(this) => out.push(*this),
+        }
+    }
+
+    /// Moves pinned cameras into their slots, the other cameras keep their order in the remaining slots
+    ///
+    /// Slots are numbered in the order the leaves are displayed, starting from the top left
+    fn arrange(&mut self, pinned: &BTreeMap<usize, Entity>) {
+        let mut leaves = Vec::new();
+        self.leaves(&mut leaves);
+
+        let pinned: BTreeMap<_, _> = pinned
+            .iter()
+            .filter(|(&slot, entity)| slot < leaves.len() && leaves.contains(entity))
+            .map(|(&slot, &entity)| (slot, entity))
+            .collect();
+        let mut unpinned = leaves
+            .iter()
+            .copied()
+            .filter(|it| !pinned.values().any(|pinned| pinned == it));
+
+        let mut arranged = (0..leaves.len()).map(|slot| match pinned.get(&slot) {
+            Some(&entity) => entity,
+            None => unpinned.next().expect("Leaf count changed"),
+        });
+        self.assign(&mut arranged);
+    }
+
+    fn assign(&mut self, entities: &mut impl Iterator<Item = Entity>) {
+        match self {
+            VideoNode::Branch(children) => {
+                for child in children {
+                    child.assign(entities);
+                }
+            }
+            VideoNode::Leaf(this) => {
+                *this = entities.next().expect("Not enough entities for leaves");
+            }
+        }
+    }
+
     fn count_children(&self) -> u32 {
         match self {
             VideoNode::Branch(children) => children.iter().map(|it| it.count_children()).sum(),
@@ -97,6 +158,19 @@ impl VideoNode {
         }
     }
 
+    /// Rows and columns of a grid that fits all the leaves
+    fn grid_size(&self) -> (u32, u32) {
+        let count = self.count_children();
+        if count == 0 {
+            return (0, 0);
+        }
+
+        let columns = (count as f32).sqrt().ceil() as u32;
+        let rows = count.div_ceil(columns);
+
+        (rows, columns)
+    }
+
     fn max_depth(&self) -> u32 {
         match self {
             VideoNode::Branch(children) => {
@@ -112,6 +186,8 @@ impl VideoLayout {
         match self {
             VideoLayout::Horizontal => VideoLayout::Vertical,
             VideoLayout::Vertical => VideoLayout::Horizontal,
+            // Grids are flat
+            VideoLayout::Grid => VideoLayout::Grid,
         }
     }
 }
@@ -134,6 +210,35 @@ struct VideoFeedDisplay;
 #[derive(Resource, Default)]
 pub struct VideoDisplay2DSettings {
     pub enabled: bool,
+    /// Camera filling the whole display with the other feeds hidden, takes precedence over `primary_feed`
+    pub focused: Option<Entity>,
+    /// Camera shown as a large tile with the remaining feeds stacked beside it
+    pub primary_feed: Option<Entity>,
+    /// How the feeds are tiled when there is no primary feed
+    pub layout: VideoLayout,
+    /// Cameras placed in a fixed slot instead of where `VideoNode::insert` puts them
+    pub pinned: BTreeMap<usize, Entity>,
+}
+
+impl VideoDisplay2DSettings {
+    /// Shows only `camera`, or goes back to the tiled layout if it is already focused
+    pub fn toggle_focus(&mut self, camera: Entity) {
+        self.focused = if self.focused == Some(camera) {
+            None
+        } else {
+            Some(camera)
+        };
+    }
+
+    /// Always shows `camera` in `slot`, slot 0 is the top left tile
+    pub fn pin(&mut self, camera: Entity, slot: usize) {
+        self.unpin(camera);
+        self.pinned.insert(slot, camera);
+    }
+
+    pub fn unpin(&mut self, camera: Entity) {
+        self.pinned.retain(|_, pinned| *pinned != camera);
+    }
 }
 
 fn setup(mut cmds: Commands) {
@@ -147,6 +252,7 @@ fn setup(mut cmds: Commands) {
                 ..default()
             },
             DisplayCamera,
+            ScreenshotSource,
             RENDER_LAYERS,
         ))
         .id();
@@ -165,13 +271,27 @@ fn create_display(
     mut cmds: Commands,
 
     new_cameras: Query<Entity, (With<Camera>, Added<Handle<Image>>)>,
+    // The robot reuses a camera's entity when its stream restarts
+    updated_cameras: Query<
+        (),
+        (
+            With<Handle<Image>>,
+            Or<(Changed<Camera>, Changed<Handle<Image>>)>,
+        ),
+    >,
     mut lost_cameras: RemovedComponents<Camera>,
 
-    cameras: Query<&Handle<Image>>,
+    cameras: Query<(&Handle<Image>, &Camera)>,
     mut parent: Query<(Entity, &mut VideoTree), With<DisplayParent>>,
+
+    settings: Res<VideoDisplay2DSettings>,
+    mut last_focused: Local<Option<Entity>>,
+    mut last_primary: Local<Option<Entity>>,
+    mut last_layout: Local<VideoLayout>,
+    mut last_pinned: Local<BTreeMap<usize, Entity>>,
 ) {
     let (parent, mut tree) = parent.single_mut();
-    let mut tree_changed = false;
+    let mut tree_changed = !updated_cameras.is_empty();
 
     for entity in &new_cameras {
         tree.0.insert(entity);
@@ -183,8 +303,70 @@ fn create_display(
         tree_changed = true;
     }
 
-    if tree_changed {
-        let layout = VideoLayout::default();
+    // Fall back to the tiled layout when the focused camera is gone
+    let focused = settings.focused.filter(|&focused| tree.0.contains(focused));
+    if focused != *last_focused {
+        *last_focused = focused;
+        tree_changed = true;
+    }
+
+    // Fall back to even tiling when the primary camera is gone
+    let primary = settings
+        .primary_feed
+        .filter(|&primary| tree.0.contains(primary));
+    if primary != *last_primary {
+        *last_primary = primary;
+        tree_changed = true;
+    }
+
+    if settings.layout != *last_layout {
+        *last_layout = settings.layout;
+        tree_changed = true;
+    }
+
+    if settings.pinned != *last_pinned {
+        last_pinned.clone_from(&settings.pinned);
+        tree_changed = true;
+    }
+
+    if !tree_changed {
+        return;
+    }
+
+    tree.0.arrange(&settings.pinned);
+
+    if let Some(focused) = focused {
+        cmds.entity(parent)
+            .despawn_descendants()
+            .with_children(move |builder| {
+                builder
+                    .spawn(root(VideoLayout::Horizontal))
+                    .with_children(|builder| {
+                        build_leaf(
+                            builder,
+                            focused,
+                            &cameras,
+                            VideoLayout::Horizontal,
+                            (100.0, 100.0),
+                        );
+                    });
+            });
+    } else if let Some(primary) = primary {
+        let mut others = Vec::new();
+        tree.0.leaves(&mut others);
+        others.retain(|&it| it != primary);
+
+        cmds.entity(parent)
+            .despawn_descendants()
+            .with_children(move |builder| {
+                builder
+                    .spawn(root(VideoLayout::Horizontal))
+                    .with_children(|builder| {
+                        build_primary(builder, primary, &others, &cameras);
+                    });
+            });
+    } else {
+        let layout = settings.layout;
         let depth = tree.0.max_depth() as i32 - 1;
 
         let size_hint = match layout {
@@ -196,6 +378,10 @@ fn create_display(
                 0.5f32.powi(depth / 2) * 100.0,
                 0.5f32.powi(depth / 2 + depth % 2) * 100.0,
             ),
+            VideoLayout::Grid => {
+                let (rows, columns) = tree.0.grid_size();
+                (100.0 / columns.max(1) as f32, 100.0 / rows.max(1) as f32)
+            }
         };
 
         cmds.entity(parent)
@@ -208,29 +394,37 @@ fn create_display(
     }
 }
 
-// FIXME: Approch in display_3d is a bit cleaner and perhaps more efficient
 fn update_aspect_ratio(
-    mut displays: Query<(&mut Style, &UiImage), With<VideoFeedDisplay>>,
+    mut displays: Query<&mut Style, With<VideoFeedDisplay>>,
+    new_displays: Query<(Entity, &UiImage), (With<VideoFeedDisplay>, Changed<UiImage>)>,
+    mut lost_displays: RemovedComponents<VideoFeedDisplay>,
+    mut index: Local<ImageDisplays>,
+
     mut image_events: EventReader<AssetEvent<Image>>,
     images: Res<Assets<Image>>,
 ) {
-    for image_event in image_events.read() {
-        if let AssetEvent::Added { id } | AssetEvent::Modified { id } = image_event {
-            for (mut style, image) in &mut displays {
-                let handle = &image.texture;
-
-                if handle.id() == *id {
-                    let aspect_ratio = images
-                        .get(handle)
-                        .map(|it| f32::from(it.aspect_ratio()))
-                        .unwrap_or(16.0 / 9.0);
-
-                    // We dont want to unnecessarially trigger anyone's change detection
-                    if style.aspect_ratio != Some(aspect_ratio) {
-                        style.aspect_ratio = Some(aspect_ratio);
-                    }
-                }
-            }
+    for entity in lost_displays.read() {
+        index.remove(entity);
+    }
+
+    for (entity, image) in &new_displays {
+        index.insert(entity, image.texture.id());
+    }
+
+    let new_displays = new_displays
+        .iter()
+        .map(|(entity, image)| (entity, image.texture.id()));
+    for (entity, image) in new_displays.chain(index.updated(image_events.read())) {
+        let Ok(mut style) = displays.get_mut(entity) else {
+            continue;
+        };
+
+        // Guessing a ratio before the first frame arrives would make the layout jump once it does
+        let aspect_ratio = images.get(image).map(|it| f32::from(it.aspect_ratio()));
+
+        // We dont want to unnecessarially trigger anyone's change detection
+        if style.aspect_ratio != aspect_ratio {
+            style.aspect_ratio = aspect_ratio;
         }
     }
 }
@@ -238,10 +432,15 @@ fn update_aspect_ratio(
 fn build_tree(
     builder: &mut ChildBuilder,
     tree: &VideoNode,
-    cameras: &Query<&Handle<Image>>,
+    cameras: &Query<(&Handle<Image>, &Camera)>,
     layout: VideoLayout,
     size_hint: (f32, f32),
 ) {
+    if let VideoLayout::Grid = layout {
+        build_grid(builder, tree, cameras, size_hint);
+        return;
+    }
+
     match tree {
         VideoNode::Branch(children) => {
             #[derive(Clone, Copy)]
@@ -272,21 +471,119 @@ fn build_tree(
             }
         }
         VideoNode::Leaf(camera_entity) => {
-            let weak_texture = cameras
-                .get(*camera_entity)
-                .map(|it| it.clone_weak())
-                .unwrap_or_else(|_| Default::default());
-
-            builder
-                .spawn(container(layout))
-                // TODO: video feed image
-                .with_children(|builder| {
-                    builder.spawn(feed(layout, weak_texture, size_hint));
-                });
+            build_leaf(builder, *camera_entity, cameras, layout, size_hint);
         }
     }
 }
 
+fn build_grid(
+    builder: &mut ChildBuilder,
+    tree: &VideoNode,
+    cameras: &Query<(&Handle<Image>, &Camera)>,
+    size_hint: (f32, f32),
+) {
+    let (_, columns) = tree.grid_size();
+
+    let mut leaves = Vec::new();
+    tree.leaves(&mut leaves);
+
+    for (row_idx, row) in leaves.chunks(columns.max(1) as usize).enumerate() {
+        if row_idx != 0 {
+            builder.spawn(separator(VideoLayout::Vertical));
+        }
+
+        builder
+            .spawn(subroot(VideoLayout::Horizontal))
+            .with_children(|builder| {
+                for idx in 0..columns as usize {
+                    if idx != 0 {
+                        builder.spawn(separator(VideoLayout::Horizontal));
+                    }
+
+                    match row.get(idx) {
+                        Some(&camera_entity) => build_leaf(
+                            builder,
+                            camera_entity,
+                            cameras,
+                            VideoLayout::Horizontal,
+                            size_hint,
+                        ),
+                        // Keep the cells of a partial last row the same size as the others
+                        None => {
+                            builder.spawn(container(VideoLayout::Horizontal));
+                        }
+                    }
+                }
+            });
+    }
+}
+
+fn build_primary(
+    builder: &mut ChildBuilder,
+    primary: Entity,
+    others: &[Entity],
+    cameras: &Query<(&Handle<Image>, &Camera)>,
+) {
+    if others.is_empty() {
+        build_leaf(
+            builder,
+            primary,
+            cameras,
+            VideoLayout::Horizontal,
+            (100.0, 100.0),
+        );
+        return;
+    }
+
+    builder.spawn(primary_column()).with_children(|builder| {
+        build_leaf(
+            builder,
+            primary,
+            cameras,
+            VideoLayout::Vertical,
+            (PRIMARY_WIDTH, 100.0),
+        );
+    });
+    builder.spawn(separator(VideoLayout::Horizontal));
+
+    let size_hint = (100.0 - PRIMARY_WIDTH, 100.0 / others.len() as f32);
+    builder
+        .spawn(subroot(VideoLayout::Vertical))
+        .with_children(|builder| {
+            for (idx, &camera_entity) in others.iter().enumerate() {
+                if idx != 0 {
+                    builder.spawn(separator(VideoLayout::Vertical));
+                }
+
+                build_leaf(
+                    builder,
+                    camera_entity,
+                    cameras,
+                    VideoLayout::Vertical,
+                    size_hint,
+                );
+            }
+        });
+}
+
+fn build_leaf(
+    builder: &mut ChildBuilder,
+    camera_entity: Entity,
+    cameras: &Query<(&Handle<Image>, &Camera)>,
+    layout: VideoLayout,
+    size_hint: (f32, f32),
+) {
+    let (weak_texture, name) = cameras
+        .get(camera_entity)
+        .map(|(texture, camera)| (texture.clone_weak(), camera.label().to_owned()))
+        .unwrap_or_else(|_| Default::default());
+
+    builder.spawn(container(layout)).with_children(|builder| {
+        builder.spawn(feed(layout, weak_texture, size_hint));
+        builder.spawn(label(name));
+    });
+}
+
 fn enable_camera(
     mut last: Local<bool>,
     mut camera: Query<&mut BevyCamera, With<DisplayCamera>>,
@@ -318,7 +615,8 @@ fn root(layout: VideoLayout) -> impl Bundle {
             RENDER_LAYERS,
             DisplayMarker,
         ),
-        VideoLayout::Vertical => (
+        // Grids are a column of rows
+        VideoLayout::Vertical | VideoLayout::Grid => (
             NodeBundle {
                 style: Style {
                     width: Val::Percent(100.0),
@@ -356,7 +654,7 @@ fn subroot(layout: VideoLayout) -> impl Bundle {
             RENDER_LAYERS,
             DisplayMarker,
         ),
-        VideoLayout::Vertical => (
+        VideoLayout::Vertical | VideoLayout::Grid => (
             NodeBundle {
                 style: Style {
                     flex_grow: 1.0,
@@ -377,6 +675,26 @@ fn subroot(layout: VideoLayout) -> impl Bundle {
     }
 }
 
+fn primary_column() -> impl Bundle {
+    (
+        NodeBundle {
+            style: Style {
+                flex_shrink: 0.0,
+                min_width: Val::Px(0.0),
+                width: Val::Percent(PRIMARY_WIDTH),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            background_color: BackgroundColor(Color::from(css::ORANGE)),
+            ..default()
+        },
+        RENDER_LAYERS,
+        DisplayMarker,
+    )
+}
+
 fn container(layout: VideoLayout) -> impl Bundle {
     match layout {
         VideoLayout::Horizontal => (
@@ -395,7 +713,7 @@ fn container(layout: VideoLayout) -> impl Bundle {
             RENDER_LAYERS,
             DisplayMarker,
         ),
-        VideoLayout::Vertical => (
+        VideoLayout::Vertical | VideoLayout::Grid => (
             NodeBundle {
                 style: Style {
                     flex_grow: 1.0,
@@ -414,6 +732,28 @@ fn container(layout: VideoLayout) -> impl Bundle {
     }
 }
 
+fn label(name: String) -> impl Bundle {
+    (
+        TextBundle::from_section(
+            name,
+            TextStyle {
+                font_size: 20.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            left: Val::Px(5.0),
+            ..default()
+        })
+        .with_background_color(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+        RENDER_LAYERS,
+        DisplayMarker,
+    )
+}
+
 fn feed(layout: VideoLayout, texture: Handle<Image>, size_hint: (f32, f32)) -> impl Bundle {
     match layout {
         VideoLayout::Horizontal => (
@@ -425,7 +765,8 @@ fn feed(layout: VideoLayout, texture: Handle<Image>, size_hint: (f32, f32)) -> i
                     max_width: Val::Vw(size_hint.0),
                     max_height: Val::Vw(size_hint.1),
                     flex_direction: FlexDirection::Row,
-                    aspect_ratio: Some(16.0 / 9.0),
+                    // Set from the image by `update_aspect_ratio`, once it is known
+                    aspect_ratio: None,
                     ..default()
                 },
                 // background_color: BackgroundColor(Color::PINK),
@@ -436,7 +777,7 @@ fn feed(layout: VideoLayout, texture: Handle<Image>, size_hint: (f32, f32)) -> i
             DisplayMarker,
             VideoFeedDisplay,
         ),
-        VideoLayout::Vertical => (
+        VideoLayout::Vertical | VideoLayout::Grid => (
             ImageBundle {
                 style: Style {
                     // width: Val::Percent(100.0),
@@ -445,7 +786,8 @@ fn feed(layout: VideoLayout, texture: Handle<Image>, size_hint: (f32, f32)) -> i
                     max_width: Val::Vw(size_hint.0),
                     max_height: Val::Vh(size_hint.1),
                     flex_direction: FlexDirection::Row,
-                    aspect_ratio: Some(16.0 / 9.0),
+                    // Set from the image by `update_aspect_ratio`, once it is known
+                    aspect_ratio: None,
                     ..default()
                 },
                 // background_color: BackgroundColor(Color::PINK),
@@ -475,7 +817,7 @@ fn separator(layout: VideoLayout) -> impl Bundle {
             RENDER_LAYERS,
             DisplayMarker,
         ),
-        VideoLayout::Vertical => (
+        VideoLayout::Vertical | VideoLayout::Grid => (
             NodeBundle {
                 style: Style {
                     height: Val::Px(5.0),
@@ -491,3 +833,79 @@ fn separator(layout: VideoLayout) -> impl Bundle {
         ),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use bevy::prelude::Entity;
+
+    use super::VideoNode;
+
+    fn leaves(tree: &VideoNode) -> Vec<Entity> {
+        let mut leaves = Vec::new();
+        tree.leaves(&mut leaves);
+        leaves
+    }
+
+    #[test]
+    fn grid_fits_all_feeds() {
+        let mut tree = VideoNode::default();
+        assert_eq!(tree.grid_size(), (0, 0));
+
+        let expected = [(1, 1), (1, 2), (2, 2), (2, 2), (2, 3), (2, 3), (3, 3)];
+        for (idx, size) in expected.into_iter().enumerate() {
+            tree.insert(Entity::from_raw(idx as u32));
+            assert_eq!(tree.grid_size(), size, "{} feeds", idx + 1);
+        }
+    }
+
+    #[test]
+    fn pinned_cameras_take_their_slot() {
+        let cameras: Vec<_> = (0..5).map(Entity::from_raw).collect();
+
+        let mut tree = VideoNode::default();
+        for &camera in &cameras {
+            tree.insert(camera);
+        }
+        let auto = leaves(&tree);
+
+        let pinned = BTreeMap::from([(0, cameras[4]), (2, cameras[0])]);
+        tree.arrange(&pinned);
+
+        let arranged = leaves(&tree);
+        assert_eq!(arranged[0], cameras[4]);
+        assert_eq!(arranged[2], cameras[0]);
+
+        // The rest keep the order they were automatically placed in
+        let unpinned: Vec<_> = auto
+            .into_iter()
+            .filter(|it| *it != cameras[4] && *it != cameras[0])
+            .collect();
+        assert_eq!(
+            [arranged[1], arranged[3], arranged[4]].as_slice(),
+            unpinned.as_slice()
+        );
+
+        // Rearranging is stable
+        tree.arrange(&pinned);
+        assert_eq!(leaves(&tree), arranged);
+    }
+
+    #[test]
+    fn missing_pins_are_ignored() {
+        let cameras: Vec<_> = (0..3).map(Entity::from_raw).collect();
+
+        let mut tree = VideoNode::default();
+        for &camera in &cameras {
+            tree.insert(camera);
+        }
+        let auto = leaves(&tree);
+
+        // A camera that is not displayed and a slot past the end
+        let pinned = BTreeMap::from([(0, Entity::from_raw(10)), (7, cameras[1])]);
+        tree.arrange(&pinned);
+
+        assert_eq!(leaves(&tree), auto);
+    }
+}
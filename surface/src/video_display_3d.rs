@@ -5,6 +5,8 @@ use bevy::{
 use bevy_panorbit_camera::PanOrbitCamera;
 use common::components::Camera;
 
+use crate::image_displays::ImageDisplays;
+
 const RENDER_LAYERS: RenderLayers = RenderLayers::layer(3);
 
 pub struct VideoDisplay3DPlugin;
@@ -13,7 +15,15 @@ impl Plugin for VideoDisplay3DPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<VideoDisplay3DSettings>()
             .add_systems(Startup, setup)
-            .add_systems(Update, (create_display, update_aspect_ratio, enable_camera));
+            .add_systems(
+                Update,
+                (
+                    create_display,
+                    update_aspect_ratio,
+                    update_labels,
+                    enable_camera,
+                ),
+            );
     }
 }
 
@@ -23,6 +33,9 @@ struct DisplayCamera;
 struct DisplayParent;
 #[derive(Component)]
 struct DisplayMarker(UVec2);
+/// Text labeling the feed displayed by the contained entity
+#[derive(Component)]
+struct DisplayLabel(Entity);
 
 #[derive(Resource, Default)]
 pub struct VideoDisplay3DSettings {
@@ -55,13 +68,20 @@ fn setup(mut cmds: Commands) {
 fn create_display(
     mut cmds: Commands,
     new_cameras: Query<
-        (Entity, &Handle<Image>, Option<&Transform>),
-        (With<Camera>, Added<Handle<Image>>),
+        (
+            Entity,
+            &Camera,
+            &Handle<Image>,
+            Option<&Transform>,
+            Has<DisplayMarker>,
+        ),
+        Changed<Handle<Image>>,
     >,
     parent: Query<Entity, With<DisplayParent>>,
+    display_camera: Query<Entity, With<DisplayCamera>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    for (entity, handle, transform) in &new_cameras {
+    for (entity, camera, handle, transform, existing) in &new_cameras {
         let material = materials.add(StandardMaterial {
             base_color: Color::WHITE,
             base_color_texture: Some(handle.clone_weak()),
@@ -80,20 +100,67 @@ fn create_display(
             RENDER_LAYERS,
         ));
 
+        if existing {
+            continue;
+        }
+
         let parent = parent.single();
         cmds.entity(parent).add_child(entity);
+
+        cmds.spawn((
+            TextBundle::from_section(
+                camera.label(),
+                TextStyle {
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                ..default()
+            })
+            .with_background_color(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+            TargetCamera(display_camera.single()),
+            DisplayLabel(entity),
+        ));
     }
 }
 
 fn update_aspect_ratio(
     mut cmds: Commands,
-    cameras: Query<(Entity, &Handle<Image>, &DisplayMarker)>,
+    cameras: Query<(&Handle<Image>, &DisplayMarker)>,
+    new_displays: Query<
+        (Entity, &Handle<Image>),
+        (
+            With<DisplayMarker>,
+            Or<(Added<DisplayMarker>, Changed<Handle<Image>>)>,
+        ),
+    >,
+    mut lost_displays: RemovedComponents<DisplayMarker>,
+    mut index: Local<ImageDisplays>,
+
+    mut image_events: EventReader<AssetEvent<Image>>,
     mut meshes: ResMut<Assets<Mesh>>,
     images: Res<Assets<Image>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    for (entity, handle, display) in &cameras {
-        let Some(image) = images.get(handle) else {
+    for entity in lost_displays.read() {
+        index.remove(entity);
+    }
+
+    for (entity, handle) in &new_displays {
+        index.insert(entity, handle.id());
+    }
+
+    let new_displays = new_displays
+        .iter()
+        .map(|(entity, handle)| (entity, handle.id()));
+    for (entity, image) in new_displays.chain(index.updated(image_events.read())) {
+        let Ok((handle, display)) = cameras.get(entity) else {
+            continue;
+        };
+        let Some(image) = images.get(image) else {
             continue;
         };
 
@@ -118,6 +185,44 @@ fn update_aspect_ratio(
     }
 }
 
+/// Keeps each label above the display it names
+fn update_labels(
+    mut cmds: Commands,
+    mut labels: Query<(
+        Entity,
+        &DisplayLabel,
+        &mut Style,
+        &mut Text,
+        &mut Visibility,
+    )>,
+    displays: Query<(&GlobalTransform, Ref<Camera>), With<DisplayMarker>>,
+    display_camera: Query<(&BevyCamera, &GlobalTransform), With<DisplayCamera>>,
+) {
+    let (camera, camera_transform) = display_camera.single();
+
+    for (entity, label, mut style, mut text, mut visibility) in &mut labels {
+        let Ok((transform, feed)) = displays.get(label.0) else {
+            cmds.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        if feed.is_changed() {
+            text.sections[0].value = feed.label().to_owned();
+        }
+
+        // Meshes are 2 units wide, so this is just above the top edge of most feeds
+        let anchor = transform.transform_point(Vec3::Y * 0.75);
+        let Some(position) = camera.world_to_viewport(camera_transform, anchor) else {
+            visibility.set_if_neq(Visibility::Hidden);
+            continue;
+        };
+
+        visibility.set_if_neq(Visibility::Inherited);
+        style.left = Val::Px(position.x);
+        style.top = Val::Px(position.y);
+    }
+}
+
 fn enable_camera(
     mut last: Local<bool>,
     mut camera: Query<&mut BevyCamera, With<DisplayCamera>>,
@@ -1,3 +1,5 @@
+pub mod aruco;
+pub mod color_threshold;
 pub mod edges;
 pub mod marker;
 pub mod measure;
@@ -36,6 +38,7 @@ use tracing::{debug, error};
 
 use crate::{
     video_pipelines::{
+        aruco::ArucoPipelinePlugin, color_threshold::ColorThresholdPipelinePlugin,
         edges::EdgesPipelinePlugin, marker::MarkerPipelinePlugin, save::SavePipelinePlugin,
         squares::SquarePipelinePlugin,
     },
@@ -54,6 +57,8 @@ impl PluginGroup for VideoPipelinePlugins {
             })
             .add(EdgesPipelinePlugin)
             .add(MarkerPipelinePlugin)
+            .add(ColorThresholdPipelinePlugin)
+            .add(ArucoPipelinePlugin)
             .add(SquarePipelinePlugin)
             .add(SavePipelinePlugin)
     }
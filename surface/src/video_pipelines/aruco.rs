@@ -0,0 +1,174 @@
+use anyhow::Context;
+use bevy::{
+    app::{App, Plugin},
+    ecs::{component::Component, entity::Entity},
+    math::Vec2,
+    prelude::{EntityRef, EntityWorldMut, World},
+    reflect::Reflect,
+};
+use opencv::{
+    core::{Point2f, Scalar, Vector},
+    objdetect::{
+        self, ArucoDetector, DetectorParameters, PredefinedDictionaryType, RefineParameters,
+    },
+    prelude::*,
+};
+
+use crate::video_pipelines::{
+    AppPipelineExt, FromWorldEntity, Pipeline, PipelineCallbacks, PipelineCamera,
+};
+
+pub struct ArucoPipelinePlugin;
+
+impl Plugin for ArucoPipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ArucoDictionary>()
+            .register_video_pipeline::<ArucoPipeline>("Aruco Pipeline");
+    }
+}
+
+/// Which family of fiducial markers `ArucoPipeline` looks for, read from the camera entity every frame
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ArucoDictionary {
+    Aruco4x4,
+    Aruco5x5,
+    Aruco6x6,
+    #[default]
+    AprilTag36h11,
+}
+
+impl ArucoDictionary {
+    fn predefined(&self) -> PredefinedDictionaryType {
+        match self {
+            ArucoDictionary::Aruco4x4 => PredefinedDictionaryType::DICT_4X4_250,
+            ArucoDictionary::Aruco5x5 => PredefinedDictionaryType::DICT_5X5_250,
+            ArucoDictionary::Aruco6x6 => PredefinedDictionaryType::DICT_6X6_250,
+            ArucoDictionary::AprilTag36h11 => PredefinedDictionaryType::DICT_APRILTAG_36h11,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArucoMarker {
+    pub id: i32,
+    /// Percentage of the frame, like `MeasurementTarget`
+    ///
+    /// Clockwise starting from the marker's top left corner, regardless of how it is rotated in the frame
+    pub corners: [Vec2; 4],
+}
+
+/// Inserted on the pipeline entity after every frame, empty when no markers were found
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct ArucoMarkers(pub Vec<ArucoMarker>);
+
+pub struct ArucoPipeline {
+    detector: Option<(ArucoDictionary, ArucoDetector)>,
+
+    corners: Vector<Vector<Point2f>>,
+    ids: Vector<i32>,
+    rejected: Vector<Vector<Point2f>>,
+}
+
+impl FromWorldEntity for ArucoPipeline {
+    fn from(world: &mut World, camera: Entity) -> anyhow::Result<Self> {
+        // Give the pilot something to pick from
+        if let Some(mut camera) = world.get_entity_mut(camera) {
+            if !camera.contains::<ArucoDictionary>() {
+                camera.insert(ArucoDictionary::default());
+            }
+        }
+
+        Ok(Self {
+            detector: None,
+            corners: Vector::new(),
+            ids: Vector::new(),
+            rejected: Vector::new(),
+        })
+    }
+}
+
+/// Building a detector is expensive, so it is only rebuilt when the dictionary changes
+fn cached_detector(
+    cache: &mut Option<(ArucoDictionary, ArucoDetector)>,
+    dictionary: ArucoDictionary,
+) -> anyhow::Result<&ArucoDetector> {
+    let stale = !matches!(cache, Some((current, _)) if *current == dictionary);
+
+    if stale {
+        let predefined = objdetect::get_predefined_dictionary(dictionary.predefined())
+            .context("Get dictionary")?;
+        let detector = ArucoDetector::new(
+            &predefined,
+            &DetectorParameters::default().context("Detector parameters")?,
+            RefineParameters::new_def().context("Refine parameters")?,
+        )
+        .context("Create detector")?;
+
+        *cache = Some((dictionary, detector));
+    }
+
+    let (_, detector) = cache.as_ref().expect("Detector was just built");
+    Ok(detector)
+}
+
+impl Pipeline for ArucoPipeline {
+    type Input = ArucoDictionary;
+
+    fn collect_inputs(world: &World, entity: &EntityRef) -> Self::Input {
+        entity
+            .get::<PipelineCamera>()
+            .and_then(|it| world.get::<ArucoDictionary>(it.camera()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        data: &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        let detector = cached_detector(&mut self.detector, *data)?;
+        detector
+            .detect_markers(img, &mut self.corners, &mut self.ids, &mut self.rejected)
+            .context("Detect markers")?;
+
+        let mut markers = Vec::with_capacity(self.ids.len());
+
+        if !self.ids.is_empty() {
+            objdetect::draw_detected_markers(
+                img,
+                &self.corners,
+                &self.ids,
+                Scalar::new(0.0, 255.0, 0.0, 0.0),
+            )
+            .context("Draw markers")?;
+
+            let size = img.size().context("Image size")?;
+            let scale =
+                |it: Point2f| Vec2::new(it.x / size.width as f32, it.y / size.height as f32);
+
+            for (id, corners) in self.ids.iter().zip(self.corners.iter()) {
+                // Every marker has exactly four corners
+                let Ok([a, b, c, d]) = <[Point2f; 4]>::try_from(corners.to_vec()) else {
+                    continue;
+                };
+
+                markers.push(ArucoMarker {
+                    id,
+                    corners: [scale(a), scale(b), scale(c), scale(d)],
+                });
+            }
+        }
+
+        cmds.pipeline(move |mut entity| {
+            entity.insert(ArucoMarkers(markers));
+        });
+
+        Ok(img)
+    }
+
+    fn cleanup(_entity_world: &mut EntityWorldMut) {
+        // No-op
+    }
+}
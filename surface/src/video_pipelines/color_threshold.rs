@@ -0,0 +1,201 @@
+use anyhow::Context;
+use bevy::{
+    app::{App, Plugin},
+    ecs::{component::Component, entity::Entity},
+    math::{Vec2, Vec3},
+    prelude::{EntityRef, EntityWorldMut, World},
+    reflect::Reflect,
+};
+use opencv::{
+    core::{self, Point, Scalar, Vector},
+    imgproc,
+    prelude::*,
+};
+
+use crate::video_pipelines::{
+    AppPipelineExt, FromWorldEntity, Pipeline, PipelineCallbacks, PipelineCamera,
+};
+
+pub struct ColorThresholdPipelinePlugin;
+
+impl Plugin for ColorThresholdPipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ColorThreshold>()
+            .register_video_pipeline::<ColorThresholdPipeline>("Color Threshold Pipeline");
+    }
+}
+
+/// What `ColorThresholdPipeline` looks for, read from the camera entity every frame
+/// so it can be tuned from the inspector while the pipeline is running
+///
+/// Colors are in OpenCV's HSV, hue is 0-180 and saturation and value are 0-255
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct ColorThreshold {
+    /// A lower hue above the upper hue wraps through 0, which is needed for reds
+    pub lower: Vec3,
+    pub upper: Vec3,
+    /// Pixels, smaller blobs are ignored
+    pub min_area: f64,
+}
+
+impl Default for ColorThreshold {
+    fn default() -> Self {
+        // Red recovery float
+        Self {
+            lower: Vec3::new(170.0, 100.0, 80.0),
+            upper: Vec3::new(10.0, 255.0, 255.0),
+            min_area: 500.0,
+        }
+    }
+}
+
+/// Inserted on the pipeline entity after every frame
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct ColorThresholdTarget {
+    /// Centroid of the largest matching blob as a percentage of the frame, like `MeasurementTarget`
+    pub centroid: Option<Vec2>,
+    /// Pixels
+    pub area: f64,
+}
+
+pub struct ColorThresholdPipeline {
+    hsv: Mat,
+    mask: Mat,
+    // Store partial masks to avoid allocation costs when the hue wraps
+    mask_tmp: (Mat, Mat),
+    contours: Vector<Vector<Point>>,
+}
+
+impl FromWorldEntity for ColorThresholdPipeline {
+    fn from(world: &mut World, camera: Entity) -> anyhow::Result<Self> {
+        // Give the pilot something to tune
+        if let Some(mut camera) = world.get_entity_mut(camera) {
+            if !camera.contains::<ColorThreshold>() {
+                camera.insert(ColorThreshold::default());
+            }
+        }
+
+        Ok(Self {
+            hsv: Mat::default(),
+            mask: Mat::default(),
+            mask_tmp: (Mat::default(), Mat::default()),
+            contours: Vector::new(),
+        })
+    }
+}
+
+impl Pipeline for ColorThresholdPipeline {
+    type Input = ColorThreshold;
+
+    fn collect_inputs(world: &World, entity: &EntityRef) -> Self::Input {
+        entity
+            .get::<PipelineCamera>()
+            .and_then(|it| world.get::<ColorThreshold>(it.camera()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        data: &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        imgproc::cvt_color_def(img, &mut self.hsv, imgproc::COLOR_BGR2HSV)
+            .context("Convert to HSV")?;
+
+        let scalar = |it: Vec3| Scalar::new(it.x as f64, it.y as f64, it.z as f64, 0.0);
+        if data.lower.x <= data.upper.x {
+            core::in_range(
+                &self.hsv,
+                &scalar(data.lower),
+                &scalar(data.upper),
+                &mut self.mask,
+            )
+            .context("Mask")?;
+        } else {
+            let upper = Vec3::new(180.0, data.upper.y, data.upper.z);
+            let lower = Vec3::new(0.0, data.lower.y, data.lower.z);
+
+            core::in_range(
+                &self.hsv,
+                &scalar(data.lower),
+                &scalar(upper),
+                &mut self.mask_tmp.0,
+            )
+            .context("Mask 1")?;
+            core::in_range(
+                &self.hsv,
+                &scalar(lower),
+                &scalar(data.upper),
+                &mut self.mask_tmp.1,
+            )
+            .context("Mask 2")?;
+            core::add_def(&self.mask_tmp.0, &self.mask_tmp.1, &mut self.mask)
+                .context("Merge masks")?;
+        }
+
+        self.contours.clear();
+        imgproc::find_contours_def(
+            &self.mask,
+            &mut self.contours,
+            imgproc::RETR_EXTERNAL,
+            imgproc::CHAIN_APPROX_SIMPLE,
+        )
+        .context("Find contours")?;
+
+        let mut largest: Option<(f64, Vector<Point>)> = None;
+        for contour in &self.contours {
+            let area = imgproc::contour_area_def(&contour).context("Area")?;
+
+            // A zero area contour has no centroid
+            if area <= 0.0 || area < data.min_area {
+                continue;
+            }
+
+            let is_largest = match &largest {
+                Some((best, _)) => area > *best,
+                None => true,
+            };
+            if is_largest {
+                largest = Some((area, contour));
+            }
+        }
+
+        let target = if let Some((area, contour)) = largest {
+            let bounds = imgproc::bounding_rect(&contour).context("Bounding rect")?;
+            imgproc::rectangle_def(img, bounds, (0, 255, 0).into()).context("Draw bounds")?;
+
+            let moments = imgproc::moments_def(&contour).context("Moments")?;
+            let centroid = Point::new(
+                (moments.m10 / moments.m00) as i32,
+                (moments.m01 / moments.m00) as i32,
+            );
+            imgproc::draw_marker_def(img, centroid, (0, 255, 0).into()).context("Draw centroid")?;
+
+            let size = img.size().context("Image size")?;
+            ColorThresholdTarget {
+                centroid: Some(Vec2::new(
+                    centroid.x as f32 / size.width as f32,
+                    centroid.y as f32 / size.height as f32,
+                )),
+                area,
+            }
+        } else {
+            ColorThresholdTarget {
+                centroid: None,
+                area: 0.0,
+            }
+        };
+
+        cmds.pipeline(move |mut entity| {
+            entity.insert(target);
+        });
+
+        Ok(img)
+    }
+
+    fn cleanup(_entity_world: &mut EntityWorldMut) {
+        // No-op
+    }
+}
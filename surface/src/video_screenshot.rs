@@ -0,0 +1,429 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context};
+use bevy::{
+    prelude::*,
+    render::{
+        camera::{Camera as BevyCamera, RenderTarget},
+        render_asset::{RenderAssetUsages, RenderAssets},
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d,
+            ImageCopyBuffer, ImageDataLayout, Maintain, MapMode, TextureDimension, TextureFormat,
+            TextureUsages,
+        },
+        renderer::{render_system, RenderDevice, RenderQueue},
+        texture::{BevyDefault, GpuImage},
+        view::RenderLayers,
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+    },
+};
+use bevy_tokio_tasks::TokioTasksRuntime;
+use common::error::{ErrorEvent, Errors};
+use crossbeam::channel::{self, Receiver, Sender};
+use time::{format_description::FormatItem, macros::format_description};
+
+/// Saves the video display to a png when `CaptureScreenshot` is sent
+pub struct VideoScreenshotPlugin;
+
+impl Plugin for VideoScreenshotPlugin {
+    fn build(&self, app: &mut App) {
+        let (tx, rx) = channel::unbounded();
+
+        app.add_event::<CaptureScreenshot>()
+            .insert_resource(Screenshots(rx))
+            .add_systems(
+                Update,
+                (start_captures, advance_captures, save_screenshots).chain(),
+            );
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app
+            .insert_resource(Readbacks {
+                requested: Vec::new(),
+                pending: Vec::new(),
+                finished: tx,
+            })
+            .add_systems(ExtractSchedule, extract_captures)
+            .add_systems(
+                Render,
+                read_back_captures
+                    .in_set(RenderSet::Render)
+                    .after(render_system),
+            );
+    }
+}
+
+/// Captures everything the video display is currently showing, at the size it is shown
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CaptureScreenshot;
+
+/// The camera rendering the video display
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ScreenshotSource;
+
+/// A temporary copy of the source camera renders into an image while a capture is in
+/// progress, this is the frame the image is copied back to the cpu
+///
+/// The image only exists on the gpu after the frame it was created in, so the copy waits
+/// for the camera to have rendered to it at least once
+const READBACK_FRAME: u32 = 2;
+
+/// Screenshots are named after when they were taken, without characters file systems reject
+const FILE_NAME_TIME: &[FormatItem] =
+    format_description!("[year][month][day]_[hour][minute][second]");
+
+/// Lives on the temporary camera, which is despawned once the image has been read back
+#[derive(Component)]
+struct Capture {
+    path: PathBuf,
+    image: Handle<Image>,
+    /// The display camera being copied, its ui is drawn by the temporary camera until the
+    /// capture finishes
+    source: Entity,
+    frames: u32,
+}
+
+struct ReadbackRequest {
+    path: PathBuf,
+    image: AssetId<Image>,
+}
+
+struct PendingReadback {
+    path: PathBuf,
+    buffer: Buffer,
+    size: UVec2,
+    padded_bytes_per_row: usize,
+    mapped: Receiver<anyhow::Result<()>>,
+}
+
+struct Screenshot {
+    path: PathBuf,
+    size: UVec2,
+    data: Vec<u8>,
+}
+
+#[derive(Resource)]
+struct Screenshots(Receiver<anyhow::Result<Screenshot>>);
+
+/// Lives in the render world
+#[derive(Resource)]
+struct Readbacks {
+    requested: Vec<ReadbackRequest>,
+    pending: Vec<PendingReadback>,
+    finished: Sender<anyhow::Result<Screenshot>>,
+}
+
+fn start_captures(
+    mut cmds: Commands,
+    mut events: EventReader<CaptureScreenshot>,
+    cameras: Query<
+        (
+            Entity,
+            &BevyCamera,
+            &OrthographicProjection,
+            &Transform,
+            Option<&RenderLayers>,
+        ),
+        With<ScreenshotSource>,
+    >,
+    captures: Query<(), With<Capture>>,
+    mut ui_roots: Query<&mut TargetCamera>,
+    mut images: ResMut<Assets<Image>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    if events.read().count() == 0 {
+        return;
+    }
+
+    let Some((source, camera, projection, transform, layers)) =
+        cameras.iter().find(|(_, it, ..)| it.is_active)
+    else {
+        errors.send(anyhow!("Cannot screenshot, the video display is not shown").into());
+        return;
+    };
+
+    if !captures.is_empty() {
+        warn!("Screenshot already in progress");
+        return;
+    }
+
+    // The display is laid out in logical pixels, an image has a scale factor of one
+    let Some(size) = camera.logical_viewport_size() else {
+        errors.send(anyhow!("Cannot screenshot, the video display has no size").into());
+        return;
+    };
+    let size = size.round().as_uvec2().max(UVec2::ONE);
+
+    let time = time::OffsetDateTime::now_utc();
+    let file_name = match time.format(FILE_NAME_TIME).context("Format time") {
+        Ok(file_name) => file_name,
+        Err(err) => {
+            errors.send(err.into());
+            return;
+        }
+    };
+
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0; 4],
+        TextureFormat::bevy_default(),
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.texture_descriptor.usage |= TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC;
+    let image = images.add(image);
+
+    let capture = cmds
+        .spawn((
+            Name::new("Screenshot Camera"),
+            Camera2dBundle {
+                camera: BevyCamera {
+                    target: RenderTarget::Image(image.clone()),
+                    viewport: None,
+                    ..camera.clone()
+                },
+                projection: projection.clone(),
+                transform: *transform,
+                ..default()
+            },
+            layers.cloned().unwrap_or_default(),
+            Capture {
+                path: format!("display_{file_name}.png").into(),
+                image,
+                source,
+                frames: 0,
+            },
+        ))
+        .id();
+
+    // Ui is only drawn by the camera it targets, so the tiled display's feeds are drawn into
+    // the image instead of the window until the capture finishes
+    for mut target in &mut ui_roots {
+        if target.entity() == source {
+            *target = TargetCamera(capture);
+        }
+    }
+}
+
+fn advance_captures(
+    mut cmds: Commands,
+    mut captures: Query<(Entity, &mut Capture)>,
+    mut ui_roots: Query<&mut TargetCamera>,
+) {
+    for (entity, mut capture) in &mut captures {
+        capture.frames += 1;
+
+        if capture.frames > READBACK_FRAME {
+            for mut target in &mut ui_roots {
+                if target.entity() == entity {
+                    *target = TargetCamera(capture.source);
+                }
+            }
+
+            cmds.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn save_screenshots(
+    screenshots: Res<Screenshots>,
+    runtime: ResMut<TokioTasksRuntime>,
+    errors: Res<Errors>,
+    mut error_events: EventWriter<ErrorEvent>,
+) {
+    for screenshot in screenshots.0.try_iter() {
+        let screenshot = match screenshot {
+            Ok(screenshot) => screenshot,
+            Err(err) => {
+                error_events.send(err.context("Read back screenshot").into());
+                continue;
+            }
+        };
+
+        let errors = errors.0.clone();
+        runtime.spawn_background_task(move |_ctx| async move {
+            let path = screenshot.path.clone();
+            let rst = tokio::task::spawn_blocking(move || write_png(screenshot)).await;
+
+            match rst {
+                Ok(Ok(())) => info!("Saved screenshot to {}", path.display()),
+                Ok(Err(err)) => {
+                    let _ = errors.send(err);
+                }
+                Err(err) => {
+                    let _ = errors.send(anyhow!(err).context("Save screenshot"));
+                }
+            }
+        });
+    }
+}
+
+fn write_png(screenshot: Screenshot) -> anyhow::Result<()> {
+    let Screenshot { path, size, data } = screenshot;
+
+    let image = Image::new(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::bevy_default(),
+        RenderAssetUsages::MAIN_WORLD,
+    );
+    let image = image.try_into_dynamic().context("Convert screenshot")?;
+
+    image
+        .to_rgba8()
+        .save(&path)
+        .with_context(|| format!("Write {}", path.display()))
+}
+
+fn extract_captures(mut readbacks: ResMut<Readbacks>, captures: Extract<Query<&Capture>>) {
+    for capture in &captures {
+        if capture.frames == READBACK_FRAME {
+            readbacks.requested.push(ReadbackRequest {
+                path: capture.path.clone(),
+                image: capture.image.id(),
+            });
+        }
+    }
+}
+
+/// Copies requested captures into buffers once the frame has been rendered, then
+/// hands them to the main world once the gpu has finished with them
+fn read_back_captures(
+    mut readbacks: ResMut<Readbacks>,
+    images: Res<RenderAssets<GpuImage>>,
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+) {
+    let Readbacks {
+        requested,
+        pending,
+        finished,
+    } = &mut *readbacks;
+
+    for request in requested.drain(..) {
+        let Some(image) = images.get(request.image) else {
+            let _ = finished.send(Err(anyhow!(
+                "Screenshot of {} was not rendered",
+                request.path.display()
+            )));
+            continue;
+        };
+
+        let padded_bytes_per_row =
+            RenderDevice::align_copy_bytes_per_row(image.size.x as usize * 4);
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Screenshot Buffer"),
+            size: (padded_bytes_per_row * image.size.y as usize) as u64,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Screenshot Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            image.texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row as u32),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: image.size.x,
+                height: image.size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit([encoder.finish()]);
+
+        let (tx, rx) = channel::bounded(1);
+        buffer.slice(..).map_async(MapMode::Read, move |rst| {
+            let _ = tx.send(rst.context("Map screenshot buffer"));
+        });
+
+        pending.push(PendingReadback {
+            path: request.path,
+            buffer,
+            size: image.size,
+            padded_bytes_per_row,
+            mapped: rx,
+        });
+    }
+
+    if pending.is_empty() {
+        return;
+    }
+
+    // Runs the map callbacks of copies that have finished, without waiting on the others
+    device.poll(Maintain::Poll);
+
+    pending.retain(|readback| {
+        let Ok(rst) = readback.mapped.try_recv() else {
+            return true;
+        };
+
+        let screenshot = rst.map(|()| Screenshot {
+            path: readback.path.clone(),
+            size: readback.size,
+            data: unpad_rows(
+                &readback.buffer.slice(..).get_mapped_range(),
+                readback.size,
+                readback.padded_bytes_per_row,
+            ),
+        });
+        readback.buffer.unmap();
+
+        let _ = finished.send(screenshot);
+
+        false
+    });
+}
+
+/// Rows of a texture copy are padded to `COPY_BYTES_PER_ROW_ALIGNMENT`
+fn unpad_rows(padded: &[u8], size: UVec2, padded_bytes_per_row: usize) -> Vec<u8> {
+    let bytes_per_row = size.x as usize * 4;
+
+    padded
+        .chunks_exact(padded_bytes_per_row)
+        .take(size.y as usize)
+        .flat_map(|row| &row[..bytes_per_row])
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::UVec2;
+
+    use super::unpad_rows;
+
+    #[test]
+    fn padding_is_stripped() {
+        // 3 pixels wide, padded to 16 bytes per row
+        let size = UVec2::new(3, 2);
+        let mut padded = vec![0xFF; 32];
+        padded[..12].copy_from_slice(&[1; 12]);
+        padded[16..28].copy_from_slice(&[2; 12]);
+
+        let data = unpad_rows(&padded, size, 16);
+
+        assert_eq!(data.len(), 24);
+        assert!(data[..12].iter().all(|&it| it == 1));
+        assert!(data[12..].iter().all(|&it| it == 2));
+    }
+}
@@ -9,7 +9,7 @@ use bevy::{
     },
 };
 use common::{
-    components::Camera,
+    components::{Camera, CameraSource},
     error::{self, ErrorEvent, Errors},
 };
 use crossbeam::channel::{self, Receiver, Sender};
@@ -97,7 +97,14 @@ fn handle_added_camera(
             images.add(Image::default()),
         ));
 
-        let camera = camera.clone();
+        // The feed stays blank if the source cannot be opened
+        let src = match gen_src(camera) {
+            Ok(src) => src,
+            Err(err) => {
+                let _ = errors.0.send(err);
+                continue;
+            }
+        };
         let errors = errors.0.clone();
         thread::Builder::new()
             .name("Video Thread".to_owned())
@@ -105,7 +112,7 @@ fn handle_added_camera(
                 let handle = Arc::downgrade(&handle);
                 let mut images: Vec<Image> = Vec::new();
 
-                let src = VideoCapture::from_file(&gen_src(&camera), videoio::CAP_GSTREAMER);
+                let src = VideoCapture::from_file(&src, videoio::CAP_GSTREAMER);
                 let mut src = match src.context("Open video capture") {
                     Ok(src) => src,
                     Err(err) => {
@@ -281,12 +288,22 @@ fn handle_video_processors(
 }
 
 /// Generates the gstreamer pipeline to recieve data from `camera`
-fn gen_src(camera: &Camera) -> String {
-    let ip = camera.location.ip();
-    let port = camera.location.port();
-
-    format!("udpsrc address={ip} port={port} caps=application/x-rtp,payload=96 ! rtph264depay ! avdec_h264 discard-corrupted-frames=true ! videoconvert ! video/x-raw,format=BGR ! appsink async=false sync=false drop=1")
-    // format!("udpsrc address={ip} port={port} caps=application/x-rtp,media=video,clock-rate=90000,encoding-name=H264,a-framerate=30,payload=96 ! rtph264depay ! h264parse ! vaapih264dec ! videoconvert ! video/x-raw,format=BGR ! appsink drop=1")
+fn gen_src(camera: &Camera) -> anyhow::Result<String> {
+    match &camera.source {
+        CameraSource::H264(location) => {
+            let ip = location.ip();
+            let port = location.port();
+
+            Ok(format!("udpsrc address={ip} port={port} caps=application/x-rtp,payload=96 ! rtph264depay ! avdec_h264 discard-corrupted-frames=true ! videoconvert ! video/x-raw,format=BGR ! appsink async=false sync=false drop=1"))
+            // format!("udpsrc address={ip} port={port} caps=application/x-rtp,media=video,clock-rate=90000,encoding-name=H264,a-framerate=30,payload=96 ! rtph264depay ! h264parse ! vaapih264dec ! videoconvert ! video/x-raw,format=BGR ! appsink drop=1")
+        }
+        CameraSource::Gstreamer(pipeline) => Ok(format!(
+            "{pipeline} ! videoconvert ! video/x-raw,format=BGR ! appsink async=false sync=false drop=1"
+        )),
+        CameraSource::Unsupported(source) => {
+            Err(anyhow!("Unsupported camera source {source:?}, is the surface out of date?"))
+        }
+    }
 }
 
 /// Efficiently converts opencv `Mat`s to bevy `Image`s